@@ -0,0 +1,54 @@
+//! Times the bytecode VM against the tree-walking interpreter on the
+//! `examples/` files both of them can run.
+
+use std::{
+    fs, io,
+    time::{Duration, Instant},
+};
+
+use lira::{eval::Interpreter, parser::parse_program, vm::Vm};
+
+const RUNS: u32 = 5;
+
+fn time<T, E>(mut run: impl FnMut() -> Result<T, E>) -> Option<Duration> {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        run().ok()?;
+    }
+    Some(start.elapsed() / RUNS)
+}
+
+fn main() {
+    let mut paths: Vec<_> = fs::read_dir("examples")
+        .expect("could not read examples dir")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "li"))
+        .collect();
+    paths.sort();
+
+    println!(
+        "{:<24} {:>12} {:>12} {:>8}",
+        "file", "tree-walker", "vm", "speedup"
+    );
+    for path in paths {
+        let source = fs::read_to_string(&path).unwrap();
+        let (program, errors) = parse_program(&source);
+        if !errors.is_empty() {
+            continue;
+        }
+        let walked = time(|| Interpreter::with_output(Box::new(io::sink())).run(&program));
+        let compiled =
+            time(|| Vm::new(Interpreter::with_output(Box::new(io::sink()))).run(&program));
+        // files with unresolved imports or runtime errors are skipped
+        let (Some(walked), Some(compiled)) = (walked, compiled) else {
+            continue;
+        };
+        println!(
+            "{:<24} {:>12.2?} {:>12.2?} {:>7.1}x",
+            path.file_name().unwrap().to_string_lossy(),
+            walked,
+            compiled,
+            walked.as_secs_f64() / compiled.as_secs_f64()
+        );
+    }
+}
@@ -1,76 +1,180 @@
-use crate::lexer::str_litteral::StringPart;
+use std::{fmt, ops::Deref};
 
-#[derive(Debug, PartialEq)]
+use serde::{Deserialize, Serialize};
+
+/// Byte range of a node in the source, as produced by the lexer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+/// A node together with the place it was parsed from.
+///
+/// Equality only looks at the node so that trees parsed from differently laid
+/// out sources can still be compared.
+#[derive(Debug, Clone, Serialize)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, start: usize, end: usize) -> Self {
+        Self {
+            node,
+            span: Span::new(start, end),
+        }
+    }
+}
+
+impl<'i> Spanned<Expr<'i>> {
+    /// Node spanning from `lhs` to `rhs`, for binary operators, pipes and ranges.
+    pub fn infix(
+        lhs: Self,
+        rhs: Self,
+        build: impl FnOnce(Box<Self>, Box<Self>) -> Expr<'i>,
+    ) -> Self {
+        let span = lhs.span.to(rhs.span);
+        Spanned {
+            node: build(Box::new(lhs), Box::new(rhs)),
+            span,
+        }
+    }
+
+    /// Node starting at `start` and ending with `operand`, for unary operators.
+    pub fn prefix(start: usize, operand: Self, build: impl FnOnce(Box<Self>) -> Expr<'i>) -> Self {
+        let end = operand.span.end;
+        Spanned::new(build(Box::new(operand)), start, end)
+    }
+
+    /// Node starting with `base` and ending at `end`, for calls, indexing and field access.
+    pub fn postfix(base: Self, end: usize, build: impl FnOnce(Box<Self>) -> Expr<'i>) -> Self {
+        let start = base.span.start;
+        Spanned::new(build(Box::new(base)), start, end)
+    }
+}
+
+impl<T: PartialEq> PartialEq for Spanned<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+    }
+}
+
+impl<T> Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node
+    }
+}
+
+/// A node together with the lines of the doc comments (`///`) before it.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Documented<'i, T> {
+    pub doc: Vec<&'i str>,
+    pub node: T,
+}
+
+impl<T> Deref for Documented<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node
+    }
+}
+
+/// A struct field, its name and type.
+pub type Field<'i> = Documented<'i, (&'i str, Spanned<Type<'i>>)>;
+
+#[derive(Debug, PartialEq, Serialize)]
 pub struct Program<'i> {
-    pub statements: Vec<Statement<'i>>,
+    pub statements: Vec<Spanned<Statement<'i>>>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub enum Statement<'i> {
     FnDecl {
         name: &'i str,
+        doc: Vec<&'i str>,
         _async: bool,
-        params: Vec<(&'i str, Option<Type<'i>>)>,
-        return_type: Option<Type<'i>>,
-        body: Vec<Statement<'i>>,
+        params: Vec<(&'i str, Option<Spanned<Type<'i>>>)>,
+        return_type: Option<Spanned<Type<'i>>>,
+        body: Vec<Spanned<Statement<'i>>>,
     },
     Let {
         atomic: bool,
         lazy: bool,
         mutable: bool,
         name: LetName<'i>,
-        ty: Option<Type<'i>>,
-        value: Expr<'i>,
+        ty: Option<Spanned<Type<'i>>>,
+        value: Spanned<Expr<'i>>,
     },
     Match(Match<'i>),
     Spawn {
-        body: Vec<Statement<'i>>,
-        with: Option<Expr<'i>>,
+        body: Vec<Spanned<Statement<'i>>>,
+        with: Option<Spanned<Expr<'i>>>,
     },
     ForLoop {
         iterator: &'i str,
-        range: Expr<'i>,
-        body: Vec<Statement<'i>>,
+        range: Spanned<Expr<'i>>,
+        body: Vec<Spanned<Statement<'i>>>,
     },
     WhileLoop {
-        condition: Expr<'i>,
-        body: Vec<Statement<'i>>,
+        condition: Spanned<Expr<'i>>,
+        body: Vec<Spanned<Statement<'i>>>,
     },
     If {
-        condition: Expr<'i>,
-        body: Vec<Statement<'i>>,
-        else_ifs: Vec<(Expr<'i>, Vec<Statement<'i>>)>,
-        else_body: Option<Vec<Statement<'i>>>,
+        condition: Spanned<Expr<'i>>,
+        body: Vec<Spanned<Statement<'i>>>,
+        else_ifs: Vec<(Spanned<Expr<'i>>, Vec<Spanned<Statement<'i>>>)>,
+        else_body: Option<Vec<Spanned<Statement<'i>>>>,
     },
-    Expr(Expr<'i>),
-    Return(Expr<'i>),
+    Expr(Spanned<Expr<'i>>),
+    Return(Spanned<Expr<'i>>),
     Break,
     TypeAlias {
         name: &'i str,
-        ty: Type<'i>,
+        doc: Vec<&'i str>,
+        ty: Spanned<Type<'i>>,
     },
     Struct {
         name: &'i str,
-        fields: Vec<(&'i str, Type<'i>)>,
+        doc: Vec<&'i str>,
+        fields: Vec<Field<'i>>,
     },
     Enum {
         name: &'i str,
-        variants: Vec<EnumVariant<'i>>,
+        doc: Vec<&'i str>,
+        variants: Vec<Documented<'i, EnumVariant<'i>>>,
     },
     // import stmt
     Use {
         path: Vec<&'i str>,
         alias: Option<&'i str>,
     },
+    // placeholder for a statement that failed to parse
+    Error,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub struct Match<'i> {
-    pub expr: Expr<'i>,
+    pub expr: Spanned<Expr<'i>>,
     pub arms: Vec<MatchArm<'i>>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub enum LetName<'i> {
     Ident(&'i str),
     ArrayDestructure(Vec<LetName<'i>>),
@@ -78,27 +182,27 @@ pub enum LetName<'i> {
     StructDestructure(&'i str, Vec<(&'i str, Option<&'i str>)>),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub enum EnumVariant<'i> {
     Unit(&'i str),
-    Tuple(&'i str, Vec<Type<'i>>),
-    Struct(&'i str, Vec<(&'i str, Option<Type<'i>>)>),
+    Tuple(&'i str, Vec<Spanned<Type<'i>>>),
+    Struct(&'i str, Vec<(&'i str, Option<Spanned<Type<'i>>>)>),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub enum EnumVariantData<'i> {
-    Tuple(Vec<Type<'i>>),
-    Struct(Vec<(&'i str, Option<Type<'i>>)>),
+    Tuple(Vec<Spanned<Type<'i>>>),
+    Struct(Vec<(&'i str, Option<Spanned<Type<'i>>>)>),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub struct MatchArm<'i> {
-    pub pattern: Pattern<'i>,
-    pub body: Vec<Statement<'i>>,
-    pub guard: Option<Expr<'i>>,
+    pub pattern: Spanned<Pattern<'i>>,
+    pub body: Vec<Spanned<Statement<'i>>>,
+    pub guard: Option<Spanned<Expr<'i>>>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub enum Pattern<'i> {
     Literal(Literal<'i>),
     Ident(&'i str),
@@ -107,53 +211,66 @@ pub enum Pattern<'i> {
     Wildcard,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub enum Expr<'i> {
     Literal(Literal<'i>),
     Ident(&'i str),
-    Array(Vec<Expr<'i>>),
+    Array(Vec<Spanned<Expr<'i>>>),
     Match(Box<Match<'i>>),
-    Awaitable(Box<Expr<'i>>),
+    Awaitable(Box<Spanned<Expr<'i>>>),
 
     // parenthesized expr<'i>ession is a tuple
-    Tuple(Vec<Expr<'i>>),
-    Binary(Box<Expr<'i>>, BinOp, Box<Expr<'i>>),
-    Pipe(Box<Expr<'i>>, Box<Expr<'i>>),
-    Call(Box<Expr<'i>>, Vec<Expr<'i>>),
-    Range(Box<Expr<'i>>, Box<Expr<'i>>),
-    Assign(Box<Expr<'i>>, BinOp, Box<Expr<'i>>),
-    Unary(UnaryOp, Box<Expr<'i>>),
-    FieldAccess(Box<Expr<'i>>, &'i str),
-    Index(Box<Expr<'i>>, Box<Expr<'i>>),
-    Closure(Vec<(&'i str, Option<Type<'i>>)>, Vec<Statement<'i>>),
-}
-
-#[derive(Debug, PartialEq)]
+    Tuple(Vec<Spanned<Expr<'i>>>),
+    Binary(Box<Spanned<Expr<'i>>>, BinOp, Box<Spanned<Expr<'i>>>),
+    Pipe(Box<Spanned<Expr<'i>>>, Box<Spanned<Expr<'i>>>),
+    Call(Box<Spanned<Expr<'i>>>, Vec<Spanned<Expr<'i>>>),
+    Range(Box<Spanned<Expr<'i>>>, Box<Spanned<Expr<'i>>>),
+    Assign(Box<Spanned<Expr<'i>>>, BinOp, Box<Spanned<Expr<'i>>>),
+    Unary(UnaryOp, Box<Spanned<Expr<'i>>>),
+    FieldAccess(Box<Spanned<Expr<'i>>>, &'i str),
+    Index(Box<Spanned<Expr<'i>>>, Box<Spanned<Expr<'i>>>),
+    Closure(
+        Vec<(&'i str, Option<Spanned<Type<'i>>>)>,
+        Vec<Spanned<Statement<'i>>>,
+    ),
+    // `spawn` as a value, the handle of the task
+    Spawn(Option<Box<Spanned<Expr<'i>>>>, Vec<Spanned<Statement<'i>>>),
+}
+
+#[derive(Debug, PartialEq, Serialize)]
 pub enum Comment<'i> {
     Line(&'i str),
     Block(&'i str),
     Doc(&'i str),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub enum Type<'i> {
     Ident(&'i str),
-    Tuple(Vec<Type<'i>>),
-    Function(Vec<Type<'i>>, Box<Type<'i>>),
-    AsyncFunction(Vec<Type<'i>>, Box<Type<'i>>),
-    Array(Vec<Type<'i>>),
-    Awaitable(Box<Type<'i>>),
+    Tuple(Vec<Spanned<Type<'i>>>),
+    Function(Vec<Spanned<Type<'i>>>, Box<Spanned<Type<'i>>>),
+    AsyncFunction(Vec<Spanned<Type<'i>>>, Box<Spanned<Type<'i>>>),
+    Array(Vec<Spanned<Type<'i>>>),
+    Awaitable(Box<Spanned<Type<'i>>>),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub enum Literal<'i> {
     Int(i32),
     Float(f64),
-    String(Vec<StringPart<'i>>),
+    String(Vec<StringSegment<'i>>),
     Inf,
     Bool(bool),
 }
 
+// string literal once its `#{...}` interpolations have been parsed
+#[derive(Debug, PartialEq, Serialize)]
+pub enum StringSegment<'i> {
+    Text(&'i str),
+    Char(char),
+    Interpolation(Box<Spanned<Expr<'i>>>),
+}
+
 impl From<i32> for Literal<'_> {
     fn from(i: i32) -> Self {
         Literal::Int(i)
@@ -170,7 +287,7 @@ impl From<bool> for Literal<'_> {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
 pub enum BinOp {
     Plus,
     Minus,
@@ -192,10 +309,47 @@ pub enum BinOp {
     ShiftRight,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
 pub enum UnaryOp {
     Not,
     Minus,
     Plus,
     BitNot,
 }
+
+/// The operator as written in Lira, for messages.
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            BinOp::Plus => "+",
+            BinOp::Minus => "-",
+            BinOp::Multiply => "*",
+            BinOp::Divide => "/",
+            BinOp::Modulo => "%",
+            BinOp::Eq => "==",
+            BinOp::Neq => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "^",
+            BinOp::ShiftLeft => "<<",
+            BinOp::ShiftRight => ">>",
+        })
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            UnaryOp::Not => "!",
+            UnaryOp::Minus => "-",
+            UnaryOp::Plus => "+",
+            UnaryOp::BitNot => "~",
+        })
+    }
+}
@@ -0,0 +1,12 @@
+use std::error::Error;
+
+use lsp_server::Connection;
+
+/// The Lira language server, speaking LSP over stdio.
+fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (connection, io_threads) = Connection::stdio();
+    lira::lsp::run(&connection)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
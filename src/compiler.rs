@@ -0,0 +1,1014 @@
+pub mod chunk;
+
+use std::{collections::HashSet, sync::Arc};
+
+use crate::{
+    ast::{
+        BinOp, Expr, LetName, Literal, Match, Pattern, Program, Spanned, Statement, StringSegment,
+        Type,
+    },
+    eval::value::{TypeDef, Value},
+};
+use chunk::{Capture, Op, Proto, Slot, Target, Test};
+
+/// Compiles a program to the function running its top level.
+pub fn compile<'a>(program: &'a Program<'a>) -> Arc<Proto<'a>> {
+    let mut compiler = Compiler::new(&program.statements);
+    compiler.statements(&program.statements, true);
+    compiler.finish()
+}
+
+/// Compiles a module, whose top level only declares its items and runs its
+/// imports, like the tree-walking interpreter does.
+pub fn compile_module<'a>(program: &'a Program<'a>) -> Arc<Proto<'a>> {
+    let mut compiler = Compiler::new(&program.statements);
+    let pending = compiler.hoist(&program.statements);
+    for stmt in &program.statements {
+        if let Statement::Use { .. } = stmt.node {
+            compiler.statement(stmt, false);
+        }
+    }
+    compiler.define_functions(pending);
+    compiler.emit(Op::Unit);
+    compiler.finish()
+}
+
+struct Local<'a> {
+    name: &'a str,
+    slot: Slot,
+    cell: bool,
+    mutable: bool,
+}
+
+struct Upvalue<'a> {
+    name: &'a str,
+    mutable: bool,
+}
+
+/// A loop or match expression being compiled, left by `break` or `:>`.
+struct Exit {
+    mark: Slot,
+    jumps: Vec<usize>,
+}
+
+/// A hoisted function, whose body is compiled once the rest of its block is,
+/// so that it can capture the bindings declared after it.
+struct Pending<'a> {
+    index: u32,
+    name: &'a str,
+    params: &'a [(&'a str, Option<Spanned<Type<'a>>>)],
+    body: &'a [Spanned<Statement<'a>>],
+}
+
+/// The state of a function being compiled.
+struct Function<'a> {
+    proto: Proto<'a>,
+    // empty at the top level of a program, whose bindings are globals
+    scopes: Vec<Vec<Local<'a>>>,
+    // the names used by nested closures, whose locals are stored in cells
+    captured: HashSet<&'a str>,
+    // the cells created ahead of the captured `let`s of a block with functions
+    reserved: Vec<(&'a str, Slot)>,
+    upvalues: Vec<Upvalue<'a>>,
+    loops: Vec<Exit>,
+    // the match expressions, which `:>` leaves with the value of the arm
+    yields: Vec<Exit>,
+}
+
+enum Place {
+    Local {
+        slot: Slot,
+        cell: bool,
+        mutable: bool,
+    },
+    Upvalue {
+        index: u16,
+        mutable: bool,
+    },
+    Global,
+}
+
+struct Compiler<'a> {
+    functions: Vec<Function<'a>>,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(stmts: &'a [Spanned<Statement<'a>>]) -> Self {
+        Self {
+            functions: vec![Function {
+                proto: Proto::default(),
+                scopes: Vec::new(),
+                captured: captured_names(stmts),
+                reserved: Vec::new(),
+                upvalues: Vec::new(),
+                loops: Vec::new(),
+                yields: Vec::new(),
+            }],
+        }
+    }
+
+    fn finish(mut self) -> Arc<Proto<'a>> {
+        self.emit(Op::Return);
+        Arc::new(self.functions.pop().unwrap().proto)
+    }
+
+    fn current(&mut self) -> &mut Function<'a> {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        let code = &mut self.current().proto.code;
+        code.push(op);
+        code.len() - 1
+    }
+
+    /// Makes the jump at `at` land on the next instruction.
+    fn patch(&mut self, at: usize) {
+        let code = &mut self.current().proto.code;
+        let target = code.len() as u32;
+        match &mut code[at] {
+            Op::Jump(to) | Op::JumpIfFalse(to) | Op::JumpIfTrue(to) | Op::Next(_, to) => {
+                *to = target
+            }
+            op => unreachable!("{:?} is not a jump", op),
+        }
+    }
+
+    fn constant(&mut self, value: Value<'a>) -> u32 {
+        let constants = &mut self.current().proto.constants;
+        constants.push(value);
+        constants.len() as u32 - 1
+    }
+
+    fn name(&mut self, name: &'a str) -> u32 {
+        let names = &mut self.current().proto.names;
+        match names.iter().position(|n| *n == name) {
+            Some(i) => i as u32,
+            None => {
+                names.push(name);
+                names.len() as u32 - 1
+            }
+        }
+    }
+
+    fn is_global(&mut self) -> bool {
+        self.functions.len() == 1 && self.current().scopes.is_empty()
+    }
+
+    fn slot(&mut self) -> Slot {
+        let proto = &mut self.current().proto;
+        proto.locals += 1;
+        (proto.locals - 1) as Slot
+    }
+
+    fn declare(&mut self, name: &'a str, mutable: bool) -> Target<'a> {
+        if self.is_global() {
+            return Target::Global(name, mutable);
+        }
+        let slot = self.slot();
+        let function = self.current();
+        let cell = function.captured.contains(name);
+        function.scopes.last_mut().unwrap().push(Local {
+            name,
+            slot,
+            cell,
+            mutable,
+        });
+        if cell {
+            Target::Cell(slot)
+        } else {
+            Target::Local(slot)
+        }
+    }
+
+    /// Binds the value on top of the stack to a freshly declared target.
+    fn store(&mut self, target: Target<'a>) {
+        match target {
+            Target::Local(slot) => self.emit(Op::SetLocal(slot)),
+            Target::Cell(slot) => self.emit(Op::DefineCell(slot)),
+            Target::Global(name, mutable) => {
+                let name = self.name(name);
+                self.emit(Op::DefineGlobal(name, mutable))
+            }
+            target => {
+                let targets = &mut self.current().proto.targets;
+                targets.push(target);
+                let index = targets.len() as u32 - 1;
+                self.emit(Op::Bind(index))
+            }
+        };
+    }
+
+    fn resolve(&mut self, name: &'a str) -> Place {
+        self.resolve_in(self.functions.len() - 1, name)
+    }
+
+    fn resolve_in(&mut self, depth: usize, name: &'a str) -> Place {
+        let function = &mut self.functions[depth];
+        let local = function
+            .scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|local| local.name == name);
+        if let Some(local) = local {
+            return Place::Local {
+                slot: local.slot,
+                cell: local.cell,
+                mutable: local.mutable,
+            };
+        }
+        if let Some(index) = function.upvalues.iter().position(|up| up.name == name) {
+            return Place::Upvalue {
+                index: index as u16,
+                mutable: function.upvalues[index].mutable,
+            };
+        }
+        if depth == 0 {
+            return Place::Global;
+        }
+
+        let (capture, mutable) = match self.resolve_in(depth - 1, name) {
+            Place::Local {
+                slot,
+                cell: true,
+                mutable,
+            } => (Capture::Local(slot), mutable),
+            Place::Upvalue { index, mutable } => (Capture::Upvalue(index), mutable),
+            // every name used by a nested closure is stored in a cell
+            Place::Local { cell: false, .. } => unreachable!("`{}` is not captured", name),
+            Place::Global => return Place::Global,
+        };
+        let function = &mut self.functions[depth];
+        function.upvalues.push(Upvalue { name, mutable });
+        function.proto.captures.push(capture);
+        Place::Upvalue {
+            index: function.upvalues.len() as u16 - 1,
+            mutable,
+        }
+    }
+
+    fn load(&mut self, name: &'a str) {
+        let op = match self.resolve(name) {
+            Place::Local {
+                slot, cell: false, ..
+            } => Op::GetLocal(slot),
+            Place::Local { slot, .. } => Op::GetCell(slot),
+            Place::Upvalue { index, .. } => Op::GetUpvalue(index),
+            Place::Global => Op::GetGlobal(self.name(name)),
+        };
+        self.emit(op);
+    }
+
+    fn assign(&mut self, name: &'a str) {
+        let op = match self.resolve(name) {
+            Place::Local { mutable: false, .. } | Place::Upvalue { mutable: false, .. } => {
+                Op::Immutable(self.name(name))
+            }
+            Place::Local {
+                slot, cell: false, ..
+            } => Op::SetLocal(slot),
+            Place::Local { slot, .. } => Op::SetCell(slot),
+            Place::Upvalue { index, .. } => Op::SetUpvalue(index),
+            Place::Global => Op::SetGlobal(self.name(name)),
+        };
+        self.emit(op);
+    }
+
+    /// Declares the functions and types of a block before its statements run.
+    fn hoist(&mut self, stmts: &'a [Spanned<Statement<'a>>]) -> Vec<Pending<'a>> {
+        let items: Vec<_> = stmts
+            .iter()
+            .filter_map(|stmt| match &stmt.node {
+                Statement::FnDecl { name, .. }
+                | Statement::Struct { name, .. }
+                | Statement::Enum { name, .. } => Some((*name, &stmt.node)),
+                _ => None,
+            })
+            .collect();
+
+        // the cells exist before any item is built, so that functions can
+        // capture each other
+        let targets: Vec<_> = items
+            .iter()
+            .map(|(name, _)| {
+                let target = self.declare(name, false);
+                if let Target::Cell(slot) = target {
+                    self.emit(Op::Unit);
+                    self.emit(Op::DefineCell(slot));
+                }
+                target
+            })
+            .collect();
+
+        let mut pending = Vec::new();
+        for ((_, item), target) in items.into_iter().zip(targets) {
+            match item {
+                Statement::FnDecl {
+                    name, params, body, ..
+                } => {
+                    let index = self.add_proto(Proto::default());
+                    pending.push(Pending {
+                        index,
+                        name,
+                        params,
+                        body,
+                    });
+                    self.emit(Op::Closure(index));
+                }
+                item => {
+                    let def = TypeDef::declared_by(item).unwrap();
+                    let constant = self.constant(Value::Type(Arc::new(def)));
+                    self.emit(Op::Const(constant));
+                }
+            }
+            match target {
+                Target::Cell(slot) => {
+                    self.emit(Op::SetCell(slot));
+                }
+                target => self.store(target),
+            }
+        }
+        pending
+    }
+
+    fn define_functions(&mut self, pending: Vec<Pending<'a>>) {
+        for Pending {
+            index,
+            name,
+            params,
+            body,
+        } in pending
+        {
+            let proto = self.function(Some(name), params, body);
+            self.current().proto.protos[index as usize] = Arc::new(proto);
+        }
+    }
+
+    fn add_proto(&mut self, proto: Proto<'a>) -> u32 {
+        let protos = &mut self.current().proto.protos;
+        protos.push(Arc::new(proto));
+        protos.len() as u32 - 1
+    }
+
+    fn function(
+        &mut self,
+        name: Option<&'a str>,
+        params: &'a [(&'a str, Option<Spanned<Type<'a>>>)],
+        body: &'a [Spanned<Statement<'a>>],
+    ) -> Proto<'a> {
+        self.functions.push(Function {
+            proto: Proto {
+                name,
+                arity: params.len(),
+                ..Proto::default()
+            },
+            scopes: vec![Vec::new()],
+            captured: captured_names(body),
+            reserved: Vec::new(),
+            upvalues: Vec::new(),
+            loops: Vec::new(),
+            yields: Vec::new(),
+        });
+        for (param, _) in params {
+            let target = self.declare(param, false);
+            if let Target::Cell(slot) = target {
+                self.emit(Op::GetLocal(slot));
+                self.emit(Op::DefineCell(slot));
+            }
+        }
+        self.statements(body, true);
+        self.emit(Op::Return);
+
+        self.functions.pop().unwrap().proto
+    }
+
+    fn block(&mut self, stmts: &'a [Spanned<Statement<'a>>], value: bool) {
+        self.current().scopes.push(Vec::new());
+        self.statements(stmts, value);
+        self.current().scopes.pop();
+    }
+
+    /// Compiles statements, leaving the value of the last one on the stack when
+    /// `value` is set.
+    fn statements(&mut self, stmts: &'a [Spanned<Statement<'a>>], value: bool) {
+        let functions = stmts
+            .iter()
+            .any(|stmt| matches!(stmt.node, Statement::FnDecl { .. }));
+        if functions && !self.is_global() {
+            self.reserve(stmts);
+        }
+        let pending = self.hoist(stmts);
+        if stmts.is_empty() && value {
+            self.emit(Op::Unit);
+        }
+        for (i, stmt) in stmts.iter().enumerate() {
+            self.statement(stmt, value && i == stmts.len() - 1);
+        }
+        self.define_functions(pending);
+    }
+
+    /// Creates the cells of the captured `let`s of a block up front, for the
+    /// functions of the block to capture them before they are bound.
+    fn reserve(&mut self, stmts: &'a [Spanned<Statement<'a>>]) {
+        for stmt in stmts {
+            if let Statement::Let {
+                name: LetName::Ident(name),
+                ..
+            } = &stmt.node
+                && self.current().captured.contains(name)
+            {
+                let slot = self.slot();
+                self.emit(Op::Unit);
+                self.emit(Op::DefineCell(slot));
+                self.current().reserved.push((name, slot));
+            }
+        }
+    }
+
+    fn statement(&mut self, stmt: &'a Spanned<Statement<'a>>, value: bool) {
+        match &stmt.node {
+            Statement::FnDecl { .. }
+            | Statement::Struct { .. }
+            | Statement::Enum { .. }
+            | Statement::TypeAlias { .. } => {}
+
+            // nothing shares the locals of the VM, atomic bindings are plain
+            // assignable ones, and lazy ones are evaluated right away
+            Statement::Let {
+                name,
+                mutable,
+                atomic,
+                value: expr,
+                ..
+            } => self.let_binding(name, *mutable || *atomic, expr),
+            Statement::Expr(expr) => {
+                self.expr(expr);
+                if !value {
+                    self.emit(Op::Pop);
+                }
+                return;
+            }
+            Statement::Return(expr) => {
+                self.expr(expr);
+                match self.current().yields.last().map(|exit| exit.mark) {
+                    Some(mark) => {
+                        self.emit(Op::Yield(mark));
+                        let jump = self.emit(Op::Jump(0));
+                        self.current().yields.last_mut().unwrap().jumps.push(jump);
+                    }
+                    None => {
+                        self.emit(Op::Return);
+                    }
+                }
+                return;
+            }
+            Statement::Break => {
+                match self.current().loops.last().map(|exit| exit.mark) {
+                    Some(mark) => {
+                        self.emit(Op::Unwind(mark));
+                        let jump = self.emit(Op::Jump(0));
+                        self.current().loops.last_mut().unwrap().jumps.push(jump);
+                    }
+                    None => {
+                        self.emit(Op::Break);
+                    }
+                }
+                return;
+            }
+            Statement::Match(m) => return self.match_arms(m, value, false),
+
+            Statement::If {
+                condition,
+                body,
+                else_ifs,
+                else_body,
+            } => {
+                let mut ends = Vec::new();
+                for (condition, body) in std::iter::once((condition, body))
+                    .chain(else_ifs.iter().map(|(condition, body)| (condition, body)))
+                {
+                    self.expr(condition);
+                    let skip = self.emit(Op::JumpIfFalse(0));
+                    self.block(body, value);
+                    ends.push(self.emit(Op::Jump(0)));
+                    self.patch(skip);
+                }
+                match else_body {
+                    Some(body) => self.block(body, value),
+                    None if value => {
+                        self.emit(Op::Unit);
+                    }
+                    None => {}
+                }
+                for end in ends {
+                    self.patch(end);
+                }
+                return;
+            }
+            Statement::WhileLoop { condition, body } => {
+                let mark = self.slot();
+                self.emit(Op::Mark(mark));
+                let start = self.current().proto.code.len() as u32;
+                self.expr(condition);
+                let exit = self.emit(Op::JumpIfFalse(0));
+                self.start_loop(mark);
+                self.block(body, false);
+                self.emit(Op::Jump(start));
+                self.patch(exit);
+                self.end_loop();
+            }
+            Statement::ForLoop {
+                iterator,
+                range,
+                body,
+            } => {
+                self.expr(range);
+                let iter = self.slot();
+                self.emit(Op::Iter(iter));
+                let mark = self.slot();
+                self.emit(Op::Mark(mark));
+                let start = self.current().proto.code.len() as u32;
+                let next = self.emit(Op::Next(iter, 0));
+                self.current().scopes.push(Vec::new());
+                let target = self.declare(iterator, false);
+                self.store(target);
+                self.start_loop(mark);
+                self.statements(body, false);
+                self.current().scopes.pop();
+                self.emit(Op::Jump(start));
+                self.patch(next);
+                self.end_loop();
+            }
+
+            Statement::Spawn { .. } => {
+                self.emit(Op::Unsupported("`spawn`"));
+            }
+            Statement::Use { path, alias } => {
+                let paths = &mut self.current().proto.paths;
+                paths.push(path);
+                let index = paths.len() as u32 - 1;
+                self.emit(Op::Import(index));
+                let name = alias.or(path.last().copied()).unwrap_or_default();
+                let target = self.declare(name, false);
+                self.store(target);
+            }
+            Statement::Error => {
+                self.emit(Op::SyntaxError);
+            }
+        }
+        if value {
+            self.emit(Op::Unit);
+        }
+    }
+
+    fn start_loop(&mut self, mark: Slot) {
+        self.current().loops.push(Exit {
+            mark,
+            jumps: Vec::new(),
+        });
+    }
+
+    /// Lands the `break`s of the innermost loop after it.
+    fn end_loop(&mut self) {
+        let exit = self.current().loops.pop().unwrap();
+        for jump in exit.jumps {
+            self.patch(jump);
+        }
+    }
+
+    fn let_binding(&mut self, name: &'a LetName<'a>, mutable: bool, value: &'a Spanned<Expr<'a>>) {
+        if let LetName::Ident(name) = name {
+            let function = self.current();
+            if let Some(i) = function.reserved.iter().position(|(n, _)| n == name) {
+                let (name, slot) = function.reserved.remove(i);
+                function.scopes.last_mut().unwrap().push(Local {
+                    name,
+                    slot,
+                    cell: true,
+                    mutable,
+                });
+                self.expr(value);
+                self.emit(Op::SetCell(slot));
+                return;
+            }
+        }
+        // declared first so that the closure can call itself
+        if let (LetName::Ident(name), Expr::Closure(..)) = (name, &value.node) {
+            let target = self.declare(name, mutable);
+            if let Target::Cell(slot) = target {
+                self.emit(Op::Unit);
+                self.emit(Op::DefineCell(slot));
+                self.expr(value);
+                self.emit(Op::SetCell(slot));
+            } else {
+                self.expr(value);
+                self.store(target);
+            }
+            return;
+        }
+        self.expr(value);
+        let target = self.target(name, mutable);
+        self.store(target);
+    }
+
+    fn target(&mut self, name: &'a LetName<'a>, mutable: bool) -> Target<'a> {
+        match name {
+            LetName::Ident(name) => self.declare(name, mutable),
+            LetName::TupleDestructure(names) | LetName::ArrayDestructure(names) => Target::Many(
+                names
+                    .iter()
+                    .map(|name| self.target(name, mutable))
+                    .collect(),
+            ),
+            LetName::StructDestructure(ty, fields) => Target::Struct(
+                ty,
+                fields
+                    .iter()
+                    .map(|(field, rename)| (*field, self.declare(rename.unwrap_or(field), mutable)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Compiles a match, `expression` making `:>` yield the value of an arm
+    /// instead of returning from the function.
+    fn match_arms(&mut self, m: &'a Match<'a>, value: bool, expression: bool) {
+        self.expr(&m.expr);
+        let scrutinee = self.slot();
+        self.emit(Op::SetLocal(scrutinee));
+        if expression {
+            let mark = self.slot();
+            self.emit(Op::Mark(mark));
+            self.current().yields.push(Exit {
+                mark,
+                jumps: Vec::new(),
+            });
+        }
+
+        let mut ends = Vec::new();
+        for arm in &m.arms {
+            self.current().scopes.push(Vec::new());
+            self.emit(Op::GetLocal(scrutinee));
+            match &arm.pattern.node {
+                Pattern::Literal(lit) => {
+                    self.literal(lit);
+                    self.emit(Op::Binary(BinOp::Eq));
+                }
+                pattern => {
+                    let test = self.test(pattern);
+                    let tests = &mut self.current().proto.tests;
+                    tests.push(test);
+                    let index = tests.len() as u32 - 1;
+                    self.emit(Op::Match(index));
+                }
+            }
+            let mut skips = vec![self.emit(Op::JumpIfFalse(0))];
+            if let Some(guard) = &arm.guard {
+                self.expr(guard);
+                skips.push(self.emit(Op::JumpIfFalse(0)));
+            }
+            self.statements(&arm.body, value);
+            ends.push(self.emit(Op::Jump(0)));
+            for skip in skips {
+                self.patch(skip);
+            }
+            self.current().scopes.pop();
+        }
+        self.emit(Op::NoMatch(scrutinee));
+
+        for end in ends {
+            self.patch(end);
+        }
+        if expression {
+            let exit = self.current().yields.pop().unwrap();
+            for jump in exit.jumps {
+                self.patch(jump);
+            }
+        }
+    }
+
+    fn test(&mut self, pattern: &'a Pattern<'a>) -> Test<'a> {
+        match pattern {
+            Pattern::Wildcard => Test::Wildcard,
+            Pattern::Literal(_) => unreachable!("literal patterns are compared with `==`"),
+            // capitalized names refer to unit variants (`Quit`), others bind the value
+            Pattern::Ident(name) if name.starts_with(char::is_uppercase) => Test::UnitVariant(name),
+            Pattern::Ident(name) => Test::Bind(self.declare(name, false)),
+            Pattern::FunctionDestructor(name, bindings) => Test::Variant(
+                name,
+                bindings
+                    .iter()
+                    .map(|binding| self.declare(binding, false))
+                    .collect(),
+            ),
+            Pattern::StructLikeDestructor(name, fields) => Test::Struct(
+                name,
+                fields
+                    .iter()
+                    .map(|(field, rename)| (*field, self.declare(rename.unwrap_or(field), false)))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn literal(&mut self, lit: &'a Literal<'a>) {
+        let value = match lit {
+            Literal::Int(i) => Value::Int(*i as i64),
+            Literal::Float(f) => Value::Float(*f),
+            Literal::Bool(b) => Value::Bool(*b),
+            Literal::Inf => Value::Float(f64::INFINITY),
+            Literal::String(segments) => {
+                let interpolated = segments
+                    .iter()
+                    .any(|segment| matches!(segment, StringSegment::Interpolation(_)));
+                if interpolated {
+                    for segment in segments {
+                        match segment {
+                            StringSegment::Text(text) => {
+                                let text = self.constant(Value::str(*text));
+                                self.emit(Op::Const(text));
+                            }
+                            StringSegment::Char(c) => {
+                                let c = self.constant(Value::str(c.to_string()));
+                                self.emit(Op::Const(c));
+                            }
+                            StringSegment::Interpolation(expr) => self.expr(expr),
+                        }
+                    }
+                    self.emit(Op::Concat(segments.len() as u32));
+                    return;
+                }
+                let mut s = String::new();
+                for segment in segments {
+                    match segment {
+                        StringSegment::Text(text) => s.push_str(text),
+                        StringSegment::Char(c) => s.push(*c),
+                        StringSegment::Interpolation(_) => {}
+                    }
+                }
+                Value::str(s)
+            }
+        };
+        let constant = self.constant(value);
+        self.emit(Op::Const(constant));
+    }
+
+    fn exprs(&mut self, exprs: &'a [Spanned<Expr<'a>>]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    /// Compiles an expression, which leaves exactly one value on the stack.
+    fn expr(&mut self, expr: &'a Spanned<Expr<'a>>) {
+        match &expr.node {
+            Expr::Literal(lit) => self.literal(lit),
+            Expr::Ident(name) => self.load(name),
+            Expr::Array(items) => {
+                self.exprs(items);
+                self.emit(Op::Array(items.len() as u32));
+            }
+            Expr::Tuple(items) if items.len() == 1 => self.expr(&items[0]),
+            Expr::Tuple(items) => {
+                self.exprs(items);
+                self.emit(Op::Tuple(items.len() as u32));
+            }
+            Expr::Match(m) => self.match_arms(m, true, true),
+            Expr::Awaitable(inner) => {
+                self.expr(inner);
+                self.emit(Op::Await);
+            }
+            Expr::Spawn(..) => {
+                self.emit(Op::Unsupported("`spawn`"));
+            }
+
+            Expr::Binary(lhs, op @ (BinOp::And | BinOp::Or), rhs) => {
+                self.expr(lhs);
+                let short = self.emit(match op {
+                    BinOp::And => Op::JumpIfFalse(0),
+                    _ => Op::JumpIfTrue(0),
+                });
+                self.expr(rhs);
+                self.emit(Op::Bool);
+                let end = self.emit(Op::Jump(0));
+                self.patch(short);
+                let constant = self.constant(Value::Bool(*op == BinOp::Or));
+                self.emit(Op::Const(constant));
+                self.patch(end);
+            }
+            Expr::Binary(lhs, op, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+                self.emit(Op::Binary(*op));
+            }
+            Expr::Unary(op, operand) => {
+                self.expr(operand);
+                self.emit(Op::Unary(*op));
+            }
+
+            Expr::Pipe(lhs, rhs) => {
+                // the piped value is evaluated before the function
+                self.expr(lhs);
+                let piped = self.slot();
+                self.emit(Op::SetLocal(piped));
+                match &rhs.node {
+                    Expr::Call(callee, args) => {
+                        self.expr(callee);
+                        self.emit(Op::GetLocal(piped));
+                        self.exprs(args);
+                        self.emit(Op::Call(args.len() as u32 + 1));
+                    }
+                    _ => {
+                        self.expr(rhs);
+                        self.emit(Op::GetLocal(piped));
+                        self.emit(Op::Call(1));
+                    }
+                }
+            }
+            Expr::Call(callee, args) => match &callee.node {
+                Expr::FieldAccess(base, name) => {
+                    self.expr(base);
+                    self.exprs(args);
+                    let name = self.name(name);
+                    self.emit(Op::Method(name, args.len() as u32));
+                }
+                _ => {
+                    self.expr(callee);
+                    self.exprs(args);
+                    self.emit(Op::Call(args.len() as u32));
+                }
+            },
+            Expr::Range(start, end) => {
+                self.expr(start);
+                self.expr(end);
+                self.emit(Op::Range);
+            }
+            Expr::Assign(target, op, value) => {
+                match op {
+                    BinOp::Eq => self.expr(value),
+                    op => {
+                        self.expr(target);
+                        self.expr(value);
+                        self.emit(Op::Binary(*op));
+                    }
+                }
+                match &target.node {
+                    Expr::Ident(name) => self.assign(name),
+                    Expr::Index(base, index) => {
+                        self.expr(base);
+                        self.expr(index);
+                        self.emit(Op::SetIndex);
+                    }
+                    Expr::FieldAccess(base, name) => {
+                        self.expr(base);
+                        let name = self.name(name);
+                        self.emit(Op::SetField(name));
+                    }
+                    _ => {
+                        self.emit(Op::Unsupported("this assignment target"));
+                    }
+                }
+                self.emit(Op::Unit);
+            }
+            Expr::FieldAccess(base, name) => {
+                self.expr(base);
+                let name = self.name(name);
+                self.emit(Op::Field(name));
+            }
+            Expr::Index(base, index) => {
+                self.expr(base);
+                self.expr(index);
+                self.emit(Op::Index);
+            }
+            Expr::Closure(params, body) => {
+                let proto = self.function(None, params, body);
+                let proto = self.add_proto(proto);
+                self.emit(Op::Closure(proto));
+            }
+        }
+    }
+}
+
+/// The names used inside the functions and closures nested in `stmts`, the
+/// only ones that may be captured.
+fn captured_names<'a>(stmts: &'a [Spanned<Statement<'a>>]) -> HashSet<&'a str> {
+    let mut names = Names {
+        depth: 0,
+        found: HashSet::new(),
+    };
+    names.statements(stmts);
+    names.found
+}
+
+struct Names<'a> {
+    // how many functions deep the walk is
+    depth: usize,
+    found: HashSet<&'a str>,
+}
+
+impl<'a> Names<'a> {
+    fn nested(&mut self, body: &'a [Spanned<Statement<'a>>]) {
+        self.depth += 1;
+        self.statements(body);
+        self.depth -= 1;
+    }
+
+    fn statements(&mut self, stmts: &'a [Spanned<Statement<'a>>]) {
+        for stmt in stmts {
+            match &stmt.node {
+                Statement::FnDecl { body, .. } => self.nested(body),
+                Statement::Let { value, .. } => self.expr(value),
+                Statement::Match(m) => self.match_arms(m),
+                Statement::Spawn { body, with } => {
+                    if let Some(with) = with {
+                        self.expr(with);
+                    }
+                    self.statements(body);
+                }
+                Statement::ForLoop { range, body, .. } => {
+                    self.expr(range);
+                    self.statements(body);
+                }
+                Statement::WhileLoop { condition, body } => {
+                    self.expr(condition);
+                    self.statements(body);
+                }
+                Statement::If {
+                    condition,
+                    body,
+                    else_ifs,
+                    else_body,
+                } => {
+                    self.expr(condition);
+                    self.statements(body);
+                    for (condition, body) in else_ifs {
+                        self.expr(condition);
+                        self.statements(body);
+                    }
+                    if let Some(body) = else_body {
+                        self.statements(body);
+                    }
+                }
+                Statement::Expr(expr) | Statement::Return(expr) => self.expr(expr),
+                _ => {}
+            }
+        }
+    }
+
+    fn match_arms(&mut self, m: &'a Match<'a>) {
+        self.expr(&m.expr);
+        for arm in &m.arms {
+            if let Some(guard) = &arm.guard {
+                self.expr(guard);
+            }
+            self.statements(&arm.body);
+        }
+    }
+
+    fn expr(&mut self, expr: &'a Spanned<Expr<'a>>) {
+        match &expr.node {
+            Expr::Ident(name) if self.depth > 0 => {
+                self.found.insert(name);
+            }
+            Expr::Literal(Literal::String(segments)) => {
+                for segment in segments {
+                    if let StringSegment::Interpolation(expr) = segment {
+                        self.expr(expr);
+                    }
+                }
+            }
+            Expr::Array(items) | Expr::Tuple(items) => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+            Expr::Match(m) => self.match_arms(m),
+            Expr::Awaitable(inner) | Expr::Unary(_, inner) | Expr::FieldAccess(inner, _) => {
+                self.expr(inner)
+            }
+            Expr::Binary(lhs, _, rhs)
+            | Expr::Pipe(lhs, rhs)
+            | Expr::Range(lhs, rhs)
+            | Expr::Assign(lhs, _, rhs)
+            | Expr::Index(lhs, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::Call(callee, args) => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::Closure(_, body) => self.nested(body),
+            Expr::Spawn(with, body) => {
+                if let Some(with) = with {
+                    self.expr(with);
+                }
+                self.statements(body);
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::{fmt, sync::Arc};
+
+use crate::{
+    ast::{BinOp, UnaryOp},
+    eval::value::Value,
+};
+
+/// Index of a local slot in a frame.
+pub type Slot = u16;
+
+/// A single instruction, operands indexing the tables of its [`Proto`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Const(u32),
+    Unit,
+    Pop,
+
+    GetLocal(Slot),
+    SetLocal(Slot),
+    // moves a value into a fresh shared cell, for the locals captured by closures
+    DefineCell(Slot),
+    GetCell(Slot),
+    SetCell(Slot),
+    GetUpvalue(u16),
+    SetUpvalue(u16),
+    GetGlobal(u32),
+    // the name, and whether the binding is mutable
+    DefineGlobal(u32, bool),
+    SetGlobal(u32),
+    // assignment to an immutable binding, named by the operand
+    Immutable(u32),
+    // destructures the value on top of the stack into a `let` pattern
+    Bind(u32),
+
+    Binary(BinOp),
+    Unary(UnaryOp),
+    // checks the value on top of the stack is a Bool, the rhs of `&&` and `||`
+    Bool,
+    Range,
+    Array(u32),
+    Tuple(u32),
+    // joins the values of a string with interpolations
+    Concat(u32),
+    // waits for the future on top of the stack, `async fn` having returned
+    // their value right away
+    Await,
+    Field(u32),
+    SetField(u32),
+    Index,
+    SetIndex,
+
+    // absolute instruction offsets
+    Jump(u32),
+    JumpIfFalse(u32),
+    JumpIfTrue(u32),
+
+    // the arguments count, the callee being below them
+    Call(u32),
+    // the method name and the arguments count, the receiver being below them
+    Method(u32, u32),
+    Closure(u32),
+    Return,
+
+    // turns the value on top of the stack into an iterator kept in a slot
+    Iter(Slot),
+    // pushes the next item of the iterator in the slot, or jumps when it is exhausted
+    Next(Slot, u32),
+    // saves the stack height in a slot, for `break` and `:>` to restore it
+    Mark(Slot),
+    Unwind(Slot),
+    // restores the stack height, keeping the value on top (`:>` in a match expression)
+    Yield(Slot),
+    // a `break` outside of any loop of the function, stopping `each` and friends
+    Break,
+
+    // tests the value on top of the stack against a pattern, binding its names
+    Match(u32),
+    // no arm matched the value in the slot
+    NoMatch(Slot),
+    Import(u32),
+    Unsupported(&'static str),
+    SyntaxError,
+}
+
+/// Where a closure finds a variable of an enclosing function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capture {
+    // a cell in the locals of the function creating the closure
+    Local(Slot),
+    // an upvalue of the function creating the closure
+    Upvalue(u16),
+}
+
+/// Where a binding introduced by a `let` or a pattern is stored.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target<'a> {
+    Local(Slot),
+    Cell(Slot),
+    Global(&'a str, bool),
+    // array and tuple destructuring
+    Many(Vec<Target<'a>>),
+    // the struct name, and the target of each field
+    Struct(&'a str, Vec<(&'a str, Target<'a>)>),
+}
+
+/// A compiled match arm pattern, literals being compared with `==` instead.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Test<'a> {
+    Wildcard,
+    UnitVariant(&'a str),
+    Bind(Target<'a>),
+    Variant(&'a str, Vec<Target<'a>>),
+    Struct(&'a str, Vec<(&'a str, Target<'a>)>),
+}
+
+/// A compiled function, or the top level of a program or module.
+#[derive(Debug, Default)]
+pub struct Proto<'a> {
+    pub name: Option<&'a str>,
+    pub arity: usize,
+    // slots count, parameters first
+    pub locals: usize,
+    pub code: Vec<Op>,
+    pub constants: Vec<Value<'a>>,
+    pub names: Vec<&'a str>,
+    pub protos: Vec<Arc<Proto<'a>>>,
+    pub captures: Vec<Capture>,
+    pub tests: Vec<Test<'a>>,
+    pub targets: Vec<Target<'a>>,
+    pub paths: Vec<&'a [&'a str]>,
+}
+
+/// Lists the instructions of a function and of the functions nested in it.
+impl fmt::Display for Proto<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "fn {} ({} params, {} locals)",
+            self.name.unwrap_or("<closure>"),
+            self.arity,
+            self.locals
+        )?;
+        for (offset, op) in self.code.iter().enumerate() {
+            write!(f, "{:>4} {:?}", offset, op)?;
+            match op {
+                Op::Const(i) => write!(f, " ; {}", self.constants[*i as usize])?,
+                Op::GetGlobal(i)
+                | Op::DefineGlobal(i, _)
+                | Op::SetGlobal(i)
+                | Op::Immutable(i)
+                | Op::Field(i)
+                | Op::SetField(i)
+                | Op::Method(i, _) => write!(f, " ; {}", self.names[*i as usize])?,
+                Op::Import(i) => write!(f, " ; {}", self.paths[*i as usize].join("::"))?,
+                _ => {}
+            }
+            writeln!(f)?;
+        }
+        for proto in &self.protos {
+            writeln!(f)?;
+            write!(f, "{}", proto)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,502 @@
+use rowan::{GreenNode, GreenNodeBuilder};
+
+use crate::{
+    Lexer, Token,
+    ast::{Expr, Match, Program, Span, Spanned, Statement, Type},
+    parser::{SyntaxError, parse_program},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(u16)]
+pub enum SyntaxKind {
+    // trivia
+    Whitespace,
+    LineEnd,
+    LineComment,
+    BlockComment,
+    DocComment,
+
+    // tokens, and a token or statement which does not lex or parse
+    Error,
+    Ident,
+    Keyword,
+    Number,
+    String,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
+    Comma,
+    // `.`, `:`, `::`, `->`, `..` and `_`
+    Punct,
+    Operator,
+
+    // nodes
+    Root,
+    FnDecl,
+    Let,
+    Match,
+    MatchArm,
+    Pattern,
+    Spawn,
+    ForLoop,
+    WhileLoop,
+    If,
+    ExprStmt,
+    Return,
+    Break,
+    TypeAlias,
+    Struct,
+    Enum,
+    Use,
+    Type,
+    Literal,
+    Name,
+    Array,
+    Tuple,
+    Await,
+    Binary,
+    Pipe,
+    Call,
+    Range,
+    Assign,
+    Unary,
+    FieldAccess,
+    Index,
+    Closure,
+}
+
+impl SyntaxKind {
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::Whitespace
+                | SyntaxKind::LineEnd
+                | SyntaxKind::LineComment
+                | SyntaxKind::BlockComment
+                | SyntaxKind::DocComment
+        )
+    }
+
+    fn of(token: &Token) -> Self {
+        use Token::*;
+        match token {
+            Fn | Let | Struct | If | TypeAlias | Enum | Use | As | Elsif | Else | Spawn
+            | Shared | With | Atomic | Match | Lazy | Mut | Inf | For | In | While | Break
+            | True | False | Async | Await | Return => SyntaxKind::Keyword,
+            Ident(_) => SyntaxKind::Ident,
+            Int(_) | Float(_) | Binary(_) | Octal(_) | Hex(_) => SyntaxKind::Number,
+            String(_) => SyntaxKind::String,
+            LParen => SyntaxKind::LParen,
+            RParen => SyntaxKind::RParen,
+            LBracket => SyntaxKind::LBracket,
+            RBracket => SyntaxKind::RBracket,
+            LBrace => SyntaxKind::LBrace,
+            RBrace => SyntaxKind::RBrace,
+            Comma => SyntaxKind::Comma,
+            Colon | DoubleColon | Arrow | Dot | Range | Wildcard => SyntaxKind::Punct,
+            LineComment(_) => SyntaxKind::LineComment,
+            BlockComment(_) => SyntaxKind::BlockComment,
+            DocComment(_) => SyntaxKind::DocComment,
+            Whitespace => SyntaxKind::Whitespace,
+            LineEnd => SyntaxKind::LineEnd,
+            _ => SyntaxKind::Operator,
+        }
+    }
+}
+
+impl From<SyntaxKind> for rowan::SyntaxKind {
+    fn from(kind: SyntaxKind) -> Self {
+        rowan::SyntaxKind(kind as u16)
+    }
+}
+
+/// The language of the rowan trees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Lira {}
+
+impl rowan::Language for Lira {
+    type Kind = SyntaxKind;
+
+    fn kind_from_raw(raw: rowan::SyntaxKind) -> SyntaxKind {
+        assert!(raw.0 <= SyntaxKind::Closure as u16);
+        // SAFETY: the kind is in the range of the enum, which is `repr(u16)`
+        unsafe { std::mem::transmute::<u16, SyntaxKind>(raw.0) }
+    }
+
+    fn kind_to_raw(kind: SyntaxKind) -> rowan::SyntaxKind {
+        kind.into()
+    }
+}
+
+pub type SyntaxNode = rowan::SyntaxNode<Lira>;
+pub type SyntaxToken = rowan::SyntaxToken<Lira>;
+pub type SyntaxElement = rowan::SyntaxElement<Lira>;
+
+/// A lossless tree of a source: its text is the source, whitespace, line
+/// ends and comments included.
+///
+/// Statements, expressions, types, patterns and match arms are nodes
+/// spanning their tokens, laid out as in the program parsed from the source.
+/// The trivia between two tokens stays outside of the nodes closing before
+/// or opening after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cst {
+    text: String,
+    green: GreenNode,
+}
+
+impl Cst {
+    pub fn parse(source: &str) -> Cst {
+        let (program, _) = parse_program(source);
+        let mut nodes = Nodes::default();
+        nodes.statements(&program.statements);
+        // parents come first among the nodes starting together
+        nodes.spans.sort_by_key(|(_, span)| span.start);
+
+        let mut builder = GreenNodeBuilder::new();
+        builder.start_node(SyntaxKind::Root.into());
+        let mut open: Vec<usize> = Vec::new();
+        let mut next = 0;
+        for (kind, start, end) in tokens(source) {
+            while open.last().is_some_and(|&end| end <= start) {
+                builder.finish_node();
+                open.pop();
+            }
+            // nodes open at their first token, after the trivia before it
+            while !kind.is_trivia()
+                && let Some(&(node, span)) = nodes.spans.get(next)
+                && span.start <= start
+            {
+                next += 1;
+                if span.end > start && open.last().is_none_or(|&end| span.end <= end) {
+                    builder.start_node(node.into());
+                    open.push(span.end);
+                }
+            }
+            builder.token(kind.into(), &source[start..end]);
+        }
+        for _ in open {
+            builder.finish_node();
+        }
+        builder.finish_node();
+
+        Cst {
+            text: source.to_string(),
+            green: builder.finish(),
+        }
+    }
+
+    /// The tree of a root edited with the rowan API, its nodes being laid
+    /// out again.
+    pub fn from_syntax(root: &SyntaxNode) -> Cst {
+        Cst::parse(&root.to_string())
+    }
+
+    pub fn syntax(&self) -> SyntaxNode {
+        SyntaxNode::new_root(self.green.clone())
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The program of the tree, whose spans are offsets in its text.
+    pub fn program(&self) -> (Program<'_>, Vec<SyntaxError<'_>>) {
+        parse_program(&self.text)
+    }
+
+    /// The edits of trivia turning this tree into `other`, or `None` when
+    /// their tokens differ.
+    ///
+    /// Each edit replaces the trivia between two tokens, so that a change of
+    /// layout (by the formatter for instance) leaves the rest of the text
+    /// untouched.
+    pub fn trivia_edits(&self, other: &Cst) -> Option<Vec<(Span, String)>> {
+        let (ours, theirs) = (runs(&self.syntax()), runs(&other.syntax()));
+        if ours.len() != theirs.len() {
+            return None;
+        }
+        let mut edits = Vec::new();
+        for ((span, trivia, token), (_, other_trivia, other_token)) in ours.into_iter().zip(theirs)
+        {
+            if token != other_token {
+                return None;
+            }
+            if trivia != other_trivia {
+                edits.push((span, other_trivia));
+            }
+        }
+        Some(edits)
+    }
+}
+
+/// The trivia following `token` on its line, up to its line end.
+///
+/// Doc comments are left to the item after them. The trivia of a line
+/// following another token belongs to that token.
+pub fn trailing_trivia(token: &SyntaxToken) -> Vec<SyntaxToken> {
+    let mut trivia = Vec::new();
+    let mut next = token.next_token();
+    while let Some(token) = next {
+        let kind = token.kind();
+        if !kind.is_trivia() || kind == SyntaxKind::DocComment {
+            break;
+        }
+        next = token.next_token();
+        trivia.push(token);
+        if kind == SyntaxKind::LineEnd {
+            break;
+        }
+    }
+    trivia
+}
+
+/// The trivia before `token` which does not trail the token before it.
+pub fn leading_trivia(token: &SyntaxToken) -> Vec<SyntaxToken> {
+    let mut trivia = Vec::new();
+    let mut previous = token.prev_token();
+    while let Some(token) = previous {
+        if !token.kind().is_trivia() {
+            let trailing = trailing_trivia(&token).len();
+            trivia.truncate(trivia.len() - trailing);
+            break;
+        }
+        previous = token.prev_token();
+        trivia.push(token);
+    }
+    trivia.reverse();
+    trivia
+}
+
+// every token of the source, the whitespace skipped by the lexer included
+fn tokens(source: &str) -> Vec<(SyntaxKind, usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut lexer = Lexer::new(source);
+    let mut last = 0;
+    while let Some(token) = lexer.next() {
+        let (start, end) = lexer.span();
+        if start > last {
+            tokens.push((SyntaxKind::Whitespace, last, start));
+        }
+        let kind = match token {
+            Ok((_, token, _)) => SyntaxKind::of(&token),
+            Err(_) => SyntaxKind::Error,
+        };
+        tokens.push((kind, start, end));
+        last = end;
+    }
+    if source.len() > last {
+        tokens.push((SyntaxKind::Whitespace, last, source.len()));
+    }
+    tokens
+}
+
+// the tokens of a tree, each with the span and text of the trivia before it,
+// and the trivia at the end
+fn runs(root: &SyntaxNode) -> Vec<(Span, String, Option<String>)> {
+    let mut runs = Vec::new();
+    let mut trivia = String::new();
+    let mut start = 0;
+    for token in root
+        .descendants_with_tokens()
+        .filter_map(|element| element.into_token())
+    {
+        let range = token.text_range();
+        if token.kind().is_trivia() {
+            trivia.push_str(token.text());
+            continue;
+        }
+        let span = Span::new(start, range.start().into());
+        runs.push((span, std::mem::take(&mut trivia), Some(token.to_string())));
+        start = range.end().into();
+    }
+    let end = root.text_range().end().into();
+    runs.push((Span::new(start, end), trivia, None));
+    runs
+}
+
+/// The spans of the nodes of a program, parents first.
+#[derive(Default)]
+struct Nodes {
+    spans: Vec<(SyntaxKind, Span)>,
+}
+
+impl Nodes {
+    fn statements(&mut self, stmts: &[Spanned<Statement>]) {
+        for stmt in stmts {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Spanned<Statement>) {
+        let kind = match &stmt.node {
+            Statement::FnDecl { .. } => SyntaxKind::FnDecl,
+            Statement::Let { .. } => SyntaxKind::Let,
+            Statement::Match(_) => SyntaxKind::Match,
+            Statement::Spawn { .. } => SyntaxKind::Spawn,
+            Statement::ForLoop { .. } => SyntaxKind::ForLoop,
+            Statement::WhileLoop { .. } => SyntaxKind::WhileLoop,
+            Statement::If { .. } => SyntaxKind::If,
+            Statement::Expr(_) => SyntaxKind::ExprStmt,
+            Statement::Return(_) => SyntaxKind::Return,
+            Statement::Break => SyntaxKind::Break,
+            Statement::TypeAlias { .. } => SyntaxKind::TypeAlias,
+            Statement::Struct { .. } => SyntaxKind::Struct,
+            Statement::Enum { .. } => SyntaxKind::Enum,
+            Statement::Use { .. } => SyntaxKind::Use,
+            Statement::Error => SyntaxKind::Error,
+        };
+        self.spans.push((kind, stmt.span));
+
+        match &stmt.node {
+            Statement::FnDecl {
+                params,
+                return_type,
+                body,
+                ..
+            } => {
+                self.params(params);
+                if let Some(ty) = return_type {
+                    self.ty(ty);
+                }
+                self.statements(body);
+            }
+            Statement::Let { ty, value, .. } => {
+                if let Some(ty) = ty {
+                    self.ty(ty);
+                }
+                self.expr(value);
+            }
+            Statement::Match(m) => self.match_(m),
+            Statement::Spawn { body, with } => {
+                if let Some(with) = with {
+                    self.expr(with);
+                }
+                self.statements(body);
+            }
+            Statement::ForLoop { range, body, .. } => {
+                self.expr(range);
+                self.statements(body);
+            }
+            Statement::WhileLoop { condition, body } => {
+                self.expr(condition);
+                self.statements(body);
+            }
+            Statement::If {
+                condition,
+                body,
+                else_ifs,
+                else_body,
+            } => {
+                self.expr(condition);
+                self.statements(body);
+                for (condition, body) in else_ifs {
+                    self.expr(condition);
+                    self.statements(body);
+                }
+                if let Some(body) = else_body {
+                    self.statements(body);
+                }
+            }
+            Statement::Expr(expr) | Statement::Return(expr) => self.expr(expr),
+            Statement::TypeAlias { ty, .. } => self.ty(ty),
+            Statement::Struct { fields, .. } => fields.iter().for_each(|field| self.ty(&field.1)),
+            Statement::Enum { .. }
+            | Statement::Use { .. }
+            | Statement::Break
+            | Statement::Error => {}
+        }
+    }
+
+    fn params(&mut self, params: &[(&str, Option<Spanned<Type>>)]) {
+        for (_, ty) in params {
+            if let Some(ty) = ty {
+                self.ty(ty);
+            }
+        }
+    }
+
+    fn match_(&mut self, m: &Match) {
+        self.expr(&m.expr);
+        for arm in &m.arms {
+            let span = arm
+                .body
+                .last()
+                .map_or(arm.pattern.span, |last| arm.pattern.span.to(last.span));
+            self.spans.push((SyntaxKind::MatchArm, span));
+            self.spans.push((SyntaxKind::Pattern, arm.pattern.span));
+            if let Some(guard) = &arm.guard {
+                self.expr(guard);
+            }
+            self.statements(&arm.body);
+        }
+    }
+
+    // the interpolations of strings are left inside of their token
+    fn expr(&mut self, expr: &Spanned<Expr>) {
+        let kind = match &expr.node {
+            Expr::Literal(_) => SyntaxKind::Literal,
+            Expr::Ident(_) => SyntaxKind::Name,
+            Expr::Array(_) => SyntaxKind::Array,
+            Expr::Match(_) => SyntaxKind::Match,
+            Expr::Awaitable(_) => SyntaxKind::Await,
+            Expr::Tuple(_) => SyntaxKind::Tuple,
+            Expr::Binary(..) => SyntaxKind::Binary,
+            Expr::Pipe(..) => SyntaxKind::Pipe,
+            Expr::Call(..) => SyntaxKind::Call,
+            Expr::Range(..) => SyntaxKind::Range,
+            Expr::Assign(..) => SyntaxKind::Assign,
+            Expr::Unary(..) => SyntaxKind::Unary,
+            Expr::FieldAccess(..) => SyntaxKind::FieldAccess,
+            Expr::Index(..) => SyntaxKind::Index,
+            Expr::Closure(..) => SyntaxKind::Closure,
+            Expr::Spawn(..) => SyntaxKind::Spawn,
+        };
+        self.spans.push((kind, expr.span));
+
+        match &expr.node {
+            Expr::Literal(_) | Expr::Ident(_) => {}
+            Expr::Array(items) | Expr::Tuple(items) => items.iter().for_each(|e| self.expr(e)),
+            Expr::Match(m) => self.match_(m),
+            Expr::Awaitable(e) | Expr::Unary(_, e) | Expr::FieldAccess(e, _) => self.expr(e),
+            Expr::Binary(lhs, _, rhs)
+            | Expr::Pipe(lhs, rhs)
+            | Expr::Range(lhs, rhs)
+            | Expr::Assign(lhs, _, rhs)
+            | Expr::Index(lhs, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::Call(callee, args) => {
+                self.expr(callee);
+                args.iter().for_each(|e| self.expr(e));
+            }
+            Expr::Closure(params, body) => {
+                self.params(params);
+                self.statements(body);
+            }
+            Expr::Spawn(with, body) => {
+                if let Some(with) = with {
+                    self.expr(with);
+                }
+                self.statements(body);
+            }
+        }
+    }
+
+    fn ty(&mut self, ty: &Spanned<Type>) {
+        self.spans.push((SyntaxKind::Type, ty.span));
+        match &ty.node {
+            Type::Ident(_) => {}
+            Type::Tuple(types) | Type::Array(types) => types.iter().for_each(|ty| self.ty(ty)),
+            Type::Function(params, ret) | Type::AsyncFunction(params, ret) => {
+                params.iter().for_each(|ty| self.ty(ty));
+                self.ty(ret);
+            }
+            Type::Awaitable(ty) => self.ty(ty),
+        }
+    }
+}
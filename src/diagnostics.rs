@@ -0,0 +1,234 @@
+use std::fmt::Write;
+
+use lalrpop_util::ParseError;
+use logos::Logos;
+
+use crate::{LexingError, Token, ast::Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// An error located in a source file, ready to be shown to the user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    // short text printed next to the caret underline
+    pub label: Option<String>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            label: None,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Diagnostic::new(message, span)
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn from_lexing_error(error: &LexingError, span: Span) -> Self {
+        Diagnostic::new(error.to_string(), span)
+    }
+
+    /// Converts a parser error, `source` being the text that was parsed.
+    pub fn from_parse_error(
+        error: &ParseError<usize, Token<'_>, LexingError>,
+        source: &str,
+    ) -> Self {
+        match error {
+            ParseError::InvalidToken { location } => {
+                Diagnostic::new("invalid token", Span::new(*location, *location + 1))
+            }
+            ParseError::UnrecognizedEof { location, expected } => {
+                Diagnostic::new("unexpected end of file", Span::new(*location, *location))
+                    .with_label(expected_label(expected))
+            }
+            ParseError::UnrecognizedToken {
+                token: (start, token, end),
+                expected,
+            } => Diagnostic::new(
+                format!(
+                    "unexpected {}",
+                    describe(token, source.get(*start..*end).unwrap_or_default())
+                ),
+                Span::new(*start, *end),
+            )
+            .with_label(expected_label(expected)),
+            ParseError::ExtraToken {
+                token: (start, token, end),
+            } => Diagnostic::new(
+                format!(
+                    "unexpected {}",
+                    describe(token, source.get(*start..*end).unwrap_or_default())
+                ),
+                Span::new(*start, *end),
+            )
+            .with_label("expected end of file"),
+            ParseError::User { error } => {
+                Diagnostic::from_lexing_error(error, locate_lexing_error(source, error))
+            }
+        }
+    }
+
+    /// Renders the diagnostic with its location and an excerpt of the offending line:
+    ///
+    /// ```text
+    /// error: unexpected `)`
+    ///  --> main.li:1:9
+    ///   |
+    /// 1 | let a = )
+    ///   |         ^ expected an expression
+    /// ```
+    pub fn render(&self, file: &str, source: &str) -> String {
+        let start = self.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line_text = source[line_start..line_end].trim_end_matches('\r');
+        let (line, col) = line_col(source, start);
+        let gutter = " ".repeat(line.to_string().len());
+
+        // underline at least one character, and only up to the end of the line
+        let underline_end = self.span.end.clamp(start, line_start + line_text.len());
+        let underline_len = source[start..underline_end].chars().count().max(1);
+
+        let mut out = String::new();
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        writeln!(out, "{}: {}", severity, self.message).unwrap();
+        writeln!(out, "{}--> {}:{}:{}", gutter, file, line, col).unwrap();
+        writeln!(out, "{} |", gutter).unwrap();
+        writeln!(out, "{} | {}", line, line_text).unwrap();
+        write!(
+            out,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(col - 1),
+            "^".repeat(underline_len)
+        )
+        .unwrap();
+        if let Some(label) = self.label.as_ref().filter(|label| !label.is_empty()) {
+            write!(out, " {}", label).unwrap();
+        }
+        out.push('\n');
+        for note in &self.notes {
+            writeln!(out, "{} = note: {}", gutter, note).unwrap();
+        }
+        out
+    }
+
+    /// The diagnostic as a JSON object, for tools reading `--format json`.
+    pub fn to_json(&self, file: &str, source: &str) -> serde_json::Value {
+        let (line, column) = line_col(source, self.span.start);
+        serde_json::json!({
+            "severity": match self.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            },
+            "message": self.message,
+            "file": file,
+            "line": line,
+            "column": column,
+            "start": self.span.start,
+            "end": self.span.end,
+            "label": self.label,
+            "notes": self.notes,
+        })
+    }
+}
+
+/// 1-based line and column (in characters) of a byte offset.
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(source.len());
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = source[..offset].matches('\n').count() + 1;
+    (line, source[line_start..offset].chars().count() + 1)
+}
+
+/// The lalrpop error for a lexing failure carries no location, so the source
+/// is lexed again up to the first token failing the same way.
+fn locate_lexing_error(source: &str, error: &LexingError) -> Span {
+    let mut lexer = Token::lexer(source);
+    let mut first = None;
+    while let Some(token) = lexer.next() {
+        if let Err(e) = token {
+            let span = Span::new(lexer.span().start, lexer.span().end);
+            if e == *error {
+                return span;
+            }
+            first.get_or_insert(span);
+        }
+    }
+    first.unwrap_or(Span::new(source.len(), source.len()))
+}
+
+/// How a token is shown to the user, e.g. `` `(` `` or `end of line`.
+fn describe(token: &Token, text: &str) -> String {
+    match token {
+        Token::LineEnd => "end of line".to_string(),
+        Token::Ident(name) => format!("identifier `{}`", name),
+        Token::String(_) => "string literal".to_string(),
+        Token::LineComment(_) | Token::BlockComment(_) | Token::DocComment(_) => {
+            "comment".to_string()
+        }
+        // tokens from string interpolations are not located in `source`
+        _ if text.is_empty() => format!("`{}`", token),
+        _ => format!("`{}`", text),
+    }
+}
+
+/// Renders lalrpop's expected terminals (e.g. `"\"fn\""`) as Lira syntax.
+fn expected_label(expected: &[String]) -> String {
+    let mut names: Vec<String> = Vec::new();
+    for terminal in expected {
+        let terminal = terminal.trim_matches('"');
+        let name = match terminal {
+            "identifier" => "identifier".to_string(),
+            "int" | "float" | "binary" | "octal" | "hex" => "number".to_string(),
+            "string" => "string".to_string(),
+            "line_end" => "end of line".to_string(),
+            "line_comment" | "block_comment" | "doc_comment" => "comment".to_string(),
+            symbol => format!("`{}`", symbol.replace("\\\"", "\"")),
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    match names.as_slice() {
+        [] => String::new(),
+        [one] => format!("expected {}", one),
+        [init @ .., last] => format!("expected one of {} or {}", init.join(", "), last),
+    }
+}
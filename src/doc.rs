@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+
+use crate::ast::{Documented, EnumVariant, Program, Spanned, Statement, Type};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Format {
+    #[default]
+    Markdown,
+    Html,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Markdown => "md",
+            Format::Html => "html",
+        }
+    }
+}
+
+/// A generated file, its path being relative to the output directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    pub path: String,
+    pub text: String,
+}
+
+/// The API docs of a module tree: a page per module, listing its top-level
+/// functions, structs, enums and type aliases with their signatures and doc
+/// comments, and an index of the modules.
+///
+/// The types named in signatures link to their declaration, looked up in the
+/// module first and then in the other modules, in order.
+pub fn generate(modules: &[(&str, &Program)], format: Format) -> Vec<Page> {
+    let mut types: HashMap<&str, Vec<(&str, String)>> = HashMap::new();
+    for (module, program) in modules {
+        for stmt in &program.statements {
+            if let Some((kind, name)) = declared_type(&stmt.node) {
+                types
+                    .entry(name)
+                    .or_default()
+                    .push((module, anchor(kind, name)));
+            }
+        }
+    }
+
+    let mut pages: Vec<_> = modules
+        .iter()
+        .map(|(module, program)| {
+            let mut page = Writer {
+                format,
+                module,
+                types: &types,
+                out: String::new(),
+            };
+            page.module(program);
+            Page {
+                path: page_path(module, format),
+                text: page.out,
+            }
+        })
+        .collect();
+    pages.push(index(modules, format));
+    pages
+}
+
+fn page_path(module: &str, format: Format) -> String {
+    format!("{}.{}", module.replace("::", "."), format.extension())
+}
+
+fn anchor(kind: &str, name: &str) -> String {
+    format!("{}.{}", kind, name)
+}
+
+fn declared_type<'i>(stmt: &Statement<'i>) -> Option<(&'static str, &'i str)> {
+    match stmt {
+        Statement::Struct { name, .. } => Some(("struct", name)),
+        Statement::Enum { name, .. } => Some(("enum", name)),
+        Statement::TypeAlias { name, .. } => Some(("type", name)),
+        _ => None,
+    }
+}
+
+fn index(modules: &[(&str, &Program)], format: Format) -> Page {
+    let mut out = String::new();
+    match format {
+        Format::Markdown => {
+            out.push_str("# API documentation\n\n");
+            for (module, _) in modules {
+                out.push_str(&format!(
+                    "- [`{}`]({})\n",
+                    module,
+                    page_path(module, format)
+                ));
+            }
+        }
+        Format::Html => {
+            out.push_str(&html_head("API documentation"));
+            out.push_str("<h1>API documentation</h1>\n<ul>\n");
+            for (module, _) in modules {
+                out.push_str(&format!(
+                    "<li><a href=\"{}\"><code>{}</code></a></li>\n",
+                    page_path(module, format),
+                    escape(module)
+                ));
+            }
+            out.push_str("</ul>\n</body>\n</html>\n");
+        }
+    }
+    Page {
+        path: format!("index.{}", format.extension()),
+        text: out,
+    }
+}
+
+fn html_head(title: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n",
+        escape(title)
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The page of a module, signatures being HTML in both formats so that they
+/// can hold links.
+struct Writer<'a> {
+    format: Format,
+    module: &'a str,
+    types: &'a HashMap<&'a str, Vec<(&'a str, String)>>,
+    out: String,
+}
+
+impl Writer<'_> {
+    fn module(&mut self, program: &Program) {
+        let title = format!("Module {}", self.module);
+        match self.format {
+            Format::Markdown => self.out.push_str(&format!("# Module `{}`\n", self.module)),
+            Format::Html => {
+                self.out.push_str(&html_head(&title));
+                self.out.push_str(&format!(
+                    "<h1>Module <code>{}</code></h1>\n",
+                    escape(self.module)
+                ));
+            }
+        }
+        for stmt in &program.statements {
+            self.item(&stmt.node);
+        }
+        if self.format == Format::Html {
+            self.out.push_str("</body>\n</html>\n");
+        }
+    }
+
+    fn item(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::FnDecl {
+                name,
+                doc,
+                _async,
+                params,
+                return_type,
+                ..
+            } => {
+                let mut signature = format!(
+                    "{}fn {}({})",
+                    if *_async { "async " } else { "" },
+                    escape(name),
+                    self.params(params)
+                );
+                if let Some(ty) = return_type {
+                    signature.push_str(&format!(" -&gt; {}", self.ty(ty)));
+                }
+                self.heading("fn", name, &signature, doc);
+            }
+            Statement::Struct { name, doc, fields } => {
+                let members: Vec<_> = fields
+                    .iter()
+                    .map(|field| {
+                        let (field_name, ty) = &field.node;
+                        let signature = format!("{}: {}", escape(field_name), self.ty(ty));
+                        (*field_name, signature, &field.doc)
+                    })
+                    .collect();
+                let signature = format!("struct {}{}", escape(name), block(&members));
+                self.heading("struct", name, &signature, doc);
+                self.members("Fields", &members);
+            }
+            Statement::Enum {
+                name,
+                doc,
+                variants,
+            } => {
+                let members: Vec<_> = variants
+                    .iter()
+                    .map(|variant| self.variant(variant))
+                    .collect();
+                let signature = format!("enum {}{}", escape(name), block(&members));
+                self.heading("enum", name, &signature, doc);
+                self.members("Variants", &members);
+            }
+            Statement::TypeAlias { name, doc, ty } => {
+                let signature = format!("typealias {} = {}", escape(name), self.ty(ty));
+                self.heading("type", name, &signature, doc);
+            }
+            _ => {}
+        }
+    }
+
+    fn variant<'v>(
+        &self,
+        variant: &'v Documented<EnumVariant<'v>>,
+    ) -> (&'v str, String, &'v Vec<&'v str>) {
+        let (name, signature) = match &variant.node {
+            EnumVariant::Unit(name) => (*name, escape(name)),
+            EnumVariant::Tuple(name, types) => {
+                (*name, format!("{}({})", escape(name), self.types(types)))
+            }
+            EnumVariant::Struct(name, fields) => (
+                *name,
+                format!("{} {{ {} }}", escape(name), self.params(fields)),
+            ),
+        };
+        (name, signature, &variant.doc)
+    }
+
+    fn heading(&mut self, kind: &str, name: &str, signature: &str, doc: &[&str]) {
+        let id = anchor(kind, name);
+        let kind = if kind == "type" { "typealias" } else { kind };
+        match self.format {
+            Format::Markdown => {
+                self.out.push_str(&format!(
+                    "\n<a id=\"{}\"></a>\n\n## {} {}\n\n<pre><code>{}</code></pre>\n",
+                    id, kind, name, signature
+                ));
+                if !doc.is_empty() {
+                    self.out.push('\n');
+                    for line in doc {
+                        self.out.push_str(line);
+                        self.out.push('\n');
+                    }
+                }
+            }
+            Format::Html => {
+                self.out.push_str(&format!(
+                    "<h2 id=\"{}\">{} {}</h2>\n<pre><code>{}</code></pre>\n",
+                    id,
+                    kind,
+                    escape(name),
+                    signature
+                ));
+                for paragraph in doc.split(|line| line.is_empty()) {
+                    if !paragraph.is_empty() {
+                        self.out
+                            .push_str(&format!("<p>{}</p>\n", inline(&paragraph.join(" "))));
+                    }
+                }
+            }
+        }
+    }
+
+    // the documented fields or variants of a type
+    fn members(&mut self, title: &str, members: &[(&str, String, &Vec<&str>)]) {
+        let documented: Vec<_> = members
+            .iter()
+            .filter(|(_, _, doc)| !doc.is_empty())
+            .collect();
+        if documented.is_empty() {
+            return;
+        }
+        match self.format {
+            Format::Markdown => {
+                self.out.push_str(&format!("\n**{}**\n\n", title));
+                for (name, _, doc) in documented {
+                    self.out
+                        .push_str(&format!("- `{}`: {}\n", name, doc.join(" ")));
+                }
+            }
+            Format::Html => {
+                self.out.push_str(&format!("<h3>{}</h3>\n<ul>\n", title));
+                for (name, _, doc) in documented {
+                    self.out.push_str(&format!(
+                        "<li><code>{}</code>: {}</li>\n",
+                        escape(name),
+                        inline(&doc.join(" "))
+                    ));
+                }
+                self.out.push_str("</ul>\n");
+            }
+        }
+    }
+
+    fn params(&self, params: &[(&str, Option<Spanned<Type>>)]) -> String {
+        params
+            .iter()
+            .map(|(name, ty)| match ty {
+                Some(ty) => format!("{}: {}", escape(name), self.ty(ty)),
+                None => escape(name),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn types(&self, types: &[Spanned<Type>]) -> String {
+        types
+            .iter()
+            .map(|ty| self.ty(ty))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn ty(&self, ty: &Type) -> String {
+        match ty {
+            Type::Ident(name) => self.link(name),
+            Type::Tuple(types) => format!("({})", self.types(types)),
+            Type::Array(types) => format!("[{}]", self.types(types)),
+            Type::Function(params, ret) => format!("({}): {}", self.types(params), self.ty(ret)),
+            Type::AsyncFunction(params, ret) => {
+                format!("async ({}): {}", self.types(params), self.ty(ret))
+            }
+            Type::Awaitable(ty) => format!("async {}", self.ty(ty)),
+        }
+    }
+
+    fn link(&self, name: &str) -> String {
+        let Some(declarations) = self.types.get(name) else {
+            return escape(name);
+        };
+        let (module, id) = declarations
+            .iter()
+            .find(|(module, _)| *module == self.module)
+            .unwrap_or(&declarations[0]);
+        let page = match *module == self.module {
+            true => String::new(),
+            false => page_path(module, self.format),
+        };
+        format!("<a href=\"{}#{}\">{}</a>", page, id, escape(name))
+    }
+}
+
+// the members of a struct or enum, one per line
+fn block(members: &[(&str, String, &Vec<&str>)]) -> String {
+    if members.is_empty() {
+        return " {}".to_string();
+    }
+    let mut block = " {\n".to_string();
+    for (_, signature, _) in members {
+        block.push_str(&format!("    {},\n", signature));
+    }
+    block.push('}');
+    block
+}
+
+// doc text as HTML, `code` spans included
+fn inline(text: &str) -> String {
+    text.split('`')
+        .enumerate()
+        .map(|(i, part)| match i % 2 {
+            0 => escape(part),
+            _ => format!("<code>{}</code>", escape(part)),
+        })
+        .collect()
+}
@@ -0,0 +1,1193 @@
+pub mod atomic;
+pub mod builtins;
+pub mod env;
+pub mod executor;
+pub mod lazy;
+pub mod parallel;
+pub mod registry;
+pub mod runtime;
+pub mod stdlib;
+pub mod value;
+pub mod waits;
+
+use std::{
+    fmt,
+    io::{BufRead, Write},
+    sync::{Arc, Mutex},
+};
+
+use crate::ast::{
+    BinOp, Expr, LetName, Literal, Match, Pattern, Program, Spanned, Statement, StringSegment,
+    UnaryOp,
+};
+use atomic::Atomic;
+use env::Env;
+use executor::{Executor, Work};
+use lazy::Thunk;
+use registry::Registry;
+use runtime::Runtime;
+use value::{
+    Entry, Function, Module, Payload, StructValue, TypeDef, Value, VariantShape, VariantValue,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    UndefinedVariable(String),
+    ImmutableAssignment(String),
+    TypeError(String),
+    NotCallable(String),
+    ArityMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    IndexOutOfBounds(i64, usize),
+    UnknownField(String, String),
+    UnknownMethod(String, String),
+    DivisionByZero,
+    IntegerOverflow,
+    NoMatchingArm(String),
+    UnknownModule(String),
+    Unsupported(&'static str),
+    SyntaxError,
+    Io(String),
+    // a panic of the interpreter while running a spawned block
+    Panic(String),
+    // every future left waits for another one
+    Deadlock,
+    // a `lazy` binding read while its own value is being computed
+    LazyCycle(String),
+    // a `break` that left the function it was written in, stops `each` and friends
+    Break,
+    // raised by `exit(code)` to unwind the whole program
+    Exit(i32),
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::UndefinedVariable(name) => write!(f, "undefined variable `{}`", name),
+            RuntimeError::ImmutableAssignment(name) => {
+                write!(f, "cannot assign twice to immutable variable `{}`", name)
+            }
+            RuntimeError::TypeError(msg) => write!(f, "type error: {}", msg),
+            RuntimeError::NotCallable(ty) => write!(f, "value of type {} is not callable", ty),
+            RuntimeError::ArityMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` expects {} argument(s) but {} were given",
+                name, expected, found
+            ),
+            RuntimeError::IndexOutOfBounds(index, len) => {
+                write!(f, "index {} out of bounds for length {}", index, len)
+            }
+            RuntimeError::UnknownField(ty, field) => write!(f, "{} has no field `{}`", ty, field),
+            RuntimeError::UnknownMethod(ty, method) => {
+                write!(f, "{} has no method `{}`", ty, method)
+            }
+            RuntimeError::DivisionByZero => f.write_str("division by zero"),
+            RuntimeError::IntegerOverflow => f.write_str("integer overflow"),
+            RuntimeError::NoMatchingArm(value) => write!(f, "no match arm matched `{}`", value),
+            RuntimeError::UnknownModule(name) => write!(f, "module `{}` is not loaded", name),
+            RuntimeError::Unsupported(what) => write!(f, "{} is not supported yet", what),
+            RuntimeError::SyntaxError => {
+                f.write_str("cannot run a statement containing syntax errors")
+            }
+            RuntimeError::Io(msg) => write!(f, "io error: {}", msg),
+            RuntimeError::Panic(msg) => write!(f, "task panicked: {}", msg),
+            RuntimeError::Deadlock => f.write_str("awaited a future that can never be done"),
+            RuntimeError::LazyCycle(name) => {
+                write!(f, "lazy binding `{}` depends on itself", name)
+            }
+            RuntimeError::Break => f.write_str("`break` outside of a loop"),
+            RuntimeError::Exit(code) => write!(f, "exited with code {}", code),
+        }
+    }
+}
+
+/// How a statement finished: normally (with the value of the last expression),
+/// through `:>`/`return`, or through `break`.
+#[derive(Debug)]
+enum Flow<'a> {
+    Normal(Value<'a>),
+    Return(Value<'a>),
+    Break,
+}
+
+/// Tree-walking interpreter running a parsed [`Program`].
+pub struct Interpreter<'a> {
+    globals: Env<'a>,
+    out: Mutex<Box<dyn Write + Send + 'a>>,
+    // read by `Io.read_line()`
+    input: Mutex<Box<dyn BufRead + Send + 'a>>,
+    // the command line arguments after the script, returned by `Process.args()`
+    args: Vec<String>,
+    modules: Registry<'a>,
+    runtime: Runtime<'a>,
+    executor: Executor<'a>,
+    #[cfg(feature = "jit")]
+    jit: Option<crate::jit::Jit>,
+}
+
+impl Default for Interpreter<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new() -> Self {
+        Self::with_output(Box::new(std::io::stdout()))
+    }
+
+    /// Creates an interpreter whose `print`/`puts` output goes to `out`.
+    pub fn with_output(out: Box<dyn Write + Send + 'a>) -> Self {
+        let globals = Env::new();
+        builtins::register(&globals);
+        Self {
+            globals,
+            out: Mutex::new(out),
+            input: Mutex::new(Box::new(std::io::BufReader::new(std::io::stdin()))),
+            args: Vec::new(),
+            modules: Registry::default(),
+            runtime: Runtime::default(),
+            executor: Executor::default(),
+            #[cfg(feature = "jit")]
+            jit: None,
+        }
+    }
+
+    /// Makes a parsed module file importable under its `use` path, e.g. `utils::math`.
+    pub fn add_module(&mut self, name: impl Into<String>, program: &'a Program<'a>) {
+        self.modules.add(name, program);
+    }
+
+    /// Runs the functions compiled by `jit` as native code.
+    #[cfg(feature = "jit")]
+    pub fn set_jit(&mut self, jit: crate::jit::Jit) {
+        self.jit = Some(jit);
+    }
+
+    /// Makes `Io.read_line()` read from `input` instead of stdin.
+    pub fn set_input(&mut self, input: Box<dyn BufRead + Send + 'a>) {
+        self.input = Mutex::new(input);
+    }
+
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// The next input line without its line ending, `None` at the end of the input.
+    pub fn read_line(&self) -> Result<Option<String>, RuntimeError> {
+        let mut line = String::new();
+        let read = self
+            .input
+            .lock()
+            .unwrap()
+            .read_line(&mut line)
+            .map_err(|e| RuntimeError::Io(e.to_string()))?;
+        if read == 0 {
+            return Ok(None);
+        }
+        let len = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(len);
+        Ok(Some(line))
+    }
+
+    pub fn globals(&self) -> &Env<'a> {
+        &self.globals
+    }
+
+    pub fn write_line(&self, line: &str) -> Result<(), RuntimeError> {
+        writeln!(self.out.lock().unwrap(), "{}", line).map_err(|e| RuntimeError::Io(e.to_string()))
+    }
+
+    /// Runs every top-level statement, then `main` if the program declares one.
+    ///
+    /// `spawn` blocks run on a pool of worker threads meanwhile, and are all
+    /// waited for before it returns.
+    pub fn run(&self, program: &'a Program<'a>) -> Result<Value<'a>, RuntimeError> {
+        self.runtime.scope(self, || {
+            let result =
+                self.exec_program(program)
+                    .and_then(|value| match self.globals.get("main") {
+                        Some(main @ Value::Function(_)) => self.call(&main, vec![]),
+                        _ => Ok(value),
+                    });
+            let outstanding = self.executor.finish(self);
+            let value = result?;
+            outstanding?;
+            Ok(value)
+        })
+    }
+
+    /// Runs `main` with the workers of the runtime, then the futures nobody
+    /// awaited, for the VM.
+    pub(crate) fn with_workers<T>(
+        &self,
+        main: impl FnOnce() -> Result<T, RuntimeError>,
+    ) -> Result<T, RuntimeError> {
+        self.runtime.scope(self, || {
+            let result = main();
+            let outstanding = self.executor.finish(self);
+            let value = result?;
+            outstanding?;
+            Ok(value)
+        })
+    }
+
+    /// The result of a future, or the value itself for the VM, whose `async`
+    /// functions return their value right away.
+    pub(crate) fn await_value(&self, value: Value<'a>) -> Result<Value<'a>, RuntimeError> {
+        match value {
+            Value::Future(future) => self.executor.wait(self, &future),
+            value => Ok(value),
+        }
+    }
+
+    /// Runs the statements of `program` in the global scope without calling
+    /// `main`, returning the value of the last one.
+    pub fn exec_program(&self, program: &'a Program<'a>) -> Result<Value<'a>, RuntimeError> {
+        match self.exec_block(&program.statements, &self.globals)? {
+            Flow::Normal(value) | Flow::Return(value) => Ok(value),
+            Flow::Break => Err(RuntimeError::Break),
+        }
+    }
+
+    /// Calls a function, closure, native function or struct constructor.
+    pub fn call(
+        &self,
+        callee: &Value<'a>,
+        args: Vec<Value<'a>>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        match callee {
+            Value::Function(function) => {
+                if function.params.len() != args.len() {
+                    return Err(RuntimeError::ArityMismatch {
+                        name: function.name.unwrap_or("<closure>").to_string(),
+                        expected: function.params.len(),
+                        found: args.len(),
+                    });
+                }
+                #[cfg(feature = "jit")]
+                if let Some(result) = self
+                    .jit
+                    .as_ref()
+                    .and_then(|jit| jit.call(function.body, &args))
+                {
+                    return result;
+                }
+
+                let env = function.env.child();
+                for ((name, _), arg) in function.params.iter().zip(args) {
+                    env.define(name, arg, false);
+                }
+                if function.is_async {
+                    let future = self.executor.submit(Work::Body(function.body, env));
+                    return Ok(Value::Future(future));
+                }
+
+                match self.exec_block(function.body, &env)? {
+                    Flow::Normal(value) | Flow::Return(value) => Ok(value),
+                    Flow::Break => Err(RuntimeError::Break),
+                }
+            }
+            Value::Native(_, native) => native(self, args),
+            Value::Type(def) => self.construct(def, args),
+            other => Err(RuntimeError::NotCallable(other.type_name().to_string())),
+        }
+    }
+
+    fn construct(
+        &self,
+        def: &TypeDef<'a>,
+        args: Vec<Value<'a>>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        match def {
+            TypeDef::Struct { name, fields } => {
+                if fields.len() != args.len() {
+                    return Err(RuntimeError::ArityMismatch {
+                        name: name.to_string(),
+                        expected: fields.len(),
+                        found: args.len(),
+                    });
+                }
+                Ok(Value::Struct(Arc::new(StructValue {
+                    name,
+                    fields: Mutex::new(fields.iter().copied().zip(args).collect()),
+                })))
+            }
+            TypeDef::Enum { name, .. } => Err(RuntimeError::NotCallable(format!("enum {}", name))),
+        }
+    }
+
+    fn construct_variant(
+        &self,
+        def: &TypeDef<'a>,
+        variant: &str,
+        args: Option<Vec<Value<'a>>>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        let TypeDef::Enum { name, variants } = def else {
+            unreachable!("only enums have variants")
+        };
+        let Some((variant, shape)) = variants.iter().find(|(v, _)| *v == variant) else {
+            return Err(RuntimeError::UnknownField(
+                name.to_string(),
+                variant.to_string(),
+            ));
+        };
+
+        let payload = match (shape, args) {
+            (VariantShape::Unit, None) => Payload::Unit,
+            (VariantShape::Tuple(len), Some(args)) if *len == args.len() => Payload::Tuple(args),
+            (VariantShape::Struct(fields), Some(args)) if fields.len() == args.len() => {
+                Payload::Struct(fields.iter().copied().zip(args).collect())
+            }
+            (shape, args) => {
+                let expected = match shape {
+                    VariantShape::Unit => 0,
+                    VariantShape::Tuple(len) => *len,
+                    VariantShape::Struct(fields) => fields.len(),
+                };
+                return Err(RuntimeError::ArityMismatch {
+                    name: format!("{}.{}", name, variant),
+                    expected,
+                    found: args.map_or(0, |args| args.len()),
+                });
+            }
+        };
+
+        Ok(Value::Variant(Arc::new(VariantValue {
+            enum_name: name,
+            name: variant,
+            payload,
+        })))
+    }
+
+    /// Calls `receiver.name(args)`: a constructor, a callable field or a built-in method.
+    pub fn call_method(
+        &self,
+        receiver: Value<'a>,
+        name: &str,
+        args: Vec<Value<'a>>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        match &receiver {
+            Value::Type(def) => match def.as_ref() {
+                TypeDef::Struct { .. } if name == "new" => self.construct(def, args),
+                TypeDef::Enum { .. } => self.construct_variant(def, name, Some(args)),
+                TypeDef::Struct { name: ty, .. } => Err(RuntimeError::UnknownMethod(
+                    ty.to_string(),
+                    name.to_string(),
+                )),
+            },
+            Value::Module(module) => {
+                let item = self.force(item_of(module, name)?)?;
+                self.call(&item, args)
+            }
+            Value::Struct(s) => {
+                let field = s
+                    .fields
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .find(|(field, _)| *field == name)
+                    .map(|(_, value)| value.clone());
+                match field {
+                    Some(field) => self.call(&field, args),
+                    None => builtins::call_method(
+                        self,
+                        &|f, args| self.call(f, args),
+                        receiver,
+                        name,
+                        args,
+                    ),
+                }
+            }
+            _ => builtins::call_method(self, &|f, args| self.call(f, args), receiver, name, args),
+        }
+    }
+
+    /// Iterates over the items of a range, array, tuple or string.
+    pub fn iterate(
+        &self,
+        value: &Value<'a>,
+    ) -> Result<Box<dyn Iterator<Item = Value<'a>> + 'a>, RuntimeError> {
+        Ok(match value {
+            Value::Range(start, Some(end)) => Box::new((*start..*end).map(Value::Int)),
+            Value::Range(start, None) => Box::new((*start..).map(Value::Int)),
+            Value::Array(values) => Box::new(values.lock().unwrap().clone().into_iter()),
+            Value::Tuple(values) => {
+                let values = values.clone();
+                Box::new((0..values.len()).map(move |i| values[i].clone()))
+            }
+            Value::Str(s) => Box::new(
+                s.chars()
+                    .map(|c| Value::str(c.to_string()))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            ),
+            other => {
+                return Err(RuntimeError::TypeError(format!(
+                    "cannot iterate over {}",
+                    other.type_name()
+                )));
+            }
+        })
+    }
+
+    /// Declares the functions and types of a block up front so they can be
+    /// called before the line declaring them.
+    fn hoist(&self, stmts: &'a [Spanned<Statement<'a>>], env: &Env<'a>) {
+        for stmt in stmts {
+            match &stmt.node {
+                Statement::FnDecl {
+                    name,
+                    _async,
+                    params,
+                    body,
+                    ..
+                } => {
+                    let function = Function {
+                        name: Some(name),
+                        params,
+                        body,
+                        env: env.clone(),
+                        is_async: *_async,
+                    };
+                    env.define(name, Value::Function(Arc::new(function)), false);
+                }
+                Statement::Struct { name, .. } | Statement::Enum { name, .. } => {
+                    if let Some(def) = TypeDef::declared_by(&stmt.node) {
+                        env.define(name, Value::Type(Arc::new(def)), false);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The module of a `use` path, whose items are declared on first import.
+    ///
+    /// Only the items and the imports of a module are evaluated, its other
+    /// top-level statements never run.
+    fn import(&self, path: &[&str]) -> Result<Value<'a>, RuntimeError> {
+        self.modules.import(path, |program, env| {
+            self.hoist(&program.statements, env);
+            for stmt in &program.statements {
+                if let Statement::Use { .. } = stmt.node {
+                    self.exec(stmt, env)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// The modules importable by the program.
+    pub(crate) fn modules(&self) -> &Registry<'a> {
+        &self.modules
+    }
+
+    fn exec_block(
+        &self,
+        stmts: &'a [Spanned<Statement<'a>>],
+        env: &Env<'a>,
+    ) -> Result<Flow<'a>, RuntimeError> {
+        self.hoist(stmts, env);
+
+        let mut last = Value::Unit;
+        for stmt in stmts {
+            match self.exec(stmt, env)? {
+                Flow::Normal(value) => last = value,
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal(last))
+    }
+
+    /// Schedules a `spawn` block, returning the handle of its task.
+    fn spawn(
+        &self,
+        with: Option<&'a Spanned<Expr<'a>>>,
+        body: &'a [Spanned<Statement<'a>>],
+        env: &Env<'a>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        let scope = env.child();
+        let mut scheduler = None;
+        if let Some(with) = with {
+            let context = self.eval(with, env)?;
+            if let Value::Scheduler(with) = &context {
+                scheduler = Some(with.clone());
+            }
+            scope.define("context", context, false);
+        }
+        let task = self.runtime.spawn(self, body, scope, scheduler.as_deref());
+        Ok(Value::Task(task))
+    }
+
+    /// Runs the body of a `spawn` block, its value being that of the task.
+    fn exec_task(
+        &self,
+        body: &'a [Spanned<Statement<'a>>],
+        env: &Env<'a>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        match self.exec_block(body, env)? {
+            Flow::Normal(value) | Flow::Return(value) => Ok(value),
+            Flow::Break => Err(RuntimeError::Break),
+        }
+    }
+
+    fn exec(
+        &self,
+        stmt: &'a Spanned<Statement<'a>>,
+        env: &Env<'a>,
+    ) -> Result<Flow<'a>, RuntimeError> {
+        match &stmt.node {
+            // already declared by `hoist`
+            Statement::FnDecl { .. }
+            | Statement::Struct { .. }
+            | Statement::Enum { .. }
+            | Statement::TypeAlias { .. } => Ok(Flow::Normal(Value::Unit)),
+
+            Statement::Let {
+                name,
+                mutable,
+                atomic,
+                lazy,
+                value,
+                ..
+            } => {
+                if let (true, LetName::Ident(name)) = (lazy, name) {
+                    let thunk = Thunk::new(name, value, env.clone(), *atomic);
+                    env.define(name, Value::Lazy(Arc::new(thunk)), *mutable);
+                    return Ok(Flow::Normal(Value::Unit));
+                }
+                let value = self.eval(value, env)?;
+                self.bind(name, value, env, *mutable, *atomic)?;
+                Ok(Flow::Normal(Value::Unit))
+            }
+            Statement::Expr(expr) => Ok(Flow::Normal(self.eval(expr, env)?)),
+            Statement::Return(expr) => Ok(Flow::Return(self.eval(expr, env)?)),
+            Statement::Break => Ok(Flow::Break),
+            Statement::Match(m) => self.eval_match(m, env),
+
+            Statement::If {
+                condition,
+                body,
+                else_ifs,
+                else_body,
+            } => {
+                if self.eval(condition, env)?.as_bool()? {
+                    return self.exec_block(body, &env.child());
+                }
+                for (condition, body) in else_ifs {
+                    if self.eval(condition, env)?.as_bool()? {
+                        return self.exec_block(body, &env.child());
+                    }
+                }
+                match else_body {
+                    Some(body) => self.exec_block(body, &env.child()),
+                    None => Ok(Flow::Normal(Value::Unit)),
+                }
+            }
+            Statement::WhileLoop { condition, body } => {
+                while self.eval(condition, env)?.as_bool()? {
+                    match self.exec_block(body, &env.child()) {
+                        Ok(Flow::Normal(_)) => {}
+                        Ok(Flow::Break) | Err(RuntimeError::Break) => break,
+                        flow => return flow,
+                    }
+                }
+                Ok(Flow::Normal(Value::Unit))
+            }
+            Statement::ForLoop {
+                iterator,
+                range,
+                body,
+            } => {
+                let iterable = self.eval(range, env)?;
+                for item in self.iterate(&iterable)? {
+                    let scope = env.child();
+                    scope.define(iterator, item, false);
+                    match self.exec_block(body, &scope) {
+                        Ok(Flow::Normal(_)) => {}
+                        Ok(Flow::Break) | Err(RuntimeError::Break) => break,
+                        flow => return flow,
+                    }
+                }
+                Ok(Flow::Normal(Value::Unit))
+            }
+
+            Statement::Spawn { body, with } => {
+                Ok(Flow::Normal(self.spawn(with.as_ref(), body, env)?))
+            }
+            Statement::Use { path, alias } => {
+                let module = self.import(path)?;
+                let name = alias.or(path.last().copied()).unwrap_or_default();
+                env.define(name, module, false);
+                Ok(Flow::Normal(Value::Unit))
+            }
+            Statement::Error => Err(RuntimeError::SyntaxError),
+        }
+    }
+
+    fn bind(
+        &self,
+        name: &'a LetName<'a>,
+        value: Value<'a>,
+        env: &Env<'a>,
+        mutable: bool,
+        atomic: bool,
+    ) -> Result<(), RuntimeError> {
+        let define = |name, value| match atomic {
+            true => env.define(name, Value::Atomic(Arc::new(Atomic::new(value))), mutable),
+            false => env.define(name, value, mutable),
+        };
+        match name {
+            LetName::Ident(name) => define(name, value),
+            LetName::TupleDestructure(names) | LetName::ArrayDestructure(names) => {
+                let values = match &value {
+                    Value::Tuple(values) => values.to_vec(),
+                    Value::Array(values) => values.lock().unwrap().clone(),
+                    other => {
+                        return Err(RuntimeError::TypeError(format!(
+                            "cannot destructure {}",
+                            other.type_name()
+                        )));
+                    }
+                };
+                if values.len() != names.len() {
+                    return Err(RuntimeError::TypeError(format!(
+                        "expected {} values to destructure, found {}",
+                        names.len(),
+                        values.len()
+                    )));
+                }
+                for (name, value) in names.iter().zip(values) {
+                    self.bind(name, value, env, mutable, atomic)?;
+                }
+            }
+            LetName::StructDestructure(ty, fields) => {
+                let Value::Struct(s) = &value else {
+                    return Err(RuntimeError::TypeError(format!(
+                        "expected {}, found {}",
+                        ty,
+                        value.type_name()
+                    )));
+                };
+                if s.name != *ty {
+                    return Err(RuntimeError::TypeError(format!(
+                        "expected {}, found {}",
+                        ty, s.name
+                    )));
+                }
+                for (field, rename) in fields {
+                    define(rename.unwrap_or(field), field_of(s, field)?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn eval_match(&self, m: &'a Match<'a>, env: &Env<'a>) -> Result<Flow<'a>, RuntimeError> {
+        let value = self.eval(&m.expr, env)?;
+
+        for arm in &m.arms {
+            let scope = env.child();
+            if !self.match_pattern(&arm.pattern, &value, &scope)? {
+                continue;
+            }
+            if let Some(guard) = &arm.guard
+                && !self.eval(guard, &scope)?.as_bool()?
+            {
+                continue;
+            }
+            return self.exec_block(&arm.body, &scope);
+        }
+
+        Err(RuntimeError::NoMatchingArm(value.to_string()))
+    }
+
+    fn match_pattern(
+        &self,
+        pattern: &'a Spanned<Pattern<'a>>,
+        value: &Value<'a>,
+        env: &Env<'a>,
+    ) -> Result<bool, RuntimeError> {
+        Ok(match &pattern.node {
+            Pattern::Wildcard => true,
+            Pattern::Literal(lit) => self.eval_literal(lit, env)? == *value,
+            // capitalized names refer to unit variants (`Quit`), others bind the value
+            Pattern::Ident(name) if name.starts_with(char::is_uppercase) => {
+                matches!(value, Value::Variant(v) if v.name == *name && matches!(v.payload, Payload::Unit))
+            }
+            Pattern::Ident(name) => {
+                env.define(name, value.clone(), false);
+                true
+            }
+            Pattern::FunctionDestructor(name, bindings) => match value {
+                Value::Variant(v) if v.name == *name => match &v.payload {
+                    Payload::Tuple(values) if values.len() == bindings.len() => {
+                        for (binding, value) in bindings.iter().zip(values) {
+                            env.define(binding, value.clone(), false);
+                        }
+                        true
+                    }
+                    _ => false,
+                },
+                _ => false,
+            },
+            Pattern::StructLikeDestructor(name, fields) => {
+                let values: Vec<(&str, Value<'a>)> = match value {
+                    Value::Struct(s) if s.name == *name => s.fields.lock().unwrap().clone(),
+                    Value::Variant(v) if v.name == *name => match &v.payload {
+                        Payload::Struct(values) => values.clone(),
+                        _ => return Ok(false),
+                    },
+                    _ => return Ok(false),
+                };
+                for (field, rename) in fields {
+                    let Some((_, value)) = values.iter().find(|(f, _)| f == field) else {
+                        return Ok(false);
+                    };
+                    env.define(rename.unwrap_or(field), value.clone(), false);
+                }
+                true
+            }
+        })
+    }
+
+    fn eval_literal(&self, lit: &'a Literal<'a>, env: &Env<'a>) -> Result<Value<'a>, RuntimeError> {
+        Ok(match lit {
+            Literal::Int(i) => Value::Int(*i as i64),
+            Literal::Float(f) => Value::Float(*f),
+            Literal::Bool(b) => Value::Bool(*b),
+            Literal::Inf => Value::Float(f64::INFINITY),
+            Literal::String(segments) => {
+                let mut s = String::new();
+                for segment in segments {
+                    match segment {
+                        StringSegment::Text(text) => s.push_str(text),
+                        StringSegment::Char(c) => s.push(*c),
+                        StringSegment::Interpolation(expr) => {
+                            s.push_str(&self.eval(expr, env)?.to_string())
+                        }
+                    }
+                }
+                Value::str(s)
+            }
+        })
+    }
+
+    pub fn eval(
+        &self,
+        expr: &'a Spanned<Expr<'a>>,
+        env: &Env<'a>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        match &expr.node {
+            Expr::Literal(lit) => self.eval_literal(lit, env),
+            // an atomic binding is read once, its cell only reached by methods
+            Expr::Ident(name) => self.lookup(name, env).map(Value::load),
+            Expr::Array(items) => Ok(Value::array(self.eval_all(items, env)?)),
+            Expr::Tuple(items) if items.len() == 1 => self.eval(&items[0], env),
+            Expr::Tuple(items) => Ok(Value::Tuple(self.eval_all(items, env)?.into())),
+            // `:>` inside a match expression arm yields the value of the arm
+            Expr::Match(m) => match self.eval_match(m, env)? {
+                Flow::Normal(value) | Flow::Return(value) => Ok(value),
+                Flow::Break => Err(RuntimeError::Break),
+            },
+            Expr::Awaitable(inner) => match self.eval(inner, env)? {
+                Value::Future(future) => self.executor.wait(self, &future),
+                other => Err(RuntimeError::TypeError(format!(
+                    "cannot await {}",
+                    other.type_name()
+                ))),
+            },
+
+            Expr::Binary(lhs, BinOp::And, rhs) => Ok(Value::Bool(
+                self.eval(lhs, env)?.as_bool()? && self.eval(rhs, env)?.as_bool()?,
+            )),
+            Expr::Binary(lhs, BinOp::Or, rhs) => Ok(Value::Bool(
+                self.eval(lhs, env)?.as_bool()? || self.eval(rhs, env)?.as_bool()?,
+            )),
+            Expr::Binary(lhs, op, rhs) => binary(*op, self.eval(lhs, env)?, self.eval(rhs, env)?),
+            Expr::Unary(op, operand) => unary(*op, self.eval(operand, env)?),
+
+            Expr::Pipe(lhs, rhs) => {
+                let value = self.eval(lhs, env)?;
+                match &rhs.node {
+                    Expr::Call(callee, args) => {
+                        let callee = self.eval(callee, env)?;
+                        let mut all = vec![value];
+                        all.extend(self.eval_all(args, env)?);
+                        self.call(&callee, all)
+                    }
+                    _ => self.call(&self.eval(rhs, env)?, vec![value]),
+                }
+            }
+            Expr::Call(callee, args) => match &callee.node {
+                Expr::FieldAccess(base, name) => {
+                    let receiver = match &base.node {
+                        Expr::Ident(name) => self.lookup(name, env)?,
+                        _ => self.eval(base, env)?,
+                    };
+                    let args = self.eval_all(args, env)?;
+                    self.call_method(receiver, name, args)
+                }
+                _ => {
+                    let callee = self.eval(callee, env)?;
+                    let args = self.eval_all(args, env)?;
+                    self.call(&callee, args)
+                }
+            },
+            Expr::Range(start, end) => range(self.eval(start, env)?, self.eval(end, env)?),
+            Expr::Assign(target, op, value) => {
+                let value = self.eval(value, env)?;
+                // atomic bindings are updated in place, in one step
+                if let Expr::Ident(name) = &target.node
+                    && let Some(Value::Atomic(cell)) =
+                        env.get(name).map(|v| self.force(v)).transpose()?
+                {
+                    match op {
+                        BinOp::Eq => cell.store(value)?,
+                        op => {
+                            cell.update(|current| binary(*op, current, value.clone()))?;
+                        }
+                    }
+                    return Ok(Value::Unit);
+                }
+                let value = match op {
+                    BinOp::Eq => value,
+                    op => binary(*op, self.eval(target, env)?, value)?,
+                };
+                self.assign(target, value, env)?;
+                Ok(Value::Unit)
+            }
+            Expr::FieldAccess(base, name) => self.field(self.eval(base, env)?, name),
+            Expr::Index(base, index) => {
+                let base = self.eval(base, env)?;
+                let index = self.eval(index, env)?;
+                index_of(&base, &index)
+            }
+            Expr::Closure(params, body) => Ok(Value::Function(Arc::new(Function {
+                name: None,
+                params,
+                body,
+                env: env.clone(),
+                is_async: false,
+            }))),
+            Expr::Spawn(with, body) => self.spawn(with.as_deref(), body, env),
+        }
+    }
+
+    /// The value bound to `name`, a `lazy` one being evaluated on first access.
+    fn lookup(&self, name: &str, env: &Env<'a>) -> Result<Value<'a>, RuntimeError> {
+        let value = env
+            .get(name)
+            .ok_or_else(|| RuntimeError::UndefinedVariable(name.to_string()))?;
+        self.force(value)
+    }
+
+    fn force(&self, value: Value<'a>) -> Result<Value<'a>, RuntimeError> {
+        match value {
+            Value::Lazy(thunk) => thunk.force(self),
+            value => Ok(value),
+        }
+    }
+
+    /// Reads `base.name`: a struct or map field, a module item or a unit variant.
+    pub(crate) fn field(&self, base: Value<'a>, name: &str) -> Result<Value<'a>, RuntimeError> {
+        match base {
+            Value::Struct(s) => field_of(&s, name),
+            Value::Module(module) => self.force(item_of(&module, name)?),
+            Value::Map(entries) => entry_of(&entries.lock().unwrap(), name),
+            Value::Variant(v) => match &v.payload {
+                Payload::Struct(fields) => fields
+                    .iter()
+                    .find(|(field, _)| *field == name)
+                    .map(|(_, value)| value.clone())
+                    .ok_or_else(|| {
+                        RuntimeError::UnknownField(v.name.to_string(), name.to_string())
+                    }),
+                _ => Err(RuntimeError::UnknownField(
+                    v.name.to_string(),
+                    name.to_string(),
+                )),
+            },
+            Value::Type(def) if matches!(def.as_ref(), TypeDef::Enum { .. }) => {
+                self.construct_variant(&def, name, None)
+            }
+            other => Err(RuntimeError::UnknownField(
+                other.type_name().to_string(),
+                name.to_string(),
+            )),
+        }
+    }
+
+    fn eval_all(
+        &self,
+        exprs: &'a [Spanned<Expr<'a>>],
+        env: &Env<'a>,
+    ) -> Result<Vec<Value<'a>>, RuntimeError> {
+        exprs.iter().map(|expr| self.eval(expr, env)).collect()
+    }
+
+    fn assign(
+        &self,
+        target: &'a Spanned<Expr<'a>>,
+        value: Value<'a>,
+        env: &Env<'a>,
+    ) -> Result<(), RuntimeError> {
+        match &target.node {
+            Expr::Ident(name) => env.assign(name, value),
+            Expr::Index(base, index) => {
+                set_index(&self.eval(base, env)?, &self.eval(index, env)?, value)
+            }
+            Expr::FieldAccess(base, name) => set_field(&self.eval(base, env)?, name, value),
+            _ => Err(RuntimeError::TypeError(
+                "invalid assignment target".to_string(),
+            )),
+        }
+    }
+}
+
+pub(crate) fn set_index<'a>(
+    base: &Value<'a>,
+    index: &Value<'a>,
+    value: Value<'a>,
+) -> Result<(), RuntimeError> {
+    match (base, index) {
+        (Value::Array(values), Value::Int(i)) => {
+            let mut values = values.lock().unwrap();
+            let len = values.len();
+            let slot = usize::try_from(*i)
+                .ok()
+                .and_then(|i| values.get_mut(i))
+                .ok_or(RuntimeError::IndexOutOfBounds(*i, len))?;
+            *slot = value;
+            Ok(())
+        }
+        (Value::Map(entries), Value::Str(key)) => {
+            let mut entries = entries.lock().unwrap();
+            match entries.iter_mut().find(|(k, _)| k == key) {
+                Some(entry) => entry.1 = value,
+                None => entries.push((key.clone(), value)),
+            }
+            Ok(())
+        }
+        _ => Err(RuntimeError::TypeError(format!(
+            "cannot assign to {} indexed by {}",
+            base.type_name(),
+            index.type_name()
+        ))),
+    }
+}
+
+pub(crate) fn set_field<'a>(
+    base: &Value<'a>,
+    name: &str,
+    value: Value<'a>,
+) -> Result<(), RuntimeError> {
+    match base {
+        Value::Struct(s) => {
+            let mut fields = s.fields.lock().unwrap();
+            let slot = fields
+                .iter_mut()
+                .find(|(field, _)| *field == name)
+                .ok_or_else(|| RuntimeError::UnknownField(s.name.to_string(), name.to_string()))?;
+            slot.1 = value;
+            Ok(())
+        }
+        other => Err(RuntimeError::UnknownField(
+            other.type_name().to_string(),
+            name.to_string(),
+        )),
+    }
+}
+
+/// A range from evaluated bounds, `inf` as upper bound making it infinite.
+pub(crate) fn range<'a>(start: Value<'a>, end: Value<'a>) -> Result<Value<'a>, RuntimeError> {
+    let start = match start {
+        Value::Int(start) => start,
+        other => {
+            return Err(RuntimeError::TypeError(format!(
+                "range bounds must be Int, found {}",
+                other.type_name()
+            )));
+        }
+    };
+    match end {
+        Value::Int(end) => Ok(Value::Range(start, Some(end))),
+        Value::Float(end) if end == f64::INFINITY => Ok(Value::Range(start, None)),
+        other => Err(RuntimeError::TypeError(format!(
+            "range bounds must be Int, found {}",
+            other.type_name()
+        ))),
+    }
+}
+
+pub(crate) fn item_of<'a>(module: &Module<'a>, name: &str) -> Result<Value<'a>, RuntimeError> {
+    module
+        .env
+        .get(name)
+        .ok_or_else(|| RuntimeError::UnknownField(module.name.clone(), name.to_string()))
+}
+
+fn entry_of<'a>(entries: &[Entry<'a>], key: &str) -> Result<Value<'a>, RuntimeError> {
+    entries
+        .iter()
+        .find(|(k, _)| **k == *key)
+        .map(|(_, value)| value.clone())
+        .ok_or_else(|| RuntimeError::UnknownField("Map".to_string(), key.to_string()))
+}
+
+pub(crate) fn field_of<'a>(s: &StructValue<'a>, name: &str) -> Result<Value<'a>, RuntimeError> {
+    s.fields
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(field, _)| *field == name)
+        .map(|(_, value)| value.clone())
+        .ok_or_else(|| RuntimeError::UnknownField(s.name.to_string(), name.to_string()))
+}
+
+pub(crate) fn index_of<'a>(base: &Value<'a>, index: &Value<'a>) -> Result<Value<'a>, RuntimeError> {
+    if let (Value::Map(entries), Value::Str(key)) = (base, index) {
+        return entry_of(&entries.lock().unwrap(), key);
+    }
+    let Value::Int(i) = index else {
+        return Err(RuntimeError::TypeError(format!(
+            "index must be an Int, found {}",
+            index.type_name()
+        )));
+    };
+    let at = |len: usize| {
+        usize::try_from(*i)
+            .ok()
+            .filter(|&i| i < len)
+            .ok_or(RuntimeError::IndexOutOfBounds(*i, len))
+    };
+
+    match base {
+        Value::Array(values) => {
+            let values = values.lock().unwrap();
+            Ok(values[at(values.len())?].clone())
+        }
+        Value::Tuple(values) => Ok(values[at(values.len())?].clone()),
+        Value::Str(s) => {
+            let len = s.chars().count();
+            let c = s.chars().nth(at(len)?).unwrap();
+            Ok(Value::str(c.to_string()))
+        }
+        other => Err(RuntimeError::TypeError(format!(
+            "cannot index into {}",
+            other.type_name()
+        ))),
+    }
+}
+
+pub(crate) fn unary<'a>(op: UnaryOp, value: Value<'a>) -> Result<Value<'a>, RuntimeError> {
+    match (op, value) {
+        (UnaryOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+        (UnaryOp::Minus, Value::Int(i)) => i
+            .checked_neg()
+            .map(Value::Int)
+            .ok_or(RuntimeError::IntegerOverflow),
+        (UnaryOp::Minus, Value::Float(f)) => Ok(Value::Float(-f)),
+        (UnaryOp::Plus, value @ (Value::Int(_) | Value::Float(_))) => Ok(value),
+        (UnaryOp::BitNot, Value::Int(i)) => Ok(Value::Int(!i)),
+        (op, value) => Err(RuntimeError::TypeError(format!(
+            "cannot apply `{}` to {}",
+            op,
+            value.type_name()
+        ))),
+    }
+}
+
+pub(crate) fn binary<'a>(
+    op: BinOp,
+    lhs: Value<'a>,
+    rhs: Value<'a>,
+) -> Result<Value<'a>, RuntimeError> {
+    use BinOp::*;
+
+    let mismatch = |lhs: &Value, rhs: &Value| {
+        RuntimeError::TypeError(format!(
+            "cannot apply `{}` to {} and {}",
+            op,
+            lhs.type_name(),
+            rhs.type_name()
+        ))
+    };
+
+    match (op, &lhs, &rhs) {
+        (Eq, _, _) => Ok(Value::Bool(lhs == rhs)),
+        (Neq, _, _) => Ok(Value::Bool(lhs != rhs)),
+
+        (_, Value::Int(a), Value::Int(b)) => {
+            let (a, b) = (*a, *b);
+            let checked =
+                |result: Option<i64>| result.map(Value::Int).ok_or(RuntimeError::IntegerOverflow);
+            match op {
+                Plus => checked(a.checked_add(b)),
+                Minus => checked(a.checked_sub(b)),
+                Multiply => checked(a.checked_mul(b)),
+                Divide if b == 0 => Err(RuntimeError::DivisionByZero),
+                Divide => checked(a.checked_div(b)),
+                Modulo if b == 0 => Err(RuntimeError::DivisionByZero),
+                Modulo => checked(a.checked_rem(b)),
+                BitAnd => Ok(Value::Int(a & b)),
+                BitOr => Ok(Value::Int(a | b)),
+                BitXor => Ok(Value::Int(a ^ b)),
+                ShiftLeft => checked(u32::try_from(b).ok().and_then(|b| a.checked_shl(b))),
+                ShiftRight => checked(u32::try_from(b).ok().and_then(|b| a.checked_shr(b))),
+                Lt => Ok(Value::Bool(a < b)),
+                Le => Ok(Value::Bool(a <= b)),
+                Gt => Ok(Value::Bool(a > b)),
+                Ge => Ok(Value::Bool(a >= b)),
+                _ => Err(mismatch(&lhs, &rhs)),
+            }
+        }
+        (_, Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            let as_float = |v: &Value| match v {
+                Value::Int(i) => *i as f64,
+                Value::Float(f) => *f,
+                _ => unreachable!(),
+            };
+            let (a, b) = (as_float(&lhs), as_float(&rhs));
+            match op {
+                Plus => Ok(Value::Float(a + b)),
+                Minus => Ok(Value::Float(a - b)),
+                Multiply => Ok(Value::Float(a * b)),
+                Divide => Ok(Value::Float(a / b)),
+                Modulo => Ok(Value::Float(a % b)),
+                Lt => Ok(Value::Bool(a < b)),
+                Le => Ok(Value::Bool(a <= b)),
+                Gt => Ok(Value::Bool(a > b)),
+                Ge => Ok(Value::Bool(a >= b)),
+                _ => Err(mismatch(&lhs, &rhs)),
+            }
+        }
+        (_, Value::Bool(a), Value::Bool(b)) => match op {
+            BitAnd => Ok(Value::Bool(a & b)),
+            BitOr => Ok(Value::Bool(a | b)),
+            BitXor => Ok(Value::Bool(a ^ b)),
+            _ => Err(mismatch(&lhs, &rhs)),
+        },
+        (_, Value::Str(a), Value::Str(b)) => match op {
+            Plus => Ok(Value::str(format!("{}{}", a, b))),
+            Lt => Ok(Value::Bool(a < b)),
+            Le => Ok(Value::Bool(a <= b)),
+            Gt => Ok(Value::Bool(a > b)),
+            Ge => Ok(Value::Bool(a >= b)),
+            _ => Err(mismatch(&lhs, &rhs)),
+        },
+        _ => Err(mismatch(&lhs, &rhs)),
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::{
+    Mutex,
+    atomic::{AtomicBool, AtomicI64, Ordering::SeqCst},
+};
+
+use super::{RuntimeError, value::Value};
+
+/// The storage of an `atomic` binding, shared by every task and closure
+/// capturing it: lock-free for `Int` and `Bool`, behind a lock otherwise.
+///
+/// A cell keeps the type it was created with, `Int` and `Bool` cells
+/// rejecting values of other types.
+#[derive(Debug)]
+pub enum Atomic<'a> {
+    Int(AtomicI64),
+    Bool(AtomicBool),
+    Locked(Mutex<Value<'a>>),
+}
+
+impl<'a> Atomic<'a> {
+    pub fn new(value: Value<'a>) -> Self {
+        match value {
+            Value::Int(i) => Atomic::Int(AtomicI64::new(i)),
+            Value::Bool(b) => Atomic::Bool(AtomicBool::new(b)),
+            value => Atomic::Locked(Mutex::new(value)),
+        }
+    }
+
+    pub fn load(&self) -> Value<'a> {
+        match self {
+            Atomic::Int(i) => Value::Int(i.load(SeqCst)),
+            Atomic::Bool(b) => Value::Bool(b.load(SeqCst)),
+            Atomic::Locked(value) => value.lock().unwrap().clone(),
+        }
+    }
+
+    pub fn store(&self, value: Value<'a>) -> Result<(), RuntimeError> {
+        self.swap(value).map(|_| ())
+    }
+
+    /// Stores `value` and returns the previous one.
+    pub fn swap(&self, value: Value<'a>) -> Result<Value<'a>, RuntimeError> {
+        Ok(match (self, value) {
+            (Atomic::Int(i), Value::Int(new)) => Value::Int(i.swap(new, SeqCst)),
+            (Atomic::Bool(b), Value::Bool(new)) => Value::Bool(b.swap(new, SeqCst)),
+            (Atomic::Locked(value), new) => std::mem::replace(&mut value.lock().unwrap(), new),
+            (_, new) => return Err(self.mismatch(&new)),
+        })
+    }
+
+    /// Stores `new` if the cell holds `current`: `Ok` with the previous value
+    /// when it did, `Err` with the value it holds otherwise.
+    pub fn compare_exchange(
+        &self,
+        current: Value<'a>,
+        new: Value<'a>,
+    ) -> Result<Result<Value<'a>, Value<'a>>, RuntimeError> {
+        Ok(match (self, current, new) {
+            (Atomic::Int(i), Value::Int(current), Value::Int(new)) => i
+                .compare_exchange(current, new, SeqCst, SeqCst)
+                .map(Value::Int)
+                .map_err(Value::Int),
+            (Atomic::Bool(b), Value::Bool(current), Value::Bool(new)) => b
+                .compare_exchange(current, new, SeqCst, SeqCst)
+                .map(Value::Bool)
+                .map_err(Value::Bool),
+            (Atomic::Locked(value), current, new) => {
+                let mut value = value.lock().unwrap();
+                if *value == current {
+                    Ok(std::mem::replace(&mut value, new))
+                } else {
+                    Err(value.clone())
+                }
+            }
+            (_, current, new) => {
+                let other = if self.holds(&current) { new } else { current };
+                return Err(self.mismatch(&other));
+            }
+        })
+    }
+
+    /// Replaces the value with `f` of it in one step, returning the previous
+    /// one; `f` runs again whenever another task got there first.
+    pub fn update(
+        &self,
+        f: impl Fn(Value<'a>) -> Result<Value<'a>, RuntimeError>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        if let Atomic::Locked(value) = self {
+            let mut value = value.lock().unwrap();
+            let new = f(value.clone())?;
+            return Ok(std::mem::replace(&mut value, new));
+        }
+        loop {
+            let previous = self.load();
+            let new = f(previous.clone())?;
+            if self.compare_exchange(previous.clone(), new)?.is_ok() {
+                return Ok(previous);
+            }
+        }
+    }
+
+    fn holds(&self, value: &Value) -> bool {
+        matches!(
+            (self, value),
+            (Atomic::Int(_), Value::Int(_))
+                | (Atomic::Bool(_), Value::Bool(_))
+                | (Atomic::Locked(_), _)
+        )
+    }
+
+    fn mismatch(&self, value: &Value) -> RuntimeError {
+        RuntimeError::TypeError(format!(
+            "atomic {} cannot hold {}",
+            self.load().type_name(),
+            value.type_name()
+        ))
+    }
+}
@@ -0,0 +1,362 @@
+use std::io::Write;
+
+use std::sync::Arc;
+
+use super::{Interpreter, RuntimeError, binary, env::Env, lazy::Sequence, parallel, value::Value};
+use crate::ast::BinOp;
+
+/// Names defined by [`register`].
+pub const NAMES: &[&str] = &["print", "puts", "eprint", "exit", "Ok", "Err"];
+
+/// Registers the functions available without any `use`.
+pub fn register(env: &Env<'_>) {
+    env.define("print", Value::Native("print", print), false);
+    env.define("puts", Value::Native("puts", print), false);
+    env.define("eprint", Value::Native("eprint", eprint), false);
+    env.define("exit", Value::Native("exit", exit), false);
+    env.define("Ok", Value::Native("Ok", ok), false);
+    env.define("Err", Value::Native("Err", err), false);
+}
+
+fn join(args: &[Value]) -> String {
+    args.iter()
+        .map(|arg| arg.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub(crate) fn print<'a>(
+    interp: &Interpreter<'a>,
+    args: Vec<Value<'a>>,
+) -> Result<Value<'a>, RuntimeError> {
+    interp.write_line(&join(&args))?;
+    Ok(Value::Unit)
+}
+
+pub(crate) fn eprint<'a>(
+    _: &Interpreter<'a>,
+    args: Vec<Value<'a>>,
+) -> Result<Value<'a>, RuntimeError> {
+    writeln!(std::io::stderr(), "{}", join(&args)).map_err(|e| RuntimeError::Io(e.to_string()))?;
+    Ok(Value::Unit)
+}
+
+pub(crate) fn exit<'a>(
+    _: &Interpreter<'a>,
+    args: Vec<Value<'a>>,
+) -> Result<Value<'a>, RuntimeError> {
+    match args.as_slice() {
+        [] => Err(RuntimeError::Exit(0)),
+        [Value::Int(code)] => Err(RuntimeError::Exit(*code as i32)),
+        [other] => Err(RuntimeError::TypeError(format!(
+            "exit code must be an Int, found {}",
+            other.type_name()
+        ))),
+        _ => Err(RuntimeError::ArityMismatch {
+            name: "exit".to_string(),
+            expected: 1,
+            found: args.len(),
+        }),
+    }
+}
+
+fn ok<'a>(_: &Interpreter<'a>, args: Vec<Value<'a>>) -> Result<Value<'a>, RuntimeError> {
+    Ok(Value::variant("Result", "Ok", args))
+}
+
+fn err<'a>(_: &Interpreter<'a>, args: Vec<Value<'a>>) -> Result<Value<'a>, RuntimeError> {
+    Ok(Value::variant("Result", "Err", args))
+}
+
+pub(crate) fn expect_args(name: &str, args: &[Value], expected: usize) -> Result<(), RuntimeError> {
+    if args.len() != expected {
+        return Err(RuntimeError::ArityMismatch {
+            name: name.to_string(),
+            expected,
+            found: args.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Calls a function value with arguments, whichever engine runs it.
+pub type Callback<'a, 'c> =
+    dyn Fn(&Value<'a>, Vec<Value<'a>>) -> Result<Value<'a>, RuntimeError> + Sync + 'c;
+
+/// Calls `f` on every item until it is exhausted or `f` hits a `break`.
+fn for_each<'a>(
+    items: impl Iterator<Item = Value<'a>>,
+    mut f: impl FnMut(Value<'a>) -> Result<(), RuntimeError>,
+) -> Result<(), RuntimeError> {
+    for item in items {
+        match f(item) {
+            Err(RuntimeError::Break) => break,
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+fn collect<'a>(
+    interp: &Interpreter<'a>,
+    call: &Callback<'a, '_>,
+    sequence: &Sequence<'a>,
+) -> Result<Vec<Value<'a>>, RuntimeError> {
+    let mut all = Vec::new();
+    sequence.each(interp, call, false, |items| {
+        all.extend(items);
+        Ok(false)
+    })?;
+    Ok(all)
+}
+
+// `par_reduce(init, f)`
+fn par_reduce<'a>(
+    interp: &Interpreter<'a>,
+    call: &Callback<'a, '_>,
+    items: &[Value<'a>],
+    args: Vec<Value<'a>>,
+) -> Result<Value<'a>, RuntimeError> {
+    let mut args = args.into_iter();
+    let (init, f) = (args.next().unwrap(), args.next().unwrap());
+    parallel::reduce(&interp.runtime, items, init, |acc, item| {
+        call(&f, vec![acc, item])
+    })
+}
+
+/// Built-in methods on arrays, maps, ranges and strings (`a.len()`, `a.map(|x| x * 2)`).
+///
+/// `call` invokes the callbacks given to `map`, `filter` and `each`, and to
+/// their `par_` counterparts from the workers running them.
+pub fn call_method<'a>(
+    interp: &Interpreter<'a>,
+    call: &Callback<'a, '_>,
+    receiver: Value<'a>,
+    name: &str,
+    args: Vec<Value<'a>>,
+) -> Result<Value<'a>, RuntimeError> {
+    match (&receiver, name) {
+        (Value::Array(values), "push") => {
+            values.lock().unwrap().extend(args);
+            Ok(Value::Unit)
+        }
+        (Value::Array(values), "pop") => {
+            expect_args(name, &args, 0)?;
+            Ok(values.lock().unwrap().pop().unwrap_or(Value::Unit))
+        }
+        (Value::Str(s), "len") => {
+            expect_args(name, &args, 0)?;
+            Ok(Value::Int(s.chars().count() as i64))
+        }
+        (Value::Atomic(cell), "load") => {
+            expect_args(name, &args, 0)?;
+            Ok(cell.load())
+        }
+        (Value::Atomic(cell), "store") => {
+            expect_args(name, &args, 1)?;
+            cell.store(args.into_iter().next().unwrap())?;
+            Ok(Value::Unit)
+        }
+        (Value::Atomic(cell), "swap") => {
+            expect_args(name, &args, 1)?;
+            cell.swap(args.into_iter().next().unwrap())
+        }
+        // `Ok` with the previous value when it was swapped, `Err` with the current one otherwise
+        (Value::Atomic(cell), "compare_exchange") => {
+            expect_args(name, &args, 2)?;
+            let mut args = args.into_iter();
+            let (current, new) = (args.next().unwrap(), args.next().unwrap());
+            Ok(match cell.compare_exchange(current, new)? {
+                Ok(previous) => Value::variant("Result", "Ok", vec![previous]),
+                Err(actual) => Value::variant("Result", "Err", vec![actual]),
+            })
+        }
+        // the previous value is returned, as by `swap`
+        (Value::Atomic(cell), "add" | "sub") => {
+            expect_args(name, &args, 1)?;
+            let op = if name == "add" {
+                BinOp::Plus
+            } else {
+                BinOp::Minus
+            };
+            cell.update(|value| binary(op, value, args[0].clone()))
+        }
+        (Value::Atomic(cell), _) => interp.call_method(cell.load(), name, args),
+        (Value::Task(task), "join") => {
+            expect_args(name, &args, 0)?;
+            interp.runtime.join(interp, task)
+        }
+        (Value::Future(future), "done") => {
+            expect_args(name, &args, 0)?;
+            Ok(Value::Bool(future.is_done()))
+        }
+        (Value::Task(task), "done") => {
+            expect_args(name, &args, 0)?;
+            Ok(Value::Bool(task.is_done()))
+        }
+        // every task is waited for, the first failure in spawn order being raised
+        (Value::Scheduler(scheduler), "join") => {
+            expect_args(name, &args, 0)?;
+            let results: Vec<_> = scheduler
+                .tasks()
+                .iter()
+                .map(|task| interp.runtime.join(interp, task))
+                .collect();
+            Ok(Value::array(results.into_iter().collect::<Result<_, _>>()?))
+        }
+        (Value::Scheduler(scheduler), "len") => {
+            expect_args(name, &args, 0)?;
+            Ok(Value::Int(scheduler.tasks().len() as i64))
+        }
+        (_, "to_string") => {
+            expect_args(name, &args, 0)?;
+            Ok(Value::str(receiver.to_string()))
+        }
+        (Value::Range(start, end), "len") => {
+            expect_args(name, &args, 0)?;
+            match end {
+                Some(end) => Ok(Value::Int((end - start).max(0))),
+                None => Err(RuntimeError::TypeError(
+                    "an infinite range has no length".to_string(),
+                )),
+            }
+        }
+        (Value::Array(values), "len") => {
+            expect_args(name, &args, 0)?;
+            Ok(Value::Int(values.lock().unwrap().len() as i64))
+        }
+        (Value::Map(entries), "len") => {
+            expect_args(name, &args, 0)?;
+            Ok(Value::Int(entries.lock().unwrap().len() as i64))
+        }
+        (Value::Map(entries), "keys") => {
+            expect_args(name, &args, 0)?;
+            let keys = entries
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(key, _)| Value::Str(key.clone()))
+                .collect();
+            Ok(Value::array(keys))
+        }
+        // nothing is computed before the items are consumed
+        (
+            Value::Range(_, None) | Value::Sequence(_),
+            "map" | "filter" | "take" | "par_map" | "par_filter",
+        ) => {
+            expect_args(name, &args, 1)?;
+            let arg = args.into_iter().next().unwrap();
+            Ok(Value::Sequence(Arc::new(Sequence::stage(
+                &receiver, name, arg,
+            )?)))
+        }
+        (Value::Sequence(sequence), "each") => {
+            expect_args(name, &args, 1)?;
+            sequence.each(interp, call, false, |items| {
+                for item in items {
+                    match call(&args[0], vec![item]) {
+                        Err(RuntimeError::Break) => return Ok(true),
+                        result => result?,
+                    };
+                }
+                Ok(false)
+            })?;
+            Ok(Value::Unit)
+        }
+        // batches of items are pulled until the closure hits a `break`
+        (Value::Range(_, None) | Value::Sequence(_), "par_each") => {
+            expect_args(name, &args, 1)?;
+            Sequence::of(&receiver).each(interp, call, true, |items| {
+                let (_, stopped) = parallel::map(&interp.runtime, &items, |item| {
+                    call(&args[0], vec![item.clone()])
+                })?;
+                Ok(stopped)
+            })?;
+            Ok(Value::Unit)
+        }
+        (Value::Sequence(sequence), "collect") => {
+            expect_args(name, &args, 0)?;
+            Ok(Value::array(collect(interp, call, sequence)?))
+        }
+        (Value::Sequence(sequence), "par_reduce") => {
+            expect_args(name, &args, 2)?;
+            let items = collect(interp, call, sequence)?;
+            par_reduce(interp, call, &items, args)
+        }
+        (Value::Range(_, None), "par_reduce") => Err(RuntimeError::TypeError(
+            "cannot `par_reduce` an infinite range, `take` some items first".to_string(),
+        )),
+        (Value::Array(_) | Value::Range(..), "par_map") => {
+            expect_args(name, &args, 1)?;
+            let items: Vec<_> = interp.iterate(&receiver)?.collect();
+            let (mapped, _) = parallel::map(&interp.runtime, &items, |item| {
+                call(&args[0], vec![item.clone()])
+            })?;
+            Ok(Value::array(mapped))
+        }
+        (Value::Array(_) | Value::Range(..), "par_filter") => {
+            expect_args(name, &args, 1)?;
+            let items: Vec<_> = interp.iterate(&receiver)?.collect();
+            let (keep, _) = parallel::map(&interp.runtime, &items, |item| {
+                call(&args[0], vec![item.clone()])?.as_bool()
+            })?;
+            let kept = items.into_iter().zip(keep).filter(|(_, keep)| *keep);
+            Ok(Value::array(kept.map(|(item, _)| item).collect()))
+        }
+        (Value::Array(_) | Value::Range(..), "par_each") => {
+            expect_args(name, &args, 1)?;
+            let items: Vec<_> = interp.iterate(&receiver)?.collect();
+            parallel::map(&interp.runtime, &items, |item| {
+                call(&args[0], vec![item.clone()])
+            })?;
+            Ok(Value::Unit)
+        }
+        (Value::Array(_) | Value::Range(..), "par_reduce") => {
+            expect_args(name, &args, 2)?;
+            let items: Vec<_> = interp.iterate(&receiver)?.collect();
+            par_reduce(interp, call, &items, args)
+        }
+        (Value::Array(_) | Value::Range(..), "take") => {
+            expect_args(name, &args, 1)?;
+            let Value::Int(n) = args[0] else {
+                return Err(RuntimeError::TypeError(format!(
+                    "`take` expects an Int, found {}",
+                    args[0].type_name()
+                )));
+            };
+            let items = interp.iterate(&receiver)?.take(n.max(0) as usize);
+            Ok(Value::array(items.collect()))
+        }
+        (Value::Array(_) | Value::Range(..), "map") => {
+            expect_args(name, &args, 1)?;
+            let mut mapped = Vec::new();
+            for_each(interp.iterate(&receiver)?, |item| {
+                mapped.push(call(&args[0], vec![item])?);
+                Ok(())
+            })?;
+            Ok(Value::array(mapped))
+        }
+        (Value::Array(_) | Value::Range(..), "filter") => {
+            expect_args(name, &args, 1)?;
+            let mut kept = Vec::new();
+            for_each(interp.iterate(&receiver)?, |item| {
+                if call(&args[0], vec![item.clone()])?.as_bool()? {
+                    kept.push(item);
+                }
+                Ok(())
+            })?;
+            Ok(Value::array(kept))
+        }
+        (Value::Array(_) | Value::Range(..), "each") => {
+            expect_args(name, &args, 1)?;
+            for_each(interp.iterate(&receiver)?, |item| {
+                call(&args[0], vec![item]).map(|_| ())
+            })?;
+            Ok(Value::Unit)
+        }
+        _ => Err(RuntimeError::UnknownMethod(
+            receiver.type_name().to_string(),
+            name.to_string(),
+        )),
+    }
+}
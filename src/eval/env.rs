@@ -0,0 +1,72 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use super::{RuntimeError, value::Value};
+
+#[derive(Debug, Clone)]
+struct Binding<'a> {
+    value: Value<'a>,
+    mutable: bool,
+}
+
+#[derive(Debug, Default)]
+struct Scope<'a> {
+    vars: Mutex<HashMap<&'a str, Binding<'a>>>,
+    parent: Option<Env<'a>>,
+}
+
+/// A chain of scopes, cheap to clone and shared by closures capturing it.
+#[derive(Debug, Clone, Default)]
+pub struct Env<'a>(Arc<Scope<'a>>);
+
+impl<'a> Env<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a scope nested inside `self`.
+    pub fn child(&self) -> Self {
+        Self(Arc::new(Scope {
+            vars: Mutex::default(),
+            parent: Some(self.clone()),
+        }))
+    }
+
+    /// Declares `name` in this scope, shadowing any outer binding.
+    pub fn define(&self, name: &'a str, value: Value<'a>, mutable: bool) {
+        self.0
+            .vars
+            .lock()
+            .unwrap()
+            .insert(name, Binding { value, mutable });
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value<'a>> {
+        let mut scope = Some(self);
+        while let Some(env) = scope {
+            if let Some(binding) = env.0.vars.lock().unwrap().get(name) {
+                return Some(binding.value.clone());
+            }
+            scope = env.0.parent.as_ref();
+        }
+        None
+    }
+
+    /// Updates the closest binding named `name`.
+    pub fn assign(&self, name: &str, value: Value<'a>) -> Result<(), RuntimeError> {
+        let mut scope = Some(self);
+        while let Some(env) = scope {
+            if let Some(binding) = env.0.vars.lock().unwrap().get_mut(name) {
+                if !binding.mutable {
+                    return Err(RuntimeError::ImmutableAssignment(name.to_string()));
+                }
+                binding.value = value;
+                return Ok(());
+            }
+            scope = env.0.parent.as_ref();
+        }
+        Err(RuntimeError::UndefinedVariable(name.to_string()))
+    }
+}
@@ -0,0 +1,349 @@
+use std::{
+    cell::Cell,
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, VecDeque},
+    sync::{
+        Arc, Condvar, Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering::SeqCst},
+    },
+    thread::{self, Scope, ThreadId},
+    time::{Duration, Instant},
+};
+
+use super::{Interpreter, RuntimeError, env::Env, runtime::catch, value::Value, waits};
+use crate::ast::{Spanned, Statement};
+
+thread_local! {
+    // the future whose body runs on this thread, `await` suspending it
+    static CURRENT: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Blocking work done off the executor, such as reading a file.
+pub type Io<'a> = Box<dyn FnOnce() -> Result<Value<'a>, RuntimeError> + Send + 'a>;
+
+/// What a future does once the executor starts it.
+pub enum Work<'a> {
+    // the body of an `async fn`, the parameters bound in `env`
+    Body(&'a [Spanned<Statement<'a>>], Env<'a>),
+    // done with `()` once the duration elapsed
+    Timer(Duration),
+    Io(Io<'a>),
+}
+
+/// The value of an `async fn` call or of an awaitable std function, holding
+/// its result once the executor ran it.
+#[derive(Debug)]
+pub struct Future<'a> {
+    // in creation order, starting at 1
+    pub id: usize,
+    result: OnceLock<Result<Value<'a>, RuntimeError>>,
+    // its failure, if any, was raised by `await` rather than left to the executor
+    awaited: AtomicBool,
+}
+
+impl<'a> Future<'a> {
+    pub fn is_done(&self) -> bool {
+        self.result.get().is_some()
+    }
+}
+
+struct Pending<'a> {
+    future: Arc<Future<'a>>,
+    // taken when started
+    work: Option<Work<'a>>,
+    // the futures suspended until this one is done
+    waiters: Vec<usize>,
+    // notified when a suspended body is handed the baton back
+    resumed: Arc<Condvar>,
+}
+
+type Body<'a> = (usize, &'a [Spanned<Statement<'a>>], Env<'a>);
+
+#[derive(Default)]
+struct State<'a> {
+    created: usize,
+    pending: HashMap<usize, Pending<'a>>,
+    // the futures to start or resume, in order
+    ready: VecDeque<usize>,
+    // soonest first
+    timers: BinaryHeap<Reverse<(Instant, usize)>>,
+    // the future whose thread runs Lira code, the driver waiting meanwhile
+    running: Option<usize>,
+    driver: Option<ThreadId>,
+    // a body started by the driver, taken by an idle thread
+    handed: Option<Body<'a>>,
+    // the threads done with their body, waiting for another one
+    idle: usize,
+    // bodies and I/O started and not done yet
+    started: usize,
+    io: usize,
+    // set when every future waits for another one, waking them to fail
+    stalled: bool,
+    // set when the driver is done, the idle threads leaving
+    stopping: bool,
+    // every future created since the program started, checked before it ends
+    all: Vec<Arc<Future<'a>>>,
+}
+
+/// An executor running futures one at a time, switching to another when the
+/// running one awaits a future not done yet.
+///
+/// A body runs on a thread of its own so that it can be suspended in the
+/// middle of the tree-walk, threads being reused once their body returned,
+/// but only the one holding the baton (`running`) runs at any time: the
+/// thread driving the executor (awaiting from outside any future) hands it
+/// over and gets it back when the future awaits or returns. Timers are kept
+/// by the driver, and I/O runs on threads of its own meanwhile, outside of
+/// the baton. Awaiting outside any future while another thread drives fails
+/// with [`RuntimeError::Deadlock`] rather than waiting for that thread.
+#[derive(Default)]
+pub struct Executor<'a> {
+    state: Mutex<State<'a>>,
+    // only the driver waits on it, for the baton or for I/O
+    changed: Condvar,
+    // the idle threads wait on it for a body
+    handed: Condvar,
+    // only one thread drives the futures at a time
+    driving: Mutex<()>,
+}
+
+impl<'a> Executor<'a> {
+    /// A future for `work`, started once a thread awaits it or another one.
+    pub fn submit(&self, work: Work<'a>) -> Arc<Future<'a>> {
+        let mut state = self.state.lock().unwrap();
+        state.created += 1;
+        let future = Arc::new(Future {
+            id: state.created,
+            result: OnceLock::new(),
+            awaited: AtomicBool::new(false),
+        });
+        let pending = Pending {
+            future: future.clone(),
+            work: Some(work),
+            waiters: Vec::new(),
+            resumed: Arc::default(),
+        };
+        state.pending.insert(future.id, pending);
+        state.ready.push_back(future.id);
+        state.all.push(future.clone());
+        future
+    }
+
+    /// The result of `future`, its failure being raised here.
+    ///
+    /// Inside a future, its body is suspended until `future` is done, the
+    /// others running meanwhile. Elsewhere, the executor is driven until it is.
+    pub fn wait(
+        &self,
+        interp: &Interpreter<'a>,
+        future: &Arc<Future<'a>>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        if !future.is_done() {
+            match CURRENT.get() {
+                Some(me) => self.suspend(me, future),
+                None => {
+                    // the driving thread may be waiting for this one, through
+                    // a task it joins or a `par_*` chunk
+                    let Ok(_driving) = self.driving.try_lock() else {
+                        return Err(RuntimeError::Deadlock);
+                    };
+                    self.drive(interp, Some(future));
+                }
+            }
+        }
+        future.awaited.store(true, SeqCst);
+        future
+            .result
+            .get()
+            .cloned()
+            .unwrap_or(Err(RuntimeError::Deadlock))
+    }
+
+    /// Runs every future left, raising the first failure, in creation order,
+    /// of one nobody awaited.
+    pub fn finish(&self, interp: &Interpreter<'a>) -> Result<(), RuntimeError> {
+        let driving = self.driving.lock().unwrap();
+        self.drive(interp, None);
+        drop(driving);
+        let all = std::mem::take(&mut self.state.lock().unwrap().all);
+        for future in all {
+            if let Some(Err(e)) = future.result.get()
+                && !future.awaited.load(SeqCst)
+            {
+                return Err(e.clone());
+            }
+        }
+        Ok(())
+    }
+
+    // gives the baton back to the driver until `target` is done
+    fn suspend(&self, me: usize, target: &Future<'a>) {
+        let mut state = self.state.lock().unwrap();
+        let resumed = state.pending[&me].resumed.clone();
+        while !target.is_done() && !state.stalled {
+            if let Some(pending) = state.pending.get_mut(&target.id) {
+                pending.waiters.push(me);
+            }
+            state.running = None;
+            self.changed.notify_one();
+            state = resumed
+                .wait_while(state, |state| state.running != Some(me))
+                .unwrap();
+        }
+    }
+
+    // runs the futures until `target` is done, or all of them without one,
+    // `driving` being held
+    fn drive(&self, interp: &Interpreter<'a>, target: Option<&Arc<Future<'a>>>) {
+        thread::scope(|scope| {
+            let mut state = self.state.lock().unwrap();
+            state.driver = Some(thread::current().id());
+            loop {
+                // the threads started here must be done before leaving the scope
+                let done = match target {
+                    Some(target) => target.is_done(),
+                    None => state.ready.is_empty() && state.timers.is_empty(),
+                };
+                if done && state.started == 0 {
+                    break;
+                }
+
+                if let Some(id) = state.ready.pop_front() {
+                    if self.start(interp, scope, &mut state, id) {
+                        state = self
+                            .changed
+                            .wait_while(state, |state| state.running.is_some())
+                            .unwrap();
+                    }
+                    continue;
+                }
+                if let Some(&Reverse((deadline, _))) = state.timers.peek() {
+                    let now = Instant::now();
+                    if deadline > now {
+                        state = self.changed.wait_timeout(state, deadline - now).unwrap().0;
+                        continue;
+                    }
+                    while let Some(&Reverse((at, id))) = state.timers.peek()
+                        && at <= now
+                    {
+                        state.timers.pop();
+                        complete(&mut state, id, Ok(Value::Unit));
+                    }
+                    continue;
+                }
+                if state.io > 0 {
+                    state = self.changed.wait(state).unwrap();
+                    continue;
+                }
+
+                // the suspended futures wait for each other, their `await` fails
+                state.stalled = true;
+                let waiters: Vec<_> = state
+                    .pending
+                    .values_mut()
+                    .flat_map(|pending| std::mem::take(&mut pending.waiters))
+                    .collect();
+                if waiters.is_empty() {
+                    break;
+                }
+                state.ready.extend(waiters);
+            }
+            state.stalled = false;
+            state.stopping = true;
+            self.handed.notify_all();
+        });
+        self.state.lock().unwrap().stopping = false;
+    }
+
+    // starts or resumes a future, returning whether it was handed the baton
+    fn start<'s>(
+        &'s self,
+        interp: &'s Interpreter<'a>,
+        scope: &'s Scope<'s, '_>,
+        state: &mut State<'a>,
+        id: usize,
+    ) -> bool {
+        let Some(pending) = state.pending.get_mut(&id) else {
+            return false;
+        };
+        match pending.work.take() {
+            Some(Work::Body(body, env)) => {
+                state.started += 1;
+                // every thread runs or awaits a body, one more is needed
+                if state.idle == 0 {
+                    state.idle += 1;
+                    scope.spawn(move || self.serve(interp));
+                }
+                state.handed = Some((id, body, env));
+                self.handed.notify_one();
+            }
+            Some(Work::Timer(duration)) => {
+                state.timers.push(Reverse((Instant::now() + duration, id)));
+                return false;
+            }
+            Some(Work::Io(io)) => {
+                state.started += 1;
+                state.io += 1;
+                scope.spawn(move || {
+                    let result = catch(io);
+                    let mut state = self.state.lock().unwrap();
+                    complete(&mut state, id, result);
+                    state.started -= 1;
+                    state.io -= 1;
+                    self.changed.notify_one();
+                });
+                return false;
+            }
+            // a suspended body whose awaited future is done
+            None => pending.resumed.notify_one(),
+        }
+        state.running = Some(id);
+        true
+    }
+
+    // runs the bodies handed to this thread until the driver is done
+    fn serve(&self, interp: &Interpreter<'a>) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            state = self
+                .handed
+                .wait_while(state, |state| state.handed.is_none() && !state.stopping)
+                .unwrap();
+            state.idle -= 1;
+            let Some((id, body, env)) = state.handed.take() else {
+                return;
+            };
+            // for a lazy binding the driver evaluates, read by the body
+            let driven = state.driver.map(waits::waited_by);
+            drop(state);
+
+            CURRENT.set(Some(id));
+            let result = catch(|| interp.exec_task(body, &env));
+            CURRENT.set(None);
+            drop(driven);
+
+            state = self.state.lock().unwrap();
+            complete(&mut state, id, result);
+            state.started -= 1;
+            state.idle += 1;
+            state.running = None;
+            self.changed.notify_one();
+        }
+    }
+}
+
+/// Runs `f` as if outside any future, an `await` there failing while another
+/// thread drives rather than suspending the future meanwhile.
+pub(super) fn detached<T>(f: impl FnOnce() -> T) -> T {
+    let current = CURRENT.replace(None);
+    let result = f();
+    CURRENT.set(current);
+    result
+}
+
+fn complete<'a>(state: &mut State<'a>, id: usize, result: Result<Value<'a>, RuntimeError>) {
+    if let Some(pending) = state.pending.remove(&id) {
+        let _ = pending.future.result.set(result);
+        state.ready.extend(pending.waiters);
+    }
+}
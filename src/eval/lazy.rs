@@ -0,0 +1,216 @@
+use std::{
+    sync::{Arc, Condvar, Mutex, OnceLock},
+    thread::{self, ThreadId},
+};
+
+use super::{
+    Interpreter, RuntimeError, atomic::Atomic, builtins::Callback, env::Env, parallel,
+    runtime::catch, value::Value, waits,
+};
+use crate::ast::{Expr, Spanned};
+
+/// The value of a `lazy` binding, evaluated the first time it is read.
+///
+/// Tasks reading it concurrently wait for the first one to evaluate it, so
+/// its expression runs once, and its failure is raised by every read. Reading
+/// it from its own expression fails instead, be it through tasks joined or
+/// futures awaited there.
+#[derive(Debug)]
+pub struct Thunk<'a> {
+    name: &'a str,
+    expr: &'a Spanned<Expr<'a>>,
+    env: Env<'a>,
+    // `lazy atomic`, the value being stored in a cell once evaluated
+    atomic: bool,
+    // the thread evaluating the expression, reading the binding there being a cycle
+    evaluating: Mutex<Option<ThreadId>>,
+    evaluated: Condvar,
+    value: OnceLock<Result<Value<'a>, RuntimeError>>,
+}
+
+impl<'a> Thunk<'a> {
+    pub fn new(name: &'a str, expr: &'a Spanned<Expr<'a>>, env: Env<'a>, atomic: bool) -> Self {
+        Self {
+            name,
+            expr,
+            env,
+            atomic,
+            evaluating: Mutex::new(None),
+            evaluated: Condvar::new(),
+            value: OnceLock::new(),
+        }
+    }
+
+    pub fn force(&self, interp: &Interpreter<'a>) -> Result<Value<'a>, RuntimeError> {
+        let me = thread::current().id();
+        let mut evaluating = self.evaluating.lock().unwrap();
+        loop {
+            if let Some(value) = self.value.get() {
+                return value.clone();
+            }
+            match *evaluating {
+                None => break,
+                Some(thread) if thread == me => {
+                    return Err(RuntimeError::LazyCycle(self.name.to_string()));
+                }
+                Some(thread) => {
+                    drop(evaluating);
+                    let _waiting = waits::wait_for(thread, Some(self.name))?;
+                    evaluating = self
+                        .evaluated
+                        .wait_while(self.evaluating.lock().unwrap(), |e| *e == Some(thread))
+                        .unwrap();
+                }
+            }
+        }
+        *evaluating = Some(me);
+        drop(evaluating);
+
+        let value = catch(|| interp.eval(self.expr, &self.env)).map(|value| match self.atomic {
+            true => Value::Atomic(Arc::new(Atomic::new(value))),
+            false => value,
+        });
+        let _ = self.value.set(value.clone());
+        *self.evaluating.lock().unwrap() = None;
+        self.evaluated.notify_all();
+        value
+    }
+}
+
+/// The items of a range passed through `map`, `filter` and `take`, computed
+/// as they are consumed, so that infinite ranges can be.
+///
+/// Items go through the stages one at a time, or in batches of [`BATCH`] when
+/// a stage or the consumer runs in parallel.
+#[derive(Debug, Clone)]
+pub struct Sequence<'a> {
+    source: Value<'a>,
+    stages: Vec<Stage<'a>>,
+}
+
+pub const BATCH: usize = 1024;
+
+#[derive(Debug, Clone)]
+enum Stage<'a> {
+    Map(Value<'a>, bool),
+    Filter(Value<'a>, bool),
+    Take(usize),
+}
+
+impl<'a> Sequence<'a> {
+    /// A range as a sequence, or the sequence itself.
+    pub fn of(source: &Value<'a>) -> Self {
+        match source {
+            Value::Sequence(sequence) => sequence.as_ref().clone(),
+            source => Sequence {
+                source: source.clone(),
+                stages: Vec::new(),
+            },
+        }
+    }
+
+    /// The sequence of `source` with `name(arg)` added, `name` being `map`,
+    /// `filter`, `take`, `par_map` or `par_filter`.
+    pub fn stage(source: &Value<'a>, name: &str, arg: Value<'a>) -> Result<Self, RuntimeError> {
+        let mut sequence = Sequence::of(source);
+        sequence.stages.push(match (name, arg) {
+            ("map", f) => Stage::Map(f, false),
+            ("par_map", f) => Stage::Map(f, true),
+            ("filter", f) => Stage::Filter(f, false),
+            ("par_filter", f) => Stage::Filter(f, true),
+            ("take", Value::Int(n)) => Stage::Take(n.max(0) as usize),
+            ("take", other) => {
+                return Err(RuntimeError::TypeError(format!(
+                    "`take` expects an Int, found {}",
+                    other.type_name()
+                )));
+            }
+            (name, _) => unreachable!("`{}` is not a stage", name),
+        });
+        Ok(sequence)
+    }
+
+    /// Calls `f` on the items, in batches, until the source or a `take` is
+    /// exhausted, or a closure hits a `break`, `f` returning whether it did.
+    pub fn each(
+        &self,
+        interp: &Interpreter<'a>,
+        call: &Callback<'a, '_>,
+        parallel: bool,
+        mut f: impl FnMut(Vec<Value<'a>>) -> Result<bool, RuntimeError>,
+    ) -> Result<(), RuntimeError> {
+        let batch = match parallel || self.stages.iter().any(Stage::is_parallel) {
+            true => BATCH,
+            false => 1,
+        };
+        let mut taken = vec![0; self.stages.len()];
+        let mut source = interp.iterate(&self.source)?;
+        // no later item could get through a full `take`, the source is not pulled any further
+        while !self.exhausted(&taken) {
+            let mut items: Vec<_> = source.by_ref().take(batch).collect();
+            if items.is_empty() {
+                break;
+            }
+            let mut stopped = false;
+            for (stage, taken) in self.stages.iter().zip(&mut taken) {
+                let stop;
+                (items, stop) = match stage {
+                    Stage::Map(g, parallel) => map(interp, &items, *parallel, |item| {
+                        call(g, vec![item.clone()])
+                    })?,
+                    Stage::Filter(p, parallel) => {
+                        let (keep, stop) = map(interp, &items, *parallel, |item| {
+                            call(p, vec![item.clone()])?.as_bool()
+                        })?;
+                        let kept = items.into_iter().zip(keep).filter(|(_, keep)| *keep);
+                        (kept.map(|(item, _)| item).collect(), stop)
+                    }
+                    Stage::Take(n) => {
+                        items.truncate(n - *taken);
+                        *taken += items.len();
+                        (items, false)
+                    }
+                };
+                stopped |= stop;
+            }
+            if f(items)? || stopped {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn exhausted(&self, taken: &[usize]) -> bool {
+        self.stages
+            .iter()
+            .zip(taken)
+            .any(|(stage, taken)| matches!(stage, Stage::Take(n) if taken == n))
+    }
+}
+
+impl Stage<'_> {
+    fn is_parallel(&self) -> bool {
+        matches!(self, Stage::Map(_, true) | Stage::Filter(_, true))
+    }
+}
+
+// the results of `f` on the items before the first `break`, and whether one was hit
+fn map<'a, T: Send>(
+    interp: &Interpreter<'a>,
+    items: &[Value<'a>],
+    parallel: bool,
+    f: impl Fn(&Value<'a>) -> Result<T, RuntimeError> + Sync,
+) -> Result<(Vec<T>, bool), RuntimeError> {
+    if parallel {
+        return parallel::map(&interp.runtime, items, f);
+    }
+    let mut results = Vec::with_capacity(items.len());
+    for item in items {
+        match f(item) {
+            Ok(result) => results.push(result),
+            Err(RuntimeError::Break) => return Ok((results, true)),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok((results, false))
+}
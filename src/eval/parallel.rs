@@ -0,0 +1,210 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    panic::{AssertUnwindSafe, catch_unwind, resume_unwind},
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicUsize, Ordering::SeqCst},
+    },
+    thread::{self, ThreadId},
+};
+
+use super::{RuntimeError, executor, runtime::Runtime, value::Value, waits};
+
+// the items are split in this many chunks at most, whatever the number of workers
+const CHUNKS: usize = 64;
+
+/// Calls `f` on every item on the workers of `runtime`, returning the results
+/// in the order of the items.
+///
+/// A `break` from `f` skips the items after the one hitting it, the results
+/// of those before it being returned along with `true`. The first failure, in
+/// the order of the items, is raised.
+pub fn map<I: Sync, T: Send>(
+    runtime: &Runtime,
+    items: &[I],
+    f: impl Fn(&I) -> Result<T, RuntimeError> + Sync,
+) -> Result<(Vec<T>, bool), RuntimeError> {
+    let size = items.len().div_ceil(CHUNKS).max(1);
+    // the first item that broke or failed
+    let stop = AtomicUsize::new(usize::MAX);
+    let slots: Vec<Mutex<Option<Result<T, RuntimeError>>>> =
+        items.iter().map(|_| Mutex::new(None)).collect();
+    steal(runtime, items.len().div_ceil(size), |chunk| {
+        for i in chunk * size..((chunk + 1) * size).min(items.len()) {
+            if i > stop.load(SeqCst) {
+                break;
+            }
+            let result = f(&items[i]);
+            if result.is_err() {
+                stop.fetch_min(i, SeqCst);
+            }
+            *slots[i].lock().unwrap() = Some(result);
+        }
+    });
+
+    let mut results = Vec::with_capacity(items.len());
+    for slot in slots {
+        match slot.into_inner().unwrap() {
+            Some(Ok(result)) => results.push(result),
+            Some(Err(RuntimeError::Break)) => return Ok((results, true)),
+            Some(Err(e)) => return Err(e),
+            None => unreachable!("only the items after a stop are skipped"),
+        }
+    }
+    Ok((results, false))
+}
+
+/// Folds the items with `f` onto `init`, `f` being expected to be associative.
+///
+/// Chunks of items are folded in parallel, then their results from left to
+/// right, the chunks only depending on the number of items so that the result
+/// is the same on any machine. A `break` stops the fold at the end of the
+/// chunk hitting it.
+pub fn reduce<'a>(
+    runtime: &Runtime,
+    items: &[Value<'a>],
+    init: Value<'a>,
+    f: impl Fn(Value<'a>, Value<'a>) -> Result<Value<'a>, RuntimeError> + Sync,
+) -> Result<Value<'a>, RuntimeError> {
+    let chunks: Vec<_> = items.chunks(items.len().div_ceil(CHUNKS).max(1)).collect();
+    let (folds, _) = map(runtime, &chunks, |chunk| {
+        let mut acc = chunk[0].clone();
+        for item in &chunk[1..] {
+            match f(acc.clone(), item.clone()) {
+                Ok(value) => acc = value,
+                Err(RuntimeError::Break) => return Ok((acc, true)),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok((acc, false))
+    })?;
+
+    let mut acc = None;
+    for (fold, stopped) in folds {
+        acc = Some(match acc {
+            Some(acc) => f(acc, fold)?,
+            None => fold,
+        });
+        if stopped {
+            break;
+        }
+    }
+    match acc {
+        Some(acc) => f(init, acc),
+        None => Ok(init),
+    }
+}
+
+/// The chunks of a `par_*` call, run by the workers of the [`Runtime`] along
+/// with the calling thread, which returns once they all ran.
+pub struct Batch {
+    // only called on a chunk taken from `queues`, taken away by `Joined`
+    job: Mutex<Option<&'static (dyn Fn(usize) + Sync)>>,
+    // each thread starts with a share of consecutive chunks, then steals the
+    // last chunk of another once done with its own
+    queues: Vec<Mutex<VecDeque<usize>>>,
+    caller: ThreadId,
+    joined: AtomicUsize,
+    left: Mutex<usize>,
+    done: Condvar,
+    // the first panic of a chunk, resumed by the calling thread
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+impl Batch {
+    /// Runs chunks until none is left to take.
+    pub fn help(&self) {
+        let threads = self.queues.len();
+        let me = self.joined.fetch_add(1, SeqCst) % threads;
+        loop {
+            let own = self.queues[me].lock().unwrap().pop_front();
+            let next = own.or_else(|| {
+                (1..threads)
+                    .find_map(|k| self.queues[(me + k) % threads].lock().unwrap().pop_back())
+            });
+            let Some(chunk) = next else {
+                break;
+            };
+            // for a lazy binding the caller evaluates, read by the chunk
+            let _helping =
+                (thread::current().id() != self.caller).then(|| waits::waited_by(self.caller));
+            let job = self
+                .job
+                .lock()
+                .unwrap()
+                .expect("a job while chunks are left");
+            if let Err(panic) = catch_unwind(AssertUnwindSafe(|| job(chunk))) {
+                self.panic.lock().unwrap().get_or_insert(panic);
+            }
+            let mut left = self.left.lock().unwrap();
+            *left -= 1;
+            if *left == 0 {
+                self.done.notify_all();
+            }
+        }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.queues
+            .iter()
+            .all(|queue| queue.lock().unwrap().is_empty())
+    }
+}
+
+/// Runs `job` on the chunks `0..chunks` on the workers of `runtime` and the
+/// calling thread, which runs them all when there are no workers.
+fn steal(runtime: &Runtime, chunks: usize, job: impl Fn(usize) + Sync) {
+    let threads = (runtime.workers() + 1).min(chunks);
+    if threads == 0 {
+        return;
+    }
+    let job: &(dyn Fn(usize) + Sync) = &job;
+    // SAFETY: the batch only calls `job` on a chunk taken from its queues, and
+    // `Joined` takes it away once no chunk is left nor running, before `job`
+    // goes out of scope however this function returns
+    let job = unsafe {
+        std::mem::transmute::<&(dyn Fn(usize) + Sync), &'static (dyn Fn(usize) + Sync)>(job)
+    };
+    let share = chunks.div_ceil(threads);
+    let batch = Arc::new(Batch {
+        job: Mutex::new(Some(job)),
+        queues: (0..threads)
+            .map(|t| Mutex::new((t * share..((t + 1) * share).min(chunks)).collect()))
+            .collect(),
+        caller: thread::current().id(),
+        joined: AtomicUsize::new(0),
+        left: Mutex::new(chunks),
+        done: Condvar::new(),
+        panic: Mutex::new(None),
+    });
+    let joined = Joined(&batch);
+    runtime.submit(batch.clone());
+    // like on the workers, a chunk awaiting does not suspend the calling future
+    executor::detached(|| batch.help());
+    drop(joined);
+    if let Some(panic) = batch.panic.lock().unwrap().take() {
+        resume_unwind(panic);
+    }
+}
+
+// closes the batch when dropped, unwinding included: the chunks nobody took
+// are dropped and the running ones waited for, then the job is taken away
+struct Joined<'b>(&'b Batch);
+
+impl Drop for Joined<'_> {
+    fn drop(&mut self) {
+        let batch = self.0;
+        let dropped: usize = batch
+            .queues
+            .iter()
+            .map(|queue| queue.lock().unwrap().drain(..).count())
+            .sum();
+        let mut left = batch.left.lock().unwrap();
+        *left -= dropped;
+        while *left > 0 {
+            left = batch.done.wait(left).unwrap();
+        }
+        *batch.job.lock().unwrap() = None;
+    }
+}
@@ -0,0 +1,64 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use super::{RuntimeError, builtins, env::Env, stdlib, value::Module, value::Value};
+use crate::{ast::Program, modules::STD};
+
+/// The modules a program can `use`, each instantiated once, on first import.
+///
+/// Shared by the tree-walking interpreter and the VM, which only differ in
+/// how they declare the items of a module file.
+#[derive(Default)]
+pub struct Registry<'a> {
+    // parsed module files by `use` path
+    files: HashMap<String, &'a Program<'a>>,
+    imported: Mutex<HashMap<String, Value<'a>>>,
+}
+
+impl<'a> Registry<'a> {
+    /// Makes a parsed module file importable under its `use` path, e.g. `utils::math`.
+    pub fn add(&mut self, name: impl Into<String>, program: &'a Program<'a>) {
+        self.files.insert(name.into(), program);
+    }
+
+    /// The module of a `use` path, `declare` running the items of a module
+    /// file in the environment of the module on first import.
+    pub fn import(
+        &self,
+        path: &[&str],
+        declare: impl FnOnce(&'a Program<'a>, &Env<'a>) -> Result<(), RuntimeError>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        let name = path.join("::");
+        if let Some(module) = self.imported.lock().unwrap().get(&name) {
+            return Ok(module.clone());
+        }
+        if path.first() == Some(&STD) {
+            let env =
+                stdlib::module(&name).ok_or_else(|| RuntimeError::UnknownModule(name.clone()))?;
+            let module = Value::Module(Arc::new(Module {
+                name: name.clone(),
+                env,
+            }));
+            self.imported.lock().unwrap().insert(name, module.clone());
+            return Ok(module);
+        }
+        let program = *self
+            .files
+            .get(&name)
+            .ok_or_else(|| RuntimeError::UnknownModule(name.clone()))?;
+
+        let env = Env::new();
+        builtins::register(&env);
+        let module = Value::Module(Arc::new(Module {
+            name: name.clone(),
+            env: env.clone(),
+        }));
+        // cached first, so that modules importing each other terminate
+        self.imported.lock().unwrap().insert(name, module.clone());
+
+        declare(program, &env)?;
+        Ok(module)
+    }
+}
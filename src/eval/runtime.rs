@@ -0,0 +1,274 @@
+use std::{
+    collections::VecDeque,
+    panic::{AssertUnwindSafe, catch_unwind},
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, ThreadId},
+};
+
+use super::{Interpreter, RuntimeError, env::Env, parallel::Batch, value::Value, waits};
+use crate::ast::{Spanned, Statement};
+
+/// A spawned block, whose handle is the value of its `spawn` statement.
+#[derive(Debug)]
+pub struct Task<'a> {
+    // in spawn order, starting at 1
+    pub id: usize,
+    state: Mutex<TaskState<'a>>,
+    finished: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct TaskState<'a> {
+    result: Option<Result<Value<'a>, RuntimeError>>,
+    // its failure, if any, was raised by `join` rather than left to the runtime
+    joined: bool,
+    // the worker running it
+    thread: Option<ThreadId>,
+}
+
+impl<'a> Task<'a> {
+    pub fn is_done(&self) -> bool {
+        self.state.lock().unwrap().result.is_some()
+    }
+
+    fn finish(&self, result: Result<Value<'a>, RuntimeError>) {
+        self.state.lock().unwrap().result = Some(result);
+        self.finished.notify_all();
+    }
+
+    fn wait(&self) -> Result<Value<'a>, RuntimeError> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(result) = &state.result {
+                return result.clone();
+            }
+            state = self.finished.wait(state).unwrap();
+        }
+    }
+}
+
+/// Where the value of `spawn with` sends a task instead of the worker pool.
+#[derive(Debug)]
+pub enum Scheduler<'a> {
+    // the spawning thread, before `spawn` returns
+    Inline,
+    // the worker pool, the handles being kept for `join`
+    Group(Mutex<Vec<Arc<Task<'a>>>>),
+}
+
+impl<'a> Scheduler<'a> {
+    pub fn tasks(&self) -> Vec<Arc<Task<'a>>> {
+        match self {
+            Scheduler::Inline => Vec::new(),
+            Scheduler::Group(tasks) => tasks.lock().unwrap().clone(),
+        }
+    }
+}
+
+struct Job<'a> {
+    task: Arc<Task<'a>>,
+    body: &'a [Spanned<Statement<'a>>],
+    env: Env<'a>,
+}
+
+#[derive(Default)]
+struct Queue<'a> {
+    jobs: VecDeque<Job<'a>>,
+    // the chunks of `par_*` calls, run before the jobs since a thread waits for them
+    batches: VecDeque<Arc<Batch>>,
+    // every task spawned since the runtime started, waited for before it stops
+    tasks: Vec<Arc<Task<'a>>>,
+    spawned: usize,
+    workers: usize,
+    stopping: bool,
+}
+
+/// The pool of worker threads running the spawned blocks and the chunks of
+/// the `par_*` methods.
+///
+/// The workers only live while [`Interpreter::run`] does: without them (in
+/// the REPL, or when running statements one by one), blocks run as soon as
+/// they are spawned, and chunks on the thread calling `par_*`.
+#[derive(Default)]
+pub struct Runtime<'a> {
+    queue: Mutex<Queue<'a>>,
+    changed: Condvar,
+}
+
+impl<'a> Runtime<'a> {
+    /// Runs `main` with a worker per core, then waits for every task.
+    ///
+    /// The error of `main` wins, then the first failure, in spawn order, of a
+    /// task nobody joined, whichever task failed first in time.
+    pub fn scope<T>(
+        &self,
+        interp: &Interpreter<'a>,
+        main: impl FnOnce() -> Result<T, RuntimeError>,
+    ) -> Result<T, RuntimeError> {
+        let workers = thread::available_parallelism().map_or(2, |n| n.get());
+        thread::scope(|scope| {
+            {
+                let mut queue = self.queue.lock().unwrap();
+                queue.workers = workers;
+                queue.stopping = false;
+            }
+            for _ in 0..workers {
+                scope.spawn(|| self.work(interp));
+            }
+
+            let result = main();
+            let outstanding = self.wait_all(interp);
+            let mut queue = self.queue.lock().unwrap();
+            queue.stopping = true;
+            queue.workers = 0;
+            queue.tasks.clear();
+            queue.batches.clear();
+            self.changed.notify_all();
+            drop(queue);
+
+            let value = result?;
+            outstanding?;
+            Ok(value)
+        })
+    }
+
+    pub fn spawn(
+        &self,
+        interp: &Interpreter<'a>,
+        body: &'a [Spanned<Statement<'a>>],
+        env: Env<'a>,
+        scheduler: Option<&Scheduler<'a>>,
+    ) -> Arc<Task<'a>> {
+        let mut queue = self.queue.lock().unwrap();
+        queue.spawned += 1;
+        let task = Arc::new(Task {
+            id: queue.spawned,
+            state: Mutex::default(),
+            finished: Condvar::new(),
+        });
+        if let Some(Scheduler::Group(tasks)) = scheduler {
+            tasks.lock().unwrap().push(task.clone());
+        }
+        if queue.workers > 0 {
+            queue.tasks.push(task.clone());
+        }
+        let job = Job {
+            task: task.clone(),
+            body,
+            env,
+        };
+        if queue.workers == 0 || matches!(scheduler, Some(Scheduler::Inline)) {
+            drop(queue);
+            run(interp, job);
+        } else {
+            queue.jobs.push_back(job);
+            self.changed.notify_one();
+        }
+        task
+    }
+
+    pub(super) fn workers(&self) -> usize {
+        self.queue.lock().unwrap().workers
+    }
+
+    /// Hands the chunks of a `par_*` call to the workers, if any.
+    pub(super) fn submit(&self, batch: Arc<Batch>) {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.workers > 0 {
+            queue.batches.push_back(batch);
+            self.changed.notify_all();
+        }
+    }
+
+    /// The value of a task once it finished, its failure being raised here.
+    ///
+    /// A task no worker started yet is run by the joining thread, so that
+    /// tasks joining others cannot starve the pool.
+    pub fn join(
+        &self,
+        interp: &Interpreter<'a>,
+        task: &Arc<Task<'a>>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        self.help(interp, task);
+        let thread = {
+            let mut state = task.state.lock().unwrap();
+            state.joined = true;
+            state.thread.filter(|_| state.result.is_none())
+        };
+        // a worker running it may be waiting for a lazy binding this thread evaluates
+        let _waiting = thread
+            .map(|thread| waits::wait_for(thread, None))
+            .transpose()?;
+        task.wait()
+    }
+
+    fn help(&self, interp: &Interpreter<'a>, task: &Arc<Task<'a>>) {
+        let mut queue = self.queue.lock().unwrap();
+        let position = queue
+            .jobs
+            .iter()
+            .position(|job| Arc::ptr_eq(&job.task, task));
+        if let Some(job) = position.and_then(|i| queue.jobs.remove(i)) {
+            drop(queue);
+            run(interp, job);
+        }
+    }
+
+    // waits for the tasks, those spawned while waiting included
+    fn wait_all(&self, interp: &Interpreter<'a>) -> Result<(), RuntimeError> {
+        let mut failure = Ok(());
+        for next in 0.. {
+            let Some(task) = self.queue.lock().unwrap().tasks.get(next).cloned() else {
+                break;
+            };
+            self.help(interp, &task);
+            let result = task.wait();
+            if failure.is_ok() && !task.state.lock().unwrap().joined {
+                failure = result.map(|_| ());
+            }
+        }
+        failure
+    }
+
+    fn work(&self, interp: &Interpreter<'a>) {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            queue.batches.retain(|batch| !batch.is_exhausted());
+            if let Some(batch) = queue.batches.front().cloned() {
+                drop(queue);
+                batch.help();
+            } else if let Some(job) = queue.jobs.pop_front() {
+                // set while the queue is locked, for `join` to find it once the job left it
+                job.task.state.lock().unwrap().thread = Some(thread::current().id());
+                drop(queue);
+                run(interp, job);
+            } else if queue.stopping {
+                return;
+            } else {
+                queue = self.changed.wait(queue).unwrap();
+                continue;
+            }
+            queue = self.queue.lock().unwrap();
+        }
+    }
+}
+
+fn run<'a>(interp: &Interpreter<'a>, job: Job<'a>) {
+    job.task
+        .finish(catch(|| interp.exec_task(job.body, &job.env)));
+}
+
+/// Runs `f`, a panic of the interpreter failing the task rather than the
+/// thread running it.
+pub(super) fn catch<'a>(
+    f: impl FnOnce() -> Result<Value<'a>, RuntimeError>,
+) -> Result<Value<'a>, RuntimeError> {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Err(RuntimeError::Panic(message))
+    })
+}
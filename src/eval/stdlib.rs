@@ -0,0 +1,45 @@
+mod fs;
+mod io;
+mod json;
+mod process;
+mod task;
+mod time;
+
+use super::{RuntimeError, env::Env, value::Value};
+
+/// The `use` paths of the standard library modules.
+pub const MODULES: &[&str] = &[
+    "std::Fs",
+    "std::Io",
+    "std::Json",
+    "std::Process",
+    "std::Task",
+    "std::Time",
+];
+
+/// The items of the standard library module named by `path`, e.g. `std::Json`.
+pub fn module<'a>(path: &str) -> Option<Env<'a>> {
+    let register = match path {
+        "std::Fs" => fs::register,
+        "std::Io" => io::register,
+        "std::Json" => json::register,
+        "std::Process" => process::register,
+        "std::Task" => task::register,
+        "std::Time" => time::register,
+        _ => return None,
+    };
+    let env = Env::new();
+    register(&env);
+    Some(env)
+}
+
+fn expect_str<'v>(name: &str, value: &'v Value) -> Result<&'v str, RuntimeError> {
+    match value {
+        Value::Str(s) => Ok(s),
+        other => Err(RuntimeError::TypeError(format!(
+            "`{}` expects a String, found {}",
+            name,
+            other.type_name()
+        ))),
+    }
+}
@@ -0,0 +1,69 @@
+use super::{
+    super::{
+        Interpreter, RuntimeError, builtins::expect_args, env::Env, executor::Work, value::Value,
+    },
+    expect_str,
+};
+
+pub fn register(env: &Env<'_>) {
+    env.define("read", Value::Native("read", read), false);
+    env.define("write", Value::Native("write", write), false);
+    env.define("exists", Value::Native("exists", exists), false);
+    env.define("read_async", Value::Native("read_async", read_async), false);
+    env.define(
+        "write_async",
+        Value::Native("write_async", write_async),
+        false,
+    );
+}
+
+fn read<'a>(_: &Interpreter<'a>, args: Vec<Value<'a>>) -> Result<Value<'a>, RuntimeError> {
+    expect_args("read", &args, 1)?;
+    read_file(expect_str("read", &args[0])?)
+}
+
+/// Writes strings as is and any other value as it would be printed.
+fn write<'a>(_: &Interpreter<'a>, args: Vec<Value<'a>>) -> Result<Value<'a>, RuntimeError> {
+    expect_args("write", &args, 2)?;
+    write_file(expect_str("write", &args[0])?, args[1].to_string())
+}
+
+fn exists<'a>(_: &Interpreter<'a>, args: Vec<Value<'a>>) -> Result<Value<'a>, RuntimeError> {
+    expect_args("exists", &args, 1)?;
+    let path = expect_str("exists", &args[0])?;
+    Ok(Value::Bool(std::path::Path::new(path).exists()))
+}
+
+/// `read` as a future, the file being read while other futures run.
+fn read_async<'a>(
+    interp: &Interpreter<'a>,
+    args: Vec<Value<'a>>,
+) -> Result<Value<'a>, RuntimeError> {
+    expect_args("read_async", &args, 1)?;
+    let path = expect_str("read_async", &args[0])?.to_string();
+    let io = Box::new(move || read_file(&path));
+    Ok(Value::Future(interp.executor.submit(Work::Io(io))))
+}
+
+/// `write` as a future.
+fn write_async<'a>(
+    interp: &Interpreter<'a>,
+    args: Vec<Value<'a>>,
+) -> Result<Value<'a>, RuntimeError> {
+    expect_args("write_async", &args, 2)?;
+    let path = expect_str("write_async", &args[0])?.to_string();
+    let contents = args[1].to_string();
+    let io = Box::new(move || write_file(&path, contents));
+    Ok(Value::Future(interp.executor.submit(Work::Io(io))))
+}
+
+fn read_file<'a>(path: &str) -> Result<Value<'a>, RuntimeError> {
+    std::fs::read_to_string(path)
+        .map(Value::str)
+        .map_err(|e| RuntimeError::Io(format!("{}: {}", path, e)))
+}
+
+fn write_file<'a>(path: &str, contents: String) -> Result<Value<'a>, RuntimeError> {
+    std::fs::write(path, contents).map_err(|e| RuntimeError::Io(format!("{}: {}", path, e)))?;
+    Ok(Value::Unit)
+}
@@ -0,0 +1,22 @@
+use super::super::{
+    Interpreter, RuntimeError,
+    builtins::{eprint, expect_args, print},
+    env::Env,
+    value::Value,
+};
+
+pub fn register(env: &Env<'_>) {
+    env.define("print", Value::Native("print", print), false);
+    env.define("puts", Value::Native("puts", print), false);
+    env.define("eprint", Value::Native("eprint", eprint), false);
+    env.define("read_line", Value::Native("read_line", read_line), false);
+}
+
+/// The next line of the input, or `()` once it is exhausted.
+fn read_line<'a>(
+    interp: &Interpreter<'a>,
+    args: Vec<Value<'a>>,
+) -> Result<Value<'a>, RuntimeError> {
+    expect_args("read_line", &args, 0)?;
+    Ok(interp.read_line()?.map(Value::str).unwrap_or(Value::Unit))
+}
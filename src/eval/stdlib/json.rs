@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use serde_json::{Map, Number, Value as Json};
+
+use super::{
+    super::{
+        Interpreter, RuntimeError,
+        builtins::expect_args,
+        env::Env,
+        value::{Payload, Value},
+    },
+    expect_str,
+};
+
+pub fn register(env: &Env<'_>) {
+    env.define("parse", Value::Native("parse", parse), false);
+    env.define("stringify", Value::Native("stringify", stringify), false);
+}
+
+/// `Ok(value)`, or `Err(message)` when the text is not valid JSON.
+fn parse<'a>(_: &Interpreter<'a>, args: Vec<Value<'a>>) -> Result<Value<'a>, RuntimeError> {
+    expect_args("parse", &args, 1)?;
+    let text = expect_str("parse", &args[0])?;
+    Ok(match serde_json::from_str(text) {
+        Ok(json) => Value::variant("Result", "Ok", vec![from_json(json)]),
+        Err(e) => Value::variant("Result", "Err", vec![Value::str(e.to_string())]),
+    })
+}
+
+fn stringify<'a>(_: &Interpreter<'a>, args: Vec<Value<'a>>) -> Result<Value<'a>, RuntimeError> {
+    expect_args("stringify", &args, 1)?;
+    Ok(Value::str(to_json(&args[0])?.to_string()))
+}
+
+/// `null` is `()`, integral numbers are `Int`s and objects are `Map`s.
+fn from_json<'a>(json: Json) -> Value<'a> {
+    match json {
+        Json::Null => Value::Unit,
+        Json::Bool(b) => Value::Bool(b),
+        Json::Number(n) => match n.as_i64() {
+            Some(i) => Value::Int(i),
+            None => Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Json::String(s) => Value::str(s),
+        Json::Array(values) => Value::array(values.into_iter().map(from_json).collect()),
+        Json::Object(entries) => Value::map(
+            entries
+                .into_iter()
+                .map(|(key, value)| (Arc::from(key), from_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// `()` is `null`, structs become objects, unit variants their name and other variants an
+/// object holding their payload under their name.
+fn to_json(value: &Value) -> Result<Json, RuntimeError> {
+    let object = |fields: &mut dyn Iterator<Item = (&str, &Value)>| {
+        fields
+            .map(|(name, value)| Ok((name.to_string(), to_json(value)?)))
+            .collect::<Result<Map<_, _>, RuntimeError>>()
+            .map(Json::Object)
+    };
+    let array = |values: &[Value]| {
+        values
+            .iter()
+            .map(to_json)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Json::Array)
+    };
+
+    Ok(match value {
+        Value::Unit => Json::Null,
+        Value::Tuple(values) if values.is_empty() => Json::Null,
+        Value::Bool(b) => Json::Bool(*b),
+        Value::Int(i) => Json::from(*i),
+        Value::Float(x) => Json::Number(Number::from_f64(*x).ok_or_else(|| {
+            RuntimeError::TypeError(format!("{} cannot be represented in JSON", value))
+        })?),
+        Value::Str(s) => Json::String(s.to_string()),
+        Value::Array(values) => array(&values.lock().unwrap())?,
+        Value::Tuple(values) => array(values)?,
+        Value::Map(entries) => object(
+            &mut entries
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(key, value)| (key.as_ref(), value)),
+        )?,
+        Value::Struct(s) => object(
+            &mut s
+                .fields
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(name, value)| (*name, value)),
+        )?,
+        Value::Variant(v) => {
+            let payload = match &v.payload {
+                Payload::Unit => return Ok(Json::String(v.name.to_string())),
+                Payload::Tuple(values) => array(values)?,
+                Payload::Struct(fields) => {
+                    object(&mut fields.iter().map(|(name, value)| (*name, value)))?
+                }
+            };
+            Json::Object(Map::from_iter([(v.name.to_string(), payload)]))
+        }
+        other => {
+            return Err(RuntimeError::TypeError(format!(
+                "cannot convert {} to JSON",
+                other.type_name()
+            )));
+        }
+    })
+}
@@ -0,0 +1,33 @@
+use super::{
+    super::{
+        Interpreter, RuntimeError,
+        builtins::{exit, expect_args},
+        env::Env,
+        value::Value,
+    },
+    expect_str,
+};
+
+pub fn register(env: &Env<'_>) {
+    env.define("exit", Value::Native("exit", exit), false);
+    env.define("args", Value::Native("args", args), false);
+    env.define("env", Value::Native("env", var), false);
+}
+
+fn args<'a>(interp: &Interpreter<'a>, args: Vec<Value<'a>>) -> Result<Value<'a>, RuntimeError> {
+    expect_args("args", &args, 0)?;
+    Ok(Value::array(
+        interp
+            .args()
+            .iter()
+            .map(|arg| Value::str(arg.as_str()))
+            .collect(),
+    ))
+}
+
+/// The value of an environment variable, or `()` when it is not set.
+fn var<'a>(_: &Interpreter<'a>, args: Vec<Value<'a>>) -> Result<Value<'a>, RuntimeError> {
+    expect_args("env", &args, 1)?;
+    let name = expect_str("env", &args[0])?;
+    Ok(std::env::var(name).map(Value::str).unwrap_or(Value::Unit))
+}
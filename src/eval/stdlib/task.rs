@@ -0,0 +1,24 @@
+use std::sync::{Arc, Mutex};
+
+use super::super::{
+    Interpreter, RuntimeError, builtins::expect_args, env::Env, runtime::Scheduler, value::Value,
+};
+
+pub fn register(env: &Env<'_>) {
+    env.define("group", Value::Native("group", group), false);
+    env.define("inline", Value::Native("inline", inline), false);
+}
+
+/// A scheduler keeping the tasks spawned with it, to join them all at once.
+fn group<'a>(_: &Interpreter<'a>, args: Vec<Value<'a>>) -> Result<Value<'a>, RuntimeError> {
+    expect_args("group", &args, 0)?;
+    Ok(Value::Scheduler(Arc::new(Scheduler::Group(
+        Mutex::default(),
+    ))))
+}
+
+/// A scheduler running the tasks on the spawning thread, before `spawn` returns.
+fn inline<'a>(_: &Interpreter<'a>, args: Vec<Value<'a>>) -> Result<Value<'a>, RuntimeError> {
+    expect_args("inline", &args, 0)?;
+    Ok(Value::Scheduler(Arc::new(Scheduler::Inline)))
+}
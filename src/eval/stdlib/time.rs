@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+use super::super::{
+    Interpreter, RuntimeError, builtins::expect_args, env::Env, executor::Work, value::Value,
+};
+
+pub fn register(env: &Env<'_>) {
+    env.define("sleep", Value::Native("sleep", sleep), false);
+}
+
+/// A future done once `ms` milliseconds elapsed, the others running meanwhile.
+fn sleep<'a>(interp: &Interpreter<'a>, args: Vec<Value<'a>>) -> Result<Value<'a>, RuntimeError> {
+    expect_args("sleep", &args, 1)?;
+    let ms = match args[0] {
+        Value::Int(ms) => ms.max(0) as u64,
+        ref other => {
+            return Err(RuntimeError::TypeError(format!(
+                "`sleep` expects an Int, found {}",
+                other.type_name()
+            )));
+        }
+    };
+    let timer = Work::Timer(Duration::from_millis(ms));
+    Ok(Value::Future(interp.executor.submit(timer)))
+}
@@ -0,0 +1,354 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    Interpreter, RuntimeError,
+    atomic::Atomic,
+    env::Env,
+    executor::Future,
+    lazy::{Sequence, Thunk},
+    runtime::{Scheduler, Task},
+};
+use crate::ast::{EnumVariant, Spanned, Statement, Type};
+use crate::vm::Closure;
+
+pub type NativeFn<'a> = fn(&Interpreter<'a>, Vec<Value<'a>>) -> Result<Value<'a>, RuntimeError>;
+
+#[derive(Debug, Clone)]
+pub enum Value<'a> {
+    Unit,
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(Arc<str>),
+    Array(Arc<Mutex<Vec<Value<'a>>>>),
+    Tuple(Arc<[Value<'a>]>),
+    // `None` as upper bound is an infinite range (`0..inf`)
+    Range(i64, Option<i64>),
+    Function(Arc<Function<'a>>),
+    // a function compiled to bytecode, run by the `vm`
+    Closure(Arc<Closure<'a>>),
+    Native(&'static str, NativeFn<'a>),
+    // a struct or enum declaration, used to build values (`Test.new(2)`, `Message.Quit`)
+    Type(Arc<TypeDef<'a>>),
+    Struct(Arc<StructValue<'a>>),
+    Variant(Arc<VariantValue<'a>>),
+    // an imported module, its items being reached with `module.item`
+    Module(Arc<Module<'a>>),
+    // string keyed entries, in insertion order, such as parsed JSON objects
+    Map(Arc<Mutex<Vec<Entry<'a>>>>),
+    // the cell of an `atomic` binding, the receiver of its methods
+    Atomic(Arc<Atomic<'a>>),
+    // a `lazy` binding not read yet, only ever held by an environment
+    Lazy(Arc<Thunk<'a>>),
+    // the pipeline of `map`, `filter` and `take` built on an infinite range
+    Sequence(Arc<Sequence<'a>>),
+    // the result of an `async fn` call or of an awaitable std function, once done
+    Future(Arc<Future<'a>>),
+    // the handle of a `spawn` block
+    Task(Arc<Task<'a>>),
+    // given to `spawn with` to run blocks elsewhere than on the worker pool
+    Scheduler(Arc<Scheduler<'a>>),
+}
+
+pub type Entry<'a> = (Arc<str>, Value<'a>);
+
+#[derive(Debug)]
+pub struct Function<'a> {
+    pub name: Option<&'a str>,
+    pub params: &'a [(&'a str, Option<Spanned<Type<'a>>>)],
+    pub body: &'a [Spanned<Statement<'a>>],
+    pub env: Env<'a>,
+    // an `async fn`, calling it returning a future
+    pub is_async: bool,
+}
+
+#[derive(Debug)]
+pub struct Module<'a> {
+    pub name: String,
+    pub env: Env<'a>,
+}
+
+#[derive(Debug)]
+pub enum TypeDef<'a> {
+    Struct {
+        name: &'a str,
+        fields: Vec<&'a str>,
+    },
+    Enum {
+        name: &'a str,
+        variants: Vec<(&'a str, VariantShape<'a>)>,
+    },
+}
+
+impl<'a> TypeDef<'a> {
+    /// The type declared by a `struct` or `enum` statement.
+    pub fn declared_by(stmt: &'a Statement<'a>) -> Option<Self> {
+        match stmt {
+            Statement::Struct { name, fields, .. } => Some(TypeDef::Struct {
+                name,
+                fields: fields.iter().map(|field| field.0).collect(),
+            }),
+            Statement::Enum { name, variants, .. } => Some(TypeDef::Enum {
+                name,
+                variants: variants
+                    .iter()
+                    .map(|variant| match &variant.node {
+                        EnumVariant::Unit(name) => (*name, VariantShape::Unit),
+                        EnumVariant::Tuple(name, types) => {
+                            (*name, VariantShape::Tuple(types.len()))
+                        }
+                        EnumVariant::Struct(name, fields) => (
+                            *name,
+                            VariantShape::Struct(fields.iter().map(|(f, _)| *f).collect()),
+                        ),
+                    })
+                    .collect(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum VariantShape<'a> {
+    Unit,
+    Tuple(usize),
+    Struct(Vec<&'a str>),
+}
+
+#[derive(Debug)]
+pub struct StructValue<'a> {
+    pub name: &'a str,
+    pub fields: Mutex<Vec<(&'a str, Value<'a>)>>,
+}
+
+#[derive(Debug)]
+pub struct VariantValue<'a> {
+    pub enum_name: &'a str,
+    pub name: &'a str,
+    pub payload: Payload<'a>,
+}
+
+#[derive(Debug)]
+pub enum Payload<'a> {
+    Unit,
+    Tuple(Vec<Value<'a>>),
+    Struct(Vec<(&'a str, Value<'a>)>),
+}
+
+impl<'a> Value<'a> {
+    pub fn str(s: impl Into<Arc<str>>) -> Self {
+        Value::Str(s.into())
+    }
+
+    pub fn array(values: Vec<Value<'a>>) -> Self {
+        Value::Array(Arc::new(Mutex::new(values)))
+    }
+
+    pub fn map(entries: Vec<Entry<'a>>) -> Self {
+        Value::Map(Arc::new(Mutex::new(entries)))
+    }
+
+    /// Builds a variant of one of the built-in enums (`Ok(x)`, `Err(e)`).
+    pub fn variant(enum_name: &'a str, name: &'a str, values: Vec<Value<'a>>) -> Self {
+        Value::Variant(Arc::new(VariantValue {
+            enum_name,
+            name,
+            payload: if values.is_empty() {
+                Payload::Unit
+            } else {
+                Payload::Tuple(values)
+            },
+        }))
+    }
+
+    /// The value itself, or the one held by an atomic binding.
+    pub fn load(self) -> Self {
+        match self {
+            Value::Atomic(cell) => cell.load(),
+            value => value,
+        }
+    }
+
+    /// Conditions must be booleans, there is no implicit truthiness.
+    pub fn as_bool(&self) -> Result<bool, RuntimeError> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            other => Err(RuntimeError::TypeError(format!(
+                "expected Bool, found {}",
+                other.type_name()
+            ))),
+        }
+    }
+
+    /// Name of the value's runtime type, as shown in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Unit => "Unit",
+            Value::Int(_) => "Int",
+            Value::Float(_) => "Float",
+            Value::Bool(_) => "Bool",
+            Value::Str(_) => "String",
+            Value::Array(_) => "Array",
+            Value::Tuple(_) => "Tuple",
+            Value::Range(..) => "Range",
+            Value::Function(_) | Value::Closure(_) | Value::Native(..) => "Function",
+            Value::Type(_) => "Type",
+            Value::Struct(_) => "Struct",
+            Value::Variant(_) => "Enum",
+            Value::Module(_) => "Module",
+            Value::Map(_) => "Map",
+            Value::Atomic(_) => "Atomic",
+            Value::Lazy(_) => "Lazy",
+            Value::Sequence(_) => "Sequence",
+            Value::Future(_) => "Future",
+            Value::Task(_) => "Task",
+            Value::Scheduler(_) => "Scheduler",
+        }
+    }
+}
+
+impl PartialEq for Value<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Unit, Value::Unit) => true,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::Int(a), Value::Float(b)) | (Value::Float(b), Value::Int(a)) => *a as f64 == *b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Array(a), Value::Array(b)) => {
+                Arc::ptr_eq(a, b) || *a.lock().unwrap() == *b.lock().unwrap()
+            }
+            (Value::Tuple(a), Value::Tuple(b)) => a == b,
+            (Value::Range(a, b), Value::Range(c, d)) => a == c && b == d,
+            (Value::Function(a), Value::Function(b)) => Arc::ptr_eq(a, b),
+            (Value::Closure(a), Value::Closure(b)) => Arc::ptr_eq(a, b),
+            (Value::Native(a, _), Value::Native(b, _)) => a == b,
+            (Value::Type(a), Value::Type(b)) => Arc::ptr_eq(a, b),
+            (Value::Module(a), Value::Module(b)) => Arc::ptr_eq(a, b),
+            (Value::Atomic(a), Value::Atomic(b)) => Arc::ptr_eq(a, b),
+            (Value::Lazy(a), Value::Lazy(b)) => Arc::ptr_eq(a, b),
+            (Value::Sequence(a), Value::Sequence(b)) => Arc::ptr_eq(a, b),
+            (Value::Future(a), Value::Future(b)) => Arc::ptr_eq(a, b),
+            (Value::Task(a), Value::Task(b)) => Arc::ptr_eq(a, b),
+            (Value::Scheduler(a), Value::Scheduler(b)) => Arc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => {
+                Arc::ptr_eq(a, b) || *a.lock().unwrap() == *b.lock().unwrap()
+            }
+            (Value::Struct(a), Value::Struct(b)) => {
+                Arc::ptr_eq(a, b)
+                    || (a.name == b.name && *a.fields.lock().unwrap() == *b.fields.lock().unwrap())
+            }
+            (Value::Variant(a), Value::Variant(b)) => {
+                a.enum_name == b.enum_name && a.name == b.name && a.payload == b.payload
+            }
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq for Payload<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Payload::Unit, Payload::Unit) => true,
+            (Payload::Tuple(a), Payload::Tuple(b)) => a == b,
+            (Payload::Struct(a), Payload::Struct(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+fn write_list<T: fmt::Display>(
+    f: &mut fmt::Formatter,
+    open: &str,
+    items: impl IntoIterator<Item = T>,
+    close: &str,
+) -> fmt::Result {
+    f.write_str(open)?;
+    for (i, item) in items.into_iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    f.write_str(close)
+}
+
+fn write_fields(f: &mut fmt::Formatter, fields: &[(&str, Value)]) -> fmt::Result {
+    write_list(
+        f,
+        " { ",
+        fields
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value)),
+        " }",
+    )
+}
+
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Unit => f.write_str("()"),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) if x.is_infinite() => {
+                f.write_str(if *x > 0.0 { "inf" } else { "-inf" })
+            }
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(s) => f.write_str(s),
+            Value::Array(values) => write_list(f, "[", values.lock().unwrap().iter(), "]"),
+            Value::Tuple(values) => write_list(f, "(", values.iter(), ")"),
+            Value::Range(start, Some(end)) => write!(f, "{}..{}", start, end),
+            Value::Range(start, None) => write!(f, "{}..inf", start),
+            Value::Function(function) => match function.name {
+                Some(name) => write!(f, "<fn {}>", name),
+                None => f.write_str("<closure>"),
+            },
+            Value::Closure(closure) => match closure.proto.name {
+                Some(name) => write!(f, "<fn {}>", name),
+                None => f.write_str("<closure>"),
+            },
+            Value::Native(name, _) => write!(f, "<native fn {}>", name),
+            Value::Module(module) => write!(f, "<module {}>", module.name),
+            Value::Atomic(cell) => write!(f, "{}", cell.load()),
+            Value::Lazy(_) => f.write_str("<lazy>"),
+            Value::Sequence(_) => f.write_str("<sequence>"),
+            Value::Future(future) => write!(f, "<future {}>", future.id),
+            Value::Task(task) => write!(f, "<task {}>", task.id),
+            Value::Scheduler(scheduler) => match scheduler.as_ref() {
+                Scheduler::Inline => f.write_str("<scheduler inline>"),
+                Scheduler::Group(_) => f.write_str("<task group>"),
+            },
+            Value::Map(entries) => write_list(
+                f,
+                "{",
+                entries
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value)),
+                "}",
+            ),
+            Value::Type(def) => match def.as_ref() {
+                TypeDef::Struct { name, .. } => write!(f, "<struct {}>", name),
+                TypeDef::Enum { name, .. } => write!(f, "<enum {}>", name),
+            },
+            Value::Struct(s) => {
+                f.write_str(s.name)?;
+                write_fields(f, &s.fields.lock().unwrap())
+            }
+            Value::Variant(v) => {
+                f.write_str(v.name)?;
+                match &v.payload {
+                    Payload::Unit => Ok(()),
+                    Payload::Tuple(values) => write_list(f, "(", values, ")"),
+                    Payload::Struct(fields) => write_fields(f, fields),
+                }
+            }
+        }
+    }
+}
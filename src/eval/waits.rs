@@ -0,0 +1,97 @@
+use std::{
+    sync::Mutex,
+    thread::{self, ThreadId},
+};
+
+use super::RuntimeError;
+
+/// A thread blocked until another one is done with something, such as a
+/// lazy binding it evaluates or a task it runs.
+struct Wait {
+    id: usize,
+    from: ThreadId,
+    to: ThreadId,
+    // the lazy binding `to` evaluates, if that is what `from` waits for
+    binding: Option<String>,
+}
+
+struct Graph {
+    waits: Vec<Wait>,
+    added: usize,
+}
+
+// the threads of every interpreter, whose ids are never reused
+static GRAPH: Mutex<Graph> = Mutex::new(Graph {
+    waits: Vec::new(),
+    added: 0,
+});
+
+/// A wait of the graph, removed once dropped.
+pub struct Waiting(usize);
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        let mut graph = GRAPH.lock().unwrap();
+        graph.waits.retain(|wait| wait.id != self.0);
+    }
+}
+
+/// Records that the current thread waits for `to`, evaluating `binding` if
+/// any, failing when `to` waits for the current thread in turn through a
+/// lazy binding.
+pub fn wait_for(to: ThreadId, binding: Option<&str>) -> Result<Waiting, RuntimeError> {
+    let from = thread::current().id();
+    let mut graph = GRAPH.lock().unwrap();
+    if let Some(cycle) = graph.path(to, from) {
+        // a cycle of joins and awaits only is a deadlock, left to the runtime
+        let binding = binding
+            .map(str::to_string)
+            .or_else(|| cycle.into_iter().flatten().next());
+        if let Some(binding) = binding {
+            return Err(RuntimeError::LazyCycle(binding));
+        }
+    }
+    Ok(graph.add(from, to, binding))
+}
+
+/// Records that `from` waits for the current thread, such as the thread
+/// driving the futures for the one holding the baton.
+pub fn waited_by(from: ThreadId) -> Waiting {
+    GRAPH
+        .lock()
+        .unwrap()
+        .add(from, thread::current().id(), None)
+}
+
+impl Graph {
+    fn add(&mut self, from: ThreadId, to: ThreadId, binding: Option<&str>) -> Waiting {
+        self.added += 1;
+        self.waits.push(Wait {
+            id: self.added,
+            from,
+            to,
+            binding: binding.map(str::to_string),
+        });
+        Waiting(self.added)
+    }
+
+    // the bindings along a path of waits from `from` to `to`, if there is one
+    fn path(&self, from: ThreadId, to: ThreadId) -> Option<Vec<Option<String>>> {
+        let mut stack = vec![(from, Vec::new())];
+        let mut seen = vec![from];
+        while let Some((thread, bindings)) = stack.pop() {
+            if thread == to {
+                return Some(bindings);
+            }
+            for wait in self.waits.iter().filter(|wait| wait.from == thread) {
+                if !seen.contains(&wait.to) {
+                    seen.push(wait.to);
+                    let mut bindings = bindings.clone();
+                    bindings.push(wait.binding.clone());
+                    stack.push((wait.to, bindings));
+                }
+            }
+        }
+        None
+    }
+}
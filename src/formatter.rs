@@ -0,0 +1,480 @@
+use std::{collections::HashSet, fmt};
+
+use crate::{
+    Lexer, Token,
+    ast::{Expr, Match, Program, Spanned, Statement},
+    diagnostics::Diagnostic,
+    parser::parse_program,
+};
+
+const INDENT: &str = "    ";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatError {
+    // the lexing and syntax errors of the source, which is left as is
+    Syntax(Vec<Diagnostic>),
+    // the formatted source parses to another program, a bug of the formatter
+    Mismatch,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::Syntax(diagnostics) => {
+                write!(f, "{} syntax error(s), not formatted", diagnostics.len())
+            }
+            FormatError::Mismatch => write!(f, "formatting would change the program"),
+        }
+    }
+}
+
+/// Formats `source` the canonical way, keeping its comments where they are.
+///
+/// Statements and match arms go on their own lines, blocks are indented by
+/// four spaces and operators are surrounded by one space. Lists keep being
+/// written one item per line when their items were, and single blank lines
+/// between statements are kept. Formatting a formatted source changes
+/// nothing.
+pub fn format(source: &str) -> Result<String, FormatError> {
+    let (program, errors) = parse_program(source);
+    if !errors.is_empty() {
+        let diagnostics = errors
+            .iter()
+            .map(|e| Diagnostic::from_parse_error(e, source))
+            .collect();
+        return Err(FormatError::Syntax(diagnostics));
+    }
+
+    let mut lines = Lines::default();
+    lines.statements(&program.statements);
+    let tokens: Vec<_> = Lexer::new(source)
+        .flatten()
+        .map(|(start, token, end)| Item {
+            token,
+            start,
+            end,
+            text: &source[start..end],
+        })
+        .collect();
+    let formatted = Printer::new(&tokens, &lines).print();
+
+    let (reparsed, errors) = parse_program(&formatted);
+    if !errors.is_empty() || shape(&reparsed) != shape(&program) {
+        return Err(FormatError::Mismatch);
+    }
+    Ok(formatted)
+}
+
+/// The program without its spans, to compare programs parsed from different
+/// layouts.
+pub(crate) fn shape(program: &Program) -> serde_json::Value {
+    fn strip(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(object) => {
+                object.remove("span");
+                object.values_mut().for_each(strip);
+            }
+            serde_json::Value::Array(values) => values.iter_mut().for_each(strip),
+            _ => {}
+        }
+    }
+    let mut value = serde_json::to_value(program).unwrap();
+    strip(&mut value);
+    value
+}
+
+/// Where the statements and match arms of a program start and end, which the
+/// tokens alone do not tell.
+#[derive(Default)]
+pub(crate) struct Lines {
+    pub(crate) starts: HashSet<usize>,
+    pub(crate) ends: HashSet<usize>,
+    arms: HashSet<usize>,
+}
+
+impl Lines {
+    pub(crate) fn statements(&mut self, stmts: &[Spanned<Statement>]) {
+        for stmt in stmts {
+            self.starts.insert(stmt.span.start);
+            self.ends.insert(stmt.span.end);
+            self.statement(&stmt.node);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::FnDecl { body, .. } => self.statements(body),
+            Statement::Let { value, .. } => self.expr(value),
+            Statement::Match(m) => self.match_(m),
+            Statement::Spawn { body, with } => {
+                if let Some(with) = with {
+                    self.expr(with);
+                }
+                self.statements(body);
+            }
+            Statement::ForLoop { range, body, .. } => {
+                self.expr(range);
+                self.statements(body);
+            }
+            Statement::WhileLoop { condition, body } => {
+                self.expr(condition);
+                self.statements(body);
+            }
+            Statement::If {
+                condition,
+                body,
+                else_ifs,
+                else_body,
+            } => {
+                self.expr(condition);
+                self.statements(body);
+                for (condition, body) in else_ifs {
+                    self.expr(condition);
+                    self.statements(body);
+                }
+                if let Some(body) = else_body {
+                    self.statements(body);
+                }
+            }
+            Statement::Expr(expr) | Statement::Return(expr) => self.expr(expr),
+            Statement::Break
+            | Statement::TypeAlias { .. }
+            | Statement::Struct { .. }
+            | Statement::Enum { .. }
+            | Statement::Use { .. }
+            | Statement::Error => {}
+        }
+    }
+
+    fn match_(&mut self, m: &Match) {
+        self.expr(&m.expr);
+        for arm in &m.arms {
+            self.arms.insert(arm.pattern.span.start);
+            if let Some(guard) = &arm.guard {
+                self.expr(guard);
+            }
+            self.statements(&arm.body);
+        }
+    }
+
+    // string interpolations are left out, being printed as they are written
+    fn expr(&mut self, expr: &Spanned<Expr>) {
+        match &expr.node {
+            Expr::Literal(_) | Expr::Ident(_) => {}
+            Expr::Array(items) | Expr::Tuple(items) => items.iter().for_each(|e| self.expr(e)),
+            Expr::Match(m) => self.match_(m),
+            Expr::Awaitable(e) | Expr::Unary(_, e) | Expr::FieldAccess(e, _) => self.expr(e),
+            Expr::Binary(lhs, _, rhs)
+            | Expr::Pipe(lhs, rhs)
+            | Expr::Range(lhs, rhs)
+            | Expr::Assign(lhs, _, rhs)
+            | Expr::Index(lhs, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::Call(callee, args) => {
+                self.expr(callee);
+                args.iter().for_each(|e| self.expr(e));
+            }
+            Expr::Closure(_, body) => self.statements(body),
+            Expr::Spawn(with, body) => {
+                if let Some(with) = with {
+                    self.expr(with);
+                }
+                self.statements(body);
+            }
+        }
+    }
+}
+
+struct Item<'i> {
+    token: Token<'i>,
+    start: usize,
+    end: usize,
+    text: &'i str,
+}
+
+impl Item<'_> {
+    fn is_comment(&self) -> bool {
+        matches!(
+            self.token,
+            Token::LineComment(_) | Token::BlockComment(_) | Token::DocComment(_)
+        )
+    }
+
+    // comments and line ends are trivia
+    fn is_trivia(&self) -> bool {
+        self.is_comment() || self.token == Token::LineEnd
+    }
+
+    fn opens(&self) -> bool {
+        matches!(self.token, Token::LParen | Token::LBracket | Token::LBrace)
+    }
+
+    fn closes(&self) -> bool {
+        matches!(self.token, Token::RParen | Token::RBracket | Token::RBrace)
+    }
+
+    // whether a `-` or `|` after this token is a binary operator
+    fn ends_operand(&self) -> bool {
+        matches!(
+            self.token,
+            Token::Ident(_)
+                | Token::Int(_)
+                | Token::Float(_)
+                | Token::Binary(_)
+                | Token::Octal(_)
+                | Token::Hex(_)
+                | Token::String(_)
+                | Token::True
+                | Token::False
+                | Token::Inf
+                | Token::Wildcard
+                | Token::RParen
+                | Token::RBracket
+                | Token::RBrace
+        )
+    }
+}
+
+// how a token was printed, for the spacing of the next one
+#[derive(Clone, Copy, PartialEq)]
+enum Role {
+    Plain,
+    Unary,
+    ClosureOpen,
+    ClosureClose,
+}
+
+struct Printer<'a, 'i> {
+    tokens: &'a [Item<'i>],
+    // tokens starting a statement or a match arm, always on a line of their own
+    leading: Vec<bool>,
+    // brackets whose items are printed on their own lines, by the index of
+    // both the opening and the closing one
+    broken: Vec<bool>,
+    out: String,
+    depth: usize,
+    // opening brackets still open, and whether they are broken
+    groups: Vec<bool>,
+    // the group depth of the `|` opening the parameters of a closure
+    closure: Option<usize>,
+}
+
+impl<'a, 'i> Printer<'a, 'i> {
+    fn new(tokens: &'a [Item<'i>], lines: &Lines) -> Self {
+        let mut leading = vec![false; tokens.len()];
+        let mut previous: Option<&Item> = None;
+        for (i, item) in tokens.iter().enumerate() {
+            if item.is_trivia() {
+                continue;
+            }
+            // match arm bodies and closures without braces are single statements
+            let statement = lines.starts.contains(&item.start)
+                && previous.is_none_or(|p| p.token == Token::LBrace || lines.ends.contains(&p.end));
+            leading[i] = statement || lines.arms.contains(&item.start);
+            previous = Some(item);
+        }
+
+        let mut printer = Printer {
+            tokens,
+            broken: vec![false; tokens.len()],
+            leading,
+            out: String::new(),
+            depth: 0,
+            groups: Vec::new(),
+            closure: None,
+        };
+        printer.find_broken();
+        printer
+    }
+
+    /// A bracket is broken when it holds statements, match arms or comments
+    /// needing a line of their own, or when it was written with a line break
+    /// after it and, unless it is a brace, after one of its commas.
+    fn find_broken(&mut self) {
+        let tokens = self.tokens;
+        // the opening brackets, whether they hold a line, and a comma followed by a line break
+        let mut open: Vec<(usize, bool, bool)> = Vec::new();
+        for (i, item) in tokens.iter().enumerate() {
+            let next = tokens.get(i + 1);
+            let line_end_after = next.is_some_and(|n| n.token == Token::LineEnd);
+            if item.closes()
+                && let Some((start, lines, comma)) = open.pop()
+            {
+                // braces only need the line break, parentheses and brackets a list
+                let written = tokens[start + 1].token == Token::LineEnd
+                    && start + 2 < i
+                    && (comma || tokens[start].token == Token::LBrace);
+                self.broken[start] = lines || written;
+                self.broken[i] = lines || written;
+            }
+            let Some((_, lines, comma)) = open.last_mut() else {
+                if item.opens() {
+                    open.push((i, false, false));
+                }
+                continue;
+            };
+            let line_end_before = i > 0 && tokens[i - 1].token == Token::LineEnd;
+            *lines |= self.leading[i]
+                || matches!(item.token, Token::LineComment(_) | Token::DocComment(_))
+                || (item.is_comment() && (line_end_before || line_end_after));
+            *comma |= item.token == Token::Comma && line_end_after;
+            if item.opens() {
+                open.push((i, false, false));
+            }
+        }
+    }
+
+    fn print(mut self) -> String {
+        let tokens = self.tokens;
+        // the last token printed, the last one which is not a comment, and how it was printed
+        let mut last: Option<&Item> = None;
+        let mut last_code: Option<usize> = None;
+        let mut role = Role::Plain;
+        // line breaks met since the last token printed
+        let mut breaks = 0;
+        // whether the last token printed is the first of its line
+        let mut first = true;
+
+        for (i, item) in tokens.iter().enumerate() {
+            if item.token == Token::LineEnd {
+                breaks += item.text.matches('\n').count().max(1);
+                continue;
+            }
+
+            let comment = item.is_comment();
+            let newline = match last {
+                None => false,
+                Some(last) => {
+                    matches!(last.token, Token::LineComment(_) | Token::DocComment(_))
+                        || ((comment || last.is_comment()) && breaks > 0)
+                        || (!comment && self.closes_broken(i))
+                        // code may follow a block comment starting its line
+                        || (!comment
+                            && self.starts_line(i, last_code)
+                            && !(last.is_comment() && first))
+                }
+            };
+            first = newline || last.is_none();
+
+            if newline {
+                if self.closes_broken(i) {
+                    self.depth -= 1;
+                }
+                // a single blank line is kept, though not at the start of a block
+                let blank =
+                    breaks > 1 && !self.closes_broken(i) && last.is_some_and(|l| !l.opens());
+                self.out.push('\n');
+                if blank {
+                    self.out.push('\n');
+                }
+                let indent = if comment {
+                    self.comment_indent(i, last_code)
+                } else {
+                    self.indent(i, last_code)
+                };
+                self.out.push_str(&INDENT.repeat(indent));
+            } else if let Some(last) = last {
+                let next_role = self.role(i, last_code);
+                let spaced = last.is_comment()
+                    || comment
+                    || !no_space(&last.token, role, &item.token, next_role);
+                if spaced {
+                    self.out.push(' ');
+                }
+            }
+
+            if comment {
+                self.out.push_str(item.text.trim_end());
+                last = Some(item);
+                breaks = 0;
+                continue;
+            }
+
+            let next_role = self.role(i, last_code);
+            if next_role == Role::ClosureOpen {
+                self.closure = Some(self.groups.len());
+            } else if next_role == Role::ClosureClose {
+                self.closure = None;
+            }
+            self.out.push_str(item.text);
+            if item.opens() {
+                self.groups.push(self.broken[i]);
+                if self.broken[i] {
+                    self.depth += 1;
+                }
+            } else if item.closes() {
+                self.groups.pop();
+            }
+
+            role = next_role;
+            last = Some(item);
+            last_code = Some(i);
+            breaks = 0;
+        }
+
+        self.out.push('\n');
+        self.out
+    }
+
+    fn closes_broken(&self, i: usize) -> bool {
+        self.tokens[i].closes() && self.broken[i]
+    }
+
+    // the first token of a statement, a match arm or an item of a broken list
+    fn starts_line(&self, i: usize, last_code: Option<usize>) -> bool {
+        self.leading[i]
+            || last_code.is_some_and(|l| {
+                let last = &self.tokens[l];
+                (last.opens() && self.broken[l])
+                    || (last.token == Token::Comma
+                        && self.groups.last() == Some(&true)
+                        && self.closure.is_none())
+            })
+    }
+
+    // lines continuing a statement are indented once more
+    fn indent(&self, i: usize, last_code: Option<usize>) -> usize {
+        if self.closes_broken(i) || self.starts_line(i, last_code) {
+            self.depth
+        } else {
+            self.depth + 1
+        }
+    }
+
+    // comments are indented like the code following them, or like the
+    // items of the list they end
+    fn comment_indent(&self, i: usize, last_code: Option<usize>) -> usize {
+        match self.tokens[i..].iter().position(|item| !item.is_trivia()) {
+            Some(next) if self.closes_broken(i + next) => self.depth,
+            Some(next) => self.indent(i + next, last_code),
+            None => self.depth,
+        }
+    }
+
+    fn role(&self, i: usize, last_code: Option<usize>) -> Role {
+        let after_operand = last_code.is_some_and(|l| self.tokens[l].ends_operand());
+        match self.tokens[i].token {
+            Token::BitOr if self.closure == Some(self.groups.len()) => Role::ClosureClose,
+            Token::BitOr if !after_operand => Role::ClosureOpen,
+            Token::Minus | Token::Plus if !after_operand => Role::Unary,
+            Token::Not | Token::BitNot => Role::Unary,
+            _ => Role::Plain,
+        }
+    }
+}
+
+/// Whether two tokens on the same line are written next to each other.
+fn no_space(last: &Token, last_role: Role, next: &Token, next_role: Role) -> bool {
+    use Token::*;
+    matches!(last_role, Role::Unary | Role::ClosureOpen)
+        || next_role == Role::ClosureClose
+        || matches!(last, LParen | LBracket | Dot | DoubleColon | Range)
+        || matches!(next, RParen | RBracket | Comma | Dot | Colon | DoubleColon | Range)
+        || (*last == LBrace && *next == RBrace)
+        // calls and indexing
+        || (matches!(next, LParen | LBracket)
+            && last_role != Role::ClosureClose
+            && matches!(last, Ident(_) | Wildcard | RParen | RBracket))
+}
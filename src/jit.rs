@@ -0,0 +1,853 @@
+//! Native code for the functions of a program computing on `Int`, `Float` and
+//! `Bool` only, generated with Cranelift.
+//!
+//! A top-level function is compiled when its parameters and return type are
+//! annotated with these types, and its body sticks to `let`, assignments,
+//! arithmetic, comparisons, `if`, `while`, `for` over a range and calls to
+//! other compiled functions. Every other function keeps being interpreted.
+
+use std::{collections::HashMap, mem::ManuallyDrop};
+
+use cranelift_codegen::{
+    Context,
+    ir::{
+        AbiParam, Block, InstBuilder, MemFlags, Signature, Type, Value as Ir,
+        condcodes::{FloatCC, IntCC},
+        types,
+    },
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{FuncId, Linkage, Module, default_libcall_names};
+
+use crate::{
+    ast::{
+        BinOp, Expr, LetName, Literal, Program, Spanned, Statement, Type as Annotation, UnaryOp,
+    },
+    eval::{RuntimeError, value::Value},
+    typeck::{self, ty::Ty as Checked},
+};
+
+// returned by compiled code along with the result
+const OK: u8 = 0;
+const DIVISION_BY_ZERO: u8 = 1;
+const OVERFLOW: u8 = 2;
+
+/// Reads the arguments from the first pointer and writes the result to the second.
+type Entry = unsafe extern "C" fn(*const u64, *mut u64) -> u8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Ty {
+    Int,
+    Float,
+    Bool,
+}
+
+impl Ty {
+    fn of(annotation: &Option<Spanned<Annotation>>) -> Option<Ty> {
+        // spelled like the type checker accepts them
+        let Some(Annotation::Ident(name)) = annotation.as_ref().map(|ty| &ty.node) else {
+            return None;
+        };
+        match typeck::builtin(name)? {
+            Checked::Int => Some(Ty::Int),
+            Checked::Float => Some(Ty::Float),
+            Checked::Bool => Some(Ty::Bool),
+            _ => None,
+        }
+    }
+
+    fn ir(self) -> Type {
+        match self {
+            Ty::Int => types::I64,
+            Ty::Float => types::F64,
+            Ty::Bool => types::I8,
+        }
+    }
+
+    fn bits(self, value: &Value) -> Option<u64> {
+        match (self, value) {
+            (Ty::Int, Value::Int(i)) => Some(*i as u64),
+            (Ty::Float, Value::Float(f)) => Some(f.to_bits()),
+            (Ty::Bool, Value::Bool(b)) => Some(*b as u64),
+            _ => None,
+        }
+    }
+
+    fn value<'a>(self, bits: u64) -> Value<'a> {
+        match self {
+            Ty::Int => Value::Int(bits as i64),
+            Ty::Float => Value::Float(f64::from_bits(bits)),
+            Ty::Bool => Value::Bool(bits != 0),
+        }
+    }
+}
+
+/// A function whose signature can be compiled, until its body turns out not to be.
+struct Candidate<'a> {
+    id: FuncId,
+    params: Vec<(&'a str, Ty)>,
+    ret: Ty,
+    body: &'a [Spanned<Statement<'a>>],
+}
+
+struct Compiled {
+    name: String,
+    params: Vec<Ty>,
+    ret: Ty,
+    entry: Entry,
+}
+
+/// The compiled functions of a program, looked up by the interpreter on calls.
+pub struct Jit {
+    module: ManuallyDrop<JITModule>,
+    // by address of the function body
+    functions: HashMap<usize, Compiled>,
+}
+
+impl Jit {
+    /// Compiles every top-level function of `program` that can be.
+    pub fn new(program: &Program) -> Self {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").unwrap();
+        let isa = cranelift_native::builder()
+            .expect("host machine is not supported by Cranelift")
+            .finish(settings::Flags::new(flags))
+            .unwrap();
+        let mut module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+
+        let candidates = candidates(&mut module, program);
+        let mut ctx = module.make_context();
+        let mut functions = HashMap::new();
+        for (name, function) in &candidates {
+            translate(&mut module, &mut ctx, &candidates, function);
+            module
+                .define_function(function.id, &mut ctx)
+                .expect("generated code should be valid");
+            module.clear_context(&mut ctx);
+
+            let entry = entry(&mut module, &mut ctx, name, function);
+            functions.insert(function.body.as_ptr() as usize, (name, entry));
+        }
+        module.finalize_definitions().unwrap();
+
+        let functions = functions
+            .into_iter()
+            .map(|(key, (name, entry))| {
+                let function = &candidates[name];
+                let code = module.get_finalized_function(entry);
+                let compiled = Compiled {
+                    name: name.to_string(),
+                    params: function.params.iter().map(|(_, ty)| *ty).collect(),
+                    ret: function.ret,
+                    // SAFETY: the entry was built with the signature of `Entry`
+                    entry: unsafe { std::mem::transmute::<*const u8, Entry>(code) },
+                };
+                (key, compiled)
+            })
+            .collect();
+        Self {
+            module: ManuallyDrop::new(module),
+            functions,
+        }
+    }
+
+    /// The names of the compiled functions, sorted.
+    pub fn compiled(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.functions.values().map(|f| f.name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    /// Runs the native code of the function with this body, if it was compiled
+    /// and the arguments have the types it was compiled for.
+    pub fn call<'a>(
+        &self,
+        body: &[Spanned<Statement>],
+        args: &[Value<'a>],
+    ) -> Option<Result<Value<'a>, RuntimeError>> {
+        let function = self.functions.get(&(body.as_ptr() as usize))?;
+        if function.params.len() != args.len() {
+            return None;
+        }
+        let args = function
+            .params
+            .iter()
+            .zip(args)
+            .map(|(ty, arg)| ty.bits(arg))
+            .collect::<Option<Vec<_>>>()?;
+        let mut out = 0;
+        // SAFETY: the entry reads one value per parameter and writes one result
+        let status = unsafe { (function.entry)(args.as_ptr(), &mut out) };
+        Some(match status {
+            OK => Ok(function.ret.value(out)),
+            DIVISION_BY_ZERO => Err(RuntimeError::DivisionByZero),
+            _ => Err(RuntimeError::IntegerOverflow),
+        })
+    }
+}
+
+// SAFETY: once compiled, the module is only touched again when dropped, and
+// the finalized code behind the entries is read-only, so spawned blocks can
+// call it from any thread
+unsafe impl Sync for Jit {}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        // SAFETY: the pointers to the code are dropped along with `functions`
+        unsafe { ManuallyDrop::take(&mut self.module).free_memory() }
+    }
+}
+
+/// Declares the functions with a primitive signature, then drops those whose
+/// body cannot be compiled until the remaining ones only call each other.
+fn candidates<'a>(
+    module: &mut JITModule,
+    program: &'a Program<'a>,
+) -> HashMap<&'a str, Candidate<'a>> {
+    // a name bound twice at the top level is looked up at runtime
+    let mut counts = HashMap::new();
+    for stmt in &program.statements {
+        match &stmt.node {
+            Statement::FnDecl { name, .. }
+            | Statement::Let {
+                name: LetName::Ident(name),
+                ..
+            } => *counts.entry(*name).or_insert(0) += 1,
+            _ => {}
+        }
+    }
+
+    let mut candidates = HashMap::new();
+    for stmt in &program.statements {
+        let Statement::FnDecl {
+            name,
+            _async: false,
+            params,
+            return_type,
+            body,
+            ..
+        } = &stmt.node
+        else {
+            continue;
+        };
+        let params = params
+            .iter()
+            .map(|(name, ty)| Some((*name, Ty::of(ty)?)))
+            .collect::<Option<Vec<_>>>();
+        if let (Some(params), Some(ret), 1) = (params, Ty::of(return_type), counts[name]) {
+            let types: Vec<_> = params.iter().map(|(_, ty)| *ty).collect();
+            let signature = signature(module, &types, ret);
+            let id = module
+                .declare_function(name, Linkage::Local, &signature)
+                .unwrap();
+            candidates.insert(
+                *name,
+                Candidate {
+                    id,
+                    params,
+                    ret,
+                    body,
+                },
+            );
+        }
+    }
+
+    let mut ctx = module.make_context();
+    loop {
+        let rejected: Vec<_> = candidates
+            .iter()
+            .filter(|(_, function)| {
+                let compiled = translate(module, &mut ctx, &candidates, function);
+                module.clear_context(&mut ctx);
+                !compiled
+            })
+            .map(|(name, _)| *name)
+            .collect();
+        if rejected.is_empty() {
+            return candidates;
+        }
+        for name in rejected {
+            candidates.remove(name);
+        }
+    }
+}
+
+/// The compiled functions return the status of the call after their result.
+fn signature(module: &JITModule, params: &[Ty], ret: Ty) -> Signature {
+    let mut signature = module.make_signature();
+    signature
+        .params
+        .extend(params.iter().map(|ty| AbiParam::new(ty.ir())));
+    signature.returns.push(AbiParam::new(ret.ir()));
+    signature.returns.push(AbiParam::new(types::I8));
+    signature
+}
+
+/// Builds the code of `function` into `ctx`, returning whether it could.
+fn translate<'a>(
+    module: &mut JITModule,
+    ctx: &mut Context,
+    candidates: &HashMap<&'a str, Candidate<'a>>,
+    function: &Candidate<'a>,
+) -> bool {
+    let types: Vec<_> = function.params.iter().map(|(_, ty)| *ty).collect();
+    ctx.func.signature = signature(module, &types, function.ret);
+    let mut builder_ctx = FunctionBuilderContext::new();
+    let mut builder = FunctionBuilder::new(&mut ctx.func, &mut builder_ctx);
+    let start = builder.create_block();
+    builder.append_block_params_for_function_params(start);
+    builder.switch_to_block(start);
+    builder.seal_block(start);
+    let fail = builder.create_block();
+    builder.append_block_param(fail, types::I8);
+
+    let args = builder.block_params(start).to_vec();
+    let mut translator = Translator {
+        builder,
+        module,
+        candidates,
+        scopes: vec![HashMap::new()],
+        vars: 0,
+        ret: function.ret,
+        fail,
+        loops: Vec::new(),
+    };
+    for ((name, ty), arg) in function.params.iter().zip(args) {
+        translator.bind(name, *ty, false, arg);
+    }
+    // the body must leave the function on every path
+    if translator.block(function.body, true) != Some(true) {
+        return false;
+    }
+
+    let builder = &mut translator.builder;
+    builder.switch_to_block(fail);
+    builder.seal_block(fail);
+    let status = builder.block_params(fail)[0];
+    let zero = match function.ret {
+        Ty::Int => builder.ins().iconst(types::I64, 0),
+        Ty::Float => builder.ins().f64const(0.0),
+        Ty::Bool => builder.ins().iconst(types::I8, 0),
+    };
+    builder.ins().return_(&[zero, status]);
+    translator.builder.finalize();
+    true
+}
+
+/// Builds the function called from Rust, moving the arguments and the result
+/// of `function` through memory.
+fn entry(module: &mut JITModule, ctx: &mut Context, name: &str, function: &Candidate) -> FuncId {
+    let pointer = module.target_config().pointer_type();
+    let mut signature = module.make_signature();
+    signature.params.push(AbiParam::new(pointer));
+    signature.params.push(AbiParam::new(pointer));
+    signature.returns.push(AbiParam::new(types::I8));
+    let id = module
+        .declare_function(&format!("{}.entry", name), Linkage::Local, &signature)
+        .unwrap();
+
+    ctx.func.signature = signature;
+    let mut builder_ctx = FunctionBuilderContext::new();
+    let mut builder = FunctionBuilder::new(&mut ctx.func, &mut builder_ctx);
+    let block = builder.create_block();
+    builder.append_block_params_for_function_params(block);
+    builder.switch_to_block(block);
+    builder.seal_block(block);
+    let (args, out) = (
+        builder.block_params(block)[0],
+        builder.block_params(block)[1],
+    );
+
+    let flags = MemFlags::trusted();
+    let values: Vec<_> = function
+        .params
+        .iter()
+        .enumerate()
+        .map(|(i, (_, ty))| {
+            let offset = i as i32 * 8;
+            match ty {
+                Ty::Bool => {
+                    let bits = builder.ins().load(types::I64, flags, args, offset);
+                    builder.ins().ireduce(types::I8, bits)
+                }
+                ty => builder.ins().load(ty.ir(), flags, args, offset),
+            }
+        })
+        .collect();
+    let callee = module.declare_func_in_func(function.id, builder.func);
+    let call = builder.ins().call(callee, &values);
+    let (result, status) = (builder.inst_results(call)[0], builder.inst_results(call)[1]);
+    let result = match function.ret {
+        Ty::Bool => builder.ins().uextend(types::I64, result),
+        _ => result,
+    };
+    builder.ins().store(flags, result, out, 0);
+    builder.ins().return_(&[status]);
+    builder.finalize();
+
+    module
+        .define_function(id, ctx)
+        .expect("generated code should be valid");
+    module.clear_context(ctx);
+    id
+}
+
+#[derive(Clone, Copy)]
+struct Binding {
+    var: Variable,
+    ty: Ty,
+    mutable: bool,
+}
+
+/// Translates the body of a function, every method returning `None` on what
+/// cannot be compiled.
+struct Translator<'a, 'f> {
+    builder: FunctionBuilder<'f>,
+    module: &'f mut JITModule,
+    candidates: &'f HashMap<&'a str, Candidate<'a>>,
+    scopes: Vec<HashMap<&'a str, Binding>>,
+    vars: u32,
+    ret: Ty,
+    // returns the status in its parameter
+    fail: Block,
+    // the blocks following the loops, where `break` jumps
+    loops: Vec<Block>,
+}
+
+impl<'a> Translator<'a, '_> {
+    fn bind(&mut self, name: &'a str, ty: Ty, mutable: bool, value: Ir) {
+        let var = Variable::from_u32(self.vars);
+        self.vars += 1;
+        self.builder.declare_var(var, ty.ir());
+        self.builder.def_var(var, value);
+        let binding = Binding { var, ty, mutable };
+        self.scopes.last_mut().unwrap().insert(name, binding);
+    }
+
+    fn lookup(&self, name: &str) -> Option<Binding> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    fn fail_if(&mut self, condition: Ir, status: u8) {
+        let status = self.builder.ins().iconst(types::I8, i64::from(status));
+        self.fail_with(condition, status);
+    }
+
+    fn fail_with(&mut self, condition: Ir, status: Ir) {
+        let next = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(condition, self.fail, &[status], next, &[]);
+        self.builder.switch_to_block(next);
+        self.builder.seal_block(next);
+    }
+
+    fn return_value(&mut self, value: Ir) {
+        let ok = self.builder.ins().iconst(types::I8, i64::from(OK));
+        self.builder.ins().return_(&[value, ok]);
+    }
+
+    /// Returns whether the block left the function or a loop, `tail` making
+    /// it return the value of its last statement.
+    fn block(&mut self, stmts: &'a [Spanned<Statement<'a>>], tail: bool) -> Option<bool> {
+        self.scopes.push(HashMap::new());
+        let mut left = false;
+        for (i, stmt) in stmts.iter().enumerate() {
+            // the statements after it never run
+            if self.statement(stmt, tail && i == stmts.len() - 1)? {
+                left = true;
+                break;
+            }
+        }
+        self.scopes.pop();
+        Some(left)
+    }
+
+    fn statement(&mut self, stmt: &'a Spanned<Statement<'a>>, tail: bool) -> Option<bool> {
+        match &stmt.node {
+            Statement::Let {
+                atomic: false,
+                lazy: false,
+                mutable,
+                name: LetName::Ident(name),
+                ty,
+                value,
+            } => {
+                let (value, found) = self.expr(value)?;
+                if ty.is_some() && Ty::of(ty) != Some(found) {
+                    return None;
+                }
+                self.bind(name, found, *mutable, value);
+                Some(false)
+            }
+            Statement::Expr(expr) => match &expr.node {
+                Expr::Assign(target, op, value) => {
+                    self.assign(target, *op, value)?;
+                    Some(false)
+                }
+                _ => {
+                    let (value, ty) = self.expr(expr)?;
+                    if !tail {
+                        return Some(false);
+                    }
+                    (ty == self.ret).then(|| self.return_value(value))?;
+                    Some(true)
+                }
+            },
+            Statement::Return(expr) => {
+                let (value, ty) = self.expr(expr)?;
+                (ty == self.ret).then(|| self.return_value(value))?;
+                Some(true)
+            }
+            Statement::Break => {
+                let exit = *self.loops.last()?;
+                self.builder.ins().jump(exit, &[]);
+                Some(true)
+            }
+            Statement::If {
+                condition,
+                body,
+                else_ifs,
+                else_body,
+            } => {
+                let mut merge = None;
+                let branches =
+                    std::iter::once((condition, body)).chain(else_ifs.iter().map(|(c, b)| (c, b)));
+                for (condition, body) in branches {
+                    let condition = self.condition(condition)?;
+                    let (then, next) = (self.builder.create_block(), self.builder.create_block());
+                    self.builder.ins().brif(condition, then, &[], next, &[]);
+                    self.builder.switch_to_block(then);
+                    self.builder.seal_block(then);
+                    if !self.block(body, tail)? {
+                        self.fall_through(&mut merge);
+                    }
+                    self.builder.switch_to_block(next);
+                    self.builder.seal_block(next);
+                }
+                match else_body {
+                    Some(body) => {
+                        if !self.block(body, tail)? {
+                            self.fall_through(&mut merge);
+                        }
+                    }
+                    None => self.fall_through(&mut merge),
+                }
+                match merge {
+                    // the value of the statement would be `()`
+                    Some(_) if tail => None,
+                    Some(merge) => {
+                        self.builder.switch_to_block(merge);
+                        self.builder.seal_block(merge);
+                        Some(false)
+                    }
+                    None => Some(true),
+                }
+            }
+            Statement::WhileLoop { condition, body } => {
+                let (header, exit) = (self.builder.create_block(), self.builder.create_block());
+                self.builder.ins().jump(header, &[]);
+                self.builder.switch_to_block(header);
+                let condition = self.condition(condition)?;
+                let start = self.builder.create_block();
+                self.builder.ins().brif(condition, start, &[], exit, &[]);
+                self.builder.switch_to_block(start);
+                self.builder.seal_block(start);
+                self.loop_body(body, header, exit, |_| {})
+            }
+            Statement::ForLoop {
+                iterator,
+                range,
+                body,
+            } => {
+                let Expr::Range(start, end) = &range.node else {
+                    return None;
+                };
+                let (Some((start, Ty::Int)), Some((end, Ty::Int))) =
+                    (self.expr(start), self.expr(end))
+                else {
+                    return None;
+                };
+                let counter = Variable::from_u32(self.vars);
+                self.vars += 1;
+                self.builder.declare_var(counter, types::I64);
+                self.builder.def_var(counter, start);
+
+                let (header, exit) = (self.builder.create_block(), self.builder.create_block());
+                self.builder.ins().jump(header, &[]);
+                self.builder.switch_to_block(header);
+                let i = self.builder.use_var(counter);
+                let condition = self.builder.ins().icmp(IntCC::SignedLessThan, i, end);
+                let start = self.builder.create_block();
+                self.builder.ins().brif(condition, start, &[], exit, &[]);
+                self.builder.switch_to_block(start);
+                self.builder.seal_block(start);
+
+                self.scopes.push(HashMap::new());
+                self.bind(iterator, Ty::Int, false, i);
+                // `i` is below `end`, so this cannot overflow
+                let left = self.loop_body(body, header, exit, |builder| {
+                    let next = builder.ins().iadd_imm(i, 1);
+                    builder.def_var(counter, next);
+                });
+                self.scopes.pop();
+                left
+            }
+            _ => None,
+        }
+    }
+
+    /// Jumps to the block following an `if`, creating it on first use.
+    fn fall_through(&mut self, merge: &mut Option<Block>) {
+        let merge = *merge.get_or_insert_with(|| self.builder.create_block());
+        self.builder.ins().jump(merge, &[]);
+    }
+
+    /// Translates the body of a loop, running `step` before going back to
+    /// `header` and leaving the builder at `exit`.
+    fn loop_body(
+        &mut self,
+        body: &'a [Spanned<Statement<'a>>],
+        header: Block,
+        exit: Block,
+        step: impl FnOnce(&mut FunctionBuilder),
+    ) -> Option<bool> {
+        self.loops.push(exit);
+        let left = self.block(body, false)?;
+        self.loops.pop();
+        if !left {
+            step(&mut self.builder);
+            self.builder.ins().jump(header, &[]);
+        }
+        self.builder.seal_block(header);
+        self.builder.switch_to_block(exit);
+        self.builder.seal_block(exit);
+        Some(false)
+    }
+
+    fn condition(&mut self, condition: &'a Spanned<Expr<'a>>) -> Option<Ir> {
+        match self.expr(condition)? {
+            (value, Ty::Bool) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn assign(
+        &mut self,
+        target: &'a Spanned<Expr<'a>>,
+        op: BinOp,
+        value: &'a Spanned<Expr<'a>>,
+    ) -> Option<()> {
+        let Expr::Ident(name) = &target.node else {
+            return None;
+        };
+        // assigning an immutable binding is left to the interpreter to report
+        let binding = self.lookup(name).filter(|binding| binding.mutable)?;
+        let (value, ty) = match (op, self.expr(value)?) {
+            (BinOp::Eq, value) => value,
+            (op, value) => {
+                let current = self.builder.use_var(binding.var);
+                self.binary(op, (current, binding.ty), value)?
+            }
+        };
+        (ty == binding.ty).then(|| self.builder.def_var(binding.var, value))
+    }
+
+    fn expr(&mut self, expr: &'a Spanned<Expr<'a>>) -> Option<(Ir, Ty)> {
+        let ins = self.builder.ins();
+        match &expr.node {
+            Expr::Literal(Literal::Int(i)) => {
+                Some((ins.iconst(types::I64, i64::from(*i)), Ty::Int))
+            }
+            Expr::Literal(Literal::Float(f)) => Some((ins.f64const(*f), Ty::Float)),
+            Expr::Literal(Literal::Inf) => Some((ins.f64const(f64::INFINITY), Ty::Float)),
+            Expr::Literal(Literal::Bool(b)) => {
+                Some((ins.iconst(types::I8, i64::from(*b)), Ty::Bool))
+            }
+            Expr::Ident(name) => {
+                let binding = self.lookup(name)?;
+                Some((self.builder.use_var(binding.var), binding.ty))
+            }
+            Expr::Tuple(items) if items.len() == 1 => self.expr(&items[0]),
+            Expr::Binary(lhs, op @ (BinOp::And | BinOp::Or), rhs) => {
+                let lhs = self.condition(lhs)?;
+                let (rhs_block, merge) = (self.builder.create_block(), self.builder.create_block());
+                self.builder.append_block_param(merge, types::I8);
+                if *op == BinOp::And {
+                    self.builder.ins().brif(lhs, rhs_block, &[], merge, &[lhs]);
+                } else {
+                    self.builder.ins().brif(lhs, merge, &[lhs], rhs_block, &[]);
+                }
+                self.builder.switch_to_block(rhs_block);
+                self.builder.seal_block(rhs_block);
+                let rhs = self.condition(rhs)?;
+                self.builder.ins().jump(merge, &[rhs]);
+                self.builder.switch_to_block(merge);
+                self.builder.seal_block(merge);
+                Some((self.builder.block_params(merge)[0], Ty::Bool))
+            }
+            Expr::Binary(lhs, op, rhs) => {
+                let lhs = self.expr(lhs)?;
+                let rhs = self.expr(rhs)?;
+                self.binary(*op, lhs, rhs)
+            }
+            Expr::Unary(op, operand) => {
+                let (value, ty) = self.expr(operand)?;
+                self.unary(*op, value, ty)
+            }
+            Expr::Call(callee, args) => {
+                let Expr::Ident(name) = &callee.node else {
+                    return None;
+                };
+                if self.lookup(name).is_some() {
+                    return None;
+                }
+                let candidates = self.candidates;
+                let callee = candidates.get(name)?;
+                if callee.params.len() != args.len() {
+                    return None;
+                }
+                let mut values = Vec::new();
+                for (arg, (_, ty)) in args.iter().zip(&callee.params) {
+                    let (value, found) = self.expr(arg)?;
+                    if found != *ty {
+                        return None;
+                    }
+                    values.push(value);
+                }
+                let func = self
+                    .module
+                    .declare_func_in_func(callee.id, self.builder.func);
+                let call = self.builder.ins().call(func, &values);
+                let (value, status) = (
+                    self.builder.inst_results(call)[0],
+                    self.builder.inst_results(call)[1],
+                );
+                self.fail_with(status, status);
+                Some((value, callee.ret))
+            }
+            _ => None,
+        }
+    }
+
+    fn unary(&mut self, op: UnaryOp, value: Ir, ty: Ty) -> Option<(Ir, Ty)> {
+        let ins = self.builder.ins();
+        match (op, ty) {
+            (UnaryOp::Not, Ty::Bool) => Some((ins.icmp_imm(IntCC::Equal, value, 0), ty)),
+            (UnaryOp::Minus, Ty::Int) => {
+                let min = ins.icmp_imm(IntCC::Equal, value, i64::MIN);
+                self.fail_if(min, OVERFLOW);
+                Some((self.builder.ins().ineg(value), ty))
+            }
+            (UnaryOp::Minus, Ty::Float) => Some((ins.fneg(value), ty)),
+            (UnaryOp::Plus, Ty::Int | Ty::Float) => Some((value, ty)),
+            (UnaryOp::BitNot, Ty::Int) => Some((ins.bnot(value), ty)),
+            _ => None,
+        }
+    }
+
+    /// Applies `op` the way the interpreter does, `&&` and `||` aside.
+    fn binary(
+        &mut self,
+        op: BinOp,
+        (lhs, lty): (Ir, Ty),
+        (rhs, rty): (Ir, Ty),
+    ) -> Option<(Ir, Ty)> {
+        use BinOp::*;
+
+        match (lty, rty) {
+            (Ty::Int, Ty::Int) => self.int(op, lhs, rhs),
+            (Ty::Bool, Ty::Bool) => {
+                let ins = self.builder.ins();
+                let value = match op {
+                    Eq => ins.icmp(IntCC::Equal, lhs, rhs),
+                    Neq => ins.icmp(IntCC::NotEqual, lhs, rhs),
+                    BitAnd => ins.band(lhs, rhs),
+                    BitOr => ins.bor(lhs, rhs),
+                    BitXor => ins.bxor(lhs, rhs),
+                    _ => return None,
+                };
+                Some((value, Ty::Bool))
+            }
+            (Ty::Bool, _) | (_, Ty::Bool) => None,
+            _ => {
+                let mut float = |value, ty| match ty {
+                    Ty::Int => self.builder.ins().fcvt_from_sint(types::F64, value),
+                    _ => value,
+                };
+                let (lhs, rhs) = (float(lhs, lty), float(rhs, rty));
+                let ins = self.builder.ins();
+                let cc = match op {
+                    Plus => return Some((ins.fadd(lhs, rhs), Ty::Float)),
+                    Minus => return Some((ins.fsub(lhs, rhs), Ty::Float)),
+                    Multiply => return Some((ins.fmul(lhs, rhs), Ty::Float)),
+                    Divide => return Some((ins.fdiv(lhs, rhs), Ty::Float)),
+                    Eq => FloatCC::Equal,
+                    Neq => FloatCC::NotEqual,
+                    Lt => FloatCC::LessThan,
+                    Le => FloatCC::LessThanOrEqual,
+                    Gt => FloatCC::GreaterThan,
+                    Ge => FloatCC::GreaterThanOrEqual,
+                    _ => return None,
+                };
+                Some((ins.fcmp(cc, lhs, rhs), Ty::Bool))
+            }
+        }
+    }
+
+    /// Integer arithmetic is checked, failing like the interpreter does.
+    fn int(&mut self, op: BinOp, lhs: Ir, rhs: Ir) -> Option<(Ir, Ty)> {
+        use BinOp::*;
+
+        let compare = |this: &mut Self, cc| Some((this.builder.ins().icmp(cc, lhs, rhs), Ty::Bool));
+        let value = match op {
+            Plus | Minus | Multiply => {
+                let ins = self.builder.ins();
+                let (value, overflow) = match op {
+                    Plus => ins.sadd_overflow(lhs, rhs),
+                    Minus => ins.ssub_overflow(lhs, rhs),
+                    _ => ins.smul_overflow(lhs, rhs),
+                };
+                self.fail_if(overflow, OVERFLOW);
+                value
+            }
+            Divide | Modulo => {
+                let zero = self.builder.ins().icmp_imm(IntCC::Equal, rhs, 0);
+                self.fail_if(zero, DIVISION_BY_ZERO);
+                let min = self.builder.ins().icmp_imm(IntCC::Equal, lhs, i64::MIN);
+                let minus_one = self.builder.ins().icmp_imm(IntCC::Equal, rhs, -1);
+                let overflow = self.builder.ins().band(min, minus_one);
+                self.fail_if(overflow, OVERFLOW);
+                match op {
+                    Divide => self.builder.ins().sdiv(lhs, rhs),
+                    _ => self.builder.ins().srem(lhs, rhs),
+                }
+            }
+            ShiftLeft | ShiftRight => {
+                let ins = self.builder.ins();
+                let overflow = ins.icmp_imm(IntCC::UnsignedGreaterThanOrEqual, rhs, 64);
+                self.fail_if(overflow, OVERFLOW);
+                match op {
+                    ShiftLeft => self.builder.ins().ishl(lhs, rhs),
+                    _ => self.builder.ins().sshr(lhs, rhs),
+                }
+            }
+            BitAnd => self.builder.ins().band(lhs, rhs),
+            BitOr => self.builder.ins().bor(lhs, rhs),
+            BitXor => self.builder.ins().bxor(lhs, rhs),
+            Eq => return compare(self, IntCC::Equal),
+            Neq => return compare(self, IntCC::NotEqual),
+            Lt => return compare(self, IntCC::SignedLessThan),
+            Le => return compare(self, IntCC::SignedLessThanOrEqual),
+            Gt => return compare(self, IntCC::SignedGreaterThan),
+            Ge => return compare(self, IntCC::SignedGreaterThanOrEqual),
+            And | Or => return None,
+        };
+        Some((value, Ty::Int))
+    }
+}
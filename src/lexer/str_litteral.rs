@@ -143,8 +143,10 @@ where
     let slice = &input[expr_start..end_pos];
     let lexer = Lexer::new(slice);
     let mut tokens = Vec::with_capacity(8);
+    // positioned within the literal, the parser shifting them to the source
     for token in lexer {
-        tokens.push(token?);
+        let (start, token, end) = token?;
+        tokens.push((expr_start + start, token, expr_start + end));
     }
 
     Ok((StringPart::Expression(tokens), end_pos + 1))
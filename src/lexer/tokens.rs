@@ -24,6 +24,19 @@ impl fmt::Display for Token<'_> {
         write!(f, "{:?}", self)
     }
 }
+impl fmt::Display for LexingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexingError::InvalidInteger(msg) => write!(f, "invalid integer literal: {}", msg),
+            LexingError::InvalidFloat(msg) => write!(f, "invalid float literal: {}", msg),
+            LexingError::InvalidBinary(msg) => write!(f, "invalid binary literal: {}", msg),
+            LexingError::InvalidOctal(msg) => write!(f, "invalid octal literal: {}", msg),
+            LexingError::InvalidHex(msg) => write!(f, "invalid hexadecimal literal: {}", msg),
+            LexingError::InvalidToken => write!(f, "unrecognized token"),
+            LexingError::UnterminatedString(msg) => write!(f, "invalid string literal: {}", msg),
+        }
+    }
+}
 impl From<ParseIntError> for LexingError {
     fn from(err: ParseIntError) -> Self {
         LexingError::InvalidInteger(err.to_string())
@@ -1,7 +1,21 @@
 pub mod ast;
+pub mod compiler;
+pub mod cst;
+pub mod diagnostics;
+pub mod doc;
+pub mod eval;
+pub mod formatter;
+#[cfg(feature = "jit")]
+pub mod jit;
 pub mod lexer;
+pub mod lsp;
 pub mod minifier;
+pub mod modules;
 pub mod parser;
+pub mod repl;
+pub mod resolve;
+pub mod typeck;
+pub mod vm;
 
 // Re-export common items
 pub use lexer::{Lexer, tokens::LexingError, tokens::Token};
@@ -0,0 +1,161 @@
+mod analysis;
+
+use std::{collections::HashMap, error::Error};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DocumentSymbolResponse, GotoDefinitionResponse, HoverProviderCapability, OneOf,
+    PublishDiagnosticsParams, SemanticTokens, SemanticTokensFullOptions, SemanticTokensOptions,
+    SemanticTokensResult, SemanticTokensServerCapabilities, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+        PublishDiagnostics,
+    },
+    request::{
+        DocumentSymbolRequest, GotoDefinition, HoverRequest, Request as _,
+        SemanticTokensFullRequest,
+    },
+};
+
+/// What `lira-lsp` answers.
+pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+            SemanticTokensOptions {
+                legend: analysis::legend(),
+                full: Some(SemanticTokensFullOptions::Bool(true)),
+                ..Default::default()
+            },
+        )),
+        ..Default::default()
+    }
+}
+
+/// Serves the client on the other end of `connection` until it shuts the
+/// server down.
+pub fn run(connection: &Connection) -> Result<(), Box<dyn Error + Send + Sync>> {
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+
+    let mut server = Server::default();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                let response = server.handle(request);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                if let Some(diagnostics) = server.notify(notification) {
+                    connection.sender.send(Message::Notification(diagnostics))?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// The documents opened in the editor, by URI.
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Url, String>,
+}
+
+impl Server {
+    fn handle(&self, request: Request) -> Response {
+        match request.method.as_str() {
+            DocumentSymbolRequest::METHOD => reply::<DocumentSymbolRequest>(request, |params| {
+                let text = self.documents.get(&params.text_document.uri)?;
+                Some(DocumentSymbolResponse::Nested(analysis::symbols(text)))
+            }),
+            HoverRequest::METHOD => reply::<HoverRequest>(request, |params| {
+                let position = params.text_document_position_params;
+                let text = self.documents.get(&position.text_document.uri)?;
+                analysis::hover(text, position.position)
+            }),
+            GotoDefinition::METHOD => reply::<GotoDefinition>(request, |params| {
+                let position = params.text_document_position_params;
+                let uri = &position.text_document.uri;
+                let text = self.documents.get(uri)?;
+                analysis::definition(text, uri, position.position)
+                    .map(GotoDefinitionResponse::Scalar)
+            }),
+            SemanticTokensFullRequest::METHOD => {
+                reply::<SemanticTokensFullRequest>(request, |params| {
+                    let text = self.documents.get(&params.text_document.uri)?;
+                    Some(SemanticTokensResult::Tokens(SemanticTokens {
+                        result_id: None,
+                        data: analysis::semantic_tokens(text),
+                    }))
+                })
+            }
+            method => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unsupported request `{}`", method),
+            ),
+        }
+    }
+
+    /// Keeps track of the documents, returning their new diagnostics.
+    fn notify(&mut self, notification: Notification) -> Option<Notification> {
+        let (uri, version) = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params).ok()?;
+                let document = params.text_document;
+                self.documents.insert(document.uri.clone(), document.text);
+                (document.uri, Some(document.version))
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params).ok()?;
+                // the whole text is sent on every change
+                let text = params.content_changes.into_iter().last()?.text;
+                let document = params.text_document;
+                self.documents.insert(document.uri.clone(), text);
+                (document.uri, Some(document.version))
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params).ok()?;
+                self.documents.remove(&params.text_document.uri);
+                (params.text_document.uri, None)
+            }
+            _ => return None,
+        };
+
+        let diagnostics = match self.documents.get(&uri) {
+            Some(text) => analysis::diagnostics(text, &uri),
+            // the diagnostics of closed documents are cleared
+            None => Vec::new(),
+        };
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version,
+        };
+        Some(Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            params,
+        ))
+    }
+}
+
+fn reply<R: lsp_types::request::Request>(
+    request: Request,
+    handler: impl FnOnce(R::Params) -> R::Result,
+) -> Response {
+    match serde_json::from_value(request.params) {
+        Ok(params) => Response::new_ok(request.id, handler(params)),
+        Err(e) => Response::new_err(request.id, ErrorCode::InvalidParams as i32, e.to_string()),
+    }
+}
@@ -0,0 +1,415 @@
+use std::path::Path;
+
+use lsp_types::{
+    DiagnosticSeverity, DocumentSymbol, Hover, HoverContents, Location, MarkupContent, MarkupKind,
+    Position, Range, SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensLegend,
+    SymbolKind, Url,
+};
+
+use crate::{
+    Lexer, Token,
+    ast::{EnumVariant, Span, Spanned, Statement},
+    diagnostics::{Diagnostic, Severity},
+    modules::{ModuleLoader, STD},
+    parser::parse_program,
+    resolve::{DefKind, PRELUDE, Resolution, resolve_program},
+    typeck::{Ty, check_program, identifier_types, infer_program},
+};
+
+/// Converts byte offsets to LSP positions, whose characters are UTF-16 code
+/// units, and back.
+struct LineIndex<'a> {
+    text: &'a str,
+    // offset of the first character of each line
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { text, starts }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        let character = self.text[self.starts[line]..offset].encode_utf16().count();
+        Position::new(line as u32, character as u32)
+    }
+
+    fn range(&self, span: Span) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+
+    fn offset(&self, position: Position) -> usize {
+        let Some(&start) = self.starts.get(position.line as usize) else {
+            return self.text.len();
+        };
+        let line = self.text[start..].split('\n').next().unwrap_or_default();
+        let mut units = 0;
+        for (i, c) in line.char_indices() {
+            if units >= position.character as usize {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        start + line.len()
+    }
+}
+
+/// The syntax errors of `text`, or its name resolution, type and module
+/// errors when it parses.
+pub fn diagnostics(text: &str, uri: &Url) -> Vec<lsp_types::Diagnostic> {
+    let (program, errors) = parse_program(text);
+    let mut found: Vec<Diagnostic> = errors
+        .iter()
+        .map(|e| Diagnostic::from_parse_error(e, text))
+        .collect();
+    if found.is_empty() {
+        let resolution = resolve_program(&program);
+        found.extend(resolution.errors.iter().map(|e| e.to_diagnostic()));
+        found.extend(check_program(&program).iter().map(|e| e.to_diagnostic()));
+    }
+    // modules are looked up next to the document, the errors of other files left out
+    if !found.iter().any(Diagnostic::is_error)
+        && let Ok(path) = uri.to_file_path()
+        && let Some(root) = path.parent()
+        && let Err(e) = ModuleLoader::new(root).load(&path, &program)
+        && e.file == path
+    {
+        found.extend(e.diagnostics());
+    }
+
+    let lines = LineIndex::new(text);
+    found
+        .iter()
+        .map(|diagnostic| {
+            let mut message = diagnostic.message.clone();
+            for detail in diagnostic.label.iter().chain(&diagnostic.notes) {
+                message.push('\n');
+                message.push_str(detail);
+            }
+            lsp_types::Diagnostic {
+                range: lines.range(diagnostic.span),
+                severity: Some(match diagnostic.severity {
+                    Severity::Error => DiagnosticSeverity::ERROR,
+                    Severity::Warning => DiagnosticSeverity::WARNING,
+                }),
+                source: Some("lira".to_string()),
+                message,
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// The functions, structs, enums and type aliases of `text`, with the
+/// functions declared in functions, the fields and the variants as children.
+pub fn symbols(text: &str) -> Vec<DocumentSymbol> {
+    let (program, _) = parse_program(text);
+    let signatures = infer_program(&program).0;
+    let lines = LineIndex::new(text);
+    let mut symbols = symbols_of(text, &lines, &program.statements);
+    for symbol in &mut symbols {
+        if symbol.kind == SymbolKind::FUNCTION {
+            symbol.detail = signatures
+                .iter()
+                .find(|signature| signature.name == symbol.name)
+                .map(|signature| signature.ty.to_string());
+        }
+    }
+    symbols
+}
+
+fn symbols_of(text: &str, lines: &LineIndex, stmts: &[Spanned<Statement>]) -> Vec<DocumentSymbol> {
+    let mut symbols = Vec::new();
+    for stmt in stmts {
+        let (name, kind, members, member_kind) = match &stmt.node {
+            Statement::FnDecl { name, .. } => (name, SymbolKind::FUNCTION, vec![], None),
+            Statement::Struct { name, fields, .. } => {
+                let fields = fields.iter().map(|field| field.0).collect();
+                (name, SymbolKind::STRUCT, fields, Some(SymbolKind::FIELD))
+            }
+            Statement::Enum { name, variants, .. } => {
+                let variants = variants
+                    .iter()
+                    .map(|variant| match &variant.node {
+                        EnumVariant::Unit(name)
+                        | EnumVariant::Tuple(name, _)
+                        | EnumVariant::Struct(name, _) => *name,
+                    })
+                    .collect();
+                (
+                    name,
+                    SymbolKind::ENUM,
+                    variants,
+                    Some(SymbolKind::ENUM_MEMBER),
+                )
+            }
+            Statement::TypeAlias { name, .. } => (name, SymbolKind::TYPE_PARAMETER, vec![], None),
+            _ => continue,
+        };
+
+        let selection = find_name(text, stmt.span, name);
+        let mut children = match &stmt.node {
+            Statement::FnDecl { body, .. } => symbols_of(text, lines, body),
+            _ => Vec::new(),
+        };
+        // members are looked for in order, after the name of their item
+        let mut from = selection.end;
+        for member in members {
+            let span = find_name(text, Span::new(from, stmt.span.end), member);
+            from = span.end;
+            let kind = member_kind.unwrap_or(SymbolKind::FIELD);
+            children.push(symbol(
+                member,
+                kind,
+                lines.range(span),
+                lines.range(span),
+                vec![],
+            ));
+        }
+        symbols.push(symbol(
+            name,
+            kind,
+            lines.range(stmt.span),
+            lines.range(selection),
+            children,
+        ));
+    }
+    symbols
+}
+
+#[allow(deprecated)]
+fn symbol(
+    name: &str,
+    kind: SymbolKind,
+    range: Range,
+    selection_range: Range,
+    children: Vec<DocumentSymbol>,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name: name.to_string(),
+        detail: None,
+        kind,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range,
+        children: Some(children),
+    }
+}
+
+/// The inferred type of the identifier at `position`.
+pub fn hover(text: &str, position: Position) -> Option<Hover> {
+    let lines = LineIndex::new(text);
+    let (span, name) = identifier_at(text, lines.offset(position))?;
+    let (program, _) = parse_program(text);
+
+    let ty = identifier_types(&program).remove(&span).or_else(|| {
+        // the name of a top-level function or binding where it is defined
+        let resolution = resolve_program(&program);
+        resolution.definitions.iter().find(|def| {
+            def.name == name
+                && resolution.scopes[def.scope].parent == Some(PRELUDE)
+                && find_name(text, def.span, def.name) == span
+        })?;
+        let signatures = infer_program(&program).0;
+        signatures
+            .into_iter()
+            .find(|s| s.name == name)
+            .map(|s| s.ty)
+    })?;
+    if ty == Ty::Unknown {
+        return None;
+    }
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("```lira\n{}: {}\n```", name, ty),
+        }),
+        range: Some(lines.range(span)),
+    })
+}
+
+/// Where the identifier at `position` is defined, the file of the module for
+/// `use` paths and imported names.
+pub fn definition(text: &str, uri: &Url, position: Position) -> Option<Location> {
+    let lines = LineIndex::new(text);
+    let (span, _) = identifier_at(text, lines.offset(position))?;
+    let (program, _) = parse_program(text);
+
+    let import = |contains: &dyn Fn(Span) -> bool| {
+        program.statements.iter().find_map(|stmt| match &stmt.node {
+            Statement::Use { path, .. } if contains(stmt.span) => Some(path),
+            _ => None,
+        })
+    };
+    if let Some(path) = import(&|stmt| stmt.start <= span.start && span.end <= stmt.end) {
+        return module(uri, path);
+    }
+
+    let resolution = resolve_program(&program);
+    let def = resolution.definition(span)?;
+    match def.kind {
+        DefKind::Builtin => None,
+        DefKind::Import
+            if let Some(location) =
+                import(&|stmt| stmt == def.span).and_then(|path| module(uri, path)) =>
+        {
+            Some(location)
+        }
+        _ => Some(Location::new(
+            uri.clone(),
+            lines.range(find_name(text, def.span, def.name)),
+        )),
+    }
+}
+
+/// The file of the module imported by `use path` from the document at `uri`.
+fn module(uri: &Url, path: &[&str]) -> Option<Location> {
+    if path.first() == Some(&STD) {
+        return None;
+    }
+    let document = uri.to_file_path().ok()?;
+    let file = ModuleLoader::new(document.parent()?).path_of(path);
+    if !Path::new(&file).exists() {
+        return None;
+    }
+    Some(Location::new(
+        Url::from_file_path(file).ok()?,
+        Range::default(),
+    ))
+}
+
+/// The identifier token at `offset`, or ending there.
+fn identifier_at(text: &str, offset: usize) -> Option<(Span, &str)> {
+    Lexer::new(text)
+        .flatten()
+        .find_map(|(start, token, end)| match token {
+            Token::Ident(name) if start <= offset && offset <= end => {
+                Some((Span::new(start, end), name))
+            }
+            _ => None,
+        })
+}
+
+/// The first identifier `name` within `span`, definitions being located by
+/// their whole statement.
+fn find_name(text: &str, span: Span, name: &str) -> Span {
+    let end = span.end.min(text.len());
+    Lexer::new(&text[span.start.min(end)..end])
+        .flatten()
+        .find_map(|(start, token, end)| match token {
+            Token::Ident(ident) if ident == name => {
+                Some(Span::new(span.start + start, span.start + end))
+            }
+            _ => None,
+        })
+        .unwrap_or(span)
+}
+
+const TOKEN_TYPES: [SemanticTokenType; 10] = [
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::TYPE,
+    SemanticTokenType::NAMESPACE,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::STRING,
+    SemanticTokenType::COMMENT,
+    SemanticTokenType::OPERATOR,
+];
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES.to_vec(),
+        token_modifiers: vec![SemanticTokenModifier::DOCUMENTATION],
+    }
+}
+
+/// The tokens of `text` classified by kind, identifiers by what they refer to,
+/// encoded relative to each other.
+pub fn semantic_tokens(text: &str) -> Vec<SemanticToken> {
+    let lines = LineIndex::new(text);
+    let (program, _) = parse_program(text);
+    let resolution = resolve_program(&program);
+
+    let mut data = Vec::new();
+    let mut previous = Position::default();
+    let mut last = None;
+    for (start, token, end) in Lexer::new(text).flatten() {
+        let class = classify(&token, last.as_ref(), Span::new(start, end), &resolution);
+        last = Some(token);
+        let Some((kind, modifiers)) = class else {
+            continue;
+        };
+        let token_type = TOKEN_TYPES.iter().position(|t| *t == kind).unwrap() as u32;
+
+        // tokens cannot span lines, block comments are split
+        let mut offset = start;
+        for line in text[start..end].split('\n') {
+            let position = lines.position(offset);
+            offset += line.len() + 1;
+            let length = line.trim_end_matches('\r').encode_utf16().count() as u32;
+            if length == 0 {
+                continue;
+            }
+            let delta_line = position.line - previous.line;
+            let delta_start = match delta_line {
+                0 => position.character - previous.character,
+                _ => position.character,
+            };
+            data.push(SemanticToken {
+                delta_line,
+                delta_start,
+                length,
+                token_type,
+                token_modifiers_bitset: modifiers,
+            });
+            previous = position;
+        }
+    }
+    data
+}
+
+fn classify(
+    token: &Token,
+    last: Option<&Token>,
+    span: Span,
+    resolution: &Resolution,
+) -> Option<(SemanticTokenType, u32)> {
+    use Token::*;
+    let kind = match token {
+        Fn | Let | Struct | If | TypeAlias | Enum | Use | As | Elsif | Else | Spawn | Shared
+        | With | Atomic | Match | Lazy | Mut | Inf | For | In | While | Break | True | False
+        | Async | Await | Return => SemanticTokenType::KEYWORD,
+        Ident(name) => match resolution.definition(span).map(|def| def.kind) {
+            Some(DefKind::Function | DefKind::Builtin) => SemanticTokenType::FUNCTION,
+            Some(DefKind::Struct | DefKind::Enum | DefKind::TypeAlias) => SemanticTokenType::TYPE,
+            Some(DefKind::Param) => SemanticTokenType::PARAMETER,
+            Some(DefKind::Import) => SemanticTokenType::NAMESPACE,
+            Some(_) => SemanticTokenType::VARIABLE,
+            // names being declared
+            None => match last {
+                Some(Fn) => SemanticTokenType::FUNCTION,
+                Some(Struct | Enum | TypeAlias) => SemanticTokenType::TYPE,
+                Some(Use | DoubleColon) => SemanticTokenType::NAMESPACE,
+                _ if name.starts_with(char::is_uppercase) => SemanticTokenType::TYPE,
+                _ => SemanticTokenType::VARIABLE,
+            },
+        },
+        Int(_) | Float(_) | Binary(_) | Octal(_) | Hex(_) => SemanticTokenType::NUMBER,
+        String(_) => SemanticTokenType::STRING,
+        LineComment(_) | BlockComment(_) => SemanticTokenType::COMMENT,
+        DocComment(_) => return Some((SemanticTokenType::COMMENT, 1)),
+        LParen | RParen | LBracket | RBracket | LBrace | RBrace | Comma | Dot | Colon
+        | DoubleColon | Wildcard | Whitespace | LineEnd => return None,
+        _ => SemanticTokenType::OPERATOR,
+    };
+    Some((kind, 0))
+}
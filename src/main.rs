@@ -1,85 +1,726 @@
-use lira::{Lexer, ProgramParser, minifier::minify};
-use std::fs;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use lira::{
+    Lexer,
+    diagnostics::{Diagnostic, line_col},
+    doc,
+    eval::{Interpreter, RuntimeError},
+    formatter::{FormatError, format},
+    minifier::{MinifyError, Options, minify_with_map, source_map::SourceMap},
+    modules::ModuleLoader,
+    parser::parse_program,
+    repl::{Session, is_incomplete},
+    resolve::resolve_program,
+    typeck::{check_program, infer_program},
+    vm::Vm,
+};
+use rustyline::{DefaultEditor, error::ReadlineError};
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::exit,
+};
 
-fn main() {
-    let args = std::env::args().collect::<Vec<_>>();
-    let print = args
-        .get(1)
-        .map(|x| x == "p" || x == "print")
-        .unwrap_or(false);
-    let minify = args
-        .get(1)
-        .map(|x| x == "m" || x == "minify")
-        .unwrap_or(false);
-
-    let source = if minify {
-        concat_dir()
+#[derive(Parser)]
+#[command(name = "lira", version, about = "The Lira programming language")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the tokens of the sources
+    Lex {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        output: Output,
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// Print the syntax tree of the sources
+    Parse {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        output: Output,
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// Report the syntax, name and type errors of the sources
+    #[command(visible_alias = "c")]
+    Check {
+        #[command(flatten)]
+        input: Input,
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// Print the inferred types of the top-level bindings of the sources
+    #[command(visible_alias = "t")]
+    Types {
+        #[command(flatten)]
+        input: Input,
+    },
+    /// Run a program, `-` reading it from stdin
+    #[command(visible_alias = "r")]
+    Run {
+        /// Run on the bytecode virtual machine
+        #[arg(long, conflicts_with = "jit")]
+        vm: bool,
+        /// Compile the numeric functions to native code
+        #[arg(long)]
+        jit: bool,
+        file: PathBuf,
+        /// Arguments passed to the program
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Format the sources, in place unless read from stdin or given `-o`
+    Fmt {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        output: Output,
+        /// Only check that the sources are formatted
+        #[arg(long)]
+        check: bool,
+    },
+    /// Strip the whitespace of the sources, keeping the programs they parse to
+    #[command(visible_alias = "m")]
+    Minify {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        output: Output,
+        /// Leave the comments out too
+        #[arg(long)]
+        drop_comments: bool,
+        /// Rename the local bindings to the shortest names available
+        #[arg(long)]
+        mangle: bool,
+        /// Write the map of the minified tokens back to the sources to this file
+        #[arg(long)]
+        source_map: Option<PathBuf>,
+    },
+    /// Find where a byte offset of a minified source comes from
+    Map {
+        /// The source map written by `minify --source-map`
+        map: PathBuf,
+        /// Byte offset in the minified source
+        offset: usize,
+    },
+    /// Generate the API docs of a program and the modules it imports
+    Doc {
+        file: PathBuf,
+        /// The directory the pages are written to
+        #[arg(short, long, default_value = "doc")]
+        output: PathBuf,
+        #[arg(long, value_enum, default_value_t)]
+        format: DocFormat,
+    },
+    /// Measure the lexing and parsing throughput on the sources
+    Bench {
+        #[command(flatten)]
+        input: Input,
+        /// How many times the sources are repeated
+        #[arg(long, default_value_t = 1000)]
+        repeat: usize,
+    },
+    /// Start an interactive session
+    Repl,
+}
+
+#[derive(Args)]
+struct Input {
+    /// Source files, stdin when none is given or for `-`
+    files: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+struct Output {
+    /// Write to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+enum Format {
+    #[default]
+    Pretty,
+    Debug,
+    Json,
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+enum DocFormat {
+    #[default]
+    Markdown,
+    Html,
+}
+
+/// A source file read from the command line.
+struct Source {
+    name: String,
+    text: String,
+}
+
+impl Input {
+    fn read(&self) -> Vec<Source> {
+        if self.files.is_empty() {
+            return vec![read_source(Path::new("-"))];
+        }
+        self.files.iter().map(|path| read_source(path)).collect()
+    }
+}
+
+fn read_source(path: &Path) -> Source {
+    let (name, text) = if path == Path::new("-") {
+        let mut text = String::new();
+        let read = io::stdin().read_to_string(&mut text);
+        ("<stdin>".to_string(), read.map(|_| text))
     } else {
-        // before changing from String to &str
-        // lexer took 88ms to parser 1000x output.li (around 30_000_000 chars/sec)
-        fs::read_to_string("output.li")
-            .expect("could not read output.li")
-            .repeat(1000)
+        (path.display().to_string(), fs::read_to_string(path))
     };
+    match text {
+        Ok(text) => Source { name, text },
+        Err(e) => {
+            eprintln!("error: could not read {}: {}", name, e);
+            exit(1);
+        }
+    }
+}
 
-    let char_count = source.len();
+impl Output {
+    fn write(&self, text: &str) {
+        let Some(path) = &self.output else {
+            print!("{}", text);
+            return;
+        };
+        if let Err(e) = fs::write(path, text) {
+            eprintln!("error: could not write {}: {}", path.display(), e);
+            exit(1);
+        }
+    }
+}
 
-    let start = std::time::Instant::now();
+fn main() {
+    let code = match Cli::parse().command {
+        Command::Lex {
+            input,
+            output,
+            format,
+        } => lexing(&input, &output, format),
+        Command::Parse {
+            input,
+            output,
+            format,
+        } => parsing(&input, &output, format),
+        Command::Check { input, format } => checking(&input, format),
+        Command::Types { input } => typing(&input),
+        Command::Run {
+            vm,
+            jit,
+            file,
+            args,
+        } => {
+            let engine = match (vm, jit) {
+                (true, _) => Engine::Vm,
+                (_, true) => Engine::Jit,
+                _ => Engine::Interpreter,
+            };
+            running(&file, &args, engine)
+        }
+        Command::Fmt {
+            input,
+            output,
+            check,
+        } => formatting(&input, &output, check),
+        Command::Minify {
+            input,
+            output,
+            drop_comments,
+            mangle,
+            source_map,
+        } => minifying(
+            &input,
+            &output,
+            Options {
+                drop_comments,
+                mangle,
+            },
+            source_map.as_deref(),
+        ),
+        Command::Map { map, offset } => mapping(&map, offset),
+        Command::Doc {
+            file,
+            output,
+            format,
+        } => documenting(&file, &output, format),
+        Command::Bench { input, repeat } => benchmarking(&input, repeat),
+        Command::Repl => {
+            repl();
+            0
+        }
+    };
+    exit(code);
+}
 
-    let lexer = Lexer::new(&source);
+/// Renders the lexing errors of `source` to stderr, returning whether there were any.
+fn report_lexing_errors(source: &Source) -> bool {
+    let mut lexer = Lexer::new(&source.text);
+    let mut failed = false;
+    while let Some(token) = lexer.next() {
+        if let Err(e) = token {
+            let (start, end) = lexer.span();
+            let diagnostic = Diagnostic::from_lexing_error(&e, lira::ast::Span::new(start, end));
+            eprint!("{}", diagnostic.render(&source.name, &source.text));
+            failed = true;
+        }
+    }
+    failed
+}
 
-    if print {
-        for t in lexer.clone() {
-            println!("{:?}", t);
+fn lexing(input: &Input, output: &Output, format: Format) -> i32 {
+    let mut out = String::new();
+    let mut json = Vec::new();
+    let mut failed = false;
+    for source in input.read() {
+        failed |= report_lexing_errors(&source);
+        let text = &source.text;
+        for (start, token, end) in Lexer::new(text).flatten() {
+            match format {
+                Format::Debug => out.push_str(&format!("{:?}\n", token)),
+                Format::Pretty => {
+                    let (line, col) = line_col(text, start);
+                    let position = format!("{}:{}:{}", source.name, line, col);
+                    out.push_str(&format!("{:<24} {:?}\n", position, token));
+                }
+                Format::Json => json.push(serde_json::json!({
+                    "file": source.name,
+                    "token": format!("{:?}", token),
+                    "text": &text[start..end],
+                    "start": start,
+                    "end": end,
+                })),
+            }
         }
     }
+    if let Format::Json = format {
+        out = serde_json::to_string_pretty(&json).unwrap() + "\n";
+    }
+    output.write(&out);
+    failed as i32
+}
 
-    if minify {
-        minifying(lexer);
-    } else {
-        parsing(lexer, print);
+fn parsing(input: &Input, output: &Output, format: Format) -> i32 {
+    let mut out = String::new();
+    let mut json = Vec::new();
+    let mut failed = false;
+    for source in input.read() {
+        let (program, errors) = parse_program(&source.text);
+        for e in &errors {
+            let diagnostic = Diagnostic::from_parse_error(e, &source.text);
+            eprint!("{}", diagnostic.render(&source.name, &source.text));
+        }
+        failed |= !errors.is_empty();
+        match format {
+            Format::Debug => out.push_str(&format!("{:?}\n", program)),
+            Format::Pretty => {
+                for stmt in &program.statements {
+                    out.push_str(&format!("{:#?}\n", stmt.node));
+                }
+            }
+            Format::Json => json.push(serde_json::json!({
+                "file": source.name,
+                "program": program,
+            })),
+        }
+    }
+    if let Format::Json = format {
+        out = serde_json::to_string_pretty(&json).unwrap() + "\n";
     }
+    output.write(&out);
+    failed as i32
+}
 
-    let duration = start.elapsed();
-    let millis = duration.as_secs_f64() * 1000.0;
-    let chars_per_sec = char_count as f64 / duration.as_secs_f64();
+/// A diagnostic together with the file it points into.
+struct Located {
+    file: String,
+    text: String,
+    diagnostic: Diagnostic,
+}
 
-    println!("{} characters processed in {:.2} ms", char_count, millis);
-    println!("Throughput: {:.2} chars/sec", chars_per_sec)
+/// Parses, resolves and type checks `source`, and loads the modules it
+/// imports, returning every diagnostic.
+fn diagnose(source: &Source, loader: &mut ModuleLoader) -> Vec<Located> {
+    let text = &source.text;
+    let (program, errors) = parse_program(text);
+    let mut diagnostics: Vec<_> = errors
+        .iter()
+        .map(|e| Diagnostic::from_parse_error(e, text))
+        .collect();
+    if diagnostics.is_empty() {
+        let resolution = resolve_program(&program);
+        diagnostics.extend(resolution.errors.iter().map(|e| e.to_diagnostic()));
+        diagnostics.extend(check_program(&program).iter().map(|e| e.to_diagnostic()));
+    }
+    let mut located: Vec<_> = diagnostics
+        .into_iter()
+        .map(|diagnostic| Located {
+            file: source.name.clone(),
+            text: text.clone(),
+            diagnostic,
+        })
+        .collect();
+
+    if !located.iter().any(|l| l.diagnostic.is_error())
+        && let Err(e) = loader.load(Path::new(&source.name), &program)
+    {
+        let text = loader.text(&e.file).unwrap_or(text);
+        for diagnostic in e.diagnostics() {
+            located.push(Located {
+                file: e.file.display().to_string(),
+                text: text.to_string(),
+                diagnostic,
+            });
+        }
+    }
+    located
 }
 
-fn parsing(lexer: Lexer<'_>, print: bool) -> () {
-    let parser = ProgramParser::new();
-    let result = parser.parse(lexer);
+/// Prints the diagnostics to stderr, or as JSON to stdout, returning the
+/// errors count, warnings aside.
+fn report(diagnostics: &[Located], format: Format) -> usize {
+    match format {
+        Format::Pretty => {
+            for l in diagnostics {
+                eprint!("{}", l.diagnostic.render(&l.file, &l.text));
+            }
+        }
+        Format::Debug => {
+            for l in diagnostics {
+                eprintln!("{}: {:?}", l.file, l.diagnostic);
+            }
+        }
+        Format::Json => {
+            let json: Vec<_> = diagnostics
+                .iter()
+                .map(|l| l.diagnostic.to_json(&l.file, &l.text))
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&json).unwrap());
+        }
+    }
+    diagnostics
+        .iter()
+        .filter(|l| l.diagnostic.is_error())
+        .count()
+}
 
-    if let Ok(result) = result {
-        if print {
-            println!("{:?}", result);
-            // for t in result.statements {
-            //     println!("{:?}", t);
-            // }
+/// Modules are looked up next to the file being run.
+fn loader_for(source: &Source) -> ModuleLoader {
+    ModuleLoader::new(Path::new(&source.name).parent().unwrap_or(Path::new("")))
+}
+
+fn checking(input: &Input, format: Format) -> i32 {
+    let diagnostics: Vec<_> = input
+        .read()
+        .iter()
+        .flat_map(|source| diagnose(source, &mut loader_for(source)))
+        .collect();
+    (report(&diagnostics, format) > 0) as i32
+}
+
+fn typing(input: &Input) -> i32 {
+    let mut failed = false;
+    for source in input.read() {
+        if report(&diagnose(&source, &mut loader_for(&source)), Format::Pretty) > 0 {
+            failed = true;
+            continue;
+        }
+        let (program, _) = parse_program(&source.text);
+        for signature in infer_program(&program).0 {
+            println!("{}", signature);
         }
-    } else {
-        println!("{:?}", result);
     }
+    failed as i32
+}
+
+enum Engine {
+    Interpreter,
+    Vm,
+    // the interpreter, running the numeric functions as native code
+    Jit,
 }
 
-fn minifying(lexer: Lexer<'_>) -> () {
-    let minified = minify(lexer);
+fn running(path: &Path, args: &[String], engine: Engine) -> i32 {
+    let source = read_source(path);
+    let mut loader = loader_for(&source);
+    if report(&diagnose(&source, &mut loader), Format::Pretty) > 0 {
+        return 1;
+    }
 
-    fs::write("output.li", minified).expect("Failed to write file");
+    let (program, _) = parse_program(&source.text);
+    let modules = loader.parse();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_args(args.to_vec());
+    let result = match engine {
+        Engine::Vm => {
+            let mut vm = Vm::new(interpreter);
+            for (name, module) in &modules {
+                vm.add_module(*name, module);
+            }
+            vm.run(&program)
+        }
+        engine => {
+            if let Engine::Jit = engine {
+                #[cfg(feature = "jit")]
+                interpreter.set_jit(lira::jit::Jit::new(&program));
+                #[cfg(not(feature = "jit"))]
+                {
+                    eprintln!("error: lira was built without the `jit` feature");
+                    return 1;
+                }
+            }
+            for (name, module) in &modules {
+                interpreter.add_module(*name, module);
+            }
+            interpreter.run(&program)
+        }
+    };
+    match result {
+        Ok(_) => 0,
+        Err(RuntimeError::Exit(code)) => code,
+        Err(e) => {
+            eprintln!("runtime error: {}", e);
+            1
+        }
+    }
 }
 
-fn concat_dir() -> String {
-    let mut r = String::new();
+fn formatting(input: &Input, output: &Output, check: bool) -> i32 {
+    let mut failed = false;
+    let mut formatted = Vec::new();
+    for source in input.read() {
+        let text = match format(&source.text) {
+            Ok(text) => text,
+            Err(FormatError::Syntax(diagnostics)) => {
+                for diagnostic in diagnostics {
+                    eprint!("{}", diagnostic.render(&source.name, &source.text));
+                }
+                failed = true;
+                continue;
+            }
+            Err(e) => {
+                eprintln!("error: {}: {}", source.name, e);
+                failed = true;
+                continue;
+            }
+        };
+        if check {
+            if text != source.text {
+                eprintln!("{} is not formatted", source.name);
+                failed = true;
+            }
+        } else if output.output.is_none() && source.name != "<stdin>" {
+            if text != source.text
+                && let Err(e) = fs::write(&source.name, text)
+            {
+                eprintln!("error: could not write {}: {}", source.name, e);
+                failed = true;
+            }
+        } else {
+            formatted.push(text);
+        }
+    }
+    if !formatted.is_empty() {
+        output.write(&formatted.concat());
+    }
+    failed as i32
+}
 
-    for entry in fs::read_dir("./examples").expect("could not read examples dir") {
-        let p = entry.unwrap().path();
-        println!("Reading file: {:?}", p);
+fn minifying(input: &Input, output: &Output, options: Options, map_path: Option<&Path>) -> i32 {
+    let mut failed = false;
+    let mut minified = String::new();
+    let mut map = SourceMap::default();
+    for source in input.read() {
+        match minify_with_map(&source.text, &source.name, options) {
+            Ok((text, source_map)) => {
+                if !map.sources.is_empty() {
+                    minified.push('\n');
+                }
+                map.append(source_map, minified.len());
+                minified.push_str(&text);
+            }
+            Err(MinifyError::Syntax(diagnostics)) => {
+                for diagnostic in diagnostics {
+                    eprint!("{}", diagnostic.render(&source.name, &source.text));
+                }
+                failed = true;
+            }
+            Err(e) => {
+                eprintln!("error: {}: {}", source.name, e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        return 1;
+    }
+    output.write(&minified);
+    if let Some(path) = map_path
+        && let Err(e) = fs::write(path, serde_json::to_string(&map).unwrap())
+    {
+        eprintln!("error: could not write {}: {}", path.display(), e);
+        return 1;
+    }
+    0
+}
 
-        r.push_str(&fs::read_to_string(p).expect("could not read to string file"));
-        r.push('\n');
+fn mapping(path: &Path, offset: usize) -> i32 {
+    let map: SourceMap = match fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| serde_json::from_str(&text).map_err(|e| e.to_string()))
+    {
+        Ok(map) => map,
+        Err(e) => {
+            eprintln!("error: could not read {}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    let (name, original) = match map.lookup(offset) {
+        Ok(Some(found)) => found,
+        Ok(None) => {
+            eprintln!("error: offset {} is not mapped", offset);
+            return 1;
+        }
+        Err(e) => {
+            eprintln!("error: could not read {}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    match fs::read_to_string(name) {
+        Ok(text) => {
+            let (line, col) = line_col(&text, original);
+            println!("{}:{}:{}", name, line, col);
+        }
+        // without the source, only the byte offset is known
+        Err(_) => println!("{}@{}", name, original),
+    }
+    0
+}
+
+fn documenting(path: &Path, output: &Path, format: DocFormat) -> i32 {
+    let source = read_source(path);
+    let mut loader = loader_for(&source);
+    // unlike `check`, names and types are left alone
+    let (program, errors) = parse_program(&source.text);
+    let diagnostics: Vec<_> = match errors.is_empty() {
+        false => errors
+            .iter()
+            .map(|e| Located {
+                file: source.name.clone(),
+                text: source.text.clone(),
+                diagnostic: Diagnostic::from_parse_error(e, &source.text),
+            })
+            .collect(),
+        true => match loader.load(path, &program) {
+            Ok(()) => vec![],
+            Err(e) => e
+                .diagnostics()
+                .into_iter()
+                .map(|diagnostic| Located {
+                    file: e.file.display().to_string(),
+                    text: loader.text(&e.file).unwrap_or(&source.text).to_string(),
+                    diagnostic,
+                })
+                .collect(),
+        },
+    };
+    if report(&diagnostics, Format::Pretty) > 0 {
+        return 1;
+    }
+
+    let loaded = loader.parse();
+    let root = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut modules = vec![(root.as_ref(), &program)];
+    modules.extend(loaded.iter().map(|(name, module)| (*name, module)));
+    let format = match format {
+        DocFormat::Markdown => doc::Format::Markdown,
+        DocFormat::Html => doc::Format::Html,
+    };
+
+    let written = fs::create_dir_all(output).and_then(|_| {
+        doc::generate(&modules, format)
+            .iter()
+            .try_for_each(|page| fs::write(output.join(&page.path), &page.text))
+    });
+    if let Err(e) = written {
+        eprintln!("error: could not write {}: {}", output.display(), e);
+        return 1;
+    }
+    0
+}
+
+fn benchmarking(input: &Input, repeat: usize) -> i32 {
+    let sources: Vec<_> = input.read().into_iter().map(|source| source.text).collect();
+    let source = sources.join("\n").repeat(repeat);
+
+    let start = std::time::Instant::now();
+    let (_, errors) = parse_program(&source);
+    let duration = start.elapsed();
+
+    let millis = duration.as_secs_f64() * 1000.0;
+    let chars_per_sec = source.len() as f64 / duration.as_secs_f64();
+    println!("{} characters processed in {:.2} ms", source.len(), millis);
+    println!("Throughput: {:.2} chars/sec", chars_per_sec);
+    if !errors.is_empty() {
+        eprintln!("error: the sources have {} syntax error(s)", errors.len());
+        return 1;
     }
+    0
+}
+
+/// Entries are remembered across sessions in this file of the current directory.
+const HISTORY: &str = ".lira_history";
+
+fn repl() {
+    let mut editor = DefaultEditor::new().expect("could not open the terminal");
+    let _ = editor.load_history(HISTORY);
+    let mut session = Session::new(Interpreter::new());
+    println!("lira repl, :help for the commands");
 
-    r
+    let mut entry = String::new();
+    loop {
+        let prompt = if entry.is_empty() { "lira> " } else { "....> " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                entry.push_str(&line);
+                entry.push('\n');
+            }
+            // Ctrl-C drops the entry being typed
+            Err(ReadlineError::Interrupted) => {
+                entry.clear();
+                continue;
+            }
+            Err(_) => break,
+        }
+        if is_incomplete(&entry) {
+            continue;
+        }
+
+        let _ = editor.add_history_entry(entry.trim_end());
+        if matches!(entry.trim(), ":quit" | ":q") {
+            break;
+        }
+        match session.handle(&entry) {
+            Ok(reply) => print!("{}", reply),
+            Err(code) => {
+                let _ = editor.save_history(HISTORY);
+                exit(code);
+            }
+        }
+        entry.clear();
+    }
+    let _ = editor.save_history(HISTORY);
 }
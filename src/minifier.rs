@@ -1,133 +1,186 @@
-use crate::{Lexer, Token};
+mod mangle;
+pub mod source_map;
 
-pub fn minify(mut lexer: Lexer<'_>) -> String {
-    let source = lexer.source();
-    let mut result = String::with_capacity(source.len());
+use std::{collections::HashMap, fmt};
 
-    let mut last_token: Option<Token> = None;
-    let mut space_pending = false;
+use crate::{
+    Lexer, Token,
+    ast::Span,
+    diagnostics::Diagnostic,
+    formatter::{Lines, shape},
+    parser::parse_program,
+};
+use source_map::SourceMap;
 
-    while let Some(Ok((start, token, end))) = lexer.next() {
-        let slice = &source[start..end];
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Options {
+    // leave the comments out instead of keeping each of them
+    pub drop_comments: bool,
+    // give the local bindings the shortest names available
+    pub mangle: bool,
+}
 
-        match &token {
-            Token::LineComment(text) => {
-                flush_space(&mut result, space_pending);
-                result.push_str("//");
-                result.push_str(text.trim_end());
-                result.push('\n');
-            }
-            Token::BlockComment(text) => {
-                flush_space(&mut result, space_pending);
-                result.push_str("/*");
-                result.push_str(text.trim());
-                result.push_str("*/\n");
-            }
-            Token::DocComment(text) => {
-                flush_space(&mut result, space_pending);
-                result.push_str("///");
-                result.push_str(text.trim_end());
-                result.push('\n');
-            }
-            Token::LineEnd => {
-                if let Some(t) = &last_token {
-                    if ends_expr(t) {
-                        result.push(';');
-                    }
-                }
-            }
-            Token::Whitespace => {}
-            Token::LParen => result.push('('),
-            Token::RParen => result.push(')'),
-            Token::LBrace => result.push('{'),
-            Token::RBrace => result.push('}'),
-            Token::LBracket => result.push('['),
-            Token::RBracket => result.push(']'),
-            Token::Dot => result.push('.'),
-            Token::Comma => result.push(','),
-            Token::Colon => result.push(':'),
-            Token::Wildcard => result.push('_'),
-            Token::Arrow => result.push_str("->"),
-            Token::Range => result.push_str(".."),
-
-            _ => {
-                if space_pending || needs_space_between(&last_token, &token) {
-                    flush_space(&mut result, true);
-                }
-                result.push_str(slice);
+#[derive(Debug, Clone, PartialEq)]
+pub enum MinifyError {
+    // the lexing and syntax errors of the source, which is left as is
+    Syntax(Vec<Diagnostic>),
+    // the minified source parses to another program, a bug of the minifier
+    Mismatch,
+}
+
+impl fmt::Display for MinifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MinifyError::Syntax(diagnostics) => {
+                write!(f, "{} syntax error(s), not minified", diagnostics.len())
             }
+            MinifyError::Mismatch => write!(f, "minifying would change the program"),
         }
+    }
+}
+
+/// Writes `source` with as few characters as possible, parsing to the same
+/// program.
+///
+/// Line ends are only kept between statements, as `;`, and a space is only
+/// kept between tokens which would otherwise lex as others. Line and doc
+/// comments are followed by a line end, unless comments are dropped.
+///
+/// When mangling, the bindings local to functions, closures, blocks and match
+/// arms are renamed too, see [`mangle::renames`]. Top-level and imported names
+/// are kept, the minified source being checked to parse to the same program
+/// once the names are given back.
+pub fn minify(source: &str, options: Options) -> Result<String, MinifyError> {
+    minify_with_map(source, "", options).map(|(minified, _)| minified)
+}
+
+/// Minifies `source` like [`minify`], along with the map from the minified
+/// tokens back to the tokens of `source`, named `name` in the map.
+pub fn minify_with_map(
+    source: &str,
+    name: &str,
+    options: Options,
+) -> Result<(String, SourceMap), MinifyError> {
+    let (program, errors) = parse_program(source);
+    if !errors.is_empty() {
+        let diagnostics = errors
+            .iter()
+            .map(|e| Diagnostic::from_parse_error(e, source))
+            .collect();
+        return Err(MinifyError::Syntax(diagnostics));
+    }
 
-        if !matches!(
+    let mut lines = Lines::default();
+    lines.statements(&program.statements);
+    let renames = match options.mangle {
+        true => mangle::renames(&program, source),
+        false => HashMap::new(),
+    };
+
+    let mut result = String::with_capacity(source.len());
+    let mut map = SourceMap::new(name);
+    // the last token written, and the end of the last code token in `source`
+    let mut last: Option<(Token, &str)> = None;
+    let mut code_end = None;
+
+    for (start, token, end) in Lexer::new(source).flatten() {
+        let (token, text) = match token {
+            Token::Ident(name) => {
+                let name = renames.get(name).map_or(name, String::as_str);
+                (Token::Ident(name), name)
+            }
+            token => (token, &source[start..end]),
+        };
+        let comment = matches!(
             token,
-            Token::Whitespace
-                | Token::LineEnd
-                | Token::LineComment(_)
-                | Token::BlockComment(_)
-                | Token::DocComment(_)
-        ) {
-            last_token = Some(token);
+            Token::LineComment(_) | Token::BlockComment(_) | Token::DocComment(_)
+        );
+        if token == Token::LineEnd || comment && options.drop_comments {
+            continue;
         }
 
-        space_pending = false;
+        let separates = !comment
+            && lines.starts.contains(&start)
+            && code_end.is_some_and(|end| lines.ends.contains(&end));
+        if separates && !result.ends_with(['\n', ';']) {
+            result.push(';');
+        } else if let Some((last_token, last_text)) = &last
+            && !result.ends_with(['\n', ';'])
+            && merges(last_token, last_text, &token, text)
+        {
+            result.push(' ');
+        }
+
+        let text = if comment { text.trim_end() } else { text };
+        let generated = result.len();
+        result.push_str(text);
+        map.push(Span::new(generated, result.len()), Span::new(start, end));
+        if !comment {
+            code_end = Some(end);
+        } else if !matches!(token, Token::BlockComment(_)) {
+            result.push('\n');
+        }
+        last = Some((token, text));
     }
 
-    result.shrink_to_fit();
-    result
+    let (reparsed, errors) = parse_program(&result);
+    let originals: HashMap<&str, &str> = renames
+        .iter()
+        .map(|(name, short)| (short.as_str(), *name))
+        .collect();
+    let (mut expected, mut minified) = (shape(&program), unmangle(shape(&reparsed), &originals));
+    if options.drop_comments {
+        undocument(&mut expected);
+        undocument(&mut minified);
+    }
+    if !errors.is_empty() || minified != expected {
+        return Err(MinifyError::Mismatch);
+    }
+    Ok((result, map))
 }
 
-#[inline(always)]
-fn flush_space(result: &mut String, insert: bool) {
-    if insert
-        && !matches!(
-            result.as_bytes().last(),
-            Some(b' ' | b'\n' | b';' | b'{' | b'(' | b'[')
-        )
-    {
-        result.push(' ');
+// the identifiers of a program shape with their original names
+fn unmangle(value: serde_json::Value, originals: &HashMap<&str, &str>) -> serde_json::Value {
+    use serde_json::Value;
+    match value {
+        Value::String(name) => match originals.get(name.as_str()) {
+            Some(original) => Value::String(original.to_string()),
+            None => Value::String(name),
+        },
+        Value::Array(values) => values
+            .into_iter()
+            .map(|value| unmangle(value, originals))
+            .collect(),
+        Value::Object(object) => object
+            .into_iter()
+            .map(|(key, value)| (key, unmangle(value, originals)))
+            .collect(),
+        value => value,
     }
 }
 
-#[inline(always)]
-fn ends_expr(token: &Token) -> bool {
-    matches!(
-        token,
-        Token::Ident(_)
-            | Token::Int(_)
-            | Token::Octal(_)
-            | Token::Binary(_)
-            | Token::Hex(_)
-            | Token::String(_)
-            | Token::Float(_)
-            | Token::RParen
-            | Token::RBracket
-            | Token::RBrace
-    )
+// a program shape without its doc comments, dropped with the other comments
+fn undocument(value: &mut serde_json::Value) {
+    use serde_json::Value;
+    match value {
+        Value::Object(object) => {
+            object.remove("doc");
+            object.values_mut().for_each(undocument);
+        }
+        Value::Array(values) => values.iter_mut().for_each(undocument),
+        _ => {}
+    }
 }
 
-fn needs_space_between(prev: &Option<Token>, next: &Token) -> bool {
-    use Token::*;
-    match (prev, next) {
-        (
-            Some(
-                Fn | Let | Use | Struct | Enum | Async | Await | While | If | Elsif | For | Match
-                | TypeAlias | Return | As | In | With,
-            ),
-            Ident(_),
-        )
-        | (Some(Async), Fn)
-        | (Some(If | While | Return), LParen)
-        | (Some(Spawn), With)
-        | (Some(Let | While | For | Match), Atomic | Lazy | Mut)
-        | (Some(Ident(_)), Ident(_) | In)
-        | (Some(Int(_) | Octal(_) | Hex(_) | Binary(_) | Float(_) | True | False), Ident(_))
-        | (
-            Some(In | While | If | Elsif | Await | Async | Return),
-            Int(_) | Octal(_) | Hex(_) | Binary(_) | Float(_) | True | False,
-        )
-        | (Some(Ident(_)), As | If)
-        | (Some(Atomic | Lazy | Mut), Ident(_) | Atomic | Mut | Lazy) => true,
-
-        _ => false,
-    }
+// whether two tokens written side by side lex as other tokens
+fn merges(last: &Token, last_text: &str, next: &Token, next_text: &str) -> bool {
+    let joined = format!("{}{}", last_text, next_text);
+    let mut tokens = Lexer::new(&joined);
+    let first = tokens.next();
+    let second = tokens.next();
+    !matches!(
+        (first, second, tokens.next()),
+        (Some(Ok((_, a, end))), Some(Ok((_, b, _))), None)
+            if a == *last && b == *next && end == last_text.len()
+    )
 }
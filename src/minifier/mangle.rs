@@ -0,0 +1,332 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    Lexer, Token,
+    ast::{
+        EnumVariant, Expr, LetName, Literal, Match, Pattern, Program, Spanned, Statement,
+        StringSegment, Type,
+    },
+    resolve::{DefKind, PRELUDE, ResolveErrorKind, resolve_program},
+};
+
+/// The shorter names given to the local bindings of `program`, by their
+/// original name.
+///
+/// A name is renamed everywhere or nowhere: only the names of bindings local
+/// to a function, closure, block or match arm are, and only when nothing else
+/// (a top-level or imported name, a field, a type, an interpolated
+/// identifier) uses them too. The new names are not found anywhere in
+/// `source`, so that they cannot capture another binding.
+pub(super) fn renames<'i>(program: &'i Program<'i>, source: &str) -> HashMap<&'i str, String> {
+    let resolution = resolve_program(program);
+    // the scope of the top-level statements, right under the prelude
+    let top_level = PRELUDE + 1;
+
+    let mut fixed = Fixed::default();
+    fixed.statements(&program.statements);
+    let mut local = HashMap::new();
+    for definition in &resolution.definitions {
+        let renamed = definition.scope != top_level
+            && matches!(
+                definition.kind,
+                DefKind::Function
+                    | DefKind::Let
+                    | DefKind::Param
+                    | DefKind::Iterator
+                    | DefKind::Binding
+            );
+        if renamed {
+            local.insert(definition.name, 0);
+        } else {
+            fixed.names.insert(definition.name);
+        }
+    }
+    for error in &resolution.errors {
+        if let ResolveErrorKind::Undefined(name) = &error.kind
+            && let Some((&name, _)) = local.get_key_value(name.as_str())
+        {
+            fixed.names.insert(name);
+        }
+    }
+    local.retain(|name, _| !fixed.names.contains(name));
+
+    // the most used names get the shortest replacements
+    for (_, token, _) in Lexer::new(source).flatten() {
+        if let Token::Ident(name) = token
+            && let Some(count) = local.get_mut(name)
+        {
+            *count += 1;
+        }
+    }
+    let mut names: Vec<_> = local.into_iter().collect();
+    names.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+
+    let words = words(source);
+    let mut fresh = (0..)
+        .map(short_name)
+        .filter(|name| {
+            !words.contains(name.as_str())
+                && !crate::eval::builtins::NAMES.contains(&name.as_str())
+                && is_identifier(name)
+        })
+        .peekable();
+    let mut renames = HashMap::new();
+    for (name, _) in names {
+        // names already as short are left as they are
+        if fresh.peek().is_some_and(|short| short.len() < name.len()) {
+            renames.insert(name, fresh.next().unwrap());
+        }
+    }
+    renames
+}
+
+// `a` to `z`, then `aa`, `ab`, ..., a letter followed by letters or digits
+fn short_name(mut index: usize) -> String {
+    const FIRST: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+    const REST: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+    // the names of the length of this one
+    let mut count = FIRST.len();
+    while index >= count {
+        index -= count;
+        count *= REST.len();
+    }
+    let mut place = count / FIRST.len();
+    let mut name = String::from(FIRST[index / place] as char);
+    while place > 1 {
+        index %= place;
+        place /= REST.len();
+        name.push(REST[index / place] as char);
+    }
+    name
+}
+
+// keywords lex as other tokens
+fn is_identifier(name: &str) -> bool {
+    let mut tokens = Lexer::new(name);
+    matches!(tokens.next(), Some(Ok((_, Token::Ident(_), _)))) && tokens.next().is_none()
+}
+
+// every word of the source, strings and comments included
+fn words(source: &str) -> HashSet<&str> {
+    source
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// The names used for something else than a local binding.
+#[derive(Default)]
+struct Fixed<'i> {
+    names: HashSet<&'i str>,
+    // within a string, where the identifiers are left as written
+    interpolated: bool,
+}
+
+impl<'i> Fixed<'i> {
+    fn statements(&mut self, stmts: &[Spanned<Statement<'i>>]) {
+        for stmt in stmts {
+            self.statement(&stmt.node);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement<'i>) {
+        match stmt {
+            Statement::FnDecl {
+                params,
+                return_type,
+                body,
+                ..
+            } => {
+                self.params(params);
+                if let Some(ty) = return_type {
+                    self.ty(ty);
+                }
+                self.statements(body);
+            }
+            Statement::Let {
+                name, ty, value, ..
+            } => {
+                self.let_name(name);
+                if let Some(ty) = ty {
+                    self.ty(ty);
+                }
+                self.expr(value);
+            }
+            Statement::Match(m) => self.match_(m),
+            Statement::Spawn { body, with } => {
+                if let Some(with) = with {
+                    self.expr(with);
+                }
+                self.statements(body);
+            }
+            Statement::ForLoop { range, body, .. } => {
+                self.expr(range);
+                self.statements(body);
+            }
+            Statement::WhileLoop { condition, body } => {
+                self.expr(condition);
+                self.statements(body);
+            }
+            Statement::If {
+                condition,
+                body,
+                else_ifs,
+                else_body,
+            } => {
+                self.expr(condition);
+                self.statements(body);
+                for (condition, body) in else_ifs {
+                    self.expr(condition);
+                    self.statements(body);
+                }
+                if let Some(body) = else_body {
+                    self.statements(body);
+                }
+            }
+            Statement::Expr(expr) | Statement::Return(expr) => self.expr(expr),
+            Statement::TypeAlias { ty, .. } => self.ty(ty),
+            Statement::Struct { fields, .. } => {
+                for field in fields {
+                    let (name, ty) = &field.node;
+                    self.names.insert(name);
+                    self.ty(ty);
+                }
+            }
+            Statement::Enum { variants, .. } => {
+                for variant in variants {
+                    match &variant.node {
+                        EnumVariant::Unit(name) => {
+                            self.names.insert(name);
+                        }
+                        EnumVariant::Tuple(name, types) => {
+                            self.names.insert(name);
+                            types.iter().for_each(|ty| self.ty(ty));
+                        }
+                        EnumVariant::Struct(name, fields) => {
+                            self.names.insert(name);
+                            self.names.extend(fields.iter().map(|(field, _)| field));
+                            self.params(fields);
+                        }
+                    }
+                }
+            }
+            Statement::Use { path, alias } => {
+                self.names.extend(path.iter().copied());
+                self.names.extend(alias);
+            }
+            Statement::Break | Statement::Error => {}
+        }
+    }
+
+    fn params(&mut self, params: &[(&'i str, Option<Spanned<Type<'i>>>)]) {
+        for (_, ty) in params {
+            if let Some(ty) = ty {
+                self.ty(ty);
+            }
+        }
+    }
+
+    fn let_name(&mut self, name: &LetName<'i>) {
+        match name {
+            LetName::Ident(_) => {}
+            LetName::ArrayDestructure(names) | LetName::TupleDestructure(names) => {
+                names.iter().for_each(|name| self.let_name(name))
+            }
+            LetName::StructDestructure(name, fields) => {
+                self.names.insert(name);
+                self.names.extend(fields.iter().map(|(field, _)| field));
+            }
+        }
+    }
+
+    fn match_(&mut self, m: &Match<'i>) {
+        self.expr(&m.expr);
+        for arm in &m.arms {
+            match &arm.pattern.node {
+                Pattern::Ident(name) if name.starts_with(char::is_uppercase) => {
+                    self.names.insert(name);
+                }
+                Pattern::FunctionDestructor(name, _) => {
+                    self.names.insert(name);
+                }
+                Pattern::StructLikeDestructor(name, fields) => {
+                    self.names.insert(name);
+                    self.names.extend(fields.iter().map(|(field, _)| field));
+                }
+                Pattern::Literal(literal) => self.literal(literal),
+                Pattern::Ident(_) | Pattern::Wildcard => {}
+            }
+            if let Some(guard) = &arm.guard {
+                self.expr(guard);
+            }
+            self.statements(&arm.body);
+        }
+    }
+
+    fn literal(&mut self, literal: &Literal<'i>) {
+        if let Literal::String(segments) = literal {
+            let outer = std::mem::replace(&mut self.interpolated, true);
+            for segment in segments {
+                if let StringSegment::Interpolation(expr) = segment {
+                    self.expr(expr);
+                }
+            }
+            self.interpolated = outer;
+        }
+    }
+
+    fn expr(&mut self, expr: &Spanned<Expr<'i>>) {
+        match &expr.node {
+            Expr::Ident(name) => {
+                if self.interpolated {
+                    self.names.insert(name);
+                }
+            }
+            Expr::Literal(literal) => self.literal(literal),
+            Expr::Array(items) | Expr::Tuple(items) => items.iter().for_each(|e| self.expr(e)),
+            Expr::Match(m) => self.match_(m),
+            Expr::Awaitable(e) | Expr::Unary(_, e) => self.expr(e),
+            Expr::FieldAccess(e, field) => {
+                self.names.insert(field);
+                self.expr(e);
+            }
+            Expr::Binary(lhs, _, rhs)
+            | Expr::Pipe(lhs, rhs)
+            | Expr::Range(lhs, rhs)
+            | Expr::Assign(lhs, _, rhs)
+            | Expr::Index(lhs, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::Call(callee, args) => {
+                self.expr(callee);
+                args.iter().for_each(|e| self.expr(e));
+            }
+            Expr::Closure(params, body) => {
+                self.params(params);
+                self.statements(body);
+            }
+            Expr::Spawn(with, body) => {
+                if let Some(with) = with {
+                    self.expr(with);
+                }
+                self.statements(body);
+            }
+        }
+    }
+
+    fn ty(&mut self, ty: &Spanned<Type<'i>>) {
+        match &ty.node {
+            Type::Ident(name) => {
+                self.names.insert(name);
+            }
+            Type::Tuple(types) | Type::Array(types) => types.iter().for_each(|ty| self.ty(ty)),
+            Type::Function(params, ret) | Type::AsyncFunction(params, ret) => {
+                params.iter().for_each(|ty| self.ty(ty));
+                self.ty(ret);
+            }
+            Type::Awaitable(ty) => self.ty(ty),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ast::Span;
+
+/// Where the tokens of a minified source were taken from.
+///
+/// Written as JSON next to the minified source, a table of byte ranges
+/// rather than the line and column based Source Map v3 format.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SourceMap {
+    // the names of the original sources, which segments refer to by index
+    pub sources: Vec<String>,
+    // in the order of the minified source
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    pub generated: Span,
+    pub source: usize,
+    pub original: Span,
+}
+
+/// A segment of a source map read from a file that does not make sense.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    // the segment refers to a source the map does not list
+    UnknownSource(usize),
+    // the generated or original range of the segment ends before it starts
+    InvalidSpan(Span),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::UnknownSource(index) => write!(f, "no source at index {}", index),
+            DecodeError::InvalidSpan(span) => {
+                write!(f, "invalid range {}..{}", span.start, span.end)
+            }
+        }
+    }
+}
+
+impl SourceMap {
+    pub(super) fn new(name: &str) -> Self {
+        SourceMap {
+            sources: vec![name.to_string()],
+            segments: Vec::new(),
+        }
+    }
+
+    pub(super) fn push(&mut self, generated: Span, original: Span) {
+        self.segments.push(Segment {
+            generated,
+            source: 0,
+            original,
+        });
+    }
+
+    /// Appends the map of a source minified `offset` bytes into the same
+    /// output, for sources minified one after the other.
+    pub fn append(&mut self, other: SourceMap, offset: usize) {
+        let first = self.sources.len();
+        self.sources.extend(other.sources);
+        self.segments
+            .extend(other.segments.into_iter().map(|segment| Segment {
+                generated: Span::new(
+                    segment.generated.start + offset,
+                    segment.generated.end + offset,
+                ),
+                source: segment.source + first,
+                original: segment.original,
+            }));
+    }
+
+    /// The original source and byte offset of a byte of the minified source,
+    /// if it is not past its last token.
+    ///
+    /// Bytes added by the minifier (separators and spaces) are mapped to the
+    /// end of the token before them, and bytes of renamed identifiers to the
+    /// start of the original name. Fails on a segment that does not make
+    /// sense, the map having been read from a file.
+    pub fn lookup(&self, offset: usize) -> Result<Option<(&str, usize)>, DecodeError> {
+        let Some(index) = self
+            .segments
+            .partition_point(|segment| segment.generated.start <= offset)
+            .checked_sub(1)
+        else {
+            return Ok(None);
+        };
+        let segment = &self.segments[index];
+        for span in [segment.generated, segment.original] {
+            if span.end < span.start {
+                return Err(DecodeError::InvalidSpan(span));
+            }
+        }
+        let source = self
+            .sources
+            .get(segment.source)
+            .ok_or(DecodeError::UnknownSource(segment.source))?;
+        if index == self.segments.len() - 1 && offset >= segment.generated.end {
+            return Ok(None);
+        }
+        let original = if offset >= segment.generated.end {
+            segment.original.end
+        } else if segment.generated.end - segment.generated.start
+            == segment.original.end - segment.original.start
+        {
+            segment.original.start + offset - segment.generated.start
+        } else {
+            segment.original.start
+        };
+        Ok(Some((source, original)))
+    }
+}
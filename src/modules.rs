@@ -0,0 +1,259 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ast::{Program, Span, Spanned, Statement},
+    diagnostics::Diagnostic,
+    eval::stdlib,
+    parser::parse_program,
+};
+
+/// The root of the paths reserved for the standard library (`use std::Json`).
+pub const STD: &str = "std";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModuleErrorKind {
+    Unreadable {
+        name: String,
+        path: PathBuf,
+        reason: String,
+        span: Span,
+    },
+    // the modules importing each other, the first one repeated at the end
+    Cycle {
+        chain: Vec<String>,
+        span: Span,
+    },
+    // a `std::` path naming no module of the standard library
+    UnknownStd {
+        name: String,
+        span: Span,
+    },
+    Syntax(Vec<Diagnostic>),
+}
+
+/// An error met while loading modules, `file` being the one it is located in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleError {
+    pub file: PathBuf,
+    pub kind: ModuleErrorKind,
+}
+
+impl ModuleError {
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match &self.kind {
+            ModuleErrorKind::Unreadable { path, span, .. } => {
+                vec![
+                    Diagnostic::new(self.to_string(), *span)
+                        .with_note(format!("looked for {}", path.display())),
+                ]
+            }
+            ModuleErrorKind::Cycle { span, .. } | ModuleErrorKind::UnknownStd { span, .. } => {
+                vec![Diagnostic::new(self.to_string(), *span)]
+            }
+            ModuleErrorKind::Syntax(diagnostics) => diagnostics.clone(),
+        }
+    }
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ModuleErrorKind::Unreadable { name, reason, .. } => {
+                write!(f, "cannot load module `{}`: {}", name, reason)
+            }
+            ModuleErrorKind::Cycle { chain, .. } => {
+                write!(f, "import cycle: {}", chain.join(" -> "))
+            }
+            ModuleErrorKind::UnknownStd { name, .. } => {
+                write!(f, "no module `{}` in the standard library", name)
+            }
+            ModuleErrorKind::Syntax(_) => {
+                write!(f, "syntax errors in {}", self.file.display())
+            }
+        }
+    }
+}
+
+/// A loaded module file.
+#[derive(Debug, Clone)]
+pub struct Source {
+    // the `use` path, e.g. `utils::math`
+    pub name: String,
+    pub file: PathBuf,
+    pub text: String,
+}
+
+/// Loads the files imported by `use` paths, `use utils::math` being the file
+/// `utils/math.li` under the project root.
+#[derive(Debug)]
+pub struct ModuleLoader {
+    root: PathBuf,
+    // dependencies before the modules using them
+    sources: Vec<Source>,
+    // the chain of modules being loaded, to detect cycles
+    loading: Vec<String>,
+    // every file read, including those that failed to parse
+    texts: HashMap<PathBuf, String>,
+}
+
+impl ModuleLoader {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            sources: Vec::new(),
+            loading: Vec::new(),
+            texts: HashMap::new(),
+        }
+    }
+
+    /// The file a `use` path refers to.
+    pub fn path_of(&self, path: &[&str]) -> PathBuf {
+        let mut file = self.root.clone();
+        file.extend(path);
+        file.set_extension("li");
+        file
+    }
+
+    /// Loads, recursively, the modules imported by `program`, the content of
+    /// `file`. The standard library is not loaded from files, its modules
+    /// only have to exist.
+    pub fn load(&mut self, file: &Path, program: &Program) -> Result<(), ModuleError> {
+        let name = file
+            .strip_prefix(&self.root)
+            .unwrap_or(file)
+            .with_extension("")
+            .iter()
+            .map(|part| part.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("::");
+        self.loading.push(name);
+        let result = self.load_imports(file, &program.statements);
+        self.loading.pop();
+        result
+    }
+
+    fn load_imports(
+        &mut self,
+        file: &Path,
+        stmts: &[Spanned<Statement>],
+    ) -> Result<(), ModuleError> {
+        let mut imports = Vec::new();
+        collect_imports(stmts, &mut imports);
+
+        for (path, span) in imports {
+            let name = path.join("::");
+            if path.first() == Some(&STD) {
+                if !stdlib::MODULES.contains(&name.as_str()) {
+                    return Err(ModuleError {
+                        file: file.to_path_buf(),
+                        kind: ModuleErrorKind::UnknownStd { name, span },
+                    });
+                }
+                continue;
+            }
+            if let Some(start) = self.loading.iter().position(|loading| *loading == name) {
+                let mut chain = self.loading[start..].to_vec();
+                chain.push(name);
+                return Err(ModuleError {
+                    file: file.to_path_buf(),
+                    kind: ModuleErrorKind::Cycle { chain, span },
+                });
+            }
+            if self.sources.iter().any(|source| source.name == name) {
+                continue;
+            }
+
+            let module_file = self.path_of(&path);
+            let text = std::fs::read_to_string(&module_file).map_err(|e| ModuleError {
+                file: file.to_path_buf(),
+                kind: ModuleErrorKind::Unreadable {
+                    name: name.clone(),
+                    path: module_file.clone(),
+                    reason: e.to_string(),
+                    span,
+                },
+            })?;
+            self.texts.insert(module_file.clone(), text.clone());
+
+            let (program, errors) = parse_program(&text);
+            if !errors.is_empty() {
+                return Err(ModuleError {
+                    file: module_file,
+                    kind: ModuleErrorKind::Syntax(
+                        errors
+                            .iter()
+                            .map(|e| Diagnostic::from_parse_error(e, &text))
+                            .collect(),
+                    ),
+                });
+            }
+
+            self.loading.push(name.clone());
+            let loaded = self.load_imports(&module_file, &program.statements);
+            self.loading.pop();
+            loaded?;
+
+            self.sources.push(Source {
+                name,
+                file: module_file,
+                text,
+            });
+        }
+        Ok(())
+    }
+
+    /// The loaded modules, dependencies first.
+    pub fn sources(&self) -> &[Source] {
+        &self.sources
+    }
+
+    /// The text of a module file read by the loader, to render its errors.
+    pub fn text(&self, file: &Path) -> Option<&str> {
+        self.texts.get(file).map(String::as_str)
+    }
+
+    /// Parses every loaded module, which all parsed successfully while loading.
+    pub fn parse(&self) -> Vec<(&str, Program<'_>)> {
+        self.sources
+            .iter()
+            .map(|source| (source.name.as_str(), parse_program(&source.text).0))
+            .collect()
+    }
+}
+
+/// The `use` statements of a block and of the blocks nested in it.
+fn collect_imports<'i>(stmts: &[Spanned<Statement<'i>>], imports: &mut Vec<(Vec<&'i str>, Span)>) {
+    for stmt in stmts {
+        match &stmt.node {
+            Statement::Use { path, .. } => imports.push((path.clone(), stmt.span)),
+            Statement::FnDecl { body, .. }
+            | Statement::Spawn { body, .. }
+            | Statement::ForLoop { body, .. }
+            | Statement::WhileLoop { body, .. } => collect_imports(body, imports),
+            Statement::If {
+                body,
+                else_ifs,
+                else_body,
+                ..
+            } => {
+                collect_imports(body, imports);
+                for (_, body) in else_ifs {
+                    collect_imports(body, imports);
+                }
+                if let Some(body) = else_body {
+                    collect_imports(body, imports);
+                }
+            }
+            Statement::Match(m) => {
+                for arm in &m.arms {
+                    collect_imports(&arm.body, imports);
+                }
+            }
+            _ => {}
+        }
+    }
+}
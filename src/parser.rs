@@ -1,5 +1,72 @@
-use lalrpop_util::lalrpop_mod;
+use lalrpop_util::{ParseError, lalrpop_mod};
 
-lalrpop_mod!(grammar, "/parser/grammar.rs");
+use crate::{
+    Lexer, LexingError, Token,
+    ast::{Program, StringSegment},
+    lexer::str_litteral::StringPart,
+};
 
-pub use grammar::ProgramParser;
+lalrpop_mod!(
+    #[allow(clippy::all)]
+    grammar,
+    "/parser/grammar.rs"
+);
+
+pub use grammar::{ExprParser, ProgramParser};
+
+pub type SyntaxError<'i> = ParseError<usize, Token<'i>, LexingError>;
+
+/// Parses `source`, recovering from syntax errors at statement boundaries.
+///
+/// Statements that failed to parse are kept as [`Statement::Error`]
+/// placeholders, and every error met along the way is returned in source
+/// order. When the parser cannot recover at all, the program is empty.
+///
+/// [`Statement::Error`]: crate::ast::Statement::Error
+pub fn parse_program(source: &str) -> (Program<'_>, Vec<SyntaxError<'_>>) {
+    let mut recovered = Vec::new();
+    let result = ProgramParser::new().parse(&mut recovered, Lexer::new(source));
+
+    let mut errors: Vec<_> = recovered.into_iter().map(|e| e.error).collect();
+    let program = match result {
+        Ok(program) => program,
+        Err(e) => {
+            errors.push(e);
+            Program { statements: vec![] }
+        }
+    };
+    (program, errors)
+}
+
+/// Turns the parts produced by the lexer for a string literal into segments,
+/// parsing every `#{...}` interpolation into an expression.
+///
+/// The lexer positions interpolated tokens within the literal, they are
+/// shifted by its `start` so that spans inside an interpolation are absolute.
+pub(crate) fn string_segments<'i>(
+    parts: Vec<StringPart<'i>>,
+    start: usize,
+) -> Result<Vec<StringSegment<'i>>, SyntaxError<'i>> {
+    // past the opening quote
+    let offset = start + 1;
+    parts
+        .into_iter()
+        .map(|part| {
+            Ok(match part {
+                StringPart::Text(text) => StringSegment::Text(text),
+                StringPart::EscapeChar(c) | StringPart::Unicode(c) => StringSegment::Char(c),
+                StringPart::Expression(tokens) => {
+                    let tokens = tokens
+                        .into_iter()
+                        .map(|(l, token, r)| Ok((offset + l, token, offset + r)));
+                    // expressions have no recovery points, nothing is pushed there
+                    let expr = ExprParser::new()
+                        .parse(&mut Vec::new(), tokens)
+                        // an empty interpolation ends at 0
+                        .map_err(|e| e.map_location(|l| l.max(start)))?;
+                    StringSegment::Interpolation(Box::new(expr))
+                }
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,186 @@
+use crate::{
+    Lexer, ProgramParser, Token,
+    ast::{Program, Span},
+    diagnostics::Diagnostic,
+    eval::{Interpreter, RuntimeError, value::Value},
+    parser::parse_program,
+    typeck::{TypeError, infer_program},
+};
+
+/// Name of the entry file in diagnostics.
+const FILE: &str = "<repl>";
+
+const HELP: &str = "\
+:tokens <code>  print the tokens of <code>
+:ast <code>     print the syntax tree of <code>
+:type <expr>    print the inferred type of <expr>
+:help           print this message
+:quit           leave the REPL
+";
+
+/// Whether `source` has an open `{`, `(` or `[`, or an unterminated string,
+/// so that the REPL keeps reading lines before running it.
+pub fn is_incomplete(source: &str) -> bool {
+    let mut depth = 0;
+    let mut lexer = Lexer::new(source);
+    while let Some(token) = lexer.next() {
+        match token {
+            Ok((_, Token::LParen | Token::LBracket | Token::LBrace, _)) => depth += 1,
+            Ok((_, Token::RParen | Token::RBracket | Token::RBrace, _)) => depth -= 1,
+            Ok(_) => {}
+            // a string is unterminated when no other quote follows its opening one
+            Err(_) if lexer.slice().starts_with('"') => {
+                return !source[lexer.end()..].contains('"');
+            }
+            Err(_) => {}
+        }
+    }
+    depth > 0
+}
+
+/// The state of a REPL: the bindings of the entries run so far.
+///
+/// Entries that ran are kept alive for the whole session since their values
+/// borrow from them.
+pub struct Session {
+    interpreter: Interpreter<'static>,
+    // the entries that parsed, to infer types in their context
+    source: String,
+}
+
+impl Session {
+    pub fn new(interpreter: Interpreter<'static>) -> Self {
+        Self {
+            interpreter,
+            source: String::new(),
+        }
+    }
+
+    /// Runs an entry or a `:` command, returning what to print.
+    ///
+    /// Fails with the exit code when the entry calls `exit`.
+    pub fn handle(&mut self, entry: &str) -> Result<String, i32> {
+        let entry = entry.trim();
+        let Some(command) = entry.strip_prefix(':') else {
+            return self.run(entry);
+        };
+        let (command, code) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        let code = code.trim();
+        Ok(match command {
+            "tokens" => tokens(code),
+            "ast" => ast(code),
+            "type" => self.type_of(code),
+            "help" => HELP.to_string(),
+            _ => format!("unknown command `:{}`, try `:help`\n", command),
+        })
+    }
+
+    fn run(&mut self, entry: &str) -> Result<String, i32> {
+        let mut source = entry.to_string();
+        let mut errors = syntax_errors(&source);
+        // an expression which is not a statement on its own is bound to `it`
+        let expression = !errors.is_empty();
+        if expression {
+            let binding = format!("let it = {}", entry);
+            if syntax_errors(&binding).is_empty() {
+                (source, errors) = (binding, String::new());
+            }
+        }
+        if !errors.is_empty() {
+            return Ok(errors);
+        }
+        self.source.push_str(&source);
+        self.source.push('\n');
+
+        let value = self.interpreter.exec_program(keep(source));
+        let value = match value {
+            Ok(_) if expression => self.interpreter.globals().get("it").unwrap(),
+            Ok(value) => value,
+            Err(RuntimeError::Exit(code)) => return Err(code),
+            Err(e) => return Ok(format!("runtime error: {}\n", e)),
+        };
+        match value {
+            Value::Unit => Ok(String::new()),
+            value => Ok(format!("{}\n", value)),
+        }
+    }
+
+    /// Infers the type of `expr` bound after the previous entries.
+    fn type_of(&self, expr: &str) -> String {
+        let binding = format!("let it = {}\n", expr);
+        let errors = parse_program(&binding).1;
+        if !errors.is_empty() {
+            return errors
+                .iter()
+                .map(|e| Diagnostic::from_parse_error(e, &binding).render(FILE, &binding))
+                .collect();
+        }
+
+        let offset = self.source.len();
+        let source = format!("{}{}", self.source, binding);
+        let (program, _) = parse_program(&source);
+        let (signatures, errors) = infer_program(&program);
+        // the errors of the previous entries were already ignored when they ran
+        let errors: String = errors
+            .iter()
+            .filter(|e| e.span.start >= offset)
+            .map(|e| {
+                let span = Span::new(e.span.start - offset, e.span.end - offset);
+                let error = TypeError { span, ..e.clone() };
+                error.to_diagnostic().render(FILE, &binding)
+            })
+            .collect();
+        if !errors.is_empty() {
+            return errors;
+        }
+        match signatures.iter().rev().find(|s| s.name == "it") {
+            Some(signature) => format!("{}\n", signature.ty),
+            None => String::new(),
+        }
+    }
+}
+
+/// The rendered syntax errors of an entry, if any.
+fn syntax_errors(source: &str) -> String {
+    parse_program(source)
+        .1
+        .iter()
+        .map(|e| Diagnostic::from_parse_error(e, source).render(FILE, source))
+        .collect()
+}
+
+/// Parses an entry without syntax errors for good, since the values it
+/// defines borrow from it.
+fn keep(source: String) -> &'static Program<'static> {
+    let source: &'static str = Box::leak(source.into_boxed_str());
+    Box::leak(Box::new(parse_program(source).0))
+}
+
+fn tokens(code: &str) -> String {
+    Lexer::new(code)
+        .map(|token| match token {
+            Ok((_, token, _)) => format!("{:?}\n", token),
+            Err(e) => format!("error: {}\n", e),
+        })
+        .collect()
+}
+
+fn ast(code: &str) -> String {
+    let mut errors = Vec::new();
+    let result = ProgramParser::new().parse(&mut errors, Lexer::new(code));
+    let mut out: String = errors
+        .iter()
+        .map(|e| Diagnostic::from_parse_error(&e.error, code).render(FILE, code))
+        .collect();
+    match result {
+        Ok(program) => {
+            for stmt in &program.statements {
+                out.push_str(&format!("{:#?}\n", stmt.node));
+            }
+        }
+        Err(e) => out.push_str(&Diagnostic::from_parse_error(&e, code).render(FILE, code)),
+    }
+    out
+}
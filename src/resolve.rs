@@ -0,0 +1,494 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    ast::{
+        Expr, LetName, Literal, Match, Pattern, Program, Span, Spanned, Statement, StringSegment,
+        Type,
+    },
+    diagnostics::Diagnostic,
+    eval::builtins,
+};
+
+pub type ScopeId = usize;
+pub type DefId = usize;
+
+/// The scope holding the built-in functions, parent of the program scope.
+pub const PRELUDE: ScopeId = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefKind {
+    Builtin,
+    Import,
+    Function,
+    Struct,
+    Enum,
+    TypeAlias,
+    Let,
+    Param,
+    Iterator,
+    // a name bound by a match pattern
+    Binding,
+    // the value given to `spawn with`, bound as `context` in its block
+    Context,
+}
+
+impl DefKind {
+    // items are hoisted to the top of their block and cannot be redeclared
+    fn is_item(self) -> bool {
+        matches!(
+            self,
+            DefKind::Function | DefKind::Struct | DefKind::Enum | DefKind::TypeAlias
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition<'i> {
+    pub name: &'i str,
+    pub kind: DefKind,
+    // the statement, closure or pattern introducing the name
+    pub span: Span,
+    pub scope: ScopeId,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Scope {
+    pub parent: Option<ScopeId>,
+    pub span: Span,
+    // in declaration order
+    pub definitions: Vec<DefId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResolveErrorKind {
+    Undefined(String),
+    Duplicate { name: String, first: Span },
+    Shadowed { name: String, previous: Span },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolveError {
+    pub kind: ResolveErrorKind,
+    pub span: Span,
+}
+
+impl ResolveError {
+    /// Shadowing is legal, it is only reported as a warning (for `let` and
+    /// loop bindings, parameters and patterns shadow freely).
+    pub fn is_warning(&self) -> bool {
+        matches!(self.kind, ResolveErrorKind::Shadowed { .. })
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        match &self.kind {
+            ResolveErrorKind::Undefined(_) => {
+                Diagnostic::new(self.to_string(), self.span).with_label("not found in this scope")
+            }
+            ResolveErrorKind::Duplicate { .. } => {
+                Diagnostic::new(self.to_string(), self.span).with_label("redefined here")
+            }
+            ResolveErrorKind::Shadowed { .. } => Diagnostic::warning(self.to_string(), self.span),
+        }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ResolveErrorKind::Undefined(name) => write!(f, "cannot find `{}` in this scope", name),
+            ResolveErrorKind::Duplicate { name, .. } => {
+                write!(f, "`{}` is defined multiple times", name)
+            }
+            ResolveErrorKind::Shadowed { name, .. } => {
+                write!(f, "`{}` shadows an earlier binding", name)
+            }
+        }
+    }
+}
+
+/// The scope tree of a program and what each identifier refers to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resolution<'i> {
+    pub scopes: Vec<Scope>,
+    pub definitions: Vec<Definition<'i>>,
+    // span of an identifier expression to the definition it uses
+    pub references: HashMap<Span, DefId>,
+    pub errors: Vec<ResolveError>,
+}
+
+impl<'i> Resolution<'i> {
+    /// The definition used by the identifier at `span`.
+    pub fn definition(&self, span: Span) -> Option<&Definition<'i>> {
+        self.references.get(&span).map(|&id| &self.definitions[id])
+    }
+
+    /// Spans of the identifiers using `def`, in source order.
+    pub fn uses(&self, def: DefId) -> Vec<Span> {
+        let mut uses: Vec<Span> = self
+            .references
+            .iter()
+            .filter(|(_, id)| **id == def)
+            .map(|(span, _)| *span)
+            .collect();
+        uses.sort_by_key(|span| span.start);
+        uses
+    }
+
+    /// The innermost scope containing `offset`.
+    pub fn scope_at(&self, offset: usize) -> ScopeId {
+        (1..self.scopes.len())
+            .rev()
+            .find(|&id| {
+                let span = self.scopes[id].span;
+                span.start <= offset && offset <= span.end
+            })
+            .unwrap_or(PRELUDE)
+    }
+}
+
+/// Builds the scope tree of `program`, linking every identifier to the
+/// binding that introduced it.
+///
+/// Items (functions, structs, enums and type aliases) are visible in their
+/// whole block, `let` bindings from the next statement on. Function and
+/// closure bodies run later, so they are resolved at the end of their block
+/// and see all of its bindings.
+pub fn resolve_program<'a>(program: &'a Program<'a>) -> Resolution<'a> {
+    let mut resolver = Resolver {
+        resolution: Resolution {
+            scopes: vec![Scope::default()],
+            definitions: Vec::new(),
+            references: HashMap::new(),
+            errors: Vec::new(),
+        },
+        current: PRELUDE,
+        deferred: Vec::new(),
+    };
+    for name in builtins::NAMES {
+        resolver.define(name, DefKind::Builtin, Span::default());
+    }
+
+    let span = match (program.statements.first(), program.statements.last()) {
+        (Some(first), Some(last)) => first.span.to(last.span),
+        _ => Span::default(),
+    };
+    resolver.resolve_block(&program.statements, span);
+    resolver.resolution
+}
+
+struct Body<'a> {
+    scope: ScopeId,
+    span: Span,
+    stmts: &'a [Spanned<Statement<'a>>],
+}
+
+struct Resolver<'a> {
+    resolution: Resolution<'a>,
+    current: ScopeId,
+    // bodies of the functions and closures met in each enclosing block
+    deferred: Vec<Vec<Body<'a>>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn error(&mut self, kind: ResolveErrorKind, span: Span) {
+        self.resolution.errors.push(ResolveError { kind, span });
+    }
+
+    fn enter(&mut self, span: Span) -> ScopeId {
+        self.resolution.scopes.push(Scope {
+            parent: Some(self.current),
+            span,
+            definitions: Vec::new(),
+        });
+        self.current = self.resolution.scopes.len() - 1;
+        self.current
+    }
+
+    fn leave(&mut self) {
+        self.current = self.resolution.scopes[self.current]
+            .parent
+            .expect("the prelude is never left");
+    }
+
+    fn lookup(&self, name: &str) -> Option<DefId> {
+        let mut scope = Some(self.current);
+        while let Some(id) = scope {
+            let found = self.resolution.scopes[id]
+                .definitions
+                .iter()
+                .rev()
+                .find(|&&def| self.resolution.definitions[def].name == name);
+            if let Some(&def) = found {
+                return Some(def);
+            }
+            scope = self.resolution.scopes[id].parent;
+        }
+        None
+    }
+
+    fn define(&mut self, name: &'a str, kind: DefKind, span: Span) {
+        if let Some(previous) = self.lookup(name) {
+            let previous = &self.resolution.definitions[previous];
+            let same_scope = previous.scope == self.current;
+            let previous_span = previous.span;
+
+            if kind.is_item() && previous.kind.is_item() && same_scope {
+                self.error(
+                    ResolveErrorKind::Duplicate {
+                        name: name.to_string(),
+                        first: previous_span,
+                    },
+                    span,
+                );
+            } else if matches!(kind, DefKind::Let | DefKind::Iterator) && previous.scope != PRELUDE
+            {
+                self.error(
+                    ResolveErrorKind::Shadowed {
+                        name: name.to_string(),
+                        previous: previous_span,
+                    },
+                    span,
+                );
+            }
+        }
+
+        self.resolution.definitions.push(Definition {
+            name,
+            kind,
+            span,
+            scope: self.current,
+        });
+        let id = self.resolution.definitions.len() - 1;
+        self.resolution.scopes[self.current].definitions.push(id);
+    }
+
+    /// Defines names bound together (parameters, destructured fields), which
+    /// cannot repeat.
+    fn define_all(&mut self, names: &[&'a str], kind: DefKind, span: Span) {
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) {
+                self.error(
+                    ResolveErrorKind::Duplicate {
+                        name: name.to_string(),
+                        first: span,
+                    },
+                    span,
+                );
+                continue;
+            }
+            self.define(name, kind, span);
+        }
+    }
+
+    fn reference(&mut self, name: &str, span: Span) {
+        match self.lookup(name) {
+            Some(def) => {
+                self.resolution.references.insert(span, def);
+            }
+            None => self.error(ResolveErrorKind::Undefined(name.to_string()), span),
+        }
+    }
+
+    fn resolve_block(&mut self, stmts: &'a [Spanned<Statement<'a>>], span: Span) {
+        self.enter(span);
+        self.deferred.push(Vec::new());
+
+        for stmt in stmts {
+            let kind = match &stmt.node {
+                Statement::FnDecl { name, .. } => Some((name, DefKind::Function)),
+                Statement::Struct { name, .. } => Some((name, DefKind::Struct)),
+                Statement::Enum { name, .. } => Some((name, DefKind::Enum)),
+                Statement::TypeAlias { name, .. } => Some((name, DefKind::TypeAlias)),
+                _ => None,
+            };
+            if let Some((name, kind)) = kind {
+                self.define(name, kind, stmt.span);
+            }
+        }
+
+        for stmt in stmts {
+            self.resolve_statement(stmt);
+        }
+
+        let block = self.current;
+        for body in self.deferred.pop().unwrap_or_default() {
+            self.current = body.scope;
+            self.resolve_block(body.stmts, body.span);
+        }
+        self.current = block;
+        self.leave();
+    }
+
+    /// Defines the parameters of a function or closure in a new scope, its
+    /// body being resolved at the end of the enclosing block.
+    fn defer(
+        &mut self,
+        params: &'a [(&'a str, Option<Spanned<Type<'a>>>)],
+        stmts: &'a [Spanned<Statement<'a>>],
+        span: Span,
+    ) {
+        let scope = self.enter(span);
+        let names: Vec<&str> = params.iter().map(|(name, _)| *name).collect();
+        self.define_all(&names, DefKind::Param, span);
+        self.leave();
+
+        self.deferred
+            .last_mut()
+            .expect("bodies are always in a block")
+            .push(Body { scope, span, stmts });
+    }
+
+    fn resolve_statement(&mut self, stmt: &'a Spanned<Statement<'a>>) {
+        match &stmt.node {
+            Statement::FnDecl { params, body, .. } => self.defer(params, body, stmt.span),
+            Statement::Let { name, value, .. } => {
+                self.resolve_expr(value);
+                let mut names = Vec::new();
+                let_names(name, &mut names);
+                self.define_all(&names, DefKind::Let, stmt.span);
+            }
+            Statement::Match(m) => self.resolve_match(m),
+            Statement::Spawn { body, with } => self.resolve_spawn(with.as_ref(), body, stmt.span),
+            Statement::ForLoop {
+                iterator,
+                range,
+                body,
+            } => {
+                self.resolve_expr(range);
+                self.enter(stmt.span);
+                self.define(iterator, DefKind::Iterator, stmt.span);
+                self.resolve_block(body, stmt.span);
+                self.leave();
+            }
+            Statement::WhileLoop { condition, body } => {
+                self.resolve_expr(condition);
+                self.resolve_block(body, stmt.span);
+            }
+            Statement::If {
+                condition,
+                body,
+                else_ifs,
+                else_body,
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_block(body, stmt.span);
+                for (condition, body) in else_ifs {
+                    self.resolve_expr(condition);
+                    self.resolve_block(body, stmt.span);
+                }
+                if let Some(body) = else_body {
+                    self.resolve_block(body, stmt.span);
+                }
+            }
+            Statement::Expr(expr) | Statement::Return(expr) => self.resolve_expr(expr),
+            Statement::Use { path, alias } => {
+                if let Some(name) = alias.or(path.last().copied()) {
+                    self.define(name, DefKind::Import, stmt.span);
+                }
+            }
+            // items were defined along with their block
+            Statement::Struct { .. }
+            | Statement::Enum { .. }
+            | Statement::TypeAlias { .. }
+            | Statement::Break
+            | Statement::Error => {}
+        }
+    }
+
+    fn resolve_match(&mut self, m: &'a Match<'a>) {
+        self.resolve_expr(&m.expr);
+        for arm in &m.arms {
+            let span = arm
+                .body
+                .last()
+                .map_or(arm.pattern.span, |last| arm.pattern.span.to(last.span));
+            self.enter(span);
+
+            let names = match &arm.pattern.node {
+                // capitalized names are unit variants
+                Pattern::Ident(name) if !name.starts_with(char::is_uppercase) => vec![*name],
+                Pattern::FunctionDestructor(_, bindings) => bindings.clone(),
+                Pattern::StructLikeDestructor(_, fields) => fields
+                    .iter()
+                    .map(|(field, rename)| rename.unwrap_or(field))
+                    .collect(),
+                Pattern::Ident(_) | Pattern::Literal(_) | Pattern::Wildcard => vec![],
+            };
+            self.define_all(&names, DefKind::Binding, arm.pattern.span);
+
+            if let Some(guard) = &arm.guard {
+                self.resolve_expr(guard);
+            }
+            self.resolve_block(&arm.body, span);
+            self.leave();
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &'a Spanned<Expr<'a>>) {
+        match &expr.node {
+            Expr::Ident(name) => self.reference(name, expr.span),
+            Expr::Literal(Literal::String(segments)) => {
+                for segment in segments {
+                    if let StringSegment::Interpolation(expr) = segment {
+                        self.resolve_expr(expr);
+                    }
+                }
+            }
+            Expr::Literal(_) => {}
+            Expr::Array(items) | Expr::Tuple(items) => {
+                for item in items {
+                    self.resolve_expr(item);
+                }
+            }
+            Expr::Match(m) => self.resolve_match(m),
+            Expr::Awaitable(inner) | Expr::Unary(_, inner) | Expr::FieldAccess(inner, _) => {
+                self.resolve_expr(inner)
+            }
+            Expr::Binary(lhs, _, rhs)
+            | Expr::Pipe(lhs, rhs)
+            | Expr::Range(lhs, rhs)
+            | Expr::Assign(lhs, _, rhs)
+            | Expr::Index(lhs, rhs) => {
+                self.resolve_expr(lhs);
+                self.resolve_expr(rhs);
+            }
+            Expr::Call(callee, args) => {
+                self.resolve_expr(callee);
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+            }
+            Expr::Closure(params, body) => self.defer(params, body, expr.span),
+            Expr::Spawn(with, body) => self.resolve_spawn(with.as_deref(), body, expr.span),
+        }
+    }
+
+    fn resolve_spawn(
+        &mut self,
+        with: Option<&'a Spanned<Expr<'a>>>,
+        body: &'a [Spanned<Statement<'a>>],
+        span: Span,
+    ) {
+        self.enter(span);
+        if let Some(with) = with {
+            self.resolve_expr(with);
+            self.define("context", DefKind::Context, span);
+        }
+        self.resolve_block(body, span);
+        self.leave();
+    }
+}
+
+fn let_names<'a>(name: &LetName<'a>, names: &mut Vec<&'a str>) {
+    match name {
+        LetName::Ident(name) => names.push(name),
+        LetName::TupleDestructure(items) | LetName::ArrayDestructure(items) => {
+            for item in items {
+                let_names(item, names);
+            }
+        }
+        LetName::StructDestructure(_, fields) => {
+            names.extend(fields.iter().map(|(field, rename)| rename.unwrap_or(field)))
+        }
+    }
+}
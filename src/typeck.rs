@@ -0,0 +1,1270 @@
+pub mod ty;
+
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    ast::{
+        BinOp, Documented, EnumVariant, Expr, Field, LetName, Literal, Match, Pattern, Program,
+        Span, Spanned, Statement, StringSegment, Type, UnaryOp,
+    },
+    diagnostics::Diagnostic,
+};
+pub use ty::Ty;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeErrorKind {
+    UnknownType(String),
+    RecursiveAlias(String),
+    InvalidType(String),
+    Mismatch { expected: Ty, found: Ty },
+    InvalidOperands(BinOp, Ty, Ty),
+    InvalidOperand(UnaryOp, Ty),
+    ArityMismatch { expected: usize, found: usize },
+    NotCallable(Ty),
+    NotIterable(Ty),
+    UnknownField(Ty, String),
+    // a non-atomic `let mut` binding used by a task
+    SharedMutable(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub kind: TypeErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            TypeErrorKind::UnknownType(name) => write!(f, "unknown type `{}`", name),
+            TypeErrorKind::RecursiveAlias(name) => {
+                write!(f, "type alias `{}` refers to itself", name)
+            }
+            TypeErrorKind::InvalidType(msg) => f.write_str(msg),
+            TypeErrorKind::Mismatch { expected, found } => {
+                write!(
+                    f,
+                    "mismatched types: expected {}, found {}",
+                    expected, found
+                )
+            }
+            TypeErrorKind::InvalidOperands(op, lhs, rhs) => {
+                write!(f, "cannot apply `{}` to {} and {}", op, lhs, rhs)
+            }
+            TypeErrorKind::InvalidOperand(op, ty) => write!(f, "cannot apply `{}` to {}", op, ty),
+            TypeErrorKind::ArityMismatch { expected, found } => write!(
+                f,
+                "expected {} argument(s) but {} were given",
+                expected, found
+            ),
+            TypeErrorKind::NotCallable(ty) => write!(f, "value of type {} is not callable", ty),
+            TypeErrorKind::NotIterable(ty) => write!(f, "cannot iterate over {}", ty),
+            TypeErrorKind::UnknownField(ty, field) => write!(f, "{} has no field `{}`", ty, field),
+            TypeErrorKind::SharedMutable(name) => {
+                write!(f, "mutable binding `{}` cannot be shared with a task", name)
+            }
+        }
+    }
+}
+
+impl TypeError {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::new(self.to_string(), self.span);
+        match &self.kind {
+            // an `async` type only accepts futures, which are only awaited explicitly
+            TypeErrorKind::Mismatch {
+                expected: Ty::Awaitable(_),
+                found,
+            } if !matches!(found, Ty::Awaitable(_)) => {
+                diagnostic.with_label(format!("{} is not async, only futures are", found))
+            }
+            TypeErrorKind::Mismatch {
+                expected,
+                found: Ty::Awaitable(inner),
+            } if !matches!(expected, Ty::Awaitable(_)) => {
+                diagnostic.with_label(format!("`await` it to get its {}", inner))
+            }
+            TypeErrorKind::Mismatch { expected, found } => {
+                diagnostic.with_label(format!("expected {}, found {}", expected, found))
+            }
+            TypeErrorKind::SharedMutable(_) => {
+                diagnostic.with_label("declare it `let atomic` to share it")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+/// The inferred type of a top-level function or binding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub name: String,
+    pub ty: Ty,
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.ty)
+    }
+}
+
+/// Checks the type annotations of a program and the expressions they apply to.
+pub fn check_program(program: &Program) -> Vec<TypeError> {
+    infer_program(program).1
+}
+
+/// Infers the types of a program, unannotated parameters and return types
+/// included, and returns the signatures of its top-level bindings.
+///
+/// Functions, and closures bound by `let`, are generalized: their unsolved
+/// type variables print as `'a`, `'b`... Values the checker knows nothing
+/// about (built-ins, methods, imports) are [`Ty::Unknown`] and accepted
+/// everywhere.
+pub fn infer_program(program: &Program) -> (Vec<Signature>, Vec<TypeError>) {
+    let mut checker = Checker::default();
+    checker.collect_types(&program.statements);
+    checker.resolve_types();
+    checker.check_block(&program.statements);
+
+    let signatures = std::mem::take(&mut checker.signatures)
+        .into_iter()
+        .map(|(name, ty)| Signature {
+            name: name.to_string(),
+            ty: checker.substitute(&ty).normalized(),
+        })
+        .collect();
+    (signatures, checker.errors)
+}
+
+/// The inferred types of the identifiers used in a program, by span, for
+/// editors to show.
+pub fn identifier_types(program: &Program) -> HashMap<Span, Ty> {
+    let mut checker = Checker::default();
+    checker.collect_types(&program.statements);
+    checker.resolve_types();
+    checker.check_block(&program.statements);
+
+    let identifiers = std::mem::take(&mut checker.identifiers);
+    identifiers
+        .into_iter()
+        .map(|(span, ty)| (span, checker.substitute(&ty).normalized()))
+        .collect()
+}
+
+/// A type generalized over some of its variables, instantiated afresh at every use.
+#[derive(Debug, Clone)]
+struct Scheme<'a> {
+    vars: Vec<u32>,
+    ty: Ty,
+    // bound by `let mut` without `atomic`, so that tasks cannot share it
+    unshared: bool,
+    // the unshared bindings a closure or function captured, with the depth of
+    // their scope, which tasks cannot share through it either
+    captures: Vec<(&'a str, usize)>,
+}
+
+impl Scheme<'_> {
+    fn mono(ty: Ty) -> Self {
+        Scheme {
+            vars: vec![],
+            ty,
+            unshared: false,
+            captures: vec![],
+        }
+    }
+}
+
+// the enclosing function's return type, and whether `:>` was met
+struct Return {
+    ty: Ty,
+    seen: bool,
+}
+
+#[derive(Debug, Clone)]
+enum VariantTys<'a> {
+    Unit,
+    Tuple(Vec<Ty>),
+    Struct(Vec<(&'a str, Ty)>),
+}
+
+#[derive(Default)]
+struct Checker<'a> {
+    // declarations, by name, before their types are resolved
+    struct_decls: HashMap<&'a str, &'a [Field<'a>]>,
+    enum_decls: HashMap<&'a str, &'a [Documented<'a, EnumVariant<'a>>]>,
+    aliases: HashMap<&'a str, &'a Spanned<Type<'a>>>,
+    resolving: Vec<&'a str>,
+
+    structs: HashMap<&'a str, Vec<(&'a str, Ty)>>,
+    enums: HashMap<&'a str, Vec<(&'a str, VariantTys<'a>)>>,
+
+    scopes: Vec<HashMap<&'a str, Scheme<'a>>>,
+    returns: Vec<Return>,
+    // what each inference variable was unified with
+    substitution: Vec<Option<Ty>>,
+    signatures: Vec<(&'a str, Ty)>,
+    errors: Vec<TypeError>,
+    // the type of each identifier expression
+    identifiers: Vec<(Span, Ty)>,
+    // the number of scopes outside of each `spawn` block or `par_*` closure being checked
+    tasks: Vec<usize>,
+    // the number of scopes outside of each closure or function body being
+    // checked, and the unshared bindings it captured from them
+    closures: Vec<(usize, Vec<(&'a str, usize)>)>,
+    // the captures of each closure expression, by span
+    captures: HashMap<Span, Vec<(&'a str, usize)>>,
+}
+
+/// Methods running their closure argument on several threads at once.
+const PARALLEL: &[&str] = &["par_map", "par_each", "par_filter", "par_reduce"];
+
+/// Built-in type names, in both the capitalized and the lowercase spelling.
+pub(crate) fn builtin(name: &str) -> Option<Ty> {
+    Some(match name {
+        "Int" | "int" | "i32" | "i64" => Ty::Int,
+        "Float" | "float" | "f32" | "f64" => Ty::Float,
+        "String" | "string" | "str" | "char" => Ty::String,
+        "Bool" | "bool" => Ty::Bool,
+        "Range" => Ty::Range,
+        _ => return None,
+    })
+}
+
+impl<'a> Checker<'a> {
+    fn error(&mut self, kind: TypeErrorKind, span: Span) {
+        self.errors.push(TypeError { kind, span });
+    }
+
+    /// Reports a mismatch unless `found` unifies with `expected`.
+    fn expect(&mut self, expected: &Ty, found: &Ty, span: Span) {
+        if !self.unify(expected, found) {
+            self.error(
+                TypeErrorKind::Mismatch {
+                    expected: self.substitute(expected),
+                    found: self.substitute(found),
+                },
+                span,
+            );
+        }
+    }
+
+    fn fresh(&mut self) -> Ty {
+        self.substitution.push(None);
+        Ty::Var(self.substitution.len() as u32 - 1)
+    }
+
+    /// Follows solved variables until the outermost constructor is known.
+    fn shallow(&self, ty: &Ty) -> Ty {
+        let mut ty = ty.clone();
+        while let Ty::Var(v) = ty {
+            match &self.substitution[v as usize] {
+                Some(solved) => ty = solved.clone(),
+                None => break,
+            }
+        }
+        ty
+    }
+
+    /// Applies the substitution throughout `ty`.
+    fn substitute(&self, ty: &Ty) -> Ty {
+        ty.map_vars(&mut |v| {
+            let solved = self.substitution[v as usize].as_ref()?;
+            Some(self.substitute(solved))
+        })
+    }
+
+    fn unify(&mut self, a: &Ty, b: &Ty) -> bool {
+        match (self.shallow(a), self.shallow(b)) {
+            (Ty::Unknown, _) | (_, Ty::Unknown) => true,
+            (Ty::Var(a), Ty::Var(b)) if a == b => true,
+            (Ty::Var(v), ty) | (ty, Ty::Var(v)) => {
+                if self.substitute(&ty).vars().contains(&v) {
+                    return false;
+                }
+                self.substitution[v as usize] = Some(ty);
+                true
+            }
+            (Ty::Array(a), Ty::Array(b)) | (Ty::Awaitable(a), Ty::Awaitable(b)) => {
+                self.unify(&a, &b)
+            }
+            (Ty::Tuple(a), Ty::Tuple(b)) => {
+                a.len() == b.len() && a.iter().zip(&b).all(|(a, b)| self.unify(a, b))
+            }
+            (Ty::Function(params_a, ret_a), Ty::Function(params_b, ret_b)) => {
+                params_a.len() == params_b.len()
+                    && params_a
+                        .iter()
+                        .zip(&params_b)
+                        .all(|(a, b)| self.unify(a, b))
+                    && self.unify(&ret_a, &ret_b)
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    /// Quantifies `ty` over the variables no binding in scope depends on.
+    fn generalize(&self, ty: &Ty) -> Scheme<'a> {
+        let ty = self.substitute(ty);
+        let bound: Vec<u32> = self
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.values())
+            .flat_map(|scheme| {
+                let vars = self.substitute(&scheme.ty).vars();
+                vars.into_iter().filter(|v| !scheme.vars.contains(v))
+            })
+            .collect();
+        let vars = ty
+            .vars()
+            .into_iter()
+            .filter(|v| !bound.contains(v))
+            .collect();
+        Scheme {
+            vars,
+            ty,
+            unshared: false,
+            captures: vec![],
+        }
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Ty {
+        let fresh: Vec<(u32, Ty)> = scheme.vars.iter().map(|&v| (v, self.fresh())).collect();
+        scheme.ty.map_vars(&mut |v| {
+            fresh
+                .iter()
+                .find(|(var, _)| *var == v)
+                .map(|(_, ty)| ty.clone())
+        })
+    }
+
+    fn collect_types(&mut self, stmts: &'a [Spanned<Statement<'a>>]) {
+        for stmt in stmts {
+            match &stmt.node {
+                Statement::Struct { name, fields, .. } => {
+                    self.struct_decls.insert(name, fields);
+                }
+                Statement::Enum { name, variants, .. } => {
+                    self.enum_decls.insert(name, variants);
+                }
+                Statement::TypeAlias { name, ty, .. } => {
+                    self.aliases.insert(name, ty);
+                }
+                Statement::FnDecl { body, .. } => self.collect_types(body),
+                _ => {}
+            }
+        }
+    }
+
+    fn resolve_types(&mut self) {
+        let mut struct_decls: Vec<_> = self.struct_decls.clone().into_iter().collect();
+        struct_decls.sort_by_key(|(name, _)| *name);
+        for (name, fields) in struct_decls {
+            let fields = fields
+                .iter()
+                .map(|field| (field.0, self.resolve(&field.1)))
+                .collect();
+            self.structs.insert(name, fields);
+        }
+
+        let mut enum_decls: Vec<_> = self.enum_decls.clone().into_iter().collect();
+        enum_decls.sort_by_key(|(name, _)| *name);
+        for (name, variants) in enum_decls {
+            let variants = variants
+                .iter()
+                .map(|variant| match &variant.node {
+                    EnumVariant::Unit(name) => (*name, VariantTys::Unit),
+                    EnumVariant::Tuple(name, types) => (
+                        *name,
+                        VariantTys::Tuple(types.iter().map(|ty| self.resolve(ty)).collect()),
+                    ),
+                    EnumVariant::Struct(name, fields) => (
+                        *name,
+                        VariantTys::Struct(
+                            fields
+                                .iter()
+                                .map(|(field, ty)| (*field, self.resolve_opt(ty.as_ref())))
+                                .collect(),
+                        ),
+                    ),
+                })
+                .collect();
+            self.enums.insert(name, variants);
+        }
+    }
+
+    fn resolve_opt(&mut self, ty: Option<&'a Spanned<Type<'a>>>) -> Ty {
+        ty.map_or(Ty::Unknown, |ty| self.resolve(ty))
+    }
+
+    /// Turns a written type into a [`Ty`], reporting unknown names.
+    fn resolve(&mut self, ty: &'a Spanned<Type<'a>>) -> Ty {
+        match &ty.node {
+            Type::Ident(name) => {
+                if let Some(builtin) = builtin(name) {
+                    builtin
+                } else if self.struct_decls.contains_key(name) || self.enum_decls.contains_key(name)
+                {
+                    Ty::Named(name.to_string())
+                } else if let Some(alias) = self.aliases.get(name).copied() {
+                    if self.resolving.contains(name) {
+                        self.error(TypeErrorKind::RecursiveAlias(name.to_string()), ty.span);
+                        return Ty::Unknown;
+                    }
+                    self.resolving.push(name);
+                    let resolved = self.resolve(alias);
+                    self.resolving.pop();
+                    resolved
+                } else {
+                    self.error(TypeErrorKind::UnknownType(name.to_string()), ty.span);
+                    Ty::Unknown
+                }
+            }
+            Type::Tuple(items) if items.is_empty() => Ty::Unit,
+            Type::Tuple(items) => Ty::Tuple(items.iter().map(|ty| self.resolve(ty)).collect()),
+            Type::Array(items) => match items.as_slice() {
+                [] => Ty::Array(Box::new(Ty::Unknown)),
+                [item] => Ty::Array(Box::new(self.resolve(item))),
+                _ => {
+                    self.error(
+                        TypeErrorKind::InvalidType(
+                            "array types take a single item type".to_string(),
+                        ),
+                        ty.span,
+                    );
+                    Ty::Array(Box::new(Ty::Unknown))
+                }
+            },
+            Type::Function(params, ret) => Ty::Function(
+                params.iter().map(|ty| self.resolve(ty)).collect(),
+                Box::new(self.resolve(ret)),
+            ),
+            Type::AsyncFunction(params, ret) => Ty::Function(
+                params.iter().map(|ty| self.resolve(ty)).collect(),
+                Box::new(Ty::Awaitable(Box::new(self.resolve(ret)))),
+            ),
+            Type::Awaitable(inner) => Ty::Awaitable(Box::new(self.resolve(inner))),
+        }
+    }
+
+    fn is_bound(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains_key(name))
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<Ty> {
+        let scheme = self.scopes.iter().rev().find_map(|scope| scope.get(name))?;
+        let scheme = scheme.clone();
+        Some(self.instantiate(&scheme))
+    }
+
+    fn define(&mut self, name: &'a str, ty: Ty) {
+        self.define_scheme(name, Scheme::mono(ty));
+    }
+
+    fn define_scheme(&mut self, name: &'a str, scheme: Scheme<'a>) {
+        // top-level bindings make up the signatures
+        if self.scopes.len() == 1 {
+            self.signatures.retain(|(n, _)| *n != name);
+            self.signatures.push((name, scheme.ty.clone()));
+        }
+        self.scopes
+            .last_mut()
+            .expect("there is always a scope")
+            .insert(name, scheme);
+    }
+
+    /// Types unannotated parameters with fresh variables.
+    fn param_types(&mut self, params: &'a [(&'a str, Option<Spanned<Type<'a>>>)]) -> Vec<Ty> {
+        params
+            .iter()
+            .map(|(_, ty)| match ty {
+                Some(ty) => self.resolve(ty),
+                None => self.fresh(),
+            })
+            .collect()
+    }
+
+    /// Checks the body of a function or closure, `ret` being its return type.
+    ///
+    /// Without an annotation nor a `:>`, a function returns its last expression.
+    fn check_body(
+        &mut self,
+        params: &'a [(&'a str, Option<Spanned<Type<'a>>>)],
+        tys: Vec<Ty>,
+        ret: &Ty,
+        annotated: bool,
+        body: &'a [Spanned<Statement<'a>>],
+    ) {
+        self.scopes.push(HashMap::new());
+        for ((name, _), ty) in params.iter().zip(tys) {
+            self.define(name, ty);
+        }
+        self.returns.push(Return {
+            ty: ret.clone(),
+            seen: false,
+        });
+        let last = self.check_block(body);
+        let returned = self.returns.pop().is_some_and(|r| r.seen);
+        self.scopes.pop();
+
+        if !annotated && !returned {
+            self.settle(ret, &last);
+        }
+    }
+
+    /// Checks a block in its own scope and returns the type of its last expression.
+    fn check_block(&mut self, stmts: &'a [Spanned<Statement<'a>>]) -> Ty {
+        self.scopes.push(HashMap::new());
+
+        // functions can be called before the line declaring them
+        let mut signatures = HashMap::new();
+        for (i, stmt) in stmts.iter().enumerate() {
+            if let Statement::FnDecl {
+                name,
+                _async,
+                params,
+                return_type,
+                ..
+            } = &stmt.node
+            {
+                let params = self.param_types(params);
+                let ret = match return_type {
+                    Some(ty) => self.resolve(ty),
+                    None => self.fresh(),
+                };
+                let called = match _async {
+                    true => Ty::Awaitable(Box::new(ret.clone())),
+                    false => ret.clone(),
+                };
+                self.define(name, Ty::Function(params.clone(), Box::new(called)));
+                signatures.insert(i, (params, ret));
+            }
+        }
+
+        let mut last = Ty::Unit;
+        for (i, stmt) in stmts.iter().enumerate() {
+            last = match (&stmt.node, signatures.remove(&i)) {
+                (
+                    Statement::FnDecl {
+                        name,
+                        params,
+                        return_type,
+                        body,
+                        ..
+                    },
+                    Some((tys, ret)),
+                ) => {
+                    self.closures.push((self.scopes.len(), Vec::new()));
+                    self.check_body(params, tys, &ret, return_type.is_some(), body);
+                    let (_, captures) = self.closures.pop().expect("pushed above");
+
+                    let scope = self.scopes.last_mut().expect("there is always a scope");
+                    if let Some(scheme) = scope.remove(name) {
+                        let scheme = Scheme {
+                            captures,
+                            ..self.generalize(&scheme.ty)
+                        };
+                        self.define_scheme(name, scheme);
+                    }
+                    Ty::Unit
+                }
+                _ => self.check_statement(stmt),
+            };
+        }
+
+        self.scopes.pop();
+        last
+    }
+
+    fn check_statement(&mut self, stmt: &'a Spanned<Statement<'a>>) -> Ty {
+        match &stmt.node {
+            Statement::Expr(expr) => return self.infer(expr),
+            Statement::Match(m) => return self.check_match(m, false),
+            Statement::Let {
+                name,
+                ty,
+                value,
+                mutable,
+                atomic,
+                ..
+            } => {
+                let found = self.infer(value);
+                match (ty, name) {
+                    (Some(ty), _) => {
+                        let expected = self.resolve(ty);
+                        self.expect(&expected, &found, value.span);
+                        self.bind(name, expected);
+                    }
+                    // only closures are generalized, other values may be mutated
+                    (None, LetName::Ident(name)) if matches!(value.node, Expr::Closure(..)) => {
+                        let scheme = self.generalize(&found);
+                        self.define_scheme(name, scheme);
+                    }
+                    (None, _) => self.bind(name, found),
+                }
+                if *mutable && !*atomic {
+                    self.unshare(name);
+                }
+                if let LetName::Ident(name) = name {
+                    let captures = self.captured(value);
+                    let scope = self.scopes.last_mut().expect("there is always a scope");
+                    if let Some(scheme) = scope.get_mut(name) {
+                        scheme.captures = captures;
+                    }
+                }
+            }
+            Statement::Return(expr) => {
+                let found = self.infer(expr);
+                if let Some(ret) = self.returns.last_mut() {
+                    ret.seen = true;
+                    let expected = ret.ty.clone();
+                    self.expect(&expected, &found, expr.span);
+                }
+            }
+            Statement::If {
+                condition,
+                body,
+                else_ifs,
+                else_body,
+            } => {
+                self.check_condition(condition);
+                self.check_block(body);
+                for (condition, body) in else_ifs {
+                    self.check_condition(condition);
+                    self.check_block(body);
+                }
+                if let Some(body) = else_body {
+                    self.check_block(body);
+                }
+            }
+            Statement::WhileLoop { condition, body } => {
+                self.check_condition(condition);
+                self.check_block(body);
+            }
+            Statement::ForLoop {
+                iterator,
+                range,
+                body,
+            } => {
+                let item = match self.infer(range) {
+                    Ty::Range => Ty::Int,
+                    Ty::Array(item) => *item,
+                    Ty::String => Ty::String,
+                    Ty::Unknown | Ty::Var(_) => Ty::Unknown,
+                    other => {
+                        self.error(TypeErrorKind::NotIterable(other), range.span);
+                        Ty::Unknown
+                    }
+                };
+                self.scopes
+                    .push(HashMap::from([(*iterator, Scheme::mono(item))]));
+                self.check_block(body);
+                self.scopes.pop();
+            }
+            Statement::Spawn { body, with } => self.check_spawn(with.as_ref(), body),
+            Statement::Use { path, alias } => {
+                // imports are not typed yet, nor listed among the signatures
+                if let Some(name) = alias.or(path.last().copied()) {
+                    let scope = self.scopes.last_mut().expect("there is always a scope");
+                    scope.insert(name, Scheme::mono(Ty::Unknown));
+                }
+            }
+            // checked by `check_block`, along with the hoisted signature
+            Statement::FnDecl { .. }
+            | Statement::Break
+            | Statement::TypeAlias { .. }
+            | Statement::Struct { .. }
+            | Statement::Enum { .. }
+            | Statement::Error => {}
+        }
+        Ty::Unit
+    }
+
+    fn check_condition(&mut self, condition: &'a Spanned<Expr<'a>>) {
+        let found = self.infer(condition);
+        self.expect(&Ty::Bool, &found, condition.span);
+    }
+
+    fn bind(&mut self, name: &'a LetName<'a>, ty: Ty) {
+        match name {
+            LetName::Ident(name) => self.define(name, ty),
+            LetName::TupleDestructure(names) => {
+                for (i, name) in names.iter().enumerate() {
+                    let item = match &ty {
+                        Ty::Tuple(items) => items.get(i).cloned().unwrap_or(Ty::Unknown),
+                        _ => Ty::Unknown,
+                    };
+                    self.bind(name, item);
+                }
+            }
+            LetName::ArrayDestructure(names) => {
+                let item = match &ty {
+                    Ty::Array(item) => (**item).clone(),
+                    _ => Ty::Unknown,
+                };
+                for name in names {
+                    self.bind(name, item.clone());
+                }
+            }
+            LetName::StructDestructure(_, fields) => {
+                for (field, rename) in fields {
+                    let ty = self.field_type(&ty, field).unwrap_or(Ty::Unknown);
+                    self.define(rename.unwrap_or(field), ty);
+                }
+            }
+        }
+    }
+
+    fn unshare(&mut self, name: &'a LetName<'a>) {
+        match name {
+            LetName::Ident(name) => {
+                let scope = self.scopes.last_mut().expect("there is always a scope");
+                if let Some(scheme) = scope.get_mut(name) {
+                    scheme.unshared = true;
+                }
+            }
+            LetName::TupleDestructure(names) | LetName::ArrayDestructure(names) => {
+                names.iter().for_each(|name| self.unshare(name));
+            }
+            LetName::StructDestructure(_, fields) => {
+                let scope = self.scopes.last_mut().expect("there is always a scope");
+                for (field, rename) in fields {
+                    if let Some(scheme) = scope.get_mut(rename.unwrap_or(field)) {
+                        scheme.unshared = true;
+                    }
+                }
+            }
+        }
+    }
+
+    // tasks may only use the unshared bindings declared inside of them, and
+    // closures capture those declared outside of them
+    fn check_shared(&mut self, name: &'a str, span: Span) {
+        let Some(depth) = self
+            .scopes
+            .iter()
+            .rposition(|scope| scope.contains_key(name))
+        else {
+            return;
+        };
+        let scheme = &self.scopes[depth][name];
+        let mut shared = scheme.captures.clone();
+        if scheme.unshared {
+            shared.push((name, depth));
+        }
+
+        if let Some(&outside) = self.tasks.last() {
+            for &(name, depth) in &shared {
+                if depth < outside {
+                    self.error(TypeErrorKind::SharedMutable(name.to_string()), span);
+                }
+            }
+        }
+        for (outside, captures) in &mut self.closures {
+            for &capture in &shared {
+                if capture.1 < *outside && !captures.contains(&capture) {
+                    captures.push(capture);
+                }
+            }
+        }
+    }
+
+    // the unshared bindings captured by the closure or function `expr` evaluates to
+    fn captured(&self, expr: &Spanned<Expr<'a>>) -> Vec<(&'a str, usize)> {
+        match &expr.node {
+            Expr::Closure(..) => self.captures.get(&expr.span).cloned().unwrap_or_default(),
+            Expr::Ident(name) => self
+                .scopes
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(name))
+                .map(|scheme| scheme.captures.clone())
+                .unwrap_or_default(),
+            _ => vec![],
+        }
+    }
+
+    fn field_type(&self, ty: &Ty, field: &str) -> Option<Ty> {
+        let Ty::Named(name) = self.shallow(ty) else {
+            return None;
+        };
+        self.structs
+            .get(name.as_str())?
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, ty)| ty.clone())
+    }
+
+    /// Checks a match and returns the type its arms agree on, if any.
+    ///
+    /// In a match expression, `:>` yields the value of the arm rather than
+    /// returning from the function.
+    fn check_match(&mut self, m: &'a Match<'a>, expression: bool) -> Ty {
+        let scrutinee = self.infer(&m.expr);
+        let result = self.fresh();
+        let mut agree = true;
+
+        for arm in &m.arms {
+            self.scopes.push(HashMap::new());
+            self.check_pattern(&arm.pattern, &scrutinee);
+            if let Some(guard) = &arm.guard {
+                self.check_condition(guard);
+            }
+            if expression {
+                self.returns.push(Return {
+                    ty: result.clone(),
+                    seen: false,
+                });
+            }
+            let ty = self.check_block(&arm.body);
+            let yielded = expression && self.returns.pop().is_some_and(|r| r.seen);
+            self.scopes.pop();
+
+            // arms that disagree leave the type unknown
+            if !yielded {
+                agree &= self.substitute(&result).accepts(&self.substitute(&ty))
+                    && self.settle(&result, &ty);
+            }
+        }
+
+        if agree { result } else { Ty::Unknown }
+    }
+
+    /// Unifies `var` with the value of a block, which when unknown makes `var`
+    /// unknown rather than leaving it unsolved.
+    fn settle(&mut self, var: &Ty, ty: &Ty) -> bool {
+        match (self.shallow(var), self.shallow(ty)) {
+            (Ty::Var(v), Ty::Unknown) => {
+                self.substitution[v as usize] = Some(Ty::Unknown);
+                true
+            }
+            _ => self.unify(var, ty),
+        }
+    }
+
+    /// The payload of `variant`, found in the enum `scrutinee` is or, when
+    /// still unsolved, in the only enum declaring such a variant.
+    fn variant_types(&mut self, scrutinee: &Ty, variant: &str) -> Option<VariantTys<'a>> {
+        let name = match self.shallow(scrutinee) {
+            Ty::Named(name) => name,
+            Ty::Var(_) => {
+                let mut owners = self
+                    .enums
+                    .iter()
+                    .filter(|(_, variants)| variants.iter().any(|(v, _)| *v == variant));
+                let name = match (owners.next(), owners.next()) {
+                    (Some((name, _)), None) => name.to_string(),
+                    _ => return None,
+                };
+                self.unify(scrutinee, &Ty::Named(name.clone()));
+                name
+            }
+            _ => return None,
+        };
+        self.enums
+            .get(name.as_str())?
+            .iter()
+            .find(|(v, _)| *v == variant)
+            .map(|(_, tys)| tys.clone())
+    }
+
+    fn check_pattern(&mut self, pattern: &'a Spanned<Pattern<'a>>, scrutinee: &Ty) {
+        match &pattern.node {
+            Pattern::Wildcard => {}
+            Pattern::Literal(lit) => {
+                let ty = self.literal_type(lit);
+                self.expect(scrutinee, &ty, pattern.span);
+            }
+            Pattern::Ident(name) if name.starts_with(char::is_uppercase) => {}
+            Pattern::Ident(name) => self.define(name, scrutinee.clone()),
+            Pattern::FunctionDestructor(variant, bindings) => {
+                let tys = match self.variant_types(scrutinee, variant) {
+                    Some(VariantTys::Tuple(tys)) => tys,
+                    _ => vec![],
+                };
+                for (i, binding) in bindings.iter().enumerate() {
+                    self.define(binding, tys.get(i).cloned().unwrap_or(Ty::Unknown));
+                }
+            }
+            Pattern::StructLikeDestructor(name, fields) => {
+                let tys = match self.variant_types(scrutinee, name) {
+                    Some(VariantTys::Struct(tys)) => tys,
+                    _ => self.structs.get(name).cloned().unwrap_or_default(),
+                };
+                for (field, rename) in fields {
+                    let ty = tys
+                        .iter()
+                        .find(|(f, _)| f == field)
+                        .map_or(Ty::Unknown, |(_, ty)| ty.clone());
+                    self.define(rename.unwrap_or(field), ty);
+                }
+            }
+        }
+    }
+
+    fn literal_type(&mut self, lit: &'a Literal<'a>) -> Ty {
+        match lit {
+            Literal::Int(_) => Ty::Int,
+            Literal::Float(_) | Literal::Inf => Ty::Float,
+            Literal::Bool(_) => Ty::Bool,
+            Literal::String(segments) => {
+                for segment in segments {
+                    if let StringSegment::Interpolation(expr) = segment {
+                        self.infer(expr);
+                    }
+                }
+                Ty::String
+            }
+        }
+    }
+
+    // the block sees the value given to `with` as `context`
+    fn check_spawn(
+        &mut self,
+        with: Option<&'a Spanned<Expr<'a>>>,
+        body: &'a [Spanned<Statement<'a>>],
+    ) {
+        let mut scope = HashMap::new();
+        if let Some(with) = with {
+            scope.insert("context", Scheme::mono(self.infer(with)));
+        }
+        self.tasks.push(self.scopes.len());
+        self.scopes.push(scope);
+        self.check_block(body);
+        self.scopes.pop();
+        self.tasks.pop();
+    }
+
+    /// Infers the type of `expr`, with its outermost variable solved if possible.
+    fn infer(&mut self, expr: &'a Spanned<Expr<'a>>) -> Ty {
+        let ty = self.infer_expr(expr);
+        self.shallow(&ty)
+    }
+
+    fn infer_expr(&mut self, expr: &'a Spanned<Expr<'a>>) -> Ty {
+        match &expr.node {
+            Expr::Literal(lit) => self.literal_type(lit),
+            Expr::Ident(name) => {
+                self.check_shared(name, expr.span);
+                let ty = self.lookup(name).unwrap_or(Ty::Unknown);
+                self.identifiers.push((expr.span, ty.clone()));
+                ty
+            }
+            Expr::Array(items) => {
+                let mut item_ty = Ty::Unknown;
+                for item in items {
+                    let ty = self.infer(item);
+                    if item_ty == Ty::Unknown {
+                        item_ty = ty;
+                    } else {
+                        self.expect(&item_ty, &ty, item.span);
+                    }
+                }
+                Ty::Array(Box::new(item_ty))
+            }
+            Expr::Tuple(items) if items.len() == 1 => self.infer(&items[0]),
+            Expr::Tuple(items) if items.is_empty() => Ty::Unit,
+            Expr::Tuple(items) => Ty::Tuple(items.iter().map(|item| self.infer(item)).collect()),
+            Expr::Match(m) => self.check_match(m, true),
+            // tasks are not typed yet
+            Expr::Spawn(with, body) => {
+                self.check_spawn(with.as_deref(), body);
+                Ty::Unknown
+            }
+            Expr::Awaitable(inner) => match self.infer(inner) {
+                Ty::Awaitable(ty) => *ty,
+                Ty::Unknown => Ty::Unknown,
+                var @ Ty::Var(_) => {
+                    let inner = self.fresh();
+                    self.unify(&var, &Ty::Awaitable(Box::new(inner.clone())));
+                    inner
+                }
+                other => {
+                    self.error(
+                        TypeErrorKind::Mismatch {
+                            expected: Ty::Awaitable(Box::new(Ty::Unknown)),
+                            found: other,
+                        },
+                        inner.span,
+                    );
+                    Ty::Unknown
+                }
+            },
+            Expr::Binary(lhs, op, rhs) => {
+                let (lhs_ty, rhs_ty) = (self.infer(lhs), self.infer(rhs));
+                self.binary(*op, lhs_ty, rhs_ty, lhs.span, rhs.span, expr.span)
+            }
+            Expr::Unary(op, operand) => {
+                let ty = self.infer(operand);
+                let valid = match op {
+                    UnaryOp::Not => self.unify(&Ty::Bool, &ty),
+                    UnaryOp::Minus | UnaryOp::Plus => {
+                        ty.is_numeric() || matches!(ty, Ty::Unknown | Ty::Var(_))
+                    }
+                    UnaryOp::BitNot => self.unify(&Ty::Int, &ty),
+                };
+                if !valid {
+                    self.error(TypeErrorKind::InvalidOperand(*op, ty.clone()), expr.span);
+                    return Ty::Unknown;
+                }
+                ty
+            }
+            Expr::Pipe(lhs, rhs) => {
+                let value = (self.infer(lhs), lhs.span);
+                match &rhs.node {
+                    Expr::Call(callee, args) => {
+                        self.check_call(callee, Some(value), args, expr.span)
+                    }
+                    _ => {
+                        let callee = self.infer(rhs);
+                        self.apply_call(callee, vec![value], rhs.span)
+                    }
+                }
+            }
+            Expr::Call(callee, args) => self.check_call(callee, None, args, expr.span),
+            Expr::Range(start, end) => {
+                let start_ty = self.infer(start);
+                self.expect(&Ty::Int, &start_ty, start.span);
+                if !matches!(end.node, Expr::Literal(Literal::Inf)) {
+                    let end_ty = self.infer(end);
+                    self.expect(&Ty::Int, &end_ty, end.span);
+                }
+                Ty::Range
+            }
+            Expr::Assign(target, op, value) => {
+                let target_ty = self.infer(target);
+                let value_ty = self.infer(value);
+                let value_ty = match op {
+                    BinOp::Eq => value_ty,
+                    op => self.binary(
+                        *op,
+                        target_ty.clone(),
+                        value_ty,
+                        target.span,
+                        value.span,
+                        expr.span,
+                    ),
+                };
+                self.expect(&target_ty, &value_ty, value.span);
+                Ty::Unit
+            }
+            Expr::FieldAccess(base, field) => {
+                // `Message.Quit`, a unit variant
+                if let Expr::Ident(name) = &base.node
+                    && !self.is_bound(name)
+                    && self.enums.contains_key(name)
+                {
+                    return Ty::Named(name.to_string());
+                }
+
+                let ty = self.infer(base);
+                match &ty {
+                    Ty::Named(name) if self.structs.contains_key(name.as_str()) => {
+                        self.field_type(&ty, field).unwrap_or_else(|| {
+                            self.error(
+                                TypeErrorKind::UnknownField(ty.clone(), field.to_string()),
+                                expr.span,
+                            );
+                            Ty::Unknown
+                        })
+                    }
+                    _ => Ty::Unknown,
+                }
+            }
+            Expr::Index(base, index) => {
+                let base_ty = self.infer(base);
+                let index_ty = self.infer(index);
+                self.expect(&Ty::Int, &index_ty, index.span);
+                match base_ty {
+                    Ty::Array(item) => *item,
+                    Ty::String => Ty::String,
+                    Ty::Tuple(items) => match &index.node {
+                        Expr::Literal(Literal::Int(i)) => {
+                            items.get(*i as usize).cloned().unwrap_or(Ty::Unknown)
+                        }
+                        _ => Ty::Unknown,
+                    },
+                    _ => Ty::Unknown,
+                }
+            }
+            Expr::Closure(params, body) => {
+                let tys = self.param_types(params);
+                let ret = self.fresh();
+                self.closures.push((self.scopes.len(), Vec::new()));
+                self.check_body(params, tys.clone(), &ret, false, body);
+                let (_, captures) = self.closures.pop().expect("pushed above");
+                self.captures.insert(expr.span, captures);
+                Ty::Function(tys, Box::new(ret))
+            }
+        }
+    }
+
+    fn binary(
+        &mut self,
+        op: BinOp,
+        lhs: Ty,
+        rhs: Ty,
+        lhs_span: Span,
+        rhs_span: Span,
+        span: Span,
+    ) -> Ty {
+        use BinOp::*;
+
+        if matches!(op, And | Or) {
+            self.expect(&Ty::Bool, &lhs, lhs_span);
+            self.expect(&Ty::Bool, &rhs, rhs_span);
+            return Ty::Bool;
+        }
+        if matches!(op, Eq | Neq) {
+            // comparing values of different types is allowed, it is just false
+            self.unify(&lhs, &rhs);
+            return Ty::Bool;
+        }
+        if lhs == Ty::Unknown || rhs == Ty::Unknown {
+            return match op {
+                Lt | Le | Gt | Ge => Ty::Bool,
+                _ => Ty::Unknown,
+            };
+        }
+        // without overloading information, both sides are taken to be the same type
+        if matches!(lhs, Ty::Var(_)) || matches!(rhs, Ty::Var(_)) {
+            if !self.unify(&lhs, &rhs) {
+                self.error(TypeErrorKind::InvalidOperands(op, lhs, rhs), span);
+                return Ty::Unknown;
+            }
+            return match op {
+                Lt | Le | Gt | Ge => Ty::Bool,
+                _ => self.shallow(&lhs),
+            };
+        }
+
+        let result = match (op, &lhs, &rhs) {
+            (Lt | Le | Gt | Ge, a, b) if a.is_numeric() && b.is_numeric() => Some(Ty::Bool),
+            (Lt | Le | Gt | Ge, Ty::String, Ty::String) => Some(Ty::Bool),
+            (Plus, Ty::String, Ty::String) => Some(Ty::String),
+            (Plus | Minus | Multiply | Divide | Modulo, Ty::Int, Ty::Int) => Some(Ty::Int),
+            (Plus | Minus | Multiply | Divide | Modulo, a, b)
+                if a.is_numeric() && b.is_numeric() =>
+            {
+                Some(Ty::Float)
+            }
+            (BitAnd | BitOr | BitXor | ShiftLeft | ShiftRight, Ty::Int, Ty::Int) => Some(Ty::Int),
+            (BitAnd | BitOr | BitXor, Ty::Bool, Ty::Bool) => Some(Ty::Bool),
+            _ => None,
+        };
+
+        result.unwrap_or_else(|| {
+            self.error(TypeErrorKind::InvalidOperands(op, lhs, rhs), span);
+            Ty::Unknown
+        })
+    }
+
+    /// Checks `callee(args)`, `piped` being the left-hand side of a `|>`.
+    fn check_call(
+        &mut self,
+        callee: &'a Spanned<Expr<'a>>,
+        piped: Option<(Ty, Span)>,
+        args: &'a [Spanned<Expr<'a>>],
+        span: Span,
+    ) -> Ty {
+        let parallel =
+            matches!(&callee.node, Expr::FieldAccess(_, name) if PARALLEL.contains(name));
+        let mut values: Vec<(Ty, Span)> = piped.into_iter().collect();
+        for arg in args {
+            let task = parallel && matches!(arg.node, Expr::Closure(..));
+            if task {
+                self.tasks.push(self.scopes.len());
+            }
+            values.push((self.infer(arg), arg.span));
+            if task {
+                self.tasks.pop();
+            }
+            // a closure passed by name, its captures from outside of the enclosing
+            // task being reported already
+            if parallel && !task {
+                let reported = self.tasks.last().copied().unwrap_or(0);
+                for (name, depth) in self.captured(arg) {
+                    if depth >= reported {
+                        self.error(TypeErrorKind::SharedMutable(name.to_string()), arg.span);
+                    }
+                }
+            }
+        }
+
+        // constructors: `Test.new(a)`, `Test(a)` and `Message.Write(s)`
+        let constructor = match &callee.node {
+            Expr::FieldAccess(base, name) => match &base.node {
+                Expr::Ident(ty) if !self.is_bound(ty) => Some((*ty, Some(*name))),
+                _ => None,
+            },
+            Expr::Ident(ty) if !self.is_bound(ty) => Some((*ty, None)),
+            _ => None,
+        };
+        if let Some((ty, member)) = constructor {
+            if let Some(fields) = self.structs.get(ty)
+                && member.is_none_or(|m| m == "new")
+            {
+                let params: Vec<Ty> = fields.iter().map(|(_, ty)| ty.clone()).collect();
+                self.check_args(&params, &values, span);
+                return Ty::Named(ty.to_string());
+            }
+            if let Some(member) = member
+                && let Some(variant) = self.variant_types(&Ty::Named(ty.to_string()), member)
+            {
+                let params = match variant {
+                    VariantTys::Unit => vec![],
+                    VariantTys::Tuple(tys) => tys,
+                    VariantTys::Struct(fields) => fields.into_iter().map(|(_, ty)| ty).collect(),
+                };
+                self.check_args(&params, &values, span);
+                return Ty::Named(ty.to_string());
+            }
+        }
+
+        let callee_ty = match &callee.node {
+            // built-in methods are not typed yet
+            Expr::FieldAccess(base, field) => {
+                let base_ty = self.infer(base);
+                self.field_type(&base_ty, field).unwrap_or(Ty::Unknown)
+            }
+            _ => self.infer(callee),
+        };
+        let callee_ty = self.shallow(&callee_ty);
+        self.apply_call(callee_ty, values, span)
+    }
+
+    fn apply_call(&mut self, callee: Ty, args: Vec<(Ty, Span)>, span: Span) -> Ty {
+        match callee {
+            Ty::Function(params, ret) => {
+                self.check_args(&params, &args, span);
+                *ret
+            }
+            Ty::Unknown => Ty::Unknown,
+            var @ Ty::Var(_) => {
+                let ret = self.fresh();
+                let params = args.into_iter().map(|(ty, _)| ty).collect();
+                self.expect(&var, &Ty::Function(params, Box::new(ret.clone())), span);
+                ret
+            }
+            other => {
+                self.error(TypeErrorKind::NotCallable(other), span);
+                Ty::Unknown
+            }
+        }
+    }
+
+    fn check_args(&mut self, params: &[Ty], args: &[(Ty, Span)], span: Span) {
+        if params.len() != args.len() {
+            self.error(
+                TypeErrorKind::ArityMismatch {
+                    expected: params.len(),
+                    found: args.len(),
+                },
+                span,
+            );
+            return;
+        }
+        for (param, (arg, span)) in params.iter().zip(args) {
+            self.expect(param, arg, *span);
+        }
+    }
+}
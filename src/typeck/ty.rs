@@ -0,0 +1,130 @@
+use std::fmt;
+
+/// A type as understood by the checker, resolved from an [`ast::Type`].
+///
+/// [`ast::Type`]: crate::ast::Type
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ty {
+    Int,
+    Float,
+    Bool,
+    String,
+    Unit,
+    Range,
+    Array(Box<Ty>),
+    Tuple(Vec<Ty>),
+    Function(Vec<Ty>, Box<Ty>),
+    Awaitable(Box<Ty>),
+    // a user declared struct or enum
+    Named(String),
+    // an inference variable, solved by unification
+    Var(u32),
+    // not annotated and not inferable, compatible with every type
+    Unknown,
+}
+
+impl Ty {
+    /// Whether a value of type `found` can be used where `self` is expected.
+    pub fn accepts(&self, found: &Ty) -> bool {
+        match (self, found) {
+            (Ty::Unknown | Ty::Var(_), _) | (_, Ty::Unknown | Ty::Var(_)) => true,
+            (Ty::Array(a), Ty::Array(b)) | (Ty::Awaitable(a), Ty::Awaitable(b)) => a.accepts(b),
+            (Ty::Tuple(a), Ty::Tuple(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.accepts(b))
+            }
+            (Ty::Function(params_a, ret_a), Ty::Function(params_b, ret_b)) => {
+                params_a.len() == params_b.len()
+                    && params_a.iter().zip(params_b).all(|(a, b)| b.accepts(a))
+                    && ret_a.accepts(ret_b)
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Ty::Int | Ty::Float)
+    }
+
+    /// Inference variables appearing in this type, in order of appearance.
+    pub fn vars(&self) -> Vec<u32> {
+        fn walk(ty: &Ty, vars: &mut Vec<u32>) {
+            match ty {
+                Ty::Var(v) if !vars.contains(v) => vars.push(*v),
+                Ty::Array(inner) | Ty::Awaitable(inner) => walk(inner, vars),
+                Ty::Tuple(items) => items.iter().for_each(|item| walk(item, vars)),
+                Ty::Function(params, ret) => {
+                    params.iter().for_each(|param| walk(param, vars));
+                    walk(ret, vars);
+                }
+                _ => {}
+            }
+        }
+
+        let mut vars = Vec::new();
+        walk(self, &mut vars);
+        vars
+    }
+
+    /// Replaces the inference variables for which `f` returns a type.
+    pub fn map_vars(&self, f: &mut impl FnMut(u32) -> Option<Ty>) -> Ty {
+        match self {
+            Ty::Var(v) => f(*v).unwrap_or(Ty::Var(*v)),
+            Ty::Array(inner) => Ty::Array(Box::new(inner.map_vars(f))),
+            Ty::Awaitable(inner) => Ty::Awaitable(Box::new(inner.map_vars(f))),
+            Ty::Tuple(items) => Ty::Tuple(items.iter().map(|item| item.map_vars(f)).collect()),
+            Ty::Function(params, ret) => Ty::Function(
+                params.iter().map(|param| param.map_vars(f)).collect(),
+                Box::new(ret.map_vars(f)),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    /// Renumbers inference variables from zero, so they print as `'a`, `'b`...
+    pub fn normalized(&self) -> Ty {
+        let vars = self.vars();
+        self.map_vars(&mut |v| {
+            let i = vars.iter().position(|&var| var == v)?;
+            Some(Ty::Var(i as u32))
+        })
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = |f: &mut fmt::Formatter, items: &[Ty]| {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}", item)?;
+            }
+            Ok(())
+        };
+
+        match self {
+            Ty::Int => f.write_str("Int"),
+            Ty::Float => f.write_str("Float"),
+            Ty::Bool => f.write_str("Bool"),
+            Ty::String => f.write_str("String"),
+            Ty::Unit => f.write_str("()"),
+            Ty::Range => f.write_str("Range"),
+            Ty::Array(item) => write!(f, "[{}]", item),
+            Ty::Tuple(items) => {
+                f.write_str("(")?;
+                list(f, items)?;
+                f.write_str(")")
+            }
+            Ty::Function(params, ret) => {
+                f.write_str("(")?;
+                list(f, params)?;
+                write!(f, "): {}", ret)
+            }
+            Ty::Awaitable(inner) => write!(f, "async {}", inner),
+            Ty::Named(name) => f.write_str(name),
+            Ty::Var(v) if *v < 26 => write!(f, "'{}", (b'a' + *v as u8) as char),
+            Ty::Var(v) => write!(f, "'t{}", v),
+            Ty::Unknown => f.write_str("_"),
+        }
+    }
+}
@@ -0,0 +1,469 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    ast::Program,
+    compiler::{
+        self,
+        chunk::{Capture, Op, Proto, Target, Test},
+    },
+    eval::{
+        self, Interpreter, RuntimeError, builtins,
+        env::Env,
+        value::{Payload, Value},
+    },
+};
+
+type Cell<'a> = Arc<Mutex<Value<'a>>>;
+
+/// A compiled function together with the variables it captured.
+#[derive(Debug)]
+pub struct Closure<'a> {
+    pub proto: Arc<Proto<'a>>,
+    upvalues: Vec<Cell<'a>>,
+    // the globals of the program or module declaring the function
+    globals: Env<'a>,
+}
+
+/// The content of a local slot of a frame.
+enum Local<'a> {
+    Value(Value<'a>),
+    Cell(Cell<'a>),
+    Iter(Box<dyn Iterator<Item = Value<'a>> + 'a>),
+    // a stack height
+    Mark(usize),
+}
+
+impl<'a> Local<'a> {
+    fn value(&self) -> Value<'a> {
+        match self {
+            Local::Value(value) => value.clone(),
+            Local::Cell(cell) => cell.lock().unwrap().clone(),
+            Local::Iter(_) | Local::Mark(_) => unreachable!("slot holds no value"),
+        }
+    }
+
+    fn cell(&self) -> &Cell<'a> {
+        match self {
+            Local::Cell(cell) => cell,
+            _ => unreachable!("slot holds no cell"),
+        }
+    }
+}
+
+/// Stack based virtual machine running programs compiled by [`compiler`].
+///
+/// Native functions, methods and constructors are shared with the
+/// tree-walking [`Interpreter`], which also provides the program output.
+pub struct Vm<'a> {
+    interp: Interpreter<'a>,
+}
+
+impl Default for Vm<'_> {
+    fn default() -> Self {
+        Self::new(Interpreter::new())
+    }
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(interp: Interpreter<'a>) -> Self {
+        Self { interp }
+    }
+
+    /// Makes a parsed module file importable under its `use` path, e.g. `utils::math`.
+    pub fn add_module(&mut self, name: impl Into<String>, program: &'a Program<'a>) {
+        self.interp.add_module(name, program);
+    }
+
+    /// Compiles and runs every top-level statement, then `main` if the program declares one.
+    pub fn run(&self, program: &'a Program<'a>) -> Result<Value<'a>, RuntimeError> {
+        let globals = self.interp.globals().clone();
+        let script = Closure {
+            proto: compiler::compile(program),
+            upvalues: Vec::new(),
+            globals: globals.clone(),
+        };
+        // `par_*` calls run on the workers meanwhile
+        self.interp.with_workers(|| {
+            let value = self.execute(&script, Vec::new())?;
+            match globals.get("main") {
+                Some(main @ Value::Closure(_)) => self.call(&main, vec![]),
+                _ => Ok(value),
+            }
+        })
+    }
+
+    /// Calls a compiled function, or anything the interpreter can call.
+    pub fn call(
+        &self,
+        callee: &Value<'a>,
+        args: Vec<Value<'a>>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        match callee {
+            Value::Closure(closure) => {
+                if closure.proto.arity != args.len() {
+                    return Err(RuntimeError::ArityMismatch {
+                        name: closure.proto.name.unwrap_or("<closure>").to_string(),
+                        expected: closure.proto.arity,
+                        found: args.len(),
+                    });
+                }
+                self.execute(closure, args)
+            }
+            _ => self.interp.call(callee, args),
+        }
+    }
+
+    fn call_method(
+        &self,
+        receiver: Value<'a>,
+        name: &str,
+        args: Vec<Value<'a>>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        match &receiver {
+            Value::Module(module) => return self.call(&eval::item_of(module, name)?, args),
+            Value::Struct(s) => {
+                let field = s
+                    .fields
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .find(|(field, _)| *field == name)
+                    .map(|(_, value)| value.clone());
+                if let Some(field) = field {
+                    return self.call(&field, args);
+                }
+            }
+            Value::Type(_) => return self.interp.call_method(receiver, name, args),
+            _ => {}
+        }
+        builtins::call_method(
+            &self.interp,
+            &|f, args| self.call(f, args),
+            receiver,
+            name,
+            args,
+        )
+    }
+
+    /// The module of a `use` path, whose items are declared on first import.
+    fn import(&self, path: &[&str]) -> Result<Value<'a>, RuntimeError> {
+        self.interp.modules().import(path, |program, env| {
+            let top_level = Closure {
+                proto: compiler::compile_module(program),
+                upvalues: Vec::new(),
+                globals: env.clone(),
+            };
+            self.execute(&top_level, Vec::new()).map(|_| ())
+        })
+    }
+
+    fn execute(
+        &self,
+        closure: &Closure<'a>,
+        args: Vec<Value<'a>>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        let proto = &*closure.proto;
+        let mut locals: Vec<Local<'a>> = Vec::with_capacity(proto.locals);
+        locals.extend(args.into_iter().map(Local::Value));
+        locals.resize_with(proto.locals, || Local::Value(Value::Unit));
+        let mut stack: Vec<Value<'a>> = Vec::new();
+        let mut ip = 0;
+
+        macro_rules! pop {
+            () => {
+                stack.pop().expect("the compiler balances the stack")
+            };
+        }
+        let name = |i: u32| proto.names[i as usize];
+
+        loop {
+            let op = proto.code[ip];
+            ip += 1;
+            match op {
+                Op::Const(i) => stack.push(proto.constants[i as usize].clone()),
+                Op::Unit => stack.push(Value::Unit),
+                Op::Pop => {
+                    pop!();
+                }
+
+                Op::GetLocal(slot) => stack.push(locals[slot as usize].value()),
+                Op::SetLocal(slot) => locals[slot as usize] = Local::Value(pop!()),
+                Op::DefineCell(slot) => {
+                    locals[slot as usize] = Local::Cell(Arc::new(Mutex::new(pop!())));
+                }
+                Op::GetCell(slot) => stack.push(locals[slot as usize].value()),
+                Op::SetCell(slot) => *locals[slot as usize].cell().lock().unwrap() = pop!(),
+                Op::GetUpvalue(i) => {
+                    stack.push(closure.upvalues[i as usize].lock().unwrap().clone());
+                }
+                Op::SetUpvalue(i) => *closure.upvalues[i as usize].lock().unwrap() = pop!(),
+                Op::GetGlobal(i) => {
+                    let value = closure
+                        .globals
+                        .get(name(i))
+                        .ok_or_else(|| RuntimeError::UndefinedVariable(name(i).to_string()))?;
+                    stack.push(value);
+                }
+                Op::DefineGlobal(i, mutable) => closure.globals.define(name(i), pop!(), mutable),
+                Op::SetGlobal(i) => closure.globals.assign(name(i), pop!())?,
+                Op::Immutable(i) => {
+                    return Err(RuntimeError::ImmutableAssignment(name(i).to_string()));
+                }
+                Op::Bind(i) => {
+                    let value = pop!();
+                    self.bind(
+                        &proto.targets[i as usize],
+                        value,
+                        &mut locals,
+                        &closure.globals,
+                    )?;
+                }
+
+                Op::Binary(op) => {
+                    let rhs = pop!();
+                    let lhs = pop!();
+                    stack.push(eval::binary(op, lhs, rhs)?);
+                }
+                Op::Unary(op) => {
+                    let operand = pop!();
+                    stack.push(eval::unary(op, operand)?);
+                }
+                Op::Bool => {
+                    stack.last().expect("an operand").as_bool()?;
+                }
+                Op::Await => {
+                    let value = pop!();
+                    stack.push(self.interp.await_value(value)?);
+                }
+                Op::Range => {
+                    let end = pop!();
+                    let start = pop!();
+                    stack.push(eval::range(start, end)?);
+                }
+                Op::Array(len) => {
+                    let items = stack.split_off(stack.len() - len as usize);
+                    stack.push(Value::array(items));
+                }
+                Op::Tuple(len) => {
+                    let items = stack.split_off(stack.len() - len as usize);
+                    stack.push(Value::Tuple(items.into()));
+                }
+                Op::Concat(len) => {
+                    let parts = stack.split_off(stack.len() - len as usize);
+                    let s: String = parts.iter().map(|part| part.to_string()).collect();
+                    stack.push(Value::str(s));
+                }
+                Op::Field(i) => {
+                    let base = pop!();
+                    stack.push(self.interp.field(base, name(i))?);
+                }
+                Op::SetField(i) => {
+                    let base = pop!();
+                    let value = pop!();
+                    eval::set_field(&base, name(i), value)?;
+                }
+                Op::Index => {
+                    let index = pop!();
+                    let base = pop!();
+                    stack.push(eval::index_of(&base, &index)?);
+                }
+                Op::SetIndex => {
+                    let index = pop!();
+                    let base = pop!();
+                    let value = pop!();
+                    eval::set_index(&base, &index, value)?;
+                }
+
+                Op::Jump(to) => ip = to as usize,
+                Op::JumpIfFalse(to) => {
+                    if !pop!().as_bool()? {
+                        ip = to as usize;
+                    }
+                }
+                Op::JumpIfTrue(to) => {
+                    if pop!().as_bool()? {
+                        ip = to as usize;
+                    }
+                }
+
+                Op::Call(argc) => {
+                    let args = stack.split_off(stack.len() - argc as usize);
+                    let callee = pop!();
+                    stack.push(self.call(&callee, args)?);
+                }
+                Op::Method(i, argc) => {
+                    let args = stack.split_off(stack.len() - argc as usize);
+                    let receiver = pop!();
+                    stack.push(self.call_method(receiver, name(i), args)?);
+                }
+                Op::Closure(i) => {
+                    let proto = proto.protos[i as usize].clone();
+                    let upvalues = proto
+                        .captures
+                        .iter()
+                        .map(|capture| match capture {
+                            Capture::Local(slot) => locals[*slot as usize].cell().clone(),
+                            Capture::Upvalue(i) => closure.upvalues[*i as usize].clone(),
+                        })
+                        .collect();
+                    stack.push(Value::Closure(Arc::new(Closure {
+                        proto,
+                        upvalues,
+                        globals: closure.globals.clone(),
+                    })));
+                }
+                Op::Return => return Ok(stack.pop().unwrap_or(Value::Unit)),
+
+                Op::Iter(slot) => {
+                    let iterable = pop!();
+                    locals[slot as usize] = Local::Iter(self.interp.iterate(&iterable)?);
+                }
+                Op::Next(slot, exit) => {
+                    let Local::Iter(iter) = &mut locals[slot as usize] else {
+                        unreachable!("slot holds no iterator")
+                    };
+                    match iter.next() {
+                        Some(item) => stack.push(item),
+                        None => ip = exit as usize,
+                    }
+                }
+                Op::Mark(slot) => locals[slot as usize] = Local::Mark(stack.len()),
+                Op::Unwind(slot) => {
+                    let Local::Mark(height) = locals[slot as usize] else {
+                        unreachable!("slot holds no mark")
+                    };
+                    stack.truncate(height);
+                }
+                Op::Yield(slot) => {
+                    let value = pop!();
+                    let Local::Mark(height) = locals[slot as usize] else {
+                        unreachable!("slot holds no mark")
+                    };
+                    stack.truncate(height);
+                    stack.push(value);
+                }
+                Op::Break => return Err(RuntimeError::Break),
+
+                Op::Match(i) => {
+                    let value = pop!();
+                    let matched = self.test(&proto.tests[i as usize], &value, &mut locals);
+                    stack.push(Value::Bool(matched));
+                }
+                Op::NoMatch(slot) => {
+                    let value = locals[slot as usize].value();
+                    return Err(RuntimeError::NoMatchingArm(value.to_string()));
+                }
+                Op::Import(i) => stack.push(self.import(proto.paths[i as usize])?),
+                Op::Unsupported(what) => return Err(RuntimeError::Unsupported(what)),
+                Op::SyntaxError => return Err(RuntimeError::SyntaxError),
+            }
+        }
+    }
+
+    fn bind(
+        &self,
+        target: &Target<'a>,
+        value: Value<'a>,
+        locals: &mut [Local<'a>],
+        globals: &Env<'a>,
+    ) -> Result<(), RuntimeError> {
+        match target {
+            Target::Local(slot) => locals[*slot as usize] = Local::Value(value),
+            Target::Cell(slot) => locals[*slot as usize] = Local::Cell(Arc::new(Mutex::new(value))),
+            Target::Global(name, mutable) => globals.define(name, value, *mutable),
+            Target::Many(targets) => {
+                let values = match &value {
+                    Value::Tuple(values) => values.to_vec(),
+                    Value::Array(values) => values.lock().unwrap().clone(),
+                    other => {
+                        return Err(RuntimeError::TypeError(format!(
+                            "cannot destructure {}",
+                            other.type_name()
+                        )));
+                    }
+                };
+                if values.len() != targets.len() {
+                    return Err(RuntimeError::TypeError(format!(
+                        "expected {} values to destructure, found {}",
+                        targets.len(),
+                        values.len()
+                    )));
+                }
+                for (target, value) in targets.iter().zip(values) {
+                    self.bind(target, value, locals, globals)?;
+                }
+            }
+            Target::Struct(ty, fields) => {
+                let Value::Struct(s) = &value else {
+                    return Err(RuntimeError::TypeError(format!(
+                        "expected {}, found {}",
+                        ty,
+                        value.type_name()
+                    )));
+                };
+                if s.name != *ty {
+                    return Err(RuntimeError::TypeError(format!(
+                        "expected {}, found {}",
+                        ty, s.name
+                    )));
+                }
+                for (field, target) in fields {
+                    let value = eval::field_of(s, field)?;
+                    self.bind(target, value, locals, globals)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `value` matches a pattern, binding its names when it does.
+    fn test(&self, test: &Test<'a>, value: &Value<'a>, locals: &mut [Local<'a>]) -> bool {
+        let mut bind = |target: &Target<'a>, value: &Value<'a>| match target {
+            Target::Local(slot) => locals[*slot as usize] = Local::Value(value.clone()),
+            Target::Cell(slot) => {
+                locals[*slot as usize] = Local::Cell(Arc::new(Mutex::new(value.clone())))
+            }
+            _ => unreachable!("patterns only bind locals"),
+        };
+
+        match test {
+            Test::Wildcard => true,
+            Test::UnitVariant(name) => {
+                matches!(value, Value::Variant(v) if v.name == *name && matches!(v.payload, Payload::Unit))
+            }
+            Test::Bind(target) => {
+                bind(target, value);
+                true
+            }
+            Test::Variant(name, targets) => match value {
+                Value::Variant(v) if v.name == *name => match &v.payload {
+                    Payload::Tuple(values) if values.len() == targets.len() => {
+                        for (target, value) in targets.iter().zip(values) {
+                            bind(target, value);
+                        }
+                        true
+                    }
+                    _ => false,
+                },
+                _ => false,
+            },
+            Test::Struct(name, fields) => {
+                let values: Vec<(&str, Value<'a>)> = match value {
+                    Value::Struct(s) if s.name == *name => s.fields.lock().unwrap().clone(),
+                    Value::Variant(v) if v.name == *name => match &v.payload {
+                        Payload::Struct(values) => values.clone(),
+                        _ => return false,
+                    },
+                    _ => return false,
+                };
+                for (field, target) in fields {
+                    let Some((_, value)) = values.iter().find(|(f, _)| f == field) else {
+                        return false;
+                    };
+                    bind(target, value);
+                }
+                true
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+fn lira(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lira"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_lex_json_from_stdin() {
+    let output = lira(&["lex", "--format", "json"], "let a = 1");
+    assert!(output.status.success());
+    let tokens: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let texts: Vec<_> = tokens
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|token| token["text"].as_str().unwrap())
+        .collect();
+    assert_eq!(texts, ["let", "a", "=", "1"]);
+    assert_eq!(tokens[3]["start"], 8);
+}
+
+#[test]
+fn test_syntax_errors_exit_nonzero() {
+    let output = lira(&["parse", "-"], "let = 3\n");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("error: unexpected `=`"), "{}", stderr);
+    assert!(stderr.contains("<stdin>:1:5"), "{}", stderr);
+
+    let output = lira(&["lex"], "let s = \"abc\n");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_output_file() {
+    let path = std::env::temp_dir().join(format!("lira-cli-{}.json", std::process::id()));
+    let output = lira(
+        &["parse", "--format", "json", "-o", path.to_str().unwrap()],
+        "let a = 1\n",
+    );
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let written = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let programs: serde_json::Value = serde_json::from_str(&written).unwrap();
+    assert_eq!(programs[0]["file"], "<stdin>");
+    assert!(programs[0]["program"]["statements"].is_array());
+}
+
+#[test]
+fn test_check_json() {
+    let output = lira(&["check", "--format", "json"], "let a: Int = \"a\"\n");
+    assert_eq!(output.status.code(), Some(1));
+    let diagnostics: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(diagnostics[0]["severity"], "error");
+    assert_eq!(diagnostics[0]["line"], 1);
+    assert_eq!(diagnostics[0]["file"], "<stdin>");
+}
+
+#[test]
+fn test_run_from_stdin() {
+    let source = "use std::Process\n\nfn main() {\n    print(Process.args().len())\n    Process.exit(3)\n}\n";
+    let output = lira(&["run", "-", "a", "--b"], source);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n");
+}
+
+#[test]
+fn test_fmt() {
+    let output = lira(&["fmt"], "let  a=1");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "let a = 1\n");
+
+    let path = std::env::temp_dir().join(format!("lira-fmt-{}.li", std::process::id()));
+    std::fs::write(&path, "let  a=1").unwrap();
+    let file = path.to_str().unwrap();
+    assert_eq!(lira(&["fmt", "--check", file], "").status.code(), Some(1));
+    assert!(lira(&["fmt", file], "").status.success());
+    assert!(lira(&["fmt", "--check", file], "").status.success());
+    let formatted = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(formatted, "let a = 1\n");
+}
+
+#[test]
+fn test_minify() {
+    let source = "let a = 1 // one\nprint( a )\n";
+    let output = lira(&["minify"], source);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "let a=1// one\nprint(a)"
+    );
+    let output = lira(&["minify", "--drop-comments"], source);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "let a=1;print(a)"
+    );
+    assert_eq!(lira(&["minify"], "let = 1").status.code(), Some(1));
+}
+
+#[test]
+fn test_doc() {
+    let dir = std::env::temp_dir().join(format!("lira-doc-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("shapes.li"), "/// A shape.\nenum Shape { Dot }\n").unwrap();
+    std::fs::write(
+        dir.join("main.li"),
+        "use shapes\n/// Draws.\nfn draw(shape: Shape) {}\n",
+    )
+    .unwrap();
+
+    let out = dir.join("doc");
+    let output = lira(
+        &[
+            "doc",
+            dir.join("main.li").to_str().unwrap(),
+            "-o",
+            out.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let main = std::fs::read_to_string(out.join("main.md")).unwrap();
+    assert!(
+        main.contains("<a href=\"shapes.md#enum.Shape\">Shape</a>"),
+        "{}",
+        main
+    );
+    assert!(out.join("shapes.md").exists() && out.join("index.md").exists());
+
+    std::fs::write(dir.join("main.li"), "use missing\n").unwrap();
+    let output = lira(&["doc", dir.join("main.li").to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(1));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
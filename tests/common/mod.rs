@@ -0,0 +1,50 @@
+//! Fixtures shared by the integration tests, each of them using its own share.
+#![allow(dead_code)]
+
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use lira::{
+    eval::{Interpreter, RuntimeError},
+    parser::parse_program,
+};
+
+/// A writer keeping what an interpreter printed, clones sharing the buffer.
+#[derive(Clone, Default)]
+pub struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Output {
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+/// Runs `source` on an interpreter passed to `setup` first, returning what it
+/// printed.
+pub fn run_with(
+    source: &str,
+    setup: impl FnOnce(&mut Interpreter),
+) -> Result<String, RuntimeError> {
+    let (program, errors) = parse_program(source);
+    assert!(errors.is_empty(), "source should parse: {:?}", errors);
+    let output = Output::default();
+    let mut interpreter = Interpreter::with_output(Box::new(output.clone()));
+    setup(&mut interpreter);
+    interpreter.run(&program)?;
+    Ok(output.text())
+}
+
+pub fn run(source: &str) -> Result<String, RuntimeError> {
+    run_with(source, |_| {})
+}
@@ -0,0 +1,165 @@
+use std::fs;
+
+use lira::{
+    cst::{Cst, SyntaxKind, SyntaxToken, leading_trivia, trailing_trivia},
+    formatter::format,
+    parser::parse_program,
+};
+use rowan::{GreenToken, NodeOrToken};
+
+fn examples() -> Vec<String> {
+    let mut paths: Vec<_> = fs::read_dir("examples")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "li"))
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| fs::read_to_string(path).unwrap())
+        .collect()
+}
+
+/// The kinds of a tree, nodes as `Kind(...)` and tokens by their text.
+fn outline(cst: &Cst) -> String {
+    fn walk(element: lira::cst::SyntaxElement, out: &mut String) {
+        match element {
+            NodeOrToken::Node(node) => {
+                out.push_str(&format!("{:?}(", node.kind()));
+                node.children_with_tokens()
+                    .for_each(|child| walk(child, out));
+                out.push(')');
+            }
+            NodeOrToken::Token(token) if token.kind().is_trivia() => out.push('_'),
+            NodeOrToken::Token(token) => out.push_str(token.text()),
+        }
+    }
+    let mut out = String::new();
+    cst.syntax()
+        .children_with_tokens()
+        .for_each(|child| walk(child, &mut out));
+    out
+}
+
+fn token(cst: &Cst, text: &str) -> SyntaxToken {
+    cst.syntax()
+        .descendants_with_tokens()
+        .filter_map(|element| element.into_token())
+        .find(|token| token.text() == text)
+        .unwrap()
+}
+
+fn texts(tokens: Vec<SyntaxToken>) -> Vec<String> {
+    tokens
+        .iter()
+        .map(|token| token.text().to_string())
+        .collect()
+}
+
+#[test]
+fn test_lossless() {
+    let sources = examples().into_iter().chain([
+        "let x = 1 $ 2\n".to_string(),
+        "fn f( {\n  \"unterminated\n".to_string(),
+        "\t  // only a comment".to_string(),
+        String::new(),
+    ]);
+    for source in sources {
+        let cst = Cst::parse(&source);
+        assert_eq!(cst.syntax().to_string(), source);
+        assert_eq!(cst.text(), source);
+    }
+}
+
+#[test]
+fn test_nodes() {
+    let cst = Cst::parse("// add\nlet x = a + f(1) // sum\nfor i in 0..x {\n  print(i)\n}\n");
+    assert_eq!(
+        outline(&cst),
+        "__Let(let_x_=_Binary(Name(a)_+_Call(Name(f)(Literal(1)))))___\
+         ForLoop(for_i_in_Range(Literal(0)..Name(x))_{_ExprStmt(Call(Name(print)(Name(i))))_})_"
+    );
+
+    let cst = Cst::parse("let y: [Int] = match x {\n  0 => 1,\n  _ => 2,\n}\n");
+    assert_eq!(
+        outline(&cst),
+        "Let(let_y:_Type([Type(Int)])_=_Match(match_Name(x)_{_\
+         MatchArm(Pattern(0)_=>_ExprStmt(Literal(1))),_MatchArm(Pattern(_)_=>_ExprStmt(Literal(2))),_}))_"
+    );
+}
+
+#[test]
+fn test_trivia() {
+    let cst = Cst::parse("let x = 1 // one\n/// docs\n// more\nfn f() {}\n");
+    assert_eq!(
+        texts(trailing_trivia(&token(&cst, "1"))),
+        [" ", "// one", "\n"]
+    );
+    assert_eq!(
+        texts(leading_trivia(&token(&cst, "fn"))),
+        ["/// docs", "\n", "// more", "\n"]
+    );
+    // the space between two tokens of a line trails the first one
+    assert_eq!(texts(trailing_trivia(&token(&cst, "x"))), [" "]);
+    assert!(leading_trivia(&token(&cst, "=")).is_empty());
+
+    let cst = Cst::parse("  \n// header\nlet x = 1");
+    assert_eq!(
+        texts(leading_trivia(&token(&cst, "let"))),
+        ["  \n", "// header", "\n"]
+    );
+    assert!(trailing_trivia(&token(&cst, "1")).is_empty());
+    assert_eq!(
+        cst.syntax()
+            .descendants_with_tokens()
+            .filter(|element| element.kind() == SyntaxKind::LineComment)
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn test_program() {
+    for source in examples() {
+        let cst = Cst::parse(&source);
+        let (program, errors) = cst.program();
+        let (expected, expected_errors) = parse_program(&source);
+        assert_eq!(program, expected);
+        assert_eq!(errors.len(), expected_errors.len());
+    }
+}
+
+#[test]
+fn test_edits_keep_layout() {
+    let source = "fn add(a, b) {\n    // the sum\n    :> a  +  b\n}\n";
+    let cst = Cst::parse(source);
+    let plus = token(&cst, "+");
+    let edited = plus.replace_with(GreenToken::new(SyntaxKind::Operator.into(), "*"));
+    let edited = Cst::from_syntax(&lira::cst::SyntaxNode::new_root(edited));
+    assert_eq!(
+        edited.text(),
+        "fn add(a, b) {\n    // the sum\n    :> a  *  b\n}\n"
+    );
+    assert_eq!(outline(&edited).replace('*', "+"), outline(&cst));
+    let (program, errors) = edited.program();
+    assert!(errors.is_empty());
+    assert_eq!(program.statements.len(), 1);
+}
+
+#[test]
+fn test_trivia_edits() {
+    let source = "let x=1\nfn f( a ) {\n  :> a\n}\n";
+    let cst = Cst::parse(source);
+    let formatted = Cst::parse(&format(source).unwrap());
+    let edits = cst.trivia_edits(&formatted).unwrap();
+
+    let mut text = source.to_string();
+    for (span, replacement) in edits.iter().rev() {
+        text.replace_range(span.start..span.end, replacement);
+    }
+    assert_eq!(text, formatted.text());
+    // only the layout around the changed tokens is replaced
+    assert!(edits.iter().all(|(span, _)| span.end - span.start <= 3));
+    assert_eq!(cst.trivia_edits(&cst), Some(Vec::new()));
+    assert_eq!(cst.trivia_edits(&Cst::parse("let x=2\n")), None);
+}
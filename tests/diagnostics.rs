@@ -0,0 +1,64 @@
+use lira::{ast::Span, diagnostics::Diagnostic, parser::parse_program};
+
+fn report(source: &str) -> String {
+    let (_, errors) = parse_program(source);
+    errors
+        .iter()
+        .map(|e| Diagnostic::from_parse_error(e, source).render("test.li", source))
+        .collect()
+}
+
+#[test]
+fn test_unrecognized_token() {
+    assert_eq!(
+        report("let a = 1\nlet b = [1, 2)"),
+        "error: unexpected `)`\n --> test.li:2:14\n  |\n2 | let b = [1, 2)\n  |              ^ expected `]`\n"
+    );
+}
+
+#[test]
+fn test_unrecognized_eof() {
+    assert_eq!(
+        report("fn main() {\n    print(1)\n"),
+        "error: unexpected end of file\n --> test.li:3:1\n  |\n3 | \n  | ^ expected `}`\n"
+    );
+}
+
+#[test]
+fn test_lexing_error_is_located() {
+    assert_eq!(
+        report("let x = 1 $ 2"),
+        "error: unrecognized token\n --> test.li:1:11\n  |\n1 | let x = 1 $ 2\n  |           ^\n"
+    );
+}
+
+#[test]
+fn test_render_with_note() {
+    let source = "let a: Int = \"x\"";
+    let diagnostic = Diagnostic::new("mismatched types", Span::new(13, 16))
+        .with_label("expected Int, found String")
+        .with_note("`a` is declared as Int");
+    assert_eq!(
+        diagnostic.render("test.li", source),
+        "error: mismatched types\n --> test.li:1:14\n  |\n1 | let a: Int = \"x\"\n  |              ^^^ expected Int, found String\n  = note: `a` is declared as Int\n"
+    );
+}
+
+#[test]
+fn test_every_recovered_error_is_reported() {
+    let report = report("let a = )\nlet b = 1\nlet c = ]\n");
+    assert_eq!(report.matches("error: ").count(), 2);
+    assert!(report.contains(" --> test.li:1:9\n"));
+    assert!(report.contains(" --> test.li:3:9\n"));
+}
+
+#[test]
+fn test_render_warning() {
+    let source = "let x = 1\nlet x = 2";
+    let diagnostic = Diagnostic::warning("`x` shadows an earlier binding", Span::new(10, 19));
+    assert!(!diagnostic.is_error());
+    assert_eq!(
+        diagnostic.render("test.li", source),
+        "warning: `x` shadows an earlier binding\n --> test.li:2:1\n  |\n2 | let x = 2\n  | ^^^^^^^^^\n"
+    );
+}
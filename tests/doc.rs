@@ -0,0 +1,126 @@
+use lira::{
+    doc::{Format, Page, generate},
+    parser::parse_program,
+};
+
+fn pages(modules: &[(&str, &str)], format: Format) -> Vec<Page> {
+    let programs: Vec<_> = modules
+        .iter()
+        .map(|(_, source)| {
+            let (program, errors) = parse_program(source);
+            assert!(errors.is_empty(), "{:?}", errors);
+            program
+        })
+        .collect();
+    let modules: Vec<_> = modules
+        .iter()
+        .zip(&programs)
+        .map(|((name, _), program)| (*name, program))
+        .collect();
+    generate(&modules, format)
+}
+
+const MAIN: &str = "\
+use utils::geometry
+
+/// The area of a shape.
+///
+/// Zero for `Empty`.
+async fn area(shape: Shape, scale) -> Int {
+    :> 0
+}
+
+fn helper() {
+    /// Nested functions are left out.
+    fn inner() {}
+}
+";
+
+const GEOMETRY: &str = "\
+/// A point.
+struct Point {
+    /// Horizontal.
+    x: Int,
+    y: Int,
+}
+
+enum Shape {
+    /// Round.
+    Circle(Point, Int),
+    Square { corner: Point },
+    Empty,
+}
+
+typealias Path = [Point]
+";
+
+#[test]
+fn test_markdown() {
+    let pages = pages(
+        &[("main", MAIN), ("utils::geometry", GEOMETRY)],
+        Format::Markdown,
+    );
+    let paths: Vec<_> = pages.iter().map(|page| page.path.as_str()).collect();
+    assert_eq!(paths, ["main.md", "utils.geometry.md", "index.md"]);
+
+    assert_eq!(
+        pages[0].text,
+        "\
+# Module `main`
+
+<a id=\"fn.area\"></a>
+
+## fn area
+
+<pre><code>async fn area(shape: <a href=\"utils.geometry.md#enum.Shape\">Shape</a>, scale) -&gt; Int</code></pre>
+
+The area of a shape.
+
+Zero for `Empty`.
+
+<a id=\"fn.helper\"></a>
+
+## fn helper
+
+<pre><code>fn helper()</code></pre>
+"
+    );
+
+    let geometry = &pages[1].text;
+    assert!(geometry.contains(
+        "<pre><code>struct Point {\n    x: Int,\n    y: Int,\n}</code></pre>\n\nA point.\n\n**Fields**\n\n- `x`: Horizontal.\n"
+    ));
+    assert!(geometry.contains("    Circle(<a href=\"#struct.Point\">Point</a>, Int),\n"));
+    assert!(geometry.contains("    Square { corner: <a href=\"#struct.Point\">Point</a> },\n"));
+    assert!(geometry.contains("- `Circle`: Round.\n"));
+    assert!(geometry.contains("typealias Path = [<a href=\"#struct.Point\">Point</a>]"));
+
+    assert_eq!(
+        pages[2].text,
+        "# API documentation\n\n- [`main`](main.md)\n- [`utils::geometry`](utils.geometry.md)\n"
+    );
+}
+
+#[test]
+fn test_html() {
+    let pages = pages(
+        &[
+            (
+                "main",
+                "/// Compares `a < b` & more.\nfn less(a, b) -> Bool {\n    :> a < b\n}\n",
+            ),
+            ("shapes", "struct Bool {}\n"),
+        ],
+        Format::Html,
+    );
+    let main = &pages[0].text;
+    assert!(main.starts_with("<!DOCTYPE html>"));
+    assert!(main.contains("<h2 id=\"fn.less\">fn less</h2>"));
+    assert!(main.contains("-&gt; <a href=\"shapes.html#struct.Bool\">Bool</a>"));
+    assert!(main.contains("<p>Compares <code>a &lt; b</code> &amp; more.</p>"));
+    assert!(
+        pages[2]
+            .text
+            .contains("<a href=\"shapes.html\"><code>shapes</code></a>")
+    );
+}
@@ -0,0 +1,470 @@
+mod common;
+
+use common::{run, run_with};
+use lira::eval::{Interpreter, RuntimeError, value::Value};
+
+#[test]
+fn test_arithmetic_and_interpolation() -> Result<(), RuntimeError> {
+    let output = run("let x = 2 + 3 * 4\nprint(\"x = #{x - 1}\", 7 / 2, 1.5 * 2)")?;
+    assert_eq!(output, "x = 13 3 3.0\n");
+    Ok(())
+}
+
+#[test]
+fn test_functions_and_recursion() -> Result<(), RuntimeError> {
+    let output = run(r#"
+fn fact(n: Int) -> Int {
+    if n <= 1 { :> 1 }
+    :> n * fact(n - 1)
+}
+print(fact(10))
+"#)?;
+    assert_eq!(output, "3628800\n");
+    Ok(())
+}
+
+#[test]
+fn test_main_is_called() -> Result<(), RuntimeError> {
+    let output = run("fn main() {\n    puts(\"hello\")\n}")?;
+    assert_eq!(output, "hello\n");
+    Ok(())
+}
+
+#[test]
+fn test_loops_and_break() -> Result<(), RuntimeError> {
+    let output = run(r#"
+let mut total = 0
+for i in 0..5 {
+    total += i
+}
+let mut n = 0
+while true {
+    n += 1
+    if n == 3 {
+        break
+    }
+}
+print(total, n)
+"#)?;
+    assert_eq!(output, "10 3\n");
+    Ok(())
+}
+
+#[test]
+fn test_closures_capture_and_pipe() -> Result<(), RuntimeError> {
+    let output = run(r#"
+let offset = 10
+let add = |x| x + offset
+let double = |x| x * 2
+let a = [1, 2, 3].map(add)
+print(a, 4 |> double |> add)
+"#)?;
+    assert_eq!(output, "[11, 12, 13] 18\n");
+    Ok(())
+}
+
+#[test]
+fn test_structs_enums_and_match() -> Result<(), RuntimeError> {
+    let output = run(r#"
+struct Point { x: Int, y: Int }
+enum Shape { Circle(Int), Rect { w: Int, h: Int }, Empty }
+
+fn area(s) {
+    :> match s {
+        Circle(r) => r * r * 3,
+        Rect { w, h: height } => w * height,
+        Empty => 0,
+    }
+}
+
+let p = Point.new(1, 2)
+let Point { x, y: b } = p
+p.x = 5
+print(x, b, p.x)
+print(area(Shape.Circle(2)), area(Shape.Rect(2, 3)), area(Shape.Empty))
+"#)?;
+    assert_eq!(output, "1 2 5\n12 6 0\n");
+    Ok(())
+}
+
+#[test]
+fn test_break_stops_each() -> Result<(), RuntimeError> {
+    let output = run(r#"
+let naturals = 0..inf
+naturals.each(|n| {
+    match n {
+        x if x < 3 => print(x),
+        _ => break,
+    }
+})
+"#)?;
+    assert_eq!(output, "0\n1\n2\n");
+    Ok(())
+}
+
+#[test]
+fn test_runtime_errors() {
+    assert_eq!(
+        run("print(y)"),
+        Err(RuntimeError::UndefinedVariable("y".to_string()))
+    );
+    assert_eq!(
+        run("let a = 1\na = 2"),
+        Err(RuntimeError::ImmutableAssignment("a".to_string()))
+    );
+    assert_eq!(run("let a = 1 / 0"), Err(RuntimeError::DivisionByZero));
+    assert_eq!(run("exit(3)"), Err(RuntimeError::Exit(3)));
+    assert_eq!(
+        run("let a = true + false"),
+        Err(RuntimeError::TypeError(
+            "cannot apply `+` to Bool and Bool".to_string()
+        ))
+    );
+    assert_eq!(
+        run("let a = -\"s\""),
+        Err(RuntimeError::TypeError(
+            "cannot apply `-` to String".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_spawn_and_join() -> Result<(), RuntimeError> {
+    let output = run(r#"
+use std::Task
+
+let t = spawn { :> 6 * 7 }
+let group = Task.group()
+for i in 0..8 {
+    spawn with group { :> i * i }
+}
+print(t.join(), t.done(), group.len())
+print(group.join())
+
+let items = [1, 2, 3]
+let c = spawn with items { :> context.len() }
+print(c.join())
+"#)?;
+    assert_eq!(output, "42 true 8\n[0, 1, 4, 9, 16, 25, 36, 49]\n3\n");
+    Ok(())
+}
+
+#[test]
+fn test_spawned_tasks_are_waited_for() -> Result<(), RuntimeError> {
+    let output = run(r#"
+use std::Task
+
+fn main() {
+    for i in 0..4 {
+        spawn { print("task #{i}") }
+    }
+    spawn with Task.inline() { print("inline") }
+}
+"#)?;
+    let mut lines: Vec<_> = output.lines().collect();
+    lines.sort();
+    assert_eq!(lines, ["inline", "task 0", "task 1", "task 2", "task 3"]);
+    Ok(())
+}
+
+#[test]
+fn test_task_errors() {
+    // the first failing task in spawn order, whichever finished first
+    let source = "spawn { :> 1 / 0 }\nspawn { :> missing }\nspawn { :> 2 / 0 }\n";
+    for _ in 0..10 {
+        assert_eq!(run(source), Err(RuntimeError::DivisionByZero));
+    }
+    assert_eq!(
+        run("let t = spawn { :> missing }\nprint(t.join())"),
+        Err(RuntimeError::UndefinedVariable("missing".to_string()))
+    );
+    // the error of the main thread wins
+    assert_eq!(
+        run("spawn { :> 1 / 0 }\nprint(y)"),
+        Err(RuntimeError::UndefinedVariable("y".to_string()))
+    );
+}
+
+#[test]
+fn test_atomic_bindings() -> Result<(), RuntimeError> {
+    let output = run(r#"
+use std::Task
+
+let atomic total = 0
+let group = Task.group()
+for i in 0..100 {
+    spawn with group {
+        total.add(i)
+        total += 1
+    }
+}
+group.join()
+print(total, total.sub(50), total.load() * 2)
+
+let atomic flag = false
+print(flag.compare_exchange(false, true), flag.compare_exchange(false, true))
+print(flag.swap(false), flag)
+
+let atomic names = ["a"]
+names.store(["b"])
+names.push("c")
+print(names, names.len(), names.compare_exchange(["b", "c"], []))
+"#)?;
+    assert_eq!(
+        output,
+        "5050 5050 10000\nOk(false) Err(true)\ntrue false\n[b, c] 2 Ok([b, c])\n"
+    );
+    assert_eq!(
+        run("let atomic n = 1\nn.store(\"one\")"),
+        Err(RuntimeError::TypeError(
+            "atomic Int cannot hold String".to_string()
+        ))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_lazy_bindings() -> Result<(), RuntimeError> {
+    let output = run(r#"
+use std::Task
+
+fn answer() -> Int {
+    print("computing")
+    return 42
+}
+let lazy a = answer()
+print("before")
+print(a, a + 1)
+
+let lazy never = 1 / 0
+let lazy atomic hits = answer()
+let group = Task.group()
+for i in 0..10 {
+    spawn with group {
+        hits += 1
+    }
+}
+group.join()
+print(hits)
+"#)?;
+    assert_eq!(output, "before\ncomputing\n42 43\ncomputing\n52\n");
+    Ok(())
+}
+
+#[test]
+fn test_lazy_cycles() {
+    let source = r#"
+fn f() -> Int {
+    :> a + 1
+}
+let lazy a = f()
+print(a)
+"#;
+    assert_eq!(run(source), Err(RuntimeError::LazyCycle("a".to_string())));
+    // through another binding, read from a task
+    let source = r#"
+let lazy b = c + 1
+let lazy c = b * 2
+let t = spawn { print(c) }
+t.join()
+"#;
+    assert_eq!(run(source), Err(RuntimeError::LazyCycle("c".to_string())));
+    // read by a task joined, or a future awaited, from its expression
+    let source = r#"
+fn f() -> Int {
+    let t = spawn { :> d + 1 }
+    :> t.join()
+}
+let lazy d = f()
+print(d)
+"#;
+    assert_eq!(run(source), Err(RuntimeError::LazyCycle("d".to_string())));
+    let source = r#"
+async fn g() -> Int {
+    :> e + 1
+}
+let lazy e = await g()
+print(e)
+"#;
+    assert_eq!(run(source), Err(RuntimeError::LazyCycle("e".to_string())));
+    let source = "let lazy f = (0..100).par_map(|x| x + f).len()\nprint(f)";
+    assert_eq!(run(source), Err(RuntimeError::LazyCycle("f".to_string())));
+}
+
+#[test]
+fn test_infinite_ranges_are_lazy() -> Result<(), RuntimeError> {
+    let output = run(r#"
+let odd_squares = (0..inf).map(|x| x * x).filter(|x| x % 2 == 1)
+print(odd_squares, odd_squares.take(4).collect(), odd_squares.take(2).collect())
+
+let naturals = 1..inf
+naturals.map(|x| x * 10).each(|x| {
+    if x > 30 {
+        break
+    }
+    print(x)
+})
+print((0..inf).take(0).collect(), [1, 2, 3].take(2), (0..10).take(3))
+"#)?;
+    assert_eq!(
+        output,
+        "<sequence> [1, 9, 25, 49] [1, 9]\n10\n20\n30\n[] [1, 2] [0, 1, 2]\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_parallel_methods() -> Result<(), RuntimeError> {
+    let output = run(r##"
+let atomic total = 0
+let numbers = 1..101
+numbers.par_each(|i| total.add(i))
+print(total)
+print((0..12).par_map(|x| x * x), [5, 3, 8, 1, 9].par_filter(|x| x > 2))
+print((1..1001).par_reduce(0, |a, b| a + b), [].par_reduce(7, |a, b| a + b))
+let mut digits = ""
+for x in 0..300 {
+    digits = "#{digits}#{x}"
+}
+print((0..300).par_reduce("", |a, b| "#{a}#{b}") == digits)
+print((0..10).par_map(|x| {
+    if x == 4 {
+        break
+    }
+    :> x * 10
+}))
+
+let lazy evens = (0..inf).par_map(|x| x * 2)
+let atomic seen = 0
+evens.par_each(|n| {
+    match n {
+        x if x < 10 => seen.add(1),
+        _ => break,
+    }
+})
+print(seen, evens.take(5).collect(), (0..inf).par_filter(|x| x % 3 == 0).take(3).par_reduce(0, |a, b| a + b))
+"##)?;
+    assert_eq!(
+        output,
+        "5050\n[0, 1, 4, 9, 16, 25, 36, 49, 64, 81, 100, 121] [5, 3, 8, 9]\n500500 7\ntrue\n[0, 10, 20, 30]\n5 [0, 2, 4, 6, 8] 9\n"
+    );
+    // calls nested in tasks and in other calls share the workers
+    let output = run(r#"
+use std::Task
+let group = Task.group()
+for i in 0..8 {
+    spawn with group {
+        :> (0..100).par_map(|x| (0..x).par_reduce(0, |a, b| a + b)).par_reduce(0, |a, b| a + b)
+    }
+}
+print(group.join())
+"#)?;
+    assert_eq!(output, format!("[{}]\n", ["161700"; 8].join(", ")));
+    assert_eq!(
+        run("let naturals = 0..inf\nnaturals.par_reduce(0, |a, b| a + b)"),
+        Err(RuntimeError::TypeError(
+            "cannot `par_reduce` an infinite range, `take` some items first".to_string()
+        ))
+    );
+    // a chunk panicking fails the call once the chunks other threads took ran
+    let source = "let t = spawn {\n    :> (0..2000).par_map(|x| check(x))\n}\nprint(t.join())";
+    let result = run_with(source, |interpreter| {
+        let check = Value::Native("check", check_not_zero);
+        interpreter.globals().define("check", check, false);
+    });
+    assert_eq!(result, Err(RuntimeError::Panic("zero".to_string())));
+    Ok(())
+}
+
+fn check_not_zero<'a>(
+    _: &Interpreter<'a>,
+    args: Vec<Value<'a>>,
+) -> Result<Value<'a>, RuntimeError> {
+    match args[0] {
+        Value::Int(0) => panic!("zero"),
+        _ => Ok(args[0].clone()),
+    }
+}
+
+#[test]
+fn test_async_functions() -> Result<(), RuntimeError> {
+    let path = std::env::temp_dir().join(format!("lira-async-{}.txt", std::process::id()));
+    let output = run(&format!(
+        r##"
+use std::Fs
+use std::Time
+
+async fn worker(name: String, ms: Int) -> String {{
+    print("#{{name}} start")
+    await Time.sleep(ms)
+    print("#{{name}} end")
+    return name
+}}
+
+async fn both() -> String {{
+    let slow = worker("slow", 60)
+    let fast = worker("fast", 5)
+    let first = await fast
+    let second = await slow
+    :> "#{{first}} then #{{second}}"
+}}
+
+let all = both()
+print(all.done())
+print(await all, all.done())
+
+await Fs.write_async("{path}", "written")
+print(await Fs.read_async("{path}"))
+let later: async String = worker("never awaited", 1)
+"##,
+        path = path.display()
+    ))?;
+    std::fs::remove_file(path).ok();
+    assert_eq!(
+        output,
+        "false\nslow start\nfast start\nfast end\nslow end\nfast then slow true\nwritten\nnever awaited start\nnever awaited end\n"
+    );
+    assert_eq!(
+        run("async fn boom() -> Int {\n    :> 1 / 0\n}\nlet f = boom()"),
+        Err(RuntimeError::DivisionByZero)
+    );
+    assert_eq!(
+        run("async fn cycle() -> Int {\n    return await f\n}\nlet f = cycle()\nprint(await f)"),
+        Err(RuntimeError::Deadlock)
+    );
+    // awaiting off the future while it waits for the thread does not hang
+    assert_eq!(
+        run(r#"
+async fn double(x: Int) -> Int {
+    :> x * 2
+}
+async fn all() -> Int {
+    let xs = (0..200).par_map(|x| {
+        let y = await double(x)
+        :> y
+    })
+    :> xs.len()
+}
+print(await all())
+"#),
+        Err(RuntimeError::Deadlock)
+    );
+    assert_eq!(
+        run(r#"
+use std::Time
+async fn one() -> Int {
+    :> 1
+}
+async fn outer() -> Int {
+    let t = spawn {
+        :> await one()
+    }
+    await Time.sleep(50)
+    :> t.join()
+}
+print(await outer())
+"#),
+        Err(RuntimeError::Deadlock)
+    );
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use lira::formatter::{FormatError, format};
+
+fn assert_formats(source: &str, expected: &str) {
+    let formatted = format(source).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(format(&formatted).unwrap(), expected, "not idempotent");
+}
+
+#[test]
+fn test_joins_broken_declarations() {
+    let source = "fn \n    parallel_sum\n    (\n    n\n    :\n    Int\n    )\n    -> \n    Int \n    {\n\n    }\n";
+    assert_formats(source, "fn parallel_sum(n: Int) -> Int {}\n");
+    assert_formats(
+        "let \n    atomic \n    lazy \n    y = 2.5e1;",
+        "let atomic lazy y = 2.5e1\n",
+    );
+}
+
+#[test]
+fn test_indents_blocks() {
+    let source = "fn main() {\nlet x=1\n  if x>0{print(x)}elsif x<0 {print(-x)}else{\n        print( 0 )\n  }\nfor i in 0..x\n{ break; }\n}";
+    let expected = "\
+fn main() {
+    let x = 1
+    if x > 0 {
+        print(x)
+    } elsif x < 0 {
+        print(-x)
+    } else {
+        print(0)
+    }
+    for i in 0..x {
+        break
+    }
+}
+";
+    assert_formats(source, expected);
+}
+
+#[test]
+fn test_spacing() {
+    assert_formats(
+        "let y=-xs.len()+ 1*2 |> f\n",
+        "let y = -xs.len() + 1 * 2 |> f\n",
+    );
+    assert_formats(
+        "typealias F = async ( Int,Int ) : Response\n",
+        "typealias F = async (Int, Int): Response\n",
+    );
+    assert_formats(
+        "let f = |a : Int,b| a+b\nlet g = |x| -x\nprint(!true, ~1, a - -1, a | b)\n",
+        "let f = |a: Int, b| a + b\nlet g = |x| -x\nprint(!true, ~1, a - -1, a | b)\n",
+    );
+    assert_formats("use std :: Json;", "use std::Json\n");
+}
+
+#[test]
+fn test_match_arms() {
+    let source = "match x { 0 => puts (\"zero\"), n if n>0 => { print(n) }\n_ => break, }";
+    let expected = "\
+match x {
+    0 => puts(\"zero\"),
+    n if n > 0 => {
+        print(n)
+    }
+    _ => break,
+}
+";
+    assert_formats(source, expected);
+}
+
+#[test]
+fn test_keeps_comments() {
+    let source = "\
+// header
+
+/// docs
+fn main( ) {   // trailing
+    let xs = [
+        1, // one
+        2,
+        /* three */ 3
+    ]
+
+
+    /* block
+       comment */
+    print(xs)  // call
+    // last
+}
+";
+    let expected = "\
+// header
+
+/// docs
+fn main() { // trailing
+    let xs = [
+        1, // one
+        2,
+        /* three */ 3
+    ]
+
+    /* block
+       comment */
+    print(xs) // call
+    // last
+}
+";
+    assert_formats(source, expected);
+}
+
+#[test]
+fn test_lists() {
+    assert_formats(
+        "struct Point {\n  x: Int,\n  y: Int\n}\nenum E {A,B}\n",
+        "struct Point {\n    x: Int,\n    y: Int\n}\nenum E { A, B }\n",
+    );
+    assert_formats(
+        "let xs = [\n1,\n2]\nlet ys = [1,\n2]\n",
+        "let xs = [\n    1,\n    2\n]\nlet ys = [1, 2]\n",
+    );
+    assert_formats(
+        "xs.each(|x| { print(x) })\n",
+        "xs.each(|x| {\n    print(x)\n})\n",
+    );
+}
+
+#[test]
+fn test_syntax_errors() {
+    let Err(FormatError::Syntax(diagnostics)) = format("let = 3\n") else {
+        panic!("expected a syntax error");
+    };
+    assert_eq!(diagnostics[0].message, "unexpected `=`");
+}
@@ -0,0 +1,158 @@
+#![cfg(feature = "jit")]
+
+mod common;
+
+use std::fs;
+
+use common::Output;
+use lira::{
+    ast::Program,
+    eval::{Interpreter, RuntimeError},
+    jit::Jit,
+    parser::parse_program,
+};
+
+fn interpret(program: &Program, jit: Option<Jit>) -> (Result<String, RuntimeError>, String) {
+    let output = Output::default();
+    let mut interpreter = Interpreter::with_output(Box::new(output.clone()));
+    if let Some(jit) = jit {
+        interpreter.set_jit(jit);
+    }
+    let result = interpreter.run(program).map(|value| value.to_string());
+    (result, output.text())
+}
+
+/// Runs `source` with and without native code, which must agree on both the
+/// output and the result, and returns the compiled functions.
+fn run(source: &str) -> (Vec<String>, Result<String, RuntimeError>, String) {
+    let (program, errors) = parse_program(source);
+    assert!(errors.is_empty(), "source should parse: {:?}", errors);
+    let jit = Jit::new(&program);
+    let compiled = jit.compiled().into_iter().map(String::from).collect();
+
+    let expected = interpret(&program, None);
+    let (result, output) = interpret(&program, Some(jit));
+    assert_eq!(result, expected.0, "results differ for:\n{}", source);
+    assert_eq!(output, expected.1, "outputs differ for:\n{}", source);
+    (compiled, result, output)
+}
+
+#[test]
+fn test_examples_match_interpreter() {
+    let mut paths: Vec<_> = fs::read_dir("examples")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    for path in paths {
+        let source = fs::read_to_string(&path).unwrap();
+        if parse_program(&source).1.is_empty() {
+            let _ = run(&source);
+        }
+    }
+}
+
+#[test]
+fn test_numeric_functions_are_compiled() {
+    let (compiled, _, output) = run(r#"
+fn collatz(n: Int) -> Int {
+    let mut steps = 0
+    let mut x = n
+    while x != 1 {
+        if x % 2 == 0 {
+            x = x / 2
+        } else {
+            x = 3 * x + 1
+        }
+        steps += 1
+    }
+    :> steps
+}
+
+fn mean(a: Int, b: Float) -> Float {
+    :> (a + b) / 2
+}
+
+fn first_square_above(limit: Int) -> Int {
+    let mut found = -1
+    for i in 0..limit {
+        if i * i > limit {
+            found = i
+            break
+        }
+    }
+    :> found
+}
+
+fn between(x: Float, lo: Float, hi: Float) -> Bool {
+    :> x >= lo && x <= hi || !(x == x)
+}
+
+fn cube(n: int) -> i64 {
+    :> n * n * n
+}
+
+fn greet(name) {
+    print("hello #{name}")
+}
+
+fn main() {
+    print(collatz(27), mean(3, 0.5), first_square_above(50), between(1.5, 1.0, 2.0), cube(3))
+    greet("jit")
+}
+"#);
+    assert_eq!(
+        compiled,
+        ["between", "collatz", "cube", "first_square_above", "mean"]
+    );
+    assert_eq!(output, "111 1.75 8 true 27\nhello jit\n");
+}
+
+#[test]
+fn test_unsupported_functions_fall_back() {
+    let (compiled, _, output) = run(r#"
+fn show(n: Int) -> Int {
+    print(n)
+    :> n
+}
+
+fn twice(n: Int) -> Int {
+    :> show(n) * 2
+}
+
+fn sign(n: Int) -> Int {
+    if n < 0 {
+        :> -1
+    }
+}
+
+fn half(x: Float) -> Float {
+    :> x / 2
+}
+
+print(twice(4), sign(5), half(3))
+"#);
+    assert_eq!(compiled, ["half"]);
+    assert_eq!(output, "4\n8 () 1\n");
+}
+
+#[test]
+fn test_runtime_errors_match_interpreter() {
+    let source = |call: &str| {
+        format!(
+            "fn div(a: Int, b: Int) -> Int {{\n    :> a / b\n}}\n\
+             fn square(n: Int) -> Int {{\n    :> n * n\n}}\n\
+             fn shift(n: Int, by: Int) -> Int {{\n    :> n << by\n}}\n\
+             print({})",
+            call
+        )
+    };
+    let error = |call| run(&source(call)).1;
+    assert_eq!(error("div(1, 0)"), Err(RuntimeError::DivisionByZero));
+    assert_eq!(
+        error("square(65536 * 65536)"),
+        Err(RuntimeError::IntegerOverflow)
+    );
+    assert_eq!(error("shift(1, 64)"), Err(RuntimeError::IntegerOverflow));
+    assert_eq!(error("div(7, -2) + shift(3, 2)"), Ok("()".to_string()));
+}
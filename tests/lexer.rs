@@ -119,7 +119,7 @@ fn test_string_with_interpolation() -> Result<(), LexingError> {
             parts,
             &[
                 StringPart::Text("hello ".into()),
-                StringPart::Expression(vec![(0usize, Token::Ident("name".into()), 4usize)]),
+                StringPart::Expression(vec![(8usize, Token::Ident("name".into()), 12usize)]),
             ]
         );
     } else {
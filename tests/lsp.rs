@@ -0,0 +1,256 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    process::{Child, ChildStdout, Command, Stdio},
+};
+
+use serde_json::{Value, json};
+
+/// A client scripting `lira-lsp` through its stdio.
+struct Client {
+    server: Child,
+    reader: BufReader<ChildStdout>,
+    next_id: i64,
+}
+
+impl Client {
+    fn start() -> Self {
+        let mut server = Command::new(env!("CARGO_BIN_EXE_lira-lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let reader = BufReader::new(server.stdout.take().unwrap());
+        let mut client = Client {
+            server,
+            reader,
+            next_id: 0,
+        };
+        client.request("initialize", json!({ "capabilities": {} }));
+        client.notify("initialized", json!({}));
+        client
+    }
+
+    fn send(&mut self, message: Value) {
+        let body = message.to_string();
+        let stdin = self.server.stdin.as_mut().unwrap();
+        write!(stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            self.reader.read_line(&mut header).unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length: ") {
+                length = value.parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        self.reader.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// Sends a request and returns the result of its response, skipping
+    /// the notifications sent meanwhile.
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        loop {
+            let message = self.receive();
+            if message["id"] == id {
+                assert!(message.get("error").is_none(), "{}", message);
+                return message["result"].clone();
+            }
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    fn notification(&mut self, method: &str) -> Value {
+        loop {
+            let message = self.receive();
+            if message["method"] == method {
+                return message["params"].clone();
+            }
+        }
+    }
+
+    fn open(&mut self, uri: &str, text: &str) -> Value {
+        let document = json!({ "uri": uri, "languageId": "lira", "version": 1, "text": text });
+        self.notify("textDocument/didOpen", json!({ "textDocument": document }));
+        self.notification("textDocument/publishDiagnostics")
+    }
+
+    fn at(&mut self, method: &str, uri: &str, line: u32, character: u32) -> Value {
+        let params = json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": line, "character": character },
+        });
+        self.request(method, params)
+    }
+
+    fn shutdown(mut self) {
+        assert_eq!(self.request("shutdown", Value::Null), Value::Null);
+        self.notify("exit", Value::Null);
+        assert!(self.server.wait().unwrap().success());
+    }
+}
+
+const URI: &str = "file:///tmp/lira-lsp/main.li";
+
+const SOURCE: &str = "\
+struct Point {
+    x: Int,
+    y: Int
+}
+
+fn add(a: Int, b: Int) -> Int {
+    :> a + b
+}
+
+let total = add(1, 2)
+print(total)
+";
+
+#[test]
+fn test_diagnostics() {
+    let mut client = Client::start();
+    let published = client.open(URI, SOURCE);
+    assert_eq!(published["uri"], URI);
+    assert_eq!(published["diagnostics"], json!([]));
+
+    let change = json!({
+        "textDocument": { "uri": URI, "version": 2 },
+        "contentChanges": [{ "text": "let = 1\nprint(missing)\n" }],
+    });
+    client.notify("textDocument/didChange", change);
+    let published = client.notification("textDocument/publishDiagnostics");
+    let diagnostic = &published["diagnostics"][0];
+    assert!(
+        diagnostic["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("unexpected `=`")
+    );
+    assert_eq!(diagnostic["severity"], 1);
+    assert_eq!(
+        diagnostic["range"]["start"],
+        json!({ "line": 0, "character": 4 })
+    );
+
+    client.notify(
+        "textDocument/didClose",
+        json!({ "textDocument": { "uri": URI } }),
+    );
+    let published = client.notification("textDocument/publishDiagnostics");
+    assert_eq!(published["diagnostics"], json!([]));
+    client.shutdown();
+}
+
+#[test]
+fn test_symbols() {
+    let mut client = Client::start();
+    client.open(URI, SOURCE);
+    let symbols = client.request(
+        "textDocument/documentSymbol",
+        json!({ "textDocument": { "uri": URI } }),
+    );
+    let names: Vec<_> = symbols
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|symbol| symbol["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["Point", "add"]);
+    assert_eq!(symbols[0]["children"][1]["name"], "y");
+    assert_eq!(
+        symbols[0]["children"][1]["selectionRange"]["start"],
+        json!({ "line": 2, "character": 4 })
+    );
+    assert_eq!(symbols[1]["detail"], "(Int, Int): Int");
+    client.shutdown();
+}
+
+#[test]
+fn test_hover_and_definition() {
+    let mut client = Client::start();
+    client.open(URI, SOURCE);
+
+    let hover = client.at("textDocument/hover", URI, 10, 7);
+    assert_eq!(hover["contents"]["value"], "```lira\ntotal: Int\n```");
+    let hover = client.at("textDocument/hover", URI, 5, 4);
+    assert_eq!(
+        hover["contents"]["value"],
+        "```lira\nadd: (Int, Int): Int\n```"
+    );
+
+    let definition = client.at("textDocument/definition", URI, 9, 13);
+    assert_eq!(definition["uri"], URI);
+    assert_eq!(
+        definition["range"],
+        json!({ "start": { "line": 5, "character": 3 }, "end": { "line": 5, "character": 6 } })
+    );
+    let definition = client.at("textDocument/definition", URI, 6, 7);
+    assert_eq!(
+        definition["range"]["start"],
+        json!({ "line": 5, "character": 7 })
+    );
+    client.shutdown();
+}
+
+#[test]
+fn test_use_definition() {
+    let dir = std::env::temp_dir().join(format!("lira-lsp-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("utils")).unwrap();
+    std::fs::write(
+        dir.join("utils/math.li"),
+        "fn double(x) {\n    :> x * 2\n}\n",
+    )
+    .unwrap();
+    let main = dir.join("main.li");
+    let uri = format!("file://{}", main.display());
+
+    let mut client = Client::start();
+    let published = client.open(&uri, "use utils::math\nprint(math.double(2))\n");
+    assert_eq!(published["diagnostics"], json!([]));
+    let module = format!("file://{}", dir.join("utils/math.li").display());
+    assert_eq!(
+        client.at("textDocument/definition", &uri, 0, 12)["uri"],
+        module
+    );
+    assert_eq!(
+        client.at("textDocument/definition", &uri, 1, 7)["uri"],
+        module
+    );
+    client.shutdown();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_semantic_tokens() {
+    let mut client = Client::start();
+    client.open(URI, "fn id(a) {\n    :> a // same\n}\n");
+    let tokens = client.request(
+        "textDocument/semanticTokens/full",
+        json!({ "textDocument": { "uri": URI } }),
+    );
+    // line delta, start delta, length, type and modifiers of each token
+    let expected = [
+        [0, 0, 2, 0, 0], // fn
+        [0, 3, 2, 2, 0], // id
+        [0, 3, 1, 1, 0], // a, where it is declared
+        [1, 4, 2, 0, 0], // :>
+        [0, 3, 1, 3, 0], // a
+        [0, 2, 7, 8, 0], // comment
+    ];
+    assert_eq!(tokens["data"], json!(expected.concat()));
+    client.shutdown();
+}
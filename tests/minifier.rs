@@ -0,0 +1,327 @@
+mod common;
+
+use std::fs;
+
+use common::run;
+use lira::{
+    Lexer, Token,
+    minifier::{
+        MinifyError, Options, minify, minify_with_map,
+        source_map::{DecodeError, SourceMap},
+    },
+    parser::parse_program,
+};
+use proptest::{prelude::*, sample::select};
+
+const DROP: Options = Options {
+    drop_comments: true,
+    mangle: false,
+};
+const MANGLE: Options = Options {
+    drop_comments: true,
+    mangle: true,
+};
+
+/// The program `source` parses to, without its spans.
+fn shape(source: &str) -> serde_json::Value {
+    fn strip(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(object) => {
+                object.remove("span");
+                object.values_mut().for_each(strip);
+            }
+            serde_json::Value::Array(values) => values.iter_mut().for_each(strip),
+            _ => {}
+        }
+    }
+    let (program, errors) = parse_program(source);
+    assert!(errors.is_empty(), "{:?} in {:?}", errors, source);
+    let mut value = serde_json::to_value(program).unwrap();
+    strip(&mut value);
+    value
+}
+
+fn assert_round_trips(source: &str) {
+    for options in [Options::default(), DROP] {
+        let minified = minify(source, options).unwrap();
+        assert_eq!(shape(&minified), shape(source), "{:?}", minified);
+        assert_eq!(minify(&minified, options).unwrap(), minified);
+        if options.drop_comments {
+            let mut tokens = Lexer::new(&minified).flatten();
+            assert!(!tokens.any(|(_, token, _)| matches!(
+                token,
+                Token::LineComment(_) | Token::BlockComment(_) | Token::DocComment(_)
+            )));
+        }
+    }
+    minify(source, MANGLE).unwrap();
+
+    // the tokens are mapped back to themselves
+    let (minified, map) = minify_with_map(source, "source.li", Options::default()).unwrap();
+    for segment in &map.segments {
+        let original = &source[segment.original.start..segment.original.end];
+        let generated = &minified[segment.generated.start..segment.generated.end];
+        assert_eq!(generated, original.trim_end());
+        assert_eq!(
+            map.lookup(segment.generated.start),
+            Ok(Some(("source.li", segment.original.start)))
+        );
+    }
+}
+
+fn examples() -> Vec<String> {
+    let mut paths: Vec<_> = fs::read_dir("examples")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "li"))
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| fs::read_to_string(path).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_joins_declarations() {
+    let source = "fn \n    parallel_sum\n    (\n    n\n    :\n    Int\n    )\n    -> \n    Int \n    {\n\n    }\n";
+    assert_eq!(
+        minify(source, Options::default()).unwrap(),
+        "fn parallel_sum(n:Int)->Int{}"
+    );
+}
+
+#[test]
+fn test_separates_statements() {
+    assert_eq!(
+        minify("let x = a\nprint( x )\n\n", DROP).unwrap(),
+        "let x=a;print(x)"
+    );
+    assert_eq!(
+        minify("let y = a - -1\nlet z = 1 .. 2\n", DROP).unwrap(),
+        "let y=a--1;let z=1..2"
+    );
+    assert_eq!(
+        minify("fn f() {\n    :> x\n}\nf()", DROP).unwrap(),
+        "fn f(){:>x};f()"
+    );
+}
+
+#[test]
+fn test_comments() {
+    let source = "// header\nlet x = 1 /* one */\n/// docs\nfn f(a) {\n    print(a) // call\n}\n";
+    assert_eq!(
+        minify(source, Options::default()).unwrap(),
+        "// header\nlet x=1/* one *//// docs\nfn f(a){print(a)// call\n}"
+    );
+    assert_eq!(minify(source, DROP).unwrap(), "let x=1;fn f(a){print(a)}");
+}
+
+#[test]
+fn test_source_map() {
+    let source = "fn add(count, b) {\n    :> count + b\n}\nprint(add(1, 2))\n";
+    let (minified, map) = minify_with_map(source, "add.li", MANGLE).unwrap();
+    assert_eq!(minified, "fn add(a,b){:>a+b};print(add(1,2))");
+    assert_eq!(map.sources, ["add.li"]);
+    // `a` is `count` renamed
+    assert_eq!(map.lookup(7), Ok(Some(("add.li", 7))));
+    assert_eq!(map.lookup(15), Ok(Some(("add.li", 32))));
+    // the separator added after `}`
+    assert_eq!(map.lookup(18), Ok(Some(("add.li", 37))));
+    assert_eq!(map.lookup(minified.len()), Ok(None));
+
+    let mut joined = SourceMap::default();
+    joined.append(map.clone(), 0);
+    joined.append(map, minified.len() + 1);
+    assert_eq!(joined.sources, ["add.li", "add.li"]);
+    assert_eq!(
+        joined.lookup(minified.len() + 1 + 15),
+        Ok(Some(("add.li", 32)))
+    );
+
+    // a map read from a file may refer to sources it does not list
+    joined.sources.truncate(1);
+    assert_eq!(
+        joined.lookup(minified.len() + 1 + 15),
+        Err(DecodeError::UnknownSource(1))
+    );
+}
+
+#[test]
+fn test_syntax_errors() {
+    let Err(MinifyError::Syntax(diagnostics)) = minify("let = 3\n", DROP) else {
+        panic!("expected a syntax error");
+    };
+    assert_eq!(diagnostics[0].message, "unexpected `=`");
+}
+
+#[test]
+fn test_mangles_locals() {
+    let source = "\
+let total = 10
+fn scale(value, factor) {
+    let result = value * factor
+    :> result + total
+}
+let doubled = [1, 2].map(|item| scale(item, 2))
+print(\"#{total}\", doubled.len())
+";
+    assert_eq!(
+        minify(source, MANGLE).unwrap(),
+        "let total=10;fn scale(d,a){let c=d*a;:>c+total};let doubled=[1,2].map(|b|scale(b,2));print(\"#{total}\",doubled.len())"
+    );
+}
+
+#[test]
+fn test_mangling_keeps_behaviour() {
+    let source = "\
+use std::Process
+fn outer(count) {
+    fn inner(step) {
+        :> step * 2
+    }
+    let mut acc = 0
+    for index in 0..count {
+        acc += inner(index)
+    }
+    :> acc
+}
+let labelled = |value| \"v=#{value}\"
+let results = [outer(3), outer(4)]
+print(results, labelled(5), Process.args().len())
+";
+    let mangled = minify(source, MANGLE).unwrap();
+    for name in ["count", "inner", "step", "acc", "index"] {
+        assert!(!mangled.contains(name), "{} in {}", name, mangled);
+    }
+    for name in ["outer", "labelled", "value", "results", "Process", "args"] {
+        assert!(mangled.contains(name), "{} not in {}", name, mangled);
+    }
+    assert_eq!(run(&mangled).unwrap(), run(source).unwrap());
+}
+
+#[test]
+fn test_examples() {
+    for source in examples() {
+        assert_round_trips(&source);
+    }
+}
+
+// Layouts of the same program: the spaces between tokens and the line ends
+// between statements are replaced by others.
+fn relayout(source: &str, choices: &[usize]) -> String {
+    const SPACES: [&str; 3] = [" ", "\t", "   "];
+    const LINE_ENDS: [&str; 5] = ["\n", "\n\n", ";", "\n    // note\n", "\n/* note */\n"];
+
+    let mut choices = choices.iter().cycle();
+    let mut result = String::new();
+    let mut last_end = 0;
+    let mut after_comment = false;
+    for (start, token, end) in Lexer::new(source).flatten() {
+        if start > last_end {
+            result.push_str(SPACES[choices.next().unwrap() % SPACES.len()]);
+        }
+        // a line comment runs until the end of its line
+        if after_comment {
+            result.push('\n');
+        }
+        after_comment = matches!(token, Token::LineComment(_) | Token::DocComment(_));
+        if token == Token::LineEnd && source[start..end].contains(['\n', ';']) {
+            result.push_str(LINE_ENDS[choices.next().unwrap() % LINE_ENDS.len()]);
+        } else {
+            result.push_str(&source[start..end]);
+        }
+        last_end = end;
+    }
+    result
+}
+
+fn expr() -> impl Strategy<Value = String> {
+    let leaf = prop_oneof![
+        (0..1000).prop_map(|n: i32| n.to_string()),
+        select(vec!["1.5", "2.5e1", "0x1f", "0b101", "true", "inf"]).prop_map(String::from),
+        name().prop_map(String::from),
+        select(vec!["\"text\"", "\"n=#{a + 1}\""]).prop_map(String::from),
+    ];
+    leaf.prop_recursive(4, 24, 3, |inner| {
+        let operators = vec![
+            "+", "-", "*", "/", "%", "==", "!=", "<", "<=", ">", ">=", "&&", "||", "&", "|", "^",
+            "<<", ">>", "..", "|>",
+        ];
+        prop_oneof![
+            (inner.clone(), select(operators), inner.clone())
+                .prop_map(|(lhs, op, rhs)| format!("{} {} {}", lhs, op, rhs)),
+            (select(vec!["-", "!", "~", "+"]), inner.clone())
+                .prop_map(|(op, e)| format!("{}{}", op, e)),
+            inner.clone().prop_map(|e| format!("({})", e)),
+            prop::collection::vec(inner.clone(), 0..3)
+                .prop_map(|items| format!("[{}]", items.join(", "))),
+            (
+                select(vec!["f", "print"]),
+                prop::collection::vec(inner.clone(), 0..3)
+            )
+                .prop_map(|(f, args)| format!("{}({})", f, args.join(", "))),
+            select(vec!["a", "xs"]).prop_map(|name| format!("{}.len()", name)),
+            inner.prop_map(|e| format!("xs.map(|x| {})", e)),
+        ]
+    })
+}
+
+fn name() -> impl Strategy<Value = &'static str> {
+    select(vec!["a", "b", "xs", "total", "value", "step"])
+}
+
+fn statement() -> impl Strategy<Value = String> {
+    let simple = prop_oneof![
+        (name(), expr()).prop_map(|(name, e)| format!("let {} = {}", name, e)),
+        (name(), expr()).prop_map(|(name, e)| format!("let mut {} = |x, y| {}", name, e)),
+        (name(), select(vec!["=", "+=", "<<="]), expr())
+            .prop_map(|(name, op, e)| format!("{} {} {}", name, op, e)),
+        prop::collection::vec(expr(), 0..3).prop_map(|args| format!("print({})", args.join(", "))),
+        (name(), expr(), expr(), expr()).prop_map(|(name, e, a, b)| format!(
+            "let {} = match {} {{\n0 => {},\n_ => {},\n}}",
+            name, e, a, b
+        )),
+        expr().prop_map(|e| format!(":> {}", e)),
+        Just("break".to_string()),
+    ];
+    simple.prop_recursive(3, 16, 3, |inner| {
+        let block = prop::collection::vec(inner, 0..3).prop_map(|stmts| {
+            if stmts.is_empty() {
+                "{}".to_string()
+            } else {
+                format!("{{\n{}\n}}", stmts.join("\n"))
+            }
+        });
+        prop_oneof![
+            (expr(), block.clone(), block.clone())
+                .prop_map(|(c, then, other)| format!("if {} {} else {}", c, then, other)),
+            (expr(), block.clone()).prop_map(|(c, body)| format!("while {} {}", c, body)),
+            (expr(), block.clone()).prop_map(|(e, body)| format!("for i in {} {}", e, body)),
+            (name(), block.clone()).prop_map(|(name, body)| format!("fn {}(a, b) {}", name, body)),
+            block.prop_map(|body| format!("xs.each(|x| {})", body)),
+        ]
+    })
+}
+
+fn program() -> impl Strategy<Value = String> {
+    prop::collection::vec(statement(), 1..6).prop_map(|stmts| stmts.join("\n") + "\n")
+}
+
+proptest! {
+    #[test]
+    fn test_relaid_examples(
+        index in 0..examples().len(),
+        choices in prop::collection::vec(0..64usize, 1..64),
+    ) {
+        assert_round_trips(&relayout(&examples()[index], &choices));
+    }
+
+    #[test]
+    fn test_generated_programs(
+        source in program(),
+        choices in prop::collection::vec(0..64usize, 1..64),
+    ) {
+        assert_round_trips(&relayout(&source, &choices));
+    }
+}
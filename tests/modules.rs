@@ -0,0 +1,123 @@
+mod common;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use common::Output;
+use lira::{
+    eval::Interpreter,
+    modules::{ModuleErrorKind, ModuleLoader},
+    parser::parse_program,
+};
+
+/// Writes `files` in a fresh project directory.
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("lira-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    for (path, text) in files {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+    root
+}
+
+fn load(root: &Path) -> (ModuleLoader, Result<(), ModuleErrorKind>) {
+    let file = root.join("main.li");
+    let source = fs::read_to_string(&file).unwrap();
+    let (program, errors) = parse_program(&source);
+    assert!(errors.is_empty(), "source should parse: {:?}", errors);
+    let mut loader = ModuleLoader::new(root);
+    let result = loader.load(&file, &program).map_err(|e| e.kind);
+    (loader, result)
+}
+
+#[test]
+fn test_imports_are_loaded_dependencies_first() {
+    let root = project(
+        "order",
+        &[
+            ("main.li", "use geo::shapes\nuse util as u\n"),
+            ("geo/shapes.li", "use util\nstruct Point { x: Int }\n"),
+            ("util.li", "fn id(x) { :> x }\n"),
+        ],
+    );
+    let (loader, result) = load(&root);
+    assert_eq!(result, Ok(()));
+    let names: Vec<_> = loader.sources().iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["util", "geo::shapes"]);
+    assert_eq!(loader.sources()[1].file, root.join("geo").join("shapes.li"));
+}
+
+#[test]
+fn test_import_cycles_are_detected() {
+    let root = project(
+        "cycle",
+        &[
+            ("main.li", "use a\n"),
+            ("a.li", "use b\n"),
+            ("b.li", "fn f() {\n    use a\n}\n"),
+        ],
+    );
+    match load(&root).1 {
+        Err(ModuleErrorKind::Cycle { chain, .. }) => assert_eq!(chain, vec!["a", "b", "a"]),
+        other => panic!("expected a cycle, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_missing_and_invalid_modules() {
+    let root = project("missing", &[("main.li", "use std::Json\nuse nope\n")]);
+    assert!(matches!(
+        load(&root).1,
+        Err(ModuleErrorKind::Unreadable { name, .. }) if name == "nope"
+    ));
+
+    let root = project("unknown-std", &[("main.li", "use std::Nope\n")]);
+    assert!(matches!(
+        load(&root).1,
+        Err(ModuleErrorKind::UnknownStd { name, .. }) if name == "std::Nope"
+    ));
+
+    let root = project(
+        "invalid",
+        &[("main.li", "use bad\n"), ("bad.li", "let = 1\n")],
+    );
+    assert!(matches!(load(&root).1, Err(ModuleErrorKind::Syntax(errors)) if errors.len() == 1));
+}
+
+#[test]
+fn test_items_are_exposed_under_path_or_alias() {
+    let root = project(
+        "run",
+        &[
+            (
+                "main.li",
+                "use math::ops\nuse shapes as sh\nprint(ops.add(1, 2), sh.area(sh.Rect.new(2, 3)))\n",
+            ),
+            (
+                "math/ops.li",
+                "fn add(a, b) { :> a + b }\nprint(\"not run\")\n",
+            ),
+            (
+                "shapes.li",
+                "use math::ops\nstruct Rect { w: Int, h: Int }\nfn area(r) { :> ops.add(r.w * r.h, 0) }\n",
+            ),
+        ],
+    );
+    let source = fs::read_to_string(root.join("main.li")).unwrap();
+    let (program, _) = parse_program(&source);
+    let (loader, result) = load(&root);
+    assert_eq!(result, Ok(()));
+
+    let modules = loader.parse();
+    let output = Output::default();
+    let mut interpreter = Interpreter::with_output(Box::new(output.clone()));
+    for (name, module) in &modules {
+        interpreter.add_module(*name, module);
+    }
+    interpreter.run(&program).unwrap();
+    assert_eq!(output.text(), "3 6\n");
+}
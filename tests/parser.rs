@@ -0,0 +1,173 @@
+use lira::{
+    ast::{BinOp, Expr, Literal, Pattern, Span, Spanned, Statement, Type},
+    parser::parse_program,
+};
+
+fn parse(source: &str) -> Vec<Spanned<Statement<'_>>> {
+    let (program, errors) = parse_program(source);
+    assert!(errors.is_empty(), "source should parse: {:?}", errors);
+    program.statements
+}
+
+fn text(source: &str, span: Span) -> &str {
+    &source[span.start..span.end]
+}
+
+#[test]
+fn test_statement_spans() {
+    let source = "let x = 1 + 2\n\nfn add(a, b) {\n    :> a + b\n}\n";
+    let statements = parse(source);
+
+    assert_eq!(statements.len(), 2);
+    assert_eq!(text(source, statements[0].span), "let x = 1 + 2");
+    assert_eq!(
+        text(source, statements[1].span),
+        "fn add(a, b) {\n    :> a + b\n}"
+    );
+}
+
+#[test]
+fn test_expression_spans() {
+    let source = "let y = foo.bar(1, 2) * -(3 + 4)";
+    let statements = parse(source);
+
+    let Statement::Let { value, .. } = &statements[0].node else {
+        panic!("Expected a let statement");
+    };
+    assert_eq!(text(source, value.span), "foo.bar(1, 2) * -(3 + 4)");
+
+    let Expr::Binary(lhs, BinOp::Multiply, rhs) = &value.node else {
+        panic!("Expected a multiplication");
+    };
+    assert_eq!(text(source, lhs.span), "foo.bar(1, 2)");
+    assert_eq!(text(source, rhs.span), "-(3 + 4)");
+
+    let Expr::Call(callee, args) = &lhs.node else {
+        panic!("Expected a call");
+    };
+    assert_eq!(text(source, callee.span), "foo.bar");
+    assert_eq!(text(source, args[1].span), "2");
+}
+
+#[test]
+fn test_type_and_pattern_spans() {
+    let source = "let t: (Int, [Float]) = x\nmatch t {\n    Some(v) => v,\n    _ => 0,\n}";
+    let statements = parse(source);
+
+    let Statement::Let { ty: Some(ty), .. } = &statements[0].node else {
+        panic!("Expected a typed let statement");
+    };
+    assert_eq!(text(source, ty.span), "(Int, [Float])");
+    let Type::Tuple(items) = &ty.node else {
+        panic!("Expected a tuple type");
+    };
+    assert_eq!(text(source, items[1].span), "[Float]");
+
+    let Statement::Match(m) = &statements[1].node else {
+        panic!("Expected a match statement");
+    };
+    assert_eq!(text(source, m.arms[0].pattern.span), "Some(v)");
+    assert_eq!(m.arms[1].pattern.node, Pattern::Wildcard);
+}
+
+#[test]
+fn test_spans_are_ignored_by_equality() {
+    let compact = parse("let a=1+2");
+    let spaced = parse("let   a =  1 +\n 2");
+
+    assert_eq!(compact, spaced);
+    let Statement::Let { value, .. } = &compact[0].node else {
+        panic!("Expected a let statement");
+    };
+    let Expr::Binary(lhs, _, _) = &value.node else {
+        panic!("Expected a binary expression");
+    };
+    assert_eq!(lhs.node, Expr::Literal(Literal::Int(1)));
+}
+
+#[test]
+fn test_recovers_from_several_errors() {
+    let source =
+        "let a = )\nlet b = 2\nfn main() {\n    let = 3\n    print(b)\n}\nlet c = [1,, 2]\n";
+    let (program, errors) = parse_program(source);
+
+    assert_eq!(errors.len(), 3);
+    let statements: Vec<_> = program.statements.iter().map(|s| &s.node).collect();
+    assert_eq!(statements.len(), 4);
+    assert_eq!(statements[0], &Statement::Error);
+    assert!(matches!(statements[1], Statement::Let { .. }));
+    assert_eq!(statements[3], &Statement::Error);
+
+    let Statement::FnDecl { body, .. } = statements[2] else {
+        panic!("Expected a function declaration");
+    };
+    assert_eq!(body[0].node, Statement::Error);
+    assert!(matches!(body[1].node, Statement::Expr(_)));
+}
+
+#[test]
+fn test_unexpected_eof_is_recovered() {
+    let (program, errors) = parse_program("let a = 1\nfn main() {\n    print(1)\n");
+
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(program.statements[0].node, Statement::Let { .. }));
+    assert_eq!(program.statements.last().unwrap().node, Statement::Error);
+}
+
+#[test]
+fn test_doc_comments() {
+    let source = "\
+/// Adds two numbers.
+///
+/// // not a line comment
+fn add(a, b) {
+    /// The sum.
+    fn sum() {}
+    :> a + b
+}
+
+/// A point.
+// a plain comment in between
+struct Point {
+    /// Horizontal.
+    x: Int, y: Int,
+}
+
+enum Shape {
+    /// Round.
+    Circle(Int),
+    Square,
+}
+
+/// Not documentation.
+let x = 1
+/// Nothing after.
+";
+    let statements = parse(source);
+    assert_eq!(statements.len(), 4);
+    assert!(text(source, statements[0].span).starts_with("fn add"));
+
+    let Statement::FnDecl { doc, body, .. } = &statements[0].node else {
+        panic!("Expected a function declaration");
+    };
+    assert_eq!(doc, &["Adds two numbers.", "", "// not a line comment"]);
+    let Statement::FnDecl { doc, .. } = &body[0].node else {
+        panic!("Expected a nested function declaration");
+    };
+    assert_eq!(doc, &["The sum."]);
+
+    let Statement::Struct { doc, fields, .. } = &statements[1].node else {
+        panic!("Expected a struct declaration");
+    };
+    assert_eq!(doc, &["A point."]);
+    assert_eq!(fields[0].doc, ["Horizontal."]);
+    assert_eq!(fields[0].0, "x");
+    assert!(fields[1].doc.is_empty());
+
+    let Statement::Enum { doc, variants, .. } = &statements[2].node else {
+        panic!("Expected an enum declaration");
+    };
+    assert!(doc.is_empty());
+    assert_eq!(variants[0].doc, ["Round."]);
+    assert!(variants[1].doc.is_empty());
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::Output;
+use lira::{
+    eval::Interpreter,
+    repl::{Session, is_incomplete},
+};
+
+#[test]
+fn test_incomplete_entries() {
+    assert!(is_incomplete("fn add(a, b) {\n"));
+    assert!(is_incomplete("let xs = [1,\n"));
+    assert!(is_incomplete("print(1,\n"));
+    assert!(is_incomplete("let s = \"abc\n"));
+    assert!(!is_incomplete("fn add(a, b) {\n    :> a + b\n}\n"));
+    assert!(!is_incomplete("let s = \"{ (\"\n"));
+    assert!(!is_incomplete("let x = 1 // {\n"));
+    assert!(!is_incomplete("}\n"));
+}
+
+#[test]
+fn test_bindings_persist_between_entries() {
+    let output = Output::default();
+    let mut session = Session::new(Interpreter::with_output(Box::new(output.clone())));
+    assert_eq!(session.handle("let mut total = 10\n"), Ok(String::new()));
+    assert_eq!(
+        session.handle("fn add(n) {\n    total += n\n}\n"),
+        Ok(String::new())
+    );
+    assert_eq!(session.handle("add(5)\n"), Ok(String::new()));
+    assert_eq!(session.handle("print(total)\n"), Ok(String::new()));
+    assert_eq!(session.handle("total * 2\n"), Ok("30\n".to_string()));
+    assert_eq!(session.handle("it + 1\n"), Ok("31\n".to_string()));
+    assert_eq!(
+        session.handle("missing(1)\n"),
+        Ok("runtime error: undefined variable `missing`\n".to_string())
+    );
+    assert_eq!(session.handle("exit(4)\n"), Err(4));
+    assert_eq!(output.text(), "15\n");
+}
+
+#[test]
+fn test_meta_commands() {
+    let mut session = Session::new(Interpreter::new());
+    assert_eq!(
+        session.handle(":tokens let x = 1"),
+        Ok("Let\nIdent(\"x\")\nEquals\nInt(1)\n".to_string())
+    );
+    let ast = session.handle(":ast break").unwrap();
+    assert_eq!(ast, "Break\n");
+
+    session.handle("fn twice(x) {\n    :> x * 2\n}").unwrap();
+    session.handle("let name = \"lira\"").unwrap();
+    assert_eq!(
+        session.handle(":type twice"),
+        Ok("(Int): Int\n".to_string())
+    );
+    assert_eq!(
+        session.handle(":type twice(2) > 3"),
+        Ok("Bool\n".to_string())
+    );
+    assert_eq!(session.handle(":type name"), Ok("String\n".to_string()));
+    let error = session.handle(":type twice(name)").unwrap();
+    assert!(error.contains("mismatched types"), "{}", error);
+    assert!(error.contains("<repl>:1:"), "{}", error);
+    assert!(
+        session
+            .handle(":nope")
+            .unwrap()
+            .contains("unknown command `:nope`")
+    );
+}
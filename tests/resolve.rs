@@ -0,0 +1,99 @@
+use lira::{
+    ast::Span,
+    parser::parse_program,
+    resolve::{DefKind, ResolveErrorKind, resolve_program},
+};
+
+fn errors(source: &str) -> Vec<(ResolveErrorKind, &str)> {
+    let (program, errors) = parse_program(source);
+    assert!(errors.is_empty(), "source should parse: {:?}", errors);
+    resolve_program(&program)
+        .errors
+        .into_iter()
+        .map(|e| (e.kind, &source[e.span.start..e.span.end]))
+        .collect()
+}
+
+#[test]
+fn test_uses_are_linked_to_definitions() {
+    let source = "let total = 1\nfn add(n) {\n    :> n + total\n}\nadd(total)";
+    let (program, _) = parse_program(source);
+    let resolution = resolve_program(&program);
+    assert_eq!(resolution.errors, vec![]);
+
+    let at = |offset: usize, len: usize| {
+        let def = resolution
+            .definition(Span::new(offset, offset + len))
+            .unwrap();
+        (def.name, def.kind, &source[def.span.start..def.span.end])
+    };
+    // `n` and `total` in the body, `add` and `total` in the call
+    assert_eq!(
+        at(33, 1),
+        ("n", DefKind::Param, "fn add(n) {\n    :> n + total\n}")
+    );
+    assert_eq!(at(37, 5), ("total", DefKind::Let, "let total = 1"));
+    assert_eq!(
+        at(45, 3),
+        ("add", DefKind::Function, "fn add(n) {\n    :> n + total\n}")
+    );
+    assert_eq!(at(49, 5), ("total", DefKind::Let, "let total = 1"));
+
+    let total = resolution.references[&Span::new(49, 54)];
+    assert_eq!(
+        resolution.uses(total),
+        vec![Span::new(37, 42), Span::new(49, 54)]
+    );
+}
+
+#[test]
+fn test_undefined_names() {
+    assert_eq!(
+        errors("let a = b + 1\nprint(\"#{c}\")\nfor i in 0..3 { print(i) }\nprint(i)"),
+        vec![
+            (ResolveErrorKind::Undefined("b".to_string()), "b"),
+            (ResolveErrorKind::Undefined("c".to_string()), "c"),
+            (ResolveErrorKind::Undefined("i".to_string()), "i"),
+        ]
+    );
+    // only `spawn with` binds `context`, in its block
+    assert_eq!(
+        errors("let t = spawn with 1 { print(context) }\nspawn { print(context) }"),
+        vec![(
+            ResolveErrorKind::Undefined("context".to_string()),
+            "context"
+        )]
+    );
+}
+
+#[test]
+fn test_bodies_see_their_whole_block() {
+    let source = r#"
+fn main() { print(greeting) }
+let greeting = "hi"
+let fact = |n| {
+    match n {
+        0 => { :> 1 }
+        m => { :> m * fact(m - 1) }
+    }
+}
+struct Point { x: Int }
+let p = Point.new(1)
+"#;
+    assert_eq!(errors(source), vec![]);
+}
+
+#[test]
+fn test_duplicates_and_shadowing() {
+    let errors = errors("fn f(a, a) {}\nfn f() {}\nlet x = 1\nlet x = 2\nlet [y, y] = [1, 2]");
+    let kinds: Vec<_> = errors.iter().map(|(kind, _)| kind).collect();
+    assert!(matches!(
+        kinds.as_slice(),
+        [
+            ResolveErrorKind::Duplicate { name: f, .. },
+            ResolveErrorKind::Duplicate { name: a, .. },
+            ResolveErrorKind::Shadowed { name: x, .. },
+            ResolveErrorKind::Duplicate { name: y, .. },
+        ] if f == "f" && x == "x" && y == "y" && a == "a"
+    ));
+}
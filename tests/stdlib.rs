@@ -0,0 +1,125 @@
+mod common;
+
+use std::fs;
+
+use common::run;
+use lira::eval::RuntimeError;
+
+/// Runs `source` with `input` as stdin and `args` as arguments, returning
+/// what it printed.
+fn run_with(source: &str, input: &str, args: &[&str]) -> Result<String, RuntimeError> {
+    common::run_with(source, |interpreter| {
+        interpreter.set_input(Box::new(std::io::Cursor::new(input.to_string())));
+        interpreter.set_args(args.iter().map(|arg| arg.to_string()).collect());
+    })
+}
+
+#[test]
+fn test_fs_read_write_exists() {
+    let dir = std::env::temp_dir().join(format!("lira-stdlib-fs-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("out.txt").display().to_string();
+    let _ = fs::remove_file(&file);
+
+    let source = format!(
+        r#"
+use std::Fs
+let path = "{file}"
+print(Fs.exists(path))
+Fs.write(path, "hello")
+print(Fs.exists(path), Fs.read(path))
+"#
+    );
+    assert_eq!(run(&source).unwrap(), "false\ntrue hello\n");
+    assert_eq!(fs::read_to_string(&file).unwrap(), "hello");
+
+    let missing = dir.join("missing.txt").display().to_string();
+    let source = format!("use std::Fs\nFs.read(\"{missing}\")");
+    assert!(matches!(run(&source), Err(RuntimeError::Io(_))));
+}
+
+#[test]
+fn test_json_parse() {
+    let source = r#"
+use std::Json
+let value = match Json.parse("{\"name\": \"lira\", \"tags\": [1, 2.5, true, null], \"nested\": {\"a\": 1}}") {
+    Ok(v) => v,
+    Err(e) => e,
+}
+print(value.name, value.tags, value.nested.a, value.keys())
+match Json.parse("{") {
+    Ok(v) => print("parsed"),
+    Err(e) => print("error"),
+}
+"#;
+    assert_eq!(
+        run(source).unwrap(),
+        "lira [1, 2.5, true, ()] 1 [name, tags, nested]\nerror\n"
+    );
+}
+
+#[test]
+fn test_json_stringify() {
+    let source = r#"
+use std::Json
+struct Point { x: Int, y: Float }
+enum Shape { Empty, Circle(Float) }
+print(Json.stringify([Point.new(1, 2.5), (), "a\"b"]))
+print(Json.stringify([Shape.Empty, Shape.Circle(1.5)]))
+let parsed = match Json.parse("{\"b\":[1,{\"c\":null}],\"a\":\"x\"}") {
+    Ok(v) => v,
+    Err(e) => e,
+}
+parsed["d"] = 1
+print(Json.stringify(parsed))
+"#;
+    assert_eq!(
+        run(source).unwrap(),
+        "[{\"x\":1,\"y\":2.5},null,\"a\\\"b\"]\n\
+         [\"Empty\",{\"Circle\":[1.5]}]\n\
+         {\"b\":[1,{\"c\":null}],\"a\":\"x\",\"d\":1}\n"
+    );
+    assert!(matches!(
+        run("use std::Json\nJson.stringify(1..2)"),
+        Err(RuntimeError::TypeError(_))
+    ));
+}
+
+#[test]
+fn test_io_read_line() {
+    let source = r#"
+use std::Io
+let first = Io.read_line()
+let second = Io.read_line()
+Io.puts(first, second, Io.read_line())
+"#;
+    assert_eq!(
+        run_with(source, "one\r\ntwo\n", &[]).unwrap(),
+        "one two ()\n"
+    );
+}
+
+#[test]
+fn test_process_args_env_exit() {
+    let source = r#"
+use std::Process as p
+print(p.args(), p.env("LIRA_STDLIB_TEST_UNSET"))
+p.exit(3)
+print("unreachable")
+"#;
+    assert_eq!(
+        run_with(source, "", &["a", "b"]),
+        Err(RuntimeError::Exit(3))
+    );
+
+    let source = "use std::Process\nprint(Process.args(), Process.env(\"LIRA_STDLIB_TEST_UNSET\"))";
+    assert_eq!(run_with(source, "", &["a", "b"]).unwrap(), "[a, b] ()\n");
+}
+
+#[test]
+fn test_unknown_std_module() {
+    assert_eq!(
+        run("use std::Nope"),
+        Err(RuntimeError::UnknownModule("std::Nope".to_string()))
+    );
+}
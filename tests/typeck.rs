@@ -0,0 +1,332 @@
+use lira::{
+    parser::parse_program,
+    typeck::{Ty, TypeError, TypeErrorKind, check_program, identifier_types, infer_program},
+};
+
+fn check(source: &str) -> Vec<TypeError> {
+    let (program, errors) = parse_program(source);
+    assert!(errors.is_empty(), "source should parse: {:?}", errors);
+    check_program(&program)
+}
+
+fn kinds(source: &str) -> Vec<TypeErrorKind> {
+    check(source).into_iter().map(|e| e.kind).collect()
+}
+
+#[test]
+fn test_well_typed_program() {
+    let errors = check(
+        r#"
+typealias Num = int
+struct Point { x: Int, y: Num }
+enum Shape { Circle(Float), Square { side: Int } }
+
+fn norm(p: Point) -> Int {
+    :> p.x * p.x + p.y * p.y
+}
+
+fn area(s: Shape) -> Float {
+    :> match s {
+        Circle(r) => r * r * 3.14,
+        Square { side } => side * 1.0,
+    }
+}
+
+let p: Point = Point.new(1, 2)
+let n: Int = norm(p)
+let items: [String] = ["a", "b"]
+let pair: (Int, Bool) = (n, n > 2)
+let f: (Int): Int = |x: Int| x + 1
+let shape: Shape = Shape.Circle(2.0)
+"#,
+    );
+    assert_eq!(errors, vec![]);
+}
+
+#[test]
+fn test_let_annotation_mismatch() {
+    let errors = check("let a: Int = \"x\"");
+    assert_eq!(
+        errors,
+        vec![TypeError {
+            kind: TypeErrorKind::Mismatch {
+                expected: Ty::Int,
+                found: Ty::String
+            },
+            span: lira::ast::Span::new(13, 16),
+        }]
+    );
+}
+
+#[test]
+fn test_return_type_mismatch() {
+    assert_eq!(
+        kinds("fn f(x: Float) -> Int {\n    :> x * 2.0\n}"),
+        vec![TypeErrorKind::Mismatch {
+            expected: Ty::Int,
+            found: Ty::Float
+        }]
+    );
+}
+
+#[test]
+fn test_unknown_and_recursive_types() {
+    assert_eq!(
+        kinds("let a: Nope = 1\ntypealias A = B\ntypealias B = A\nlet b: A = 1"),
+        vec![
+            TypeErrorKind::UnknownType("Nope".to_string()),
+            TypeErrorKind::RecursiveAlias("A".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_calls_and_fields() {
+    assert_eq!(
+        kinds(
+            r#"
+struct Point { x: Int, y: Int }
+fn add(a: Int, b: Int) -> Int { :> a + b }
+let s: String = add(1, 2)
+add(1)
+let p = Point.new(1, "2")
+let z = p.z
+"#
+        ),
+        vec![
+            TypeErrorKind::Mismatch {
+                expected: Ty::String,
+                found: Ty::Int
+            },
+            TypeErrorKind::ArityMismatch {
+                expected: 2,
+                found: 1
+            },
+            TypeErrorKind::Mismatch {
+                expected: Ty::Int,
+                found: Ty::String
+            },
+            TypeErrorKind::UnknownField(Ty::Named("Point".to_string()), "z".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_conditions_and_operators() {
+    assert_eq!(
+        kinds("if 1 { print(1) }\nlet a = \"a\" - 1\nlet ok = 1 < 2.5 && true"),
+        vec![
+            TypeErrorKind::Mismatch {
+                expected: Ty::Bool,
+                found: Ty::Int
+            },
+            TypeErrorKind::InvalidOperands(lira::ast::BinOp::Minus, Ty::String, Ty::Int),
+        ]
+    );
+    let errors = check("let a = \"a\" - 1\nlet b = !1");
+    let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "cannot apply `-` to String and Int",
+            "cannot apply `!` to Int"
+        ]
+    );
+}
+
+fn signatures(source: &str) -> Vec<String> {
+    let (program, errors) = parse_program(source);
+    assert!(errors.is_empty(), "source should parse: {:?}", errors);
+    let (signatures, errors) = infer_program(&program);
+    assert_eq!(errors, vec![]);
+    signatures.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn test_infer_unannotated_functions() {
+    assert_eq!(
+        signatures(
+            r#"
+fn fact(n) {
+    if n <= 1 { :> 1 }
+    :> n * fact(n - 1)
+}
+fn id(x) { :> x }
+fn twice(f, x) { :> f(f(x)) }
+fn last(a: Int, b) { id(b) }
+"#
+        ),
+        vec![
+            "fact: (Int): Int",
+            "id: ('a): 'a",
+            "twice: (('a): 'a, 'a): 'a",
+            "last: (Int, 'a): 'a",
+        ]
+    );
+}
+
+#[test]
+fn test_generalized_bindings_and_closures() {
+    assert_eq!(
+        signatures(
+            r#"
+fn id(x) { :> x }
+let compose = |f, g| { :> |x| g(f(x)) }
+let n = id(1)
+let s = id("s")
+let inc = |i| i + 1
+let names = ["a", "b"].map(|name| name)
+let label = match n {
+    0 => "zero",
+    _ => { :> "many" }
+}
+"#
+        ),
+        vec![
+            "id: ('a): 'a",
+            "compose: (('a): 'b, ('b): 'c): ('a): 'c",
+            "n: Int",
+            "s: String",
+            "inc: (Int): Int",
+            "names: _",
+            "label: String",
+        ]
+    );
+}
+
+#[test]
+fn test_inferred_mismatches() {
+    let errors = kinds("fn inc(i) { :> i + 1 }\ninc(\"one\")\nlet f = |x| x(x)");
+    assert_eq!(
+        errors[0],
+        TypeErrorKind::Mismatch {
+            expected: Ty::Int,
+            found: Ty::String
+        }
+    );
+    // `x` would have to be a function taking itself
+    assert!(matches!(
+        &errors[1..],
+        [TypeErrorKind::Mismatch {
+            found: Ty::Function(..),
+            ..
+        }]
+    ));
+}
+
+#[test]
+fn test_identifier_types() {
+    let source = "fn id(x) { :> x }\nlet n = id(1)\nprint(\"#{n}\", n)\n";
+    let (program, _) = parse_program(source);
+    let types = identifier_types(&program);
+    let type_at = |offset: usize| {
+        let (span, ty) = types.iter().find(|(span, _)| span.start == offset).unwrap();
+        (&source[span.start..span.end], ty.to_string())
+    };
+    assert_eq!(type_at(14), ("x", "'a".to_string()));
+    assert_eq!(type_at(26), ("id", "(Int): Int".to_string()));
+    assert_eq!(type_at(41), ("n", "Int".to_string()));
+    assert_eq!(type_at(46), ("n", "Int".to_string()));
+    assert_eq!(types.len(), 5);
+}
+
+#[test]
+fn test_tasks_share_atomic_bindings_only() {
+    let shared = |name: &str| TypeErrorKind::SharedMutable(name.to_string());
+    let source = r##"
+let mut count = 0
+let atomic total = 0
+let limit = 10
+let items = [1, 2, 3]
+spawn {
+    let mut local = limit
+    local += 1
+    total.add(local)
+    count += 1
+}
+items.par_each(|i| { print("#{count}") })
+items.each(|i| { count += i })
+fn f() {
+    let mut n = 1
+    spawn with n { print(context) }
+}
+"##;
+    assert_eq!(kinds(source), vec![shared("count"), shared("count")]);
+    let errors = check(source);
+    assert_eq!(&source[errors[1].span.start..errors[1].span.end], "count");
+}
+
+#[test]
+fn test_async_bindings_need_futures() {
+    let source = r#"
+async fn fetch(n: Int) -> Int {
+    return n
+}
+let a: async Int = fetch(1)
+let b: Int = await fetch(2)
+let c: async Int = 3
+let d: Int = fetch(4)
+"#;
+    let errors = check(source);
+    let async_int = Ty::Awaitable(Box::new(Ty::Int));
+    assert_eq!(
+        errors.iter().map(|e| e.kind.clone()).collect::<Vec<_>>(),
+        vec![
+            TypeErrorKind::Mismatch {
+                expected: async_int.clone(),
+                found: Ty::Int
+            },
+            TypeErrorKind::Mismatch {
+                expected: Ty::Int,
+                found: async_int
+            },
+        ]
+    );
+    let labels: Vec<_> = errors.iter().map(|e| e.to_diagnostic().label).collect();
+    assert_eq!(
+        labels,
+        vec![
+            Some("Int is not async, only futures are".to_string()),
+            Some("`await` it to get its Int".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_tasks_share_captured_bindings() {
+    let shared = |name: &str| TypeErrorKind::SharedMutable(name.to_string());
+    let source = r#"
+let r = [1, 2, 3]
+let mut n = 0
+let mut m = 0
+let f = |x| { n += x }
+let g = |x| f(x)
+fn h(x) {
+    m += x
+}
+r.par_each(f)
+r.par_each(g)
+r.par_map(h)
+spawn { g(1) }
+r.each(f)
+spawn {
+    let mut local = 0
+    let add = |x| { local += x }
+    r.par_each(add)
+    add(1)
+}
+"#;
+    assert_eq!(
+        kinds(source),
+        vec![
+            shared("n"),
+            shared("n"),
+            shared("m"),
+            shared("n"),
+            shared("local")
+        ]
+    );
+    let errors = check(source);
+    assert_eq!(&source[errors[0].span.start..errors[0].span.end], "f");
+    assert_eq!(&source[errors[3].span.start..errors[3].span.end], "g");
+}
@@ -0,0 +1,254 @@
+mod common;
+
+use common::Output;
+use lira::{
+    ast::BinOp,
+    compiler::{chunk::Op, compile},
+    eval::{Interpreter, RuntimeError},
+    parser::parse_program,
+    vm::Vm,
+};
+
+/// Runs `source` on the VM and on the tree-walking interpreter, which must
+/// agree on both the output and the result.
+fn run(source: &str) -> Result<String, RuntimeError> {
+    let (program, errors) = parse_program(source);
+    assert!(errors.is_empty(), "source should parse: {:?}", errors);
+
+    let walked = Output::default();
+    let expected = Interpreter::with_output(Box::new(walked.clone()))
+        .run(&program)
+        .map(|value| value.to_string());
+    let compiled = Output::default();
+    let result = Vm::new(Interpreter::with_output(Box::new(compiled.clone())))
+        .run(&program)
+        .map(|value| value.to_string());
+
+    assert_eq!(result, expected, "results differ for:\n{}", source);
+    assert_eq!(
+        compiled.text(),
+        walked.text(),
+        "outputs differ for:\n{}",
+        source
+    );
+    result.map(|_| compiled.text())
+}
+
+#[test]
+fn test_locals_use_slots() {
+    let (program, _) = parse_program("fn inc(a) {\n    let b = a + 1\n    :> b\n}");
+    let script = compile(&program);
+    let inc = &script.protos[0];
+    assert_eq!((inc.name, inc.arity, inc.locals), (Some("inc"), 1, 2));
+    assert_eq!(
+        inc.code[..6],
+        [
+            Op::GetLocal(0),
+            Op::Const(0),
+            Op::Binary(BinOp::Plus),
+            Op::SetLocal(1),
+            Op::GetLocal(1),
+            Op::Return,
+        ]
+    );
+}
+
+#[test]
+fn test_programs_match_interpreter() -> Result<(), RuntimeError> {
+    let output = run(r#"
+fn fact(n: Int) -> Int {
+    if n <= 1 { :> 1 }
+    :> n * fact(n - 1)
+}
+let x = 2 + 3 * 4
+print("x = #{x - 1}", 7 / 2, 1.5 * 2, fact(10))
+
+let mut total = 0
+for i in 0..5 {
+    total += i
+}
+let mut n = 0
+while true {
+    n += 1
+    if n == 3 {
+        break
+    }
+}
+print(total, n, n > 2 && total < 5, n > 2 || 1 / 0 == 0)
+
+let offset = 10
+let add = |x| x + offset
+let double = |x| x * 2
+print([1, 2, 3].map(add), 4 |> double |> add)
+"#)?;
+    assert_eq!(
+        output,
+        "x = 13 3 3.0 3628800\n10 3 false true\n[11, 12, 13] 18\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_structs_enums_and_match() -> Result<(), RuntimeError> {
+    let output = run(r#"
+struct Point { x: Int, y: Int }
+enum Shape { Circle(Int), Rect { w: Int, h: Int }, Empty }
+
+fn area(s) {
+    :> match s {
+        Circle(r) => r * r * 3,
+        Rect { w, h: height } => w * height,
+        Empty => 0,
+    }
+}
+
+fn main() {
+    let p = Point.new(1, 2)
+    let Point { x, y: b } = p
+    p.x = 5
+    let (first, [second, third]) = (x, [b, p.x])
+    print(first, second, third)
+    print(area(Shape.Circle(2)), area(Shape.Rect(2, 3)), area(Shape.Empty))
+}
+"#)?;
+    assert_eq!(output, "1 2 5\n12 6 0\n");
+    Ok(())
+}
+
+#[test]
+fn test_closures_share_captured_variables() -> Result<(), RuntimeError> {
+    let output = run(r#"
+fn counter() {
+    let mut count = 0
+    fn next() {
+        count += 1
+        :> count
+    }
+    :> next
+}
+
+fn main() {
+    let a = counter()
+    let b = counter()
+    a()
+    a()
+    let fib = |n| {
+        if n < 2 { :> n }
+        :> fib(n - 1) + fib(n - 2)
+    }
+    let adders = [1, 2, 3].map(|i| { :> |x| x + i })
+    print(a(), b(), fib(15), adders.map(|f| f(10)))
+}
+"#)?;
+    assert_eq!(output, "3 1 610 [11, 12, 13]\n");
+    Ok(())
+}
+
+#[test]
+fn test_break_and_match_values() -> Result<(), RuntimeError> {
+    let output = run(r##"
+fn label(n) {
+    let name = match n {
+        0 => "zero",
+        x if x < 0 => {
+            :> "negative"
+        }
+        _ => "many",
+    }
+    :> "#{n} is #{name}"
+}
+
+fn main() {
+    for i in [0, -1, 5] {
+        print(label(i))
+    }
+    let mut found = 0
+    for i in 1..inf {
+        if i * i > 50 {
+            found = i
+            break
+        }
+    }
+    let naturals = 0..inf
+    naturals.each(|n| {
+        match n {
+            x if x < 2 => print(x),
+            _ => break,
+        }
+    })
+    print(found)
+}
+"##)?;
+    assert_eq!(output, "0 is zero\n-1 is negative\n5 is many\n0\n1\n8\n");
+    Ok(())
+}
+
+#[test]
+fn test_runtime_errors_match_interpreter() {
+    assert_eq!(
+        run("print(y)"),
+        Err(RuntimeError::UndefinedVariable("y".to_string()))
+    );
+    assert_eq!(
+        run("fn f() {\n    let a = 1\n    a = 2\n}\nf()"),
+        Err(RuntimeError::ImmutableAssignment("a".to_string()))
+    );
+    assert_eq!(run("let a = 1 / 0"), Err(RuntimeError::DivisionByZero));
+    assert_eq!(
+        run("match 3 {\n    1 => print(1),\n}"),
+        Err(RuntimeError::NoMatchingArm("3".to_string()))
+    );
+    assert_eq!(run("exit(3)"), Err(RuntimeError::Exit(3)));
+}
+
+#[test]
+fn test_calls_take_more_than_255_arguments() -> Result<(), RuntimeError> {
+    let args: Vec<_> = (0..256).map(|i| i.to_string()).collect();
+    let output = run(&format!("print({})", args.join(", ")))?;
+    assert_eq!(output, format!("{}\n", args.join(" ")));
+    Ok(())
+}
+
+#[test]
+fn test_atomic_bindings_are_plain_locals() -> Result<(), RuntimeError> {
+    let output = run(r#"
+let atomic total = 0
+for i in 0..10 {
+    total = total + i
+}
+total += 1
+print(total)
+"#)?;
+    assert_eq!(output, "46\n");
+    Ok(())
+}
+
+#[test]
+fn test_lazy_bindings_are_evaluated() -> Result<(), RuntimeError> {
+    let output = run(r#"
+fn square(x: Int) -> Int {
+    :> x * x
+}
+let lazy a = square(4)
+let lazy atomic b = a + 1
+print(a, b)
+"#)?;
+    assert_eq!(output, "16 17\n");
+    Ok(())
+}
+
+#[test]
+fn test_async_functions_return_right_away() -> Result<(), RuntimeError> {
+    let output = run(r#"
+use std::Time
+
+async fn add(a: Int, b: Int) -> Int {
+    await Time.sleep(1)
+    :> a + b
+}
+let sum = await add(1, 2)
+print(sum, await add(sum, 4))
+"#)?;
+    assert_eq!(output, "3 7\n");
+    Ok(())
+}